@@ -0,0 +1,54 @@
+use std::path::Path;
+use std::time::Duration;
+use vimputti::capture::Capture;
+use vimputti::*;
+
+const USAGE: &str = "Usage: capture record <device> <seconds> <file> | capture replay <file>";
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    match args
+        .iter()
+        .map(String::as_str)
+        .collect::<Vec<_>>()
+        .as_slice()
+    {
+        ["record", device, seconds, file] => {
+            let duration = Duration::from_secs_f64(seconds.parse()?);
+            println!("Capturing {} for {:?}...", device, duration);
+
+            let capture = Capture::record(Path::new(device), duration)?;
+            capture.save(Path::new(file))?;
+            println!(
+                "Captured {} events of {} to {}",
+                capture.events.len(),
+                capture.config.name,
+                file
+            );
+        }
+        ["replay", file] => {
+            let capture = Capture::load(Path::new(file))?;
+            let client = VimputtiClient::connect_default().await?;
+            let device = client.create_device(capture.config.clone()).await?;
+            println!(
+                "Replaying {} events ({:?}) into {}",
+                capture.events.len(),
+                capture.duration(),
+                device.event_node()
+            );
+            if let Some(started_at) = capture.started_at() {
+                let unix_time = started_at.duration_since(std::time::UNIX_EPOCH)?;
+                println!("Captured at Unix time {:.6}", unix_time.as_secs_f64());
+            }
+
+            capture.replay(&device).await?;
+            device.close().await?;
+            println!("Replay complete");
+        }
+        _ => anyhow::bail!(USAGE),
+    }
+
+    Ok(())
+}
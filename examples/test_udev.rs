@@ -1,31 +1,22 @@
 use std::time::Duration;
-use tokio::io::{AsyncBufReadExt, BufReader};
-use tokio::net::UnixStream;
-use tokio::time::sleep;
+use vimputti::manager::udev::UdevAction;
 use vimputti::*;
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     println!("Starting udev monitor test...");
 
-    // Connect to udev socket
-    let udev_socket = "/tmp/vimputti-0";
-
-    println!("Connecting to udev socket: {}", udev_socket);
-    let stream = UnixStream::connect(&udev_socket).await?;
-    let reader = BufReader::new(stream);
-
-    // Spawn task to monitor udev events
-    tokio::spawn(async move {
-        monitor_udev(reader).await;
-    });
-
-    // Give monitor time to connect
-    sleep(Duration::from_millis(100)).await;
-
-    // Connect to manager and create a device
-    println!("\nConnecting to vimputti manager...");
+    // Connect to manager and its udev socket
     let client = VimputtiClient::connect_default().await?;
+    let mut monitor = UdevMonitorClient::for_client(&client).await?;
+
+    // Skip the add events of devices that already exist
+    while let Some(event) = monitor
+        .next_event_timeout(Duration::from_millis(200))
+        .await?
+    {
+        println!("Existing device: {}", event.device_info.devpath);
+    }
 
     println!("Creating virtual controller...");
     let config = DeviceConfig {
@@ -36,44 +27,39 @@ async fn main() -> anyhow::Result<()> {
         bustype: BusType::Usb,
         buttons: vec![Button::A, Button::B],
         axes: vec![AxisConfig::new(Axis::LeftStickX, -32768, 32767)],
+        ..Default::default()
     };
 
     let device = client.create_device(config).await?;
-    println!("Device created: {}", device.event_node());
+    let devname = format!("/dev/input/{}", device.event_node());
+    println!("Device created: {}", devname);
 
-    // Wait a bit to see the add event
-    sleep(Duration::from_secs(2)).await;
+    let event = monitor.next_event().await?;
+    println!("{:#?}", event);
+    assert_eq!(event.action, UdevAction::Add);
+    assert_eq!(event.device_info.subsystem, "input");
+    assert_eq!(event.device_info.devname, devname);
+    let property = |key: &str| {
+        event
+            .device_info
+            .properties
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    };
+    assert_eq!(property("ID_INPUT_JOYSTICK"), Some("1"));
+    assert_eq!(property("ID_VENDOR_ID"), Some("045e"));
+    assert_eq!(property("ID_MODEL_ID"), Some("028e"));
 
     println!("\nDestroying device...");
     drop(device);
 
-    // Wait to see the remove event
-    sleep(Duration::from_secs(2)).await;
+    let event = monitor.next_event().await?;
+    println!("{:#?}", event);
+    assert_eq!(event.action, UdevAction::Remove);
+    assert_eq!(event.device_info.devname, devname);
 
     println!("\nTest complete!");
 
     Ok(())
 }
-
-async fn monitor_udev(mut reader: BufReader<UnixStream>) {
-    println!("Udev monitor started, waiting for events...\n");
-
-    let mut line = String::new();
-    loop {
-        line.clear();
-        match reader.read_line(&mut line).await {
-            Ok(0) => break,
-            Ok(_) => {
-                if line.trim().is_empty() {
-                    println!("--- End of udev event ---\n");
-                } else {
-                    print!("{}", line);
-                }
-            }
-            Err(e) => {
-                eprintln!("Error reading udev event: {}", e);
-                break;
-            }
-        }
-    }
-}
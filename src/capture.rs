@@ -0,0 +1,313 @@
+//! Capturing the event stream of real input devices and replaying it
+//!
+//! A capture holds the config of a real device (see `probe`) and the raw `input_event` frames
+//! it produced, with their timing. Replayed into a virtual device created from that config,
+//! the frames reach readers verbatim, so a bug report can come with a capture of the real
+//! hardware that reproduces the issue. Captures are saved as JSON.
+//!
+//! Events carry their offset from the start of the capture, taken from a monotonic clock and
+//! used for replaying, and their wall-clock time for lining them up with other logs.
+
+use crate::client::VirtualController;
+use crate::probe::{RawDevice, probe_device};
+use crate::protocol::*;
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Largest number of frames read from the device at once
+const READ_FRAMES: usize = 64;
+
+/// Format version of captures, bumped on incompatible changes
+///
+/// 2 added the wall-clock time of events.
+pub const CAPTURE_FORMAT_VERSION: u32 = 2;
+
+fn default_format_version() -> u32 {
+    // Captures saved before the field existed are in the first format
+    1
+}
+
+/// Current time of `clock` in microseconds
+fn clock_us(clock: libc::clockid_t) -> i64 {
+    let mut now = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    unsafe { libc::clock_gettime(clock, &mut now) };
+    Duration::new(now.tv_sec as u64, now.tv_nsec as u32).as_micros() as i64
+}
+
+/// Layout of `struct input_event` in a raw event dump
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameLayout {
+    /// 64-bit `timeval`, 24 bytes per event
+    Time64,
+    /// 32-bit `timeval` of 32-bit processes, 16 bytes per event
+    Time32,
+}
+impl FrameLayout {
+    /// Layout the kernel uses for readers of this process
+    pub const fn native() -> Self {
+        if size_of::<libc::time_t>() == 8 {
+            FrameLayout::Time64
+        } else {
+            FrameLayout::Time32
+        }
+    }
+
+    /// Size of one event
+    pub const fn size(self) -> usize {
+        match self {
+            FrameLayout::Time64 => 24,
+            FrameLayout::Time32 => 16,
+        }
+    }
+
+    /// Decode one event, None if `bytes` is too short
+    ///
+    /// Returns the event with its timestamp in microseconds.
+    pub fn decode(self, bytes: &[u8]) -> Option<(u64, LinuxInputEvent)> {
+        let bytes = bytes.get(..self.size())?;
+        let (tv_sec, tv_usec, rest) = match self {
+            FrameLayout::Time64 => (
+                i64::from_ne_bytes(bytes[0..8].try_into().ok()?),
+                i64::from_ne_bytes(bytes[8..16].try_into().ok()?),
+                &bytes[16..],
+            ),
+            FrameLayout::Time32 => (
+                i32::from_ne_bytes(bytes[0..4].try_into().ok()?) as i64,
+                i32::from_ne_bytes(bytes[4..8].try_into().ok()?) as i64,
+                &bytes[8..],
+            ),
+        };
+
+        let event = LinuxInputEvent {
+            time: TimeVal { tv_sec, tv_usec },
+            event_type: u16::from_ne_bytes(rest[0..2].try_into().ok()?),
+            code: u16::from_ne_bytes(rest[2..4].try_into().ok()?),
+            value: i32::from_ne_bytes(rest[4..8].try_into().ok()?),
+        };
+        let time_us = (tv_sec.max(0) as u64) * 1_000_000 + tv_usec.max(0) as u64;
+        Some((time_us, event))
+    }
+}
+
+/// Event of a capture
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CapturedEvent {
+    /// Microseconds since the first event of the capture
+    pub time_us: u64,
+    /// Wall-clock time in microseconds since the Unix epoch, None in first format captures
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub wall_time_us: Option<u64>,
+    pub event_type: u16,
+    pub code: u16,
+    pub value: i32,
+}
+impl CapturedEvent {
+    /// Wall-clock time of the event, for lining it up with other timestamped logs
+    pub fn wall_time(&self) -> Option<SystemTime> {
+        self.wall_time_us
+            .map(|us| UNIX_EPOCH + Duration::from_micros(us))
+    }
+
+    /// The event as sent to a virtual device, `SYN_REPORT` ending its frame
+    pub fn to_input_event(&self) -> InputEvent {
+        if self.event_type == EV_SYN && self.code == SYN_REPORT {
+            InputEvent::Sync
+        } else {
+            InputEvent::Raw {
+                event_type: self.event_type,
+                code: self.code,
+                value: self.value,
+            }
+        }
+    }
+}
+
+/// Config and event stream of a real device
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Capture {
+    /// Format version, see `CAPTURE_FORMAT_VERSION`
+    #[serde(default = "default_format_version")]
+    pub format_version: u32,
+    /// Config of the captured device, for creating the virtual device to replay into
+    pub config: DeviceConfig,
+    pub events: Vec<CapturedEvent>,
+}
+impl Capture {
+    /// Capture the events of a real evdev device, e.g. `/dev/input/event3`, for `duration`
+    ///
+    /// Reads the device through raw syscalls, bypassing the shim, and needs the same access
+    /// as `probe_device`. Events are read without grabbing the device, so it keeps working.
+    pub fn record(path: &Path, duration: Duration) -> Result<Self> {
+        let config = probe_device(path)?;
+        let device = RawDevice::open(path)?;
+        let layout = FrameLayout::native();
+
+        // Monotonic timestamps keep the offsets right if the wall clock is changed meanwhile,
+        // wall-clock times are then derived from the difference between the clocks
+        let wall_offset_us = match device.set_clock(libc::CLOCK_MONOTONIC) {
+            Ok(()) => clock_us(libc::CLOCK_REALTIME) - clock_us(libc::CLOCK_MONOTONIC),
+            // Timestamps stay on the wall clock
+            Err(_) => 0,
+        };
+
+        let deadline = Instant::now() + duration;
+        let mut buf = vec![0u8; READ_FRAMES * layout.size()];
+        let mut pending = Vec::new();
+        let mut capture = Self {
+            format_version: CAPTURE_FORMAT_VERSION,
+            config,
+            events: Vec::new(),
+        };
+        let mut first_us = None;
+
+        loop {
+            let now = Instant::now();
+            if now >= deadline {
+                break;
+            }
+            if !device
+                .wait_readable(deadline - now)
+                .with_context(|| format!("Failed to poll {}", path.display()))?
+            {
+                continue;
+            }
+
+            let n = device
+                .read(&mut buf)
+                .with_context(|| format!("Failed to read {}", path.display()))?;
+            pending.extend_from_slice(&buf[..n]);
+            capture.take_frames(&mut pending, layout, &mut first_us, wall_offset_us);
+        }
+
+        Ok(capture)
+    }
+
+    /// Capture from a raw event dump, e.g. `cat /dev/input/event3 > dump`
+    ///
+    /// The layout depends on the process that read the dump, `FrameLayout::Time32` for
+    /// 32-bit ones. Trailing bytes of an incomplete event are an error. Timestamps are taken
+    /// as wall-clock times, which readers get unless they changed the clock of their fd.
+    pub fn from_raw(config: DeviceConfig, bytes: &[u8], layout: FrameLayout) -> Result<Self> {
+        if !bytes.len().is_multiple_of(layout.size()) {
+            bail!(
+                "Raw dump of {} bytes isn't made of {} byte events",
+                bytes.len(),
+                layout.size()
+            );
+        }
+
+        let mut capture = Self {
+            format_version: CAPTURE_FORMAT_VERSION,
+            config,
+            events: Vec::new(),
+        };
+        let mut pending = bytes.to_vec();
+        capture.take_frames(&mut pending, layout, &mut None, 0);
+        Ok(capture)
+    }
+
+    /// Move the complete events in `pending` to the capture
+    ///
+    /// `wall_offset_us` turns the event timestamps into wall-clock times.
+    fn take_frames(
+        &mut self,
+        pending: &mut Vec<u8>,
+        layout: FrameLayout,
+        first_us: &mut Option<u64>,
+        wall_offset_us: i64,
+    ) {
+        let complete = pending.len() - pending.len() % layout.size();
+        for chunk in pending[..complete].chunks_exact(layout.size()) {
+            let Some((time_us, event)) = layout.decode(chunk) else {
+                continue;
+            };
+            let first_us = *first_us.get_or_insert(time_us);
+            self.events.push(CapturedEvent {
+                time_us: time_us.saturating_sub(first_us),
+                wall_time_us: Some((time_us as i64 + wall_offset_us).max(0) as u64),
+                event_type: event.event_type,
+                code: event.code,
+                value: event.value,
+            });
+        }
+        pending.drain(..complete);
+    }
+
+    /// Load a capture saved with `save`
+    ///
+    /// Captures of older formats load too, without what was added since.
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read capture {}", path.display()))?;
+        let capture: Self = serde_json::from_str(&content)
+            .with_context(|| format!("Failed to parse capture {}", path.display()))?;
+
+        if capture.format_version > CAPTURE_FORMAT_VERSION {
+            bail!(
+                "Capture {} has format version {}, newer than the supported version {}",
+                path.display(),
+                capture.format_version,
+                CAPTURE_FORMAT_VERSION
+            );
+        }
+        Ok(capture)
+    }
+
+    /// Save the capture as JSON
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let content = serde_json::to_string_pretty(self)?;
+        std::fs::write(path, content)
+            .with_context(|| format!("Failed to write capture {}", path.display()))
+    }
+
+    /// Total length of the capture
+    pub fn duration(&self) -> Duration {
+        Duration::from_micros(self.events.last().map_or(0, |e| e.time_us))
+    }
+
+    /// Wall-clock time of the first event, None for empty and first format captures
+    pub fn started_at(&self) -> Option<SystemTime> {
+        self.events.first().and_then(CapturedEvent::wall_time)
+    }
+
+    /// Feed the captured events into `device`, with their original timing
+    ///
+    /// Events are sent a frame at a time, each at its captured offset from the start of the
+    /// replay, whatever the wall clock did while capturing. `device` should be created from
+    /// the capture's `config`, so the codes it reports are advertised.
+    pub async fn replay(&self, device: &VirtualController) -> Result<()> {
+        let start = tokio::time::Instant::now();
+        let mut frame = Vec::new();
+        let mut frame_time_us = 0;
+
+        for event in &self.events {
+            // Events of a frame share its timestamp, also in dumps missing the SYN_REPORTs
+            if !frame.is_empty() && event.time_us != frame_time_us {
+                device.send_events(frame.drain(..)).await?;
+            }
+            if frame.is_empty() {
+                frame_time_us = event.time_us;
+                tokio::time::sleep_until(start + Duration::from_micros(event.time_us)).await;
+            }
+
+            let input = event.to_input_event();
+            let end_of_frame = matches!(input, InputEvent::Sync);
+            frame.push(input);
+            if end_of_frame {
+                device.send_events(frame.drain(..)).await?;
+            }
+        }
+
+        // A capture cut off mid-frame still delivers its last events
+        if !frame.is_empty() {
+            device.send_events(frame).await?;
+        }
+
+        Ok(())
+    }
+}
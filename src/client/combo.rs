@@ -0,0 +1,264 @@
+use crate::protocol::*;
+use std::time::Duration;
+
+/// A single frame of a combo
+///
+/// A frame describes the complete controller state while it is held:
+/// buttons not listed are released and axes not listed return to rest, see `AxisConfig::rest`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ComboFrame {
+    pub buttons: Vec<Button>,
+    pub axes: Vec<(Axis, i32)>,
+    /// How many frames (at the combo's FPS) this state is held for
+    pub frames: u32,
+}
+
+/// Frame-timed input sequence, e.g. "quarter-circle forward + punch"
+///
+/// Played back with `VirtualController::execute_combo`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Combo {
+    fps: u32,
+    frames: Vec<ComboFrame>,
+}
+impl Combo {
+    /// Create an empty combo running at the given frame rate
+    pub fn new(fps: u32) -> Self {
+        Self {
+            fps: fps.max(1),
+            frames: Vec::new(),
+        }
+    }
+
+    /// Add a frame holding the given buttons for `frames` frames
+    pub fn buttons(mut self, buttons: &[Button], frames: u32) -> Self {
+        self.frames.push(ComboFrame {
+            buttons: buttons.to_vec(),
+            axes: Vec::new(),
+            frames,
+        });
+        self
+    }
+
+    /// Add a frame holding the given axis positions for `frames` frames
+    pub fn axes(mut self, axes: &[(Axis, i32)], frames: u32) -> Self {
+        self.frames.push(ComboFrame {
+            buttons: Vec::new(),
+            axes: axes.to_vec(),
+            frames,
+        });
+        self
+    }
+
+    /// Add a frame with both buttons and axis positions
+    pub fn frame(mut self, buttons: &[Button], axes: &[(Axis, i32)], frames: u32) -> Self {
+        self.frames.push(ComboFrame {
+            buttons: buttons.to_vec(),
+            axes: axes.to_vec(),
+            frames,
+        });
+        self
+    }
+
+    /// Add a neutral frame (nothing pressed, all axes at rest)
+    pub fn wait(self, frames: u32) -> Self {
+        self.frame(&[], &[], frames)
+    }
+
+    /// Frame rate used for scheduling
+    pub fn fps(&self) -> u32 {
+        self.fps
+    }
+
+    /// Frames in this combo
+    pub fn frames(&self) -> &[ComboFrame] {
+        &self.frames
+    }
+
+    /// Duration of a single frame
+    pub fn frame_duration(&self) -> Duration {
+        Duration::from_secs(1) / self.fps
+    }
+
+    /// Offset from the start of playback at which frame `index` begins
+    pub fn frame_offset(&self, index: usize) -> Duration {
+        let elapsed: u64 = self.frames[..index.min(self.frames.len())]
+            .iter()
+            .map(|f| f.frames as u64)
+            .sum();
+        // Computed from the frame count rather than accumulated, so rounding doesn't drift
+        Duration::from_nanos(elapsed * 1_000_000_000 / self.fps as u64)
+    }
+
+    /// Total playback duration
+    pub fn total_duration(&self) -> Duration {
+        self.frame_offset(self.frames.len())
+    }
+
+    /// Build the events for each frame on a device with `config`, including a final release
+    /// of everything still held
+    ///
+    /// Only state changes relative to the previous frame are emitted, each batch ends with a
+    /// sync. Axes return to their rest value, 0 for axes the device doesn't have.
+    pub fn to_event_frames(&self, config: &DeviceConfig) -> Vec<Vec<InputEvent>> {
+        let rest = |axis: Axis| config.axis(axis).map_or(0, AxisConfig::rest);
+        let mut held_buttons: Vec<Button> = Vec::new();
+        let mut held_axes: Vec<(Axis, i32)> = Vec::new();
+        let mut result = Vec::with_capacity(self.frames.len() + 1);
+
+        let neutral = ComboFrame::default();
+        for frame in self.frames.iter().chain(std::iter::once(&neutral)) {
+            let mut events = Vec::new();
+
+            for button in held_buttons.iter().filter(|b| !frame.buttons.contains(b)) {
+                events.push(InputEvent::Button {
+                    button: *button,
+                    pressed: false,
+                });
+            }
+            for button in frame.buttons.iter().filter(|b| !held_buttons.contains(b)) {
+                events.push(InputEvent::Button {
+                    button: *button,
+                    pressed: true,
+                });
+            }
+
+            for (axis, _) in held_axes
+                .iter()
+                .filter(|(a, _)| !frame.axes.iter().any(|(fa, _)| fa == a))
+            {
+                events.push(InputEvent::Axis {
+                    axis: *axis,
+                    value: rest(*axis),
+                });
+            }
+            for (axis, value) in &frame.axes {
+                if !held_axes.contains(&(*axis, *value)) {
+                    events.push(InputEvent::Axis {
+                        axis: *axis,
+                        value: *value,
+                    });
+                }
+            }
+
+            if !events.is_empty() {
+                events.push(InputEvent::Sync);
+            }
+
+            held_buttons = frame.buttons.clone();
+            held_axes = frame
+                .axes
+                .iter()
+                .filter(|&&(axis, value)| value != rest(axis))
+                .copied()
+                .collect();
+            result.push(events);
+        }
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ControllerBuilder, ControllerTemplates};
+
+    #[test]
+    fn three_frame_combo_at_60_fps() {
+        let combo = Combo::new(60)
+            .axes(&[(Axis::LeftStickY, 32767)], 1)
+            .axes(&[(Axis::LeftStickX, 32767), (Axis::LeftStickY, 32767)], 1)
+            .frame(&[Button::X], &[(Axis::LeftStickX, 32767)], 1);
+
+        assert_eq!(combo.frame_duration(), Duration::from_nanos(16_666_666));
+        assert_eq!(combo.frame_offset(1), Duration::from_nanos(16_666_666));
+        assert_eq!(combo.frame_offset(2), Duration::from_nanos(33_333_333));
+        assert_eq!(combo.total_duration(), Duration::from_millis(50));
+
+        let frames = combo.to_event_frames(&ControllerTemplates::xbox360());
+        assert_eq!(frames.len(), 4);
+        assert_eq!(
+            frames[0],
+            vec![
+                InputEvent::Axis {
+                    axis: Axis::LeftStickY,
+                    value: 32767
+                },
+                InputEvent::Sync,
+            ]
+        );
+        // Only the newly moved axis is sent, Y is still held
+        assert_eq!(
+            frames[1],
+            vec![
+                InputEvent::Axis {
+                    axis: Axis::LeftStickX,
+                    value: 32767
+                },
+                InputEvent::Sync,
+            ]
+        );
+        assert_eq!(
+            frames[2],
+            vec![
+                InputEvent::Button {
+                    button: Button::X,
+                    pressed: true
+                },
+                InputEvent::Axis {
+                    axis: Axis::LeftStickY,
+                    value: 0
+                },
+                InputEvent::Sync,
+            ]
+        );
+        // Everything still held is released at the end
+        assert_eq!(
+            frames[3],
+            vec![
+                InputEvent::Button {
+                    button: Button::X,
+                    pressed: false
+                },
+                InputEvent::Axis {
+                    axis: Axis::LeftStickX,
+                    value: 0
+                },
+                InputEvent::Sync,
+            ]
+        );
+    }
+
+    #[test]
+    fn axes_return_to_their_rest_value() {
+        let config = ControllerBuilder::new("Pedals")
+            .axis(Axis::LowerRightTrigger, 100, 1000)
+            .axis_config(AxisConfig::new(Axis::LeftStickX, 0, 4095).with_center(2048))
+            .build();
+        let combo =
+            Combo::new(60).axes(&[(Axis::LowerRightTrigger, 800), (Axis::LeftStickX, 0)], 1);
+
+        let frames = combo.to_event_frames(&config);
+        assert_eq!(
+            frames[1],
+            vec![
+                InputEvent::Axis {
+                    axis: Axis::LowerRightTrigger,
+                    value: 100
+                },
+                InputEvent::Axis {
+                    axis: Axis::LeftStickX,
+                    value: 2048
+                },
+                InputEvent::Sync,
+            ]
+        );
+    }
+
+    #[test]
+    fn frame_offsets_do_not_drift() {
+        let combo = (0..60).fold(Combo::new(60), |combo, _| combo.wait(1));
+        assert_eq!(combo.total_duration(), Duration::from_secs(1));
+    }
+}
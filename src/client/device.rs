@@ -1,4 +1,4 @@
-use crate::client::ClientInner;
+use crate::client::{ClientInner, Combo};
 use crate::protocol::*;
 use anyhow::Result;
 use std::sync::Arc;
@@ -9,29 +9,69 @@ use tokio::net::UnixStream;
 use tokio::sync::broadcast;
 use tracing::debug;
 
+/// How long `VirtualController::is_connected` waits for the manager to answer
+const CONNECTED_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(1);
+
+/// Serializes like `ControlCommand::SendInput`, taking the events from an iterator
+#[derive(serde::Serialize)]
+#[serde(bound = "I: Iterator<Item = InputEvent>")]
+enum SendInputRef<I> {
+    SendInput {
+        device_id: DeviceId,
+        events: EventSeq<I>,
+    },
+}
+
+/// Events serialized as a sequence straight from an iterator, which is used up by it
+struct EventSeq<I>(std::cell::Cell<Option<I>>);
+impl<I: Iterator<Item = InputEvent>> serde::Serialize for EventSeq<I> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.0.take().into_iter().flatten())
+    }
+}
+
 /// Handle to a virtual input device
 ///
 /// This struct provides a high-level API for sending input events to a virtual device.
 /// Events are automatically batched and flushed after a configurable timeout
 /// or when explicitly flushed.
 ///
-/// The device is automatically destroyed when this handle is dropped.
+/// The device is automatically destroyed when this handle is dropped, unless it was
+/// obtained from `VimputtiClient::ensure_device`.
 pub struct VirtualController {
     client: Arc<ClientInner>,
     device_id: DeviceId,
     event_node: String,
+    /// Config the device was created or updated with, for clamping and normalizing axes and
+    /// `set_dpad`
+    config: DeviceConfig,
     feedback_rx: Option<broadcast::Receiver<FeedbackEvent>>,
+    /// Destroy the device when the handle is dropped
+    destroy_on_drop: bool,
 }
 impl VirtualController {
-    pub(crate) fn new(client: Arc<ClientInner>, device_id: DeviceId, event_node: String) -> Self {
+    pub(crate) fn new(
+        client: Arc<ClientInner>,
+        device_id: DeviceId,
+        event_node: String,
+        config: DeviceConfig,
+    ) -> Self {
         Self {
             client,
             device_id,
             event_node,
+            config,
             feedback_rx: None,
+            destroy_on_drop: true,
         }
     }
 
+    /// Leave the device in place when the handle is dropped
+    pub(crate) fn keep_on_drop(mut self) -> Self {
+        self.destroy_on_drop = false;
+        self
+    }
+
     /// Get the device ID
     pub fn device_id(&self) -> DeviceId {
         self.device_id
@@ -42,10 +82,76 @@ impl VirtualController {
         &self.event_node
     }
 
+    /// Config the device was created with, or last given to `update_config`, known without
+    /// asking the manager
+    ///
+    /// Doesn't reflect other changes made since, such as `set_device_ids`, see
+    /// `VimputtiClient::get_device_config` for the config the device presents now.
+    pub fn config(&self) -> &DeviceConfig {
+        &self.config
+    }
+
+    /// Check if the manager connection is still alive
+    ///
+    /// Pings the manager, counting no answer within a second as disconnected.
+    /// A connection that timed out shouldn't be used further.
+    pub async fn is_connected(&self) -> bool {
+        let ping = self.client.send_command(ControlCommand::Ping);
+        matches!(
+            tokio::time::timeout(CONNECTED_TIMEOUT, ping).await,
+            Ok(Ok(ControlResult::Pong))
+        )
+    }
+
+    /// Check if the device still exists in the manager
+    ///
+    /// False as well if the manager can't be reached.
+    pub async fn exists(&self) -> bool {
+        let query = ControlCommand::GetDeviceConfig {
+            device_id: self.device_id,
+        };
+        matches!(
+            self.client.send_command(query).await,
+            Ok(ControlResult::DeviceConfig(_))
+        )
+    }
+
+    /// Let go of the handle without destroying the device
+    ///
+    /// The device stays until destroyed through its ID or the manager stops. Returns its ID.
+    pub fn detach(mut self) -> DeviceId {
+        self.destroy_on_drop = false;
+        self.device_id
+    }
+
+    /// Destroy the device and wait until the manager has removed it
+    ///
+    /// Unlike dropping the handle, which destroys the device in the background, the device is
+    /// gone from `list_devices` and its udev remove event sent once this returns. If closing
+    /// fails or is cancelled, the handle still destroys the device when dropped.
+    pub async fn close(mut self) -> Result<()> {
+        let response = self
+            .client
+            .send_command(ControlCommand::DestroyDevice {
+                device_id: self.device_id,
+            })
+            .await?;
+
+        match response {
+            ControlResult::DeviceDestroyed => {
+                self.destroy_on_drop = false;
+                Ok(())
+            }
+            ControlResult::Error { message, .. } => {
+                anyhow::bail!("Failed to destroy device: {}", message)
+            }
+            _ => anyhow::bail!("Unexpected response to DestroyDevice"),
+        }
+    }
+
     /// Press or release a button
     pub async fn button(&self, button: Button, pressed: bool) -> Result<()> {
-        self.send_events(vec![InputEvent::Button { button, pressed }])
-            .await
+        self.send_one(InputEvent::Button { button, pressed }).await
     }
 
     /// Convenience method to press a button
@@ -58,69 +164,443 @@ impl VirtualController {
         self.button(button, false).await
     }
 
+    /// Press a button and sync, where `button_press` leaves the sync to the caller
+    pub async fn press_button(&self, button: Button) -> Result<()> {
+        self.send_events([
+            InputEvent::Button {
+                button,
+                pressed: true,
+            },
+            InputEvent::Sync,
+        ])
+        .await
+    }
+
+    /// Release a button and sync, where `button_release` leaves the sync to the caller
+    pub async fn release_button(&self, button: Button) -> Result<()> {
+        self.send_events([
+            InputEvent::Button {
+                button,
+                pressed: false,
+            },
+            InputEvent::Sync,
+        ])
+        .await
+    }
+
+    /// Press a button, hold it for `hold` and release it, each in a frame of its own
+    pub async fn tap_button(&self, button: Button, hold: std::time::Duration) -> Result<()> {
+        self.press_button(button).await?;
+        tokio::time::sleep(hold).await;
+        self.release_button(button).await
+    }
+
     /// Move an axis to a specific value
     pub async fn axis(&self, axis: Axis, value: i32) -> Result<()> {
-        self.send_events(vec![InputEvent::Axis { axis, value }])
-            .await
+        self.send_one(InputEvent::Axis { axis, value }).await
+    }
+
+    /// Move along a relative axis by `value`, e.g. a mouse by a number of counts
+    pub async fn relative(&self, axis: RelAxis, value: i32) -> Result<()> {
+        self.send_one(InputEvent::Relative { axis, value }).await
+    }
+
+    /// Move the pointer by `dx` and `dy` in a single frame, e.g. of a mouse
+    pub async fn move_pointer(&self, dx: i32, dy: i32) -> Result<()> {
+        self.send_events([
+            InputEvent::Relative {
+                axis: RelAxis::X,
+                value: dx,
+            },
+            InputEvent::Relative {
+                axis: RelAxis::Y,
+                value: dy,
+            },
+            InputEvent::Sync,
+        ])
+        .await
+    }
+
+    /// Move an axis in a frame of its own, clamping the value to the axis' configured range
+    pub async fn set_axis(&self, axis: Axis, value: i32) -> Result<()> {
+        self.set_axes(&[(axis, value)]).await
+    }
+
+    /// Move several axes at once, in a single frame
+    ///
+    /// Values are clamped to each axis' configured range, e.g. for feeding a full
+    /// gamepad snapshot every frame.
+    pub async fn set_axes(&self, values: &[(Axis, i32)]) -> Result<()> {
+        let mut events: Vec<InputEvent> = values
+            .iter()
+            .map(|&(axis, value)| InputEvent::Axis {
+                axis,
+                value: self
+                    .axis_config(axis)
+                    .map_or(value, |config| config.clamp(value)),
+            })
+            .collect();
+        events.push(InputEvent::Sync);
+        self.send_events(events).await
+    }
+
+    /// Move several axes at once to normalized positions, in a single frame
+    ///
+    /// See `AxisConfig::denormalize` for how values map to each axis' range.
+    pub async fn set_axes_normalized(&self, values: &[(Axis, f32)]) -> Result<()> {
+        let mut events = Vec::with_capacity(values.len() + 1);
+        for &(axis, value) in values {
+            let Some(config) = self.axis_config(axis) else {
+                anyhow::bail!("Device has no axis {:?} to normalize for", axis);
+            };
+            events.push(InputEvent::Axis {
+                axis,
+                value: config.denormalize(value),
+            });
+        }
+        events.push(InputEvent::Sync);
+        self.send_events(events).await
+    }
+
+    /// Set the D-pad direction in a single frame, as the device reports it
+    ///
+    /// `x` is -1 for left and 1 for right, `y` -1 for up and 1 for down, 0 centers.
+    /// Sent as hat axes or as button presses and releases depending on the device's
+    /// `DPadStyle`.
+    pub async fn set_dpad(&self, x: i32, y: i32) -> Result<()> {
+        let (x, y) = (x.signum(), y.signum());
+        let mut events = match self.config.dpad_style() {
+            DPadStyle::Hat => vec![
+                InputEvent::Axis {
+                    axis: Axis::DPadX,
+                    value: x,
+                },
+                InputEvent::Axis {
+                    axis: Axis::DPadY,
+                    value: y,
+                },
+            ],
+            DPadStyle::Buttons => [
+                (Button::DPadLeft, x < 0),
+                (Button::DPadRight, x > 0),
+                (Button::DPadUp, y < 0),
+                (Button::DPadDown, y > 0),
+            ]
+            .into_iter()
+            .map(|(button, pressed)| InputEvent::Button { button, pressed })
+            .collect(),
+        };
+        events.push(InputEvent::Sync);
+        self.send_events(events).await
+    }
+
+    fn axis_config(&self, axis: Axis) -> Option<&AxisConfig> {
+        self.config.axis(axis)
     }
 
     /// Send a raw Linux input event
     pub async fn raw_event(&self, event_type: u16, code: u16, value: i32) -> Result<()> {
-        self.send_events(vec![InputEvent::Raw {
+        self.send_one(InputEvent::Raw {
             event_type,
             code,
             value,
-        }])
+        })
         .await
     }
 
     /// Sends a sync (SYN_REPORT) event
     pub async fn sync(&self) -> Result<()> {
-        self.send_events(vec![InputEvent::Sync]).await
+        self.send_one(InputEvent::Sync).await
+    }
+
+    /// Play back a frame-timed combo
+    ///
+    /// Frame deadlines are derived from a single monotonic start instant,
+    /// so send latency doesn't accumulate into drift across frames.
+    pub async fn execute_combo(&self, combo: &Combo) -> Result<()> {
+        let start = tokio::time::Instant::now();
+
+        for (idx, events) in combo.to_event_frames(&self.config).into_iter().enumerate() {
+            tokio::time::sleep_until(start + combo.frame_offset(idx)).await;
+            if !events.is_empty() {
+                self.send_events(events).await?;
+            }
+        }
+
+        Ok(())
     }
 
     /// Send events and wait for them to be delivered
     ///
     /// This is useful when you want to ensure events are sent immediately
-    /// without relying on auto-batching.
-    pub async fn send_events(&self, events: Vec<InputEvent>) -> Result<()> {
-        use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
-
-        let id = ulid::Ulid::new().to_string();
-        let command = ControlCommand::SendInput {
+    /// without relying on auto-batching. Takes a `Vec`, an array or any other iterator of
+    /// events, which are serialized straight from it without being collected first.
+    pub async fn send_events(&self, events: impl IntoIterator<Item = InputEvent>) -> Result<()> {
+        let (id, message_json) = ClientInner::encode(&SendInputRef::SendInput {
             device_id: self.device_id,
-            events,
-        };
-        let message = ControlMessage {
-            id: id.clone(),
-            command,
-        };
+            events: EventSeq(std::cell::Cell::new(Some(events.into_iter()))),
+        })?;
+        let response = self.client.send_encoded(&id, &message_json).await?;
 
-        let message_json = serde_json::to_string(&message)?;
+        match response {
+            ControlResult::InputSent => Ok(()),
+            ControlResult::Error { message, .. } => {
+                anyhow::bail!("Failed to send input: {}", message)
+            }
+            _ => anyhow::bail!("Unexpected response to SendInput"),
+        }
+    }
 
-        let mut stream = self.client.stream.lock().await;
+    /// Send a single event, without allocating for it
+    pub async fn send_one(&self, event: InputEvent) -> Result<()> {
+        self.send_events([event]).await
+    }
+
+    /// Wait until all input sent so far has been written to the device's readers
+    ///
+    /// This is a synchronization point for tests: once it returns, the events are
+    /// in the readers' sockets. It doesn't wait for the readers to process them.
+    pub async fn flush(&self) -> Result<()> {
+        let response = self
+            .client
+            .send_command(ControlCommand::Flush {
+                device_id: self.device_id,
+            })
+            .await?;
+
+        match response {
+            ControlResult::Flushed => Ok(()),
+            ControlResult::Error { message, .. } => {
+                anyhow::bail!("Failed to flush: {}", message)
+            }
+            _ => anyhow::bail!("Unexpected response to Flush"),
+        }
+    }
+
+    /// Number of readers with the device open, over its evdev and joystick nodes
+    ///
+    /// Tests can wait for this to become non-zero before sending input, so it isn't sent
+    /// before the application under test opened the device.
+    pub async fn client_count(&self) -> Result<usize> {
+        let counts = self.client_counts().await?;
+        Ok(counts.evdev + counts.joystick)
+    }
+
+    /// Number of clients connected to the evdev, joystick and feedback sockets of the device
+    pub async fn client_counts(&self) -> Result<ClientCounts> {
+        let response = self
+            .client
+            .send_command(ControlCommand::GetDeviceClients {
+                device_id: self.device_id,
+            })
+            .await?;
+
+        match response {
+            ControlResult::DeviceClients(counts) => Ok(counts),
+            ControlResult::Error { message, .. } => {
+                anyhow::bail!("Failed to get device clients: {}", message)
+            }
+            _ => anyhow::bail!("Unexpected response to GetDeviceClients"),
+        }
+    }
+
+    /// Stop forwarding input to readers until `resume` is called
+    ///
+    /// Input sent while paused is discarded.
+    pub async fn pause(&self) -> Result<()> {
+        self.pause_with(PauseMode::Discard).await
+    }
+
+    /// Stop forwarding input to readers, choosing what happens to input sent meanwhile
+    pub async fn pause_with(&self, mode: PauseMode) -> Result<()> {
+        self.set_paused(true, mode).await
+    }
 
-        // Send command
-        stream.write_all(message_json.as_bytes()).await?;
-        stream.write_all(b"\n").await?;
+    /// Resume forwarding input, bringing readers up to date with the current state
+    pub async fn resume(&self) -> Result<()> {
+        self.set_paused(false, PauseMode::default()).await
+    }
 
-        // Read response
-        let mut reader = BufReader::new(&mut *stream);
-        let mut response_line = String::new();
-        reader.read_line(&mut response_line).await?;
+    async fn set_paused(&self, paused: bool, mode: PauseMode) -> Result<()> {
+        let response = self
+            .client
+            .send_command(ControlCommand::SetDevicePaused {
+                device_id: self.device_id,
+                paused,
+                mode,
+            })
+            .await?;
+
+        match response {
+            ControlResult::DevicePaused { .. } => Ok(()),
+            ControlResult::Error { message, .. } => {
+                anyhow::bail!("Failed to set paused state: {}", message)
+            }
+            _ => anyhow::bail!("Unexpected response to SetDevicePaused"),
+        }
+    }
 
-        let response: ControlResponse = serde_json::from_str(&response_line)?;
+    /// Delay this device's frames by `base_ms ± jitter_ms` before readers get them
+    ///
+    /// Useful for reproducing a laggy controller, set both to 0 to remove the delay.
+    pub async fn set_latency(&self, base_ms: u32, jitter_ms: u32) -> Result<()> {
+        self.send_latency(base_ms, jitter_ms, None).await
+    }
+
+    /// Like `set_latency`, but with jitter reproducible from `seed`
+    pub async fn set_latency_seeded(&self, base_ms: u32, jitter_ms: u32, seed: u64) -> Result<()> {
+        self.send_latency(base_ms, jitter_ms, Some(seed)).await
+    }
 
-        if response.id != id {
-            anyhow::bail!("Response ID mismatch");
+    async fn send_latency(&self, base_ms: u32, jitter_ms: u32, seed: Option<u64>) -> Result<()> {
+        let response = self
+            .client
+            .send_command(ControlCommand::SetLatency {
+                device_id: self.device_id,
+                base_ms,
+                jitter_ms,
+                seed,
+            })
+            .await?;
+
+        match response {
+            ControlResult::LatencySet => Ok(()),
+            ControlResult::Error { message, .. } => {
+                anyhow::bail!("Failed to set latency: {}", message)
+            }
+            _ => anyhow::bail!("Unexpected response to SetLatency"),
         }
+    }
 
-        match response.result {
-            ControlResult::InputSent => Ok(()),
-            ControlResult::Error { message } => {
-                anyhow::bail!("Failed to send input: {}", message)
+    /// Present the device with another vendor and product id, keeping its connections
+    ///
+    /// For checking if a game's detection depends on the ids. Readers opening the device from
+    /// now on see the new ids, already open fds keep the old ones, so some applications need
+    /// to reopen it. See `set_device_ids` to also change the version and bus.
+    pub async fn set_device_id(&self, vendor_id: u16, product_id: u16) -> Result<()> {
+        self.set_device_ids(vendor_id, product_id, None, None).await
+    }
+
+    /// Like `set_device_id`, changing the version and bus type too when given
+    pub async fn set_device_ids(
+        &self,
+        vendor_id: u16,
+        product_id: u16,
+        version: Option<u16>,
+        bustype: Option<BusType>,
+    ) -> Result<()> {
+        let response = self
+            .client
+            .send_command(ControlCommand::SetDeviceId {
+                device_id: self.device_id,
+                vendor_id,
+                product_id,
+                version,
+                bustype,
+            })
+            .await?;
+
+        match response {
+            ControlResult::DeviceIdSet => Ok(()),
+            ControlResult::Error { message, .. } => {
+                anyhow::bail!("Failed to set device id: {}", message)
             }
-            _ => anyhow::bail!("Unexpected response to SendInput"),
+            _ => anyhow::bail!("Unexpected response to SetDeviceId"),
+        }
+    }
+
+    /// Remove or bring back this device's joystick interface (`jsN`), keeping its evdev node
+    ///
+    /// For forcing a game down its evdev path, or back, with one long-lived device. Readers of
+    /// the joystick node see their stream end when it's removed. Returns the joystick node
+    /// the device has afterwards.
+    pub async fn set_joystick_interface(&self, enabled: bool) -> Result<Option<String>> {
+        let response = self
+            .client
+            .send_command(ControlCommand::SetJoystickInterface {
+                device_id: self.device_id,
+                enabled,
+            })
+            .await?;
+
+        match response {
+            ControlResult::JoystickInterfaceSet { joystick_node } => Ok(joystick_node),
+            ControlResult::Error { message, .. } => {
+                anyhow::bail!("Failed to set joystick interface: {}", message)
+            }
+            _ => anyhow::bail!("Unexpected response to SetJoystickInterface"),
+        }
+    }
+
+    /// Update the battery of this device, which must have been created with one
+    ///
+    /// Its power supply in sysfs follows and a udev `change` event is sent, e.g. for testing
+    /// low battery warnings.
+    pub async fn set_battery(&self, capacity: u8, status: BatteryStatus) -> Result<()> {
+        let response = self
+            .client
+            .send_command(ControlCommand::SetBattery {
+                device_id: self.device_id,
+                capacity,
+                status,
+            })
+            .await?;
+
+        match response {
+            ControlResult::BatterySet => Ok(()),
+            ControlResult::Error { message, .. } => {
+                anyhow::bail!("Failed to set battery: {}", message)
+            }
+            _ => anyhow::bail!("Unexpected response to SetBattery"),
+        }
+    }
+
+    /// Replace the config of this device, e.g. to add buttons or axes, keeping its nodes
+    ///
+    /// Readers opening the device afterwards see the new layout and a udev `change` event is
+    /// sent, but readers that already have it open keep the old one until they open it again.
+    /// The ids and battery the device presents are kept, see `set_device_ids` and
+    /// `set_battery`.
+    pub async fn update_config(&mut self, config: DeviceConfig) -> Result<()> {
+        let response = self
+            .client
+            .send_command(ControlCommand::UpdateDevice {
+                device_id: self.device_id,
+                config: config.clone(),
+            })
+            .await?;
+
+        match response {
+            ControlResult::DeviceUpdated => {
+                self.config = config;
+                Ok(())
+            }
+            ControlResult::Error { message, .. } => {
+                anyhow::bail!("Failed to update device: {}", message)
+            }
+            _ => anyhow::bail!("Unexpected response to UpdateDevice"),
+        }
+    }
+
+    /// Have the manager deliver feedback to this device's feedback readers
+    ///
+    /// The feedback arrives exactly as if a game had played an effect,
+    /// which allows testing `on_rumble` handlers without a game.
+    pub async fn inject_feedback(&self, event: FeedbackEvent) -> Result<()> {
+        let response = self
+            .client
+            .send_command(ControlCommand::InjectFeedback {
+                device_id: self.device_id,
+                event,
+            })
+            .await?;
+
+        match response {
+            ControlResult::FeedbackInjected => Ok(()),
+            ControlResult::Error { message, .. } => {
+                anyhow::bail!("Failed to inject feedback: {}", message)
+            }
+            _ => anyhow::bail!("Unexpected response to InjectFeedback"),
         }
     }
 
@@ -183,6 +663,25 @@ impl VirtualController {
                                     pending_strong, pending_weak, pending_duration
                                 );
                                 let _ = tx.send(feedback);
+                            } else if event.code == FF_UPLOAD_MAGNITUDE {
+                                pending_strong = (event.value >> 16) as u16;
+                                pending_weak = (event.value & 0xFFFF) as u16;
+                            } else if event.code == FF_UPLOAD {
+                                // Completes the upload, the magnitudes came before
+                                let feedback = FeedbackEvent::RumbleUpload {
+                                    id: (event.value >> 16) as i16,
+                                    strong: pending_strong,
+                                    weak: pending_weak,
+                                    duration: (event.value & 0xFFFF) as u16,
+                                };
+                                debug!("Sending rumble upload: {:?}", feedback);
+                                let _ = tx.send(feedback);
+                            } else if event.code == FF_ERASE {
+                                let feedback = FeedbackEvent::RumbleErase {
+                                    id: event.value as i16,
+                                };
+                                debug!("Sending rumble erase: {:?}", feedback);
+                                let _ = tx.send(feedback);
                             }
                         }
                     }
@@ -198,6 +697,30 @@ impl VirtualController {
         Ok(())
     }
 
+    /// Wait for the next feedback of the device: rumble played or stopped, effects uploaded
+    /// or erased
+    ///
+    /// Connects to the feedback socket on first use, feedback from before isn't seen. If
+    /// feedback comes faster than it is polled, the oldest is dropped.
+    pub async fn poll_feedback(&mut self) -> Result<FeedbackEvent> {
+        if self.feedback_rx.is_none() {
+            self.enable_feedback().await?;
+        }
+
+        let rx = self.feedback_rx.as_mut().unwrap();
+        loop {
+            match rx.recv().await {
+                Ok(event) => return Ok(event),
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    debug!("Dropped {} feedback events", skipped);
+                }
+                Err(broadcast::error::RecvError::Closed) => {
+                    anyhow::bail!("Feedback socket of {} closed", self.event_node)
+                }
+            }
+        }
+    }
+
     /// Register a callback for rumble events
     pub async fn on_rumble<F>(&mut self, mut callback: F) -> Result<tokio::task::JoinHandle<()>>
     where
@@ -232,6 +755,10 @@ impl VirtualController {
 }
 impl Drop for VirtualController {
     fn drop(&mut self) {
+        if !self.destroy_on_drop {
+            return;
+        }
+
         let client = Arc::clone(&self.client);
         let device_id = self.device_id;
 
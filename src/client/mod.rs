@@ -1,15 +1,30 @@
 use crate::protocol::*;
 use anyhow::{Context, Result};
-use std::path::Path;
+use serde::Serialize;
+use std::os::unix::fs::FileTypeExt;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
 use tokio::net::UnixStream;
 use tokio::sync::Mutex;
 use tracing::debug;
 
+mod combo;
 mod device;
+#[cfg(feature = "udev-monitor")]
+mod udev_monitor;
 
+pub use combo::{Combo, ComboFrame};
 pub use device::VirtualController;
+#[cfg(feature = "udev-monitor")]
+pub use udev_monitor::UdevMonitorClient;
+
+/// How often `VimputtiClient::wait_for_device` checks the device list
+const WAIT_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// How long `VimputtiClient::discover` waits for each manager to answer
+const DISCOVER_TIMEOUT: Duration = Duration::from_millis(500);
 
 pub(crate) struct ClientInner {
     stream: Mutex<UnixStream>,
@@ -17,16 +32,54 @@ pub(crate) struct ClientInner {
 }
 impl ClientInner {
     pub(crate) fn get_base_path(&self) -> String {
-        // Manager creates base_path as socket_path.parent()/vimputti
-        // So for socket /tmp/vimputti-0, base is /tmp/vimputti
-        let socket_path = Path::new(&self.socket_path);
-        socket_path
-            .parent()
-            .unwrap_or_else(|| Path::new("/tmp"))
-            .join("vimputti")
+        // Same base path the manager derives from its socket path
+        base_path_for_socket(Path::new(&self.socket_path))
             .to_string_lossy()
             .to_string()
     }
+
+    /// Send a command to the manager and wait for response
+    pub(crate) async fn send_command(&self, command: ControlCommand) -> Result<ControlResult> {
+        let (id, message_json) = Self::encode(&command)?;
+        self.send_encoded(&id, &message_json).await
+    }
+
+    /// Encode a message with a new ID, from anything serializing like a `ControlCommand`
+    pub(crate) fn encode(command: &impl Serialize) -> Result<(String, String)> {
+        let id = ulid::Ulid::new().to_string();
+        let message_json = serde_json::to_string(&MessageRef { id: &id, command })?;
+        Ok((id, message_json))
+    }
+
+    /// Send a message from `encode` and wait for response
+    pub(crate) async fn send_encoded(&self, id: &str, message_json: &str) -> Result<ControlResult> {
+        let mut stream = self.stream.lock().await;
+
+        // Send command
+        stream.write_all(message_json.as_bytes()).await?;
+        stream.write_all(b"\n").await?;
+
+        // Read response
+        let mut reader = BufReader::new(&mut *stream);
+        let mut response_line = String::new();
+        reader.read_line(&mut response_line).await?;
+
+        let response: ControlResponse = serde_json::from_str(&response_line)
+            .with_context(|| format!("Failed to parse response: {}", response_line))?;
+
+        if response.id != id {
+            anyhow::bail!("Response ID mismatch: expected {}, got {}", id, response.id);
+        }
+
+        Ok(response.result)
+    }
+}
+
+/// Serializes like a `ControlMessage`, without owning its command
+#[derive(Serialize)]
+struct MessageRef<'a, C> {
+    id: &'a str,
+    command: &'a C,
 }
 
 /// Client for communicating with the vimputti manager
@@ -38,7 +91,7 @@ impl VimputtiClient {
     pub async fn connect(socket_path: impl AsRef<Path>) -> Result<Self> {
         let socket_path = socket_path.as_ref().to_string_lossy().to_string();
 
-        let stream = UnixStream::connect(&socket_path)
+        let stream = Self::connect_stream(Path::new(&socket_path))
             .await
             .with_context(|| format!("Failed to connect to manager at {}", socket_path))?;
 
@@ -52,9 +105,79 @@ impl VimputtiClient {
         })
     }
 
+    /// Connect to a file socket, or an abstract one for `@name` paths
+    async fn connect_stream(socket_path: &Path) -> std::io::Result<UnixStream> {
+        match abstract_socket_name(socket_path) {
+            Some(name) => {
+                use std::os::unix::ffi::OsStrExt;
+
+                // Tokio takes a leading NUL as the abstract namespace
+                let path = [b"\0", name].concat();
+                UnixStream::connect(std::ffi::OsStr::from_bytes(&path)).await
+            }
+            None => UnixStream::connect(socket_path).await,
+        }
+    }
+
     /// Connect to default vimputti manager (instance 0)
     pub async fn connect_default() -> Result<Self> {
-        Self::connect("/tmp/vimputti-0").await
+        Self::connect_instance(0).await
+    }
+
+    /// Connect to the manager started with `--instance <instance>`
+    pub async fn connect_instance(instance: u32) -> Result<Self> {
+        Self::connect(instance_socket_path(instance)).await
+    }
+
+    /// Find the running managers, by their control sockets
+    ///
+    /// Looks for `vimputti-*` sockets in `/tmp` and `/run/user/<uid>` and returns those of
+    /// managers answering a ping, in that order. Sockets left behind by a crashed manager are
+    /// skipped, going by their lock file where there is one. Abstract sockets aren't found.
+    pub async fn discover() -> Vec<PathBuf> {
+        let uid = unsafe { libc::getuid() };
+        let dirs = [
+            PathBuf::from("/tmp"),
+            PathBuf::from(format!("/run/user/{}", uid)),
+        ];
+
+        let mut managers = Vec::new();
+        for dir in dirs {
+            let Ok(entries) = std::fs::read_dir(&dir) else {
+                continue;
+            };
+            let mut sockets: Vec<PathBuf> = entries
+                .filter_map(|entry| entry.ok())
+                .filter(|entry| entry.file_name().to_string_lossy().starts_with("vimputti-"))
+                .filter(|entry| entry.file_type().is_ok_and(|t| t.is_socket()))
+                .map(|entry| entry.path())
+                .collect();
+            sockets.sort();
+
+            for socket_path in sockets {
+                let lock_path = socket_path.with_extension("lock");
+                if lock_path.exists() && !crate::manager::LockFile::is_held(&lock_path) {
+                    debug!("Skipping stale manager socket {}", socket_path.display());
+                    continue;
+                }
+                if Self::is_alive(&socket_path).await {
+                    managers.push(socket_path);
+                }
+            }
+        }
+        managers
+    }
+
+    /// Check if a manager answers a ping at `socket_path` within `DISCOVER_TIMEOUT`
+    async fn is_alive(socket_path: &Path) -> bool {
+        let ping = async {
+            let client = Self::connect(socket_path).await?;
+            client.ping().await
+        };
+        matches!(
+            tokio::time::timeout(DISCOVER_TIMEOUT, ping).await,
+            Ok(Ok(()))
+        )
     }
 
     /// Ping the manager to check if it's alive
@@ -62,7 +185,7 @@ impl VimputtiClient {
         let response = self.send_command(ControlCommand::Ping).await?;
         match response {
             ControlResult::Pong => Ok(()),
-            ControlResult::Error { message } => {
+            ControlResult::Error { message, .. } => {
                 anyhow::bail!("Manager returned error: {}", message)
             }
             _ => anyhow::bail!("Unexpected response to ping"),
@@ -71,71 +194,258 @@ impl VimputtiClient {
 
     /// Create a new virtual device from a configuration
     pub async fn create_device(&self, config: DeviceConfig) -> Result<VirtualController> {
+        let command = ControlCommand::CreateDevice {
+            config: config.clone(),
+        };
+        self.create(command, config).await
+    }
+
+    /// Create a new virtual device with a specific ID, e.g. for a known `eventN` node
+    ///
+    /// Fails if a device with that ID already exists.
+    pub async fn create_device_with_id(
+        &self,
+        device_id: DeviceId,
+        config: DeviceConfig,
+    ) -> Result<VirtualController> {
+        let command = ControlCommand::CreateDeviceWithId {
+            device_id,
+            config: config.clone(),
+        };
+        self.create(command, config).await
+    }
+
+    /// Get the device registered under `key`, creating it if there is none
+    ///
+    /// Makes test setup idempotent across reruns against a long-lived manager. Fails if the
+    /// device exists with a different config, see `ensure_device_with` to recreate it instead.
+    /// The device outlives the returned handle, it stays registered under the key until
+    /// destroyed explicitly.
+    pub async fn ensure_device(
+        &self,
+        key: impl Into<String>,
+        config: DeviceConfig,
+    ) -> Result<VirtualController> {
+        self.ensure_device_with(key, config, EnsureMismatch::Error)
+            .await
+    }
+
+    /// Get the device registered under `key`, handling a different config as asked
+    pub async fn ensure_device_with(
+        &self,
+        key: impl Into<String>,
+        config: DeviceConfig,
+        on_mismatch: EnsureMismatch,
+    ) -> Result<VirtualController> {
         let response = self
-            .send_command(ControlCommand::CreateDevice { config })
+            .send_command(ControlCommand::EnsureDevice {
+                key: key.into(),
+                config: config.clone(),
+                on_mismatch,
+            })
             .await?;
 
+        match response {
+            ControlResult::DeviceEnsured {
+                device_id,
+                event_node,
+                created,
+                config: ensured,
+            } => {
+                debug!(
+                    "Ensured device {} as {} (created: {})",
+                    device_id, event_node, created
+                );
+                let config = ensured.map_or(config, |ensured| *ensured);
+                Ok(
+                    VirtualController::new(Arc::clone(&self.inner), device_id, event_node, config)
+                        .keep_on_drop(),
+                )
+            }
+            ControlResult::Error { message, .. } => {
+                anyhow::bail!("Failed to ensure device: {}", message)
+            }
+            _ => anyhow::bail!("Unexpected response to EnsureDevice"),
+        }
+    }
+
+    async fn create(
+        &self,
+        command: ControlCommand,
+        config: DeviceConfig,
+    ) -> Result<VirtualController> {
+        let response = self.send_command(command).await?;
+
         match response {
             ControlResult::DeviceCreated {
                 device_id,
                 event_node,
+                config: created,
             } => {
                 debug!("Created device {} as {}", device_id, event_node);
+                // Managers from before quirks were reported only send the ID and node
+                let config = created.map_or(config, |created| *created);
                 Ok(VirtualController::new(
                     Arc::clone(&self.inner),
                     device_id,
                     event_node,
+                    config,
                 ))
             }
-            ControlResult::Error { message } => {
+            ControlResult::Error { message, .. } => {
                 anyhow::bail!("Failed to create device: {}", message)
             }
             _ => anyhow::bail!("Unexpected response to CreateDevice"),
         }
     }
 
+    /// Get the configs of all active devices, e.g. to save them with `DeviceConfig::to_json_file`
+    ///
+    /// Configs are as the devices present now, see `get_device_config`.
+    pub async fn export_devices(&self) -> Result<Vec<DeviceConfig>> {
+        let mut configs = Vec::new();
+        for device in self.list_devices().await? {
+            configs.push(self.get_device_config(device.device_id).await?);
+        }
+        Ok(configs)
+    }
+
+    /// Create a device for each config, e.g. those of `export_devices`, in order
+    ///
+    /// The manager assigns the devices IDs as for `create_device`, configs don't carry the IDs
+    /// devices had when exported, and importing next to devices of the same name creates
+    /// further ones. If one fails, the devices created before it are destroyed again.
+    pub async fn import_devices(&self, configs: &[DeviceConfig]) -> Result<Vec<VirtualController>> {
+        let mut devices = Vec::with_capacity(configs.len());
+        for config in configs {
+            // Dropping the devices created so far destroys them
+            devices.push(self.create_device(config.clone()).await?);
+        }
+        Ok(devices)
+    }
+
     /// List all active devices
     pub async fn list_devices(&self) -> Result<Vec<DeviceInfo>> {
-        let response = self.send_command(ControlCommand::ListDevices).await?;
+        self.list_devices_filtered(None).await
+    }
+
+    /// List the active devices matching a filter, e.g. all devices with a joystick node
+    pub async fn find_devices_by(&self, filter: DeviceFilter) -> Result<Vec<DeviceInfo>> {
+        self.list_devices_filtered(Some(filter)).await
+    }
+
+    async fn list_devices_filtered(&self, filter: Option<DeviceFilter>) -> Result<Vec<DeviceInfo>> {
+        let response = self
+            .send_command(ControlCommand::ListDevices { filter })
+            .await?;
 
         match response {
             ControlResult::DeviceList(devices) => Ok(devices),
-            ControlResult::Error { message } => {
+            ControlResult::Error { message, .. } => {
                 anyhow::bail!("Failed to list devices: {}", message)
             }
             _ => anyhow::bail!("Unexpected response to ListDevices"),
         }
     }
 
-    /// Send a command to the manager and wait for response
-    pub(crate) async fn send_command(&self, command: ControlCommand) -> Result<ControlResult> {
-        let id = ulid::Ulid::new().to_string();
-        let message = ControlMessage {
-            id: id.clone(),
-            command,
-        };
+    /// Get the full config of a device, as it presents now
+    ///
+    /// Includes the button and axis layout `list_devices` leaves out, and changes made since
+    /// creation such as new IDs or battery state.
+    pub async fn get_device_config(&self, device_id: DeviceId) -> Result<DeviceConfig> {
+        let response = self
+            .send_command(ControlCommand::GetDeviceConfig { device_id })
+            .await?;
 
-        let message_json = serde_json::to_string(&message)?;
+        match response {
+            ControlResult::DeviceConfig(config) => Ok(*config),
+            ControlResult::Error { message, .. } => {
+                anyhow::bail!("Failed to get device config: {}", message)
+            }
+            _ => anyhow::bail!("Unexpected response to GetDeviceConfig"),
+        }
+    }
 
-        let mut stream = self.inner.stream.lock().await;
+    /// Wait until a device matching `predicate` exists, e.g. a mirror created by Steam
+    ///
+    /// Polls the device list every `WAIT_POLL_INTERVAL` and fails once `timeout` passes
+    /// without a match, also while a manager not answering holds up a request. Requests run
+    /// on their own task, so one cut off by the timeout still finishes rather than being left
+    /// half done on the connection.
+    pub async fn wait_for_device(
+        &self,
+        predicate: impl Fn(&DeviceInfo) -> bool,
+        timeout: Duration,
+    ) -> Result<DeviceInfo> {
+        let deadline = tokio::time::Instant::now() + timeout;
+        loop {
+            let client = self.clone();
+            let request = tokio::spawn(async move { client.list_devices().await });
+            let Ok(devices) = tokio::time::timeout_at(deadline, request).await else {
+                anyhow::bail!("No matching device appeared within {:?}", timeout);
+            };
+            if let Some(device) = devices??.into_iter().find(&predicate) {
+                return Ok(device);
+            }
 
-        // Send command
-        stream.write_all(message_json.as_bytes()).await?;
-        stream.write_all(b"\n").await?;
+            let now = tokio::time::Instant::now();
+            if now >= deadline {
+                anyhow::bail!("No matching device appeared within {:?}", timeout);
+            }
+            tokio::time::sleep(WAIT_POLL_INTERVAL.min(deadline - now)).await;
+        }
+    }
 
-        // Read response
-        let mut reader = BufReader::new(&mut *stream);
-        let mut response_line = String::new();
-        reader.read_line(&mut response_line).await?;
+    /// Send udev and netlink add events for all devices again
+    ///
+    /// Returns the number of devices announced.
+    pub async fn rebroadcast_devices(&self) -> Result<usize> {
+        let response = self
+            .send_command(ControlCommand::RebroadcastDevices)
+            .await?;
 
-        let response: ControlResponse = serde_json::from_str(&response_line)
-            .with_context(|| format!("Failed to parse response: {}", response_line))?;
+        match response {
+            ControlResult::DevicesRebroadcast { count } => Ok(count),
+            ControlResult::Error { message, .. } => {
+                anyhow::bail!("Failed to rebroadcast devices: {}", message)
+            }
+            _ => anyhow::bail!("Unexpected response to RebroadcastDevices"),
+        }
+    }
 
-        if response.id != id {
-            anyhow::bail!("Response ID mismatch: expected {}, got {}", id, response.id);
+    /// Have the manager run `command` (program and arguments) on every `event`, or stop with `None`
+    ///
+    /// Needs a manager built with the `hooks` feature, see `ManagerInfo::features`.
+    pub async fn set_hook(&self, event: HookEvent, command: Option<Vec<String>>) -> Result<()> {
+        let response = self
+            .send_command(ControlCommand::SetHook { event, command })
+            .await?;
+
+        match response {
+            ControlResult::HookSet => Ok(()),
+            ControlResult::Error { message, .. } => {
+                anyhow::bail!("Failed to set hook: {}", message)
+            }
+            _ => anyhow::bail!("Unexpected response to SetHook"),
         }
+    }
 
-        Ok(response.result)
+    /// Query manager version, paths, limits and supported features
+    pub async fn manager_info(&self) -> Result<ManagerInfo> {
+        let response = self.send_command(ControlCommand::GetManagerInfo).await?;
+
+        match response {
+            ControlResult::ManagerInfo(info) => Ok(info),
+            ControlResult::Error { message, .. } => {
+                anyhow::bail!("Failed to get manager info: {}", message)
+            }
+            _ => anyhow::bail!("Unexpected response to GetManagerInfo"),
+        }
+    }
+
+    /// Send a command to the manager and wait for response
+    pub(crate) async fn send_command(&self, command: ControlCommand) -> Result<ControlResult> {
+        self.inner.send_command(command).await
     }
 }
 impl Clone for VimputtiClient {
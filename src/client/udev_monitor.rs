@@ -0,0 +1,69 @@
+use crate::manager::udev::{UdevEvent, wire};
+use anyhow::{Context, Result};
+use std::path::Path;
+use std::time::Duration;
+use tokio::io::AsyncReadExt;
+use tokio::net::UnixStream;
+
+/// Largest message accepted before the stream is considered garbled
+const MAX_MESSAGE_LEN: usize = 64 * 1024;
+
+/// Observer of the udev events the manager broadcasts, e.g. for asserting hotplug in tests
+///
+/// The manager first sends add events for the devices that already exist when the monitor
+/// connects, then the events of devices created, changed and destroyed after.
+pub struct UdevMonitorClient {
+    stream: UnixStream,
+    buffer: Vec<u8>,
+}
+impl UdevMonitorClient {
+    /// Connect to the udev socket of the manager using `base_path`
+    pub async fn connect(base_path: impl AsRef<Path>) -> Result<Self> {
+        let socket_path = base_path.as_ref().join("udev");
+        let stream = UnixStream::connect(&socket_path)
+            .await
+            .with_context(|| format!("Failed to connect to {}", socket_path.display()))?;
+
+        Ok(Self {
+            stream,
+            buffer: Vec::new(),
+        })
+    }
+
+    /// Connect to the udev socket of the manager `client` is connected to
+    pub async fn for_client(client: &super::VimputtiClient) -> Result<Self> {
+        Self::connect(client.inner.get_base_path()).await
+    }
+
+    /// Wait for the next event
+    pub async fn next_event(&mut self) -> Result<UdevEvent> {
+        loop {
+            if let Some(len) = wire::message_len(&self.buffer)
+                && self.buffer.len() >= len
+            {
+                let message: Vec<u8> = self.buffer.drain(..len).collect();
+                return wire::decode_event(&message).context("Failed to decode udev message");
+            }
+            if self.buffer.len() > MAX_MESSAGE_LEN {
+                anyhow::bail!("Invalid udev message of {} bytes", self.buffer.len());
+            }
+
+            let mut buf = [0u8; 4096];
+            let n = self.stream.read(&mut buf).await?;
+            if n == 0 {
+                anyhow::bail!("udev socket closed by the manager");
+            }
+            self.buffer.extend_from_slice(&buf[..n]);
+        }
+    }
+
+    /// Wait for the next event up to `timeout`, None if there was none
+    ///
+    /// Useful for skipping the add events of existing devices after connecting.
+    pub async fn next_event_timeout(&mut self, timeout: Duration) -> Result<Option<UdevEvent>> {
+        match tokio::time::timeout(timeout, self.next_event()).await {
+            Ok(event) => event.map(Some),
+            Err(_) => Ok(None),
+        }
+    }
+}
@@ -3,16 +3,27 @@
 //! This library provides a high-level API for creating and controlling
 //! virtual input devices in isolated containers.
 
+#[cfg(all(feature = "probe", target_os = "linux"))]
+pub mod capture;
 pub mod client;
 pub mod manager;
-pub mod protocol;
+#[cfg(all(feature = "probe", target_os = "linux"))]
+pub mod probe;
+pub mod quirks;
 pub mod templates;
 
+/// Protocol types, see the `vimputti-protocol` crate
+pub use vimputti_protocol as protocol;
+
 // Re-export commonly used types
 pub use protocol::{
-    Axis, AxisConfig, BusType, Button, DeviceConfig, DeviceId, DeviceInfo, EV_ABS, EV_FF, EV_KEY,
-    EV_REL, EV_SYN, InputEvent, LinuxAbsEvent, LinuxJsEvent, TimeVal,
+    Axis, AxisConfig, BatteryConfig, BatteryStatus, BusType, Button, ButtonNamespace, ClientCounts,
+    DPadStyle, DeviceConfig, DeviceFilter, DeviceId, DeviceInfo, EV_ABS, EV_FF, EV_KEY, EV_REL,
+    EV_SYN, EnsureMismatch, FeedbackEvent, HookEvent, InputEvent, LinuxAbsEvent, LinuxJsEvent,
+    ManagerInfo, PauseMode, RelAxis, TimeVal, TriggerLayout,
 };
 
-pub use client::{VimputtiClient, VirtualController};
-pub use templates::{ControllerBuilder, ControllerTemplates};
+#[cfg(feature = "udev-monitor")]
+pub use client::UdevMonitorClient;
+pub use client::{Combo, ComboFrame, VimputtiClient, VirtualController};
+pub use templates::{ControllerBuilder, ControllerTemplates, TemplateInfo};
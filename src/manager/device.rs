@@ -1,63 +1,604 @@
+use crate::manager::event_log::{EventLogConfig, EventLogger};
 use crate::manager::sysfs::SysfsGenerator;
+use crate::protocol::linux::{JS_EVENT_AXIS, JS_EVENT_BUTTON};
 use crate::protocol::*;
+use anyhow::Context;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::os::fd::{AsFd, AsRawFd, OwnedFd};
 use std::path::{Path, PathBuf};
-use std::sync::Arc;
-use tokio::io::AsyncReadExt;
+use std::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+use std::sync::{Arc, Weak};
+use std::time::{Duration, Instant};
 use tokio::io::AsyncWriteExt;
+use tokio::io::unix::AsyncFd;
+use tokio::io::{AsyncReadExt, Interest};
 use tokio::net::{UnixListener, UnixStream};
-use tokio::sync::Mutex;
+use tokio::sync::{Mutex, mpsc, oneshot, watch};
 use tracing::{debug, error, info, trace};
 
+/// Current button and axis state of a device, as seen by its readers
+#[derive(Debug, Clone, Default)]
+pub struct DeviceState {
+    pub pressed_buttons: HashSet<Button>,
+    pub axes: HashMap<Axis, i32>,
+}
+impl DeviceState {
+    /// State of a device no input was sent to yet, axes resting at `AxisConfig::rest`
+    pub fn initial(config: &DeviceConfig) -> Self {
+        Self {
+            pressed_buttons: HashSet::new(),
+            axes: config
+                .axes
+                .iter()
+                .filter(|a| !a.axis.is_multitouch())
+                .map(|a| (a.axis, a.rest()))
+                .collect(),
+        }
+    }
+
+    /// Update the state from a sent event
+    ///
+    /// Returns false if the event doesn't change the state, e.g. a press of an already held button.
+    pub fn apply(&mut self, event: &InputEvent) -> bool {
+        match *event {
+            InputEvent::Button { button, pressed } => self.set_button(button, pressed),
+            InputEvent::Axis { axis, value } => self.set_axis(axis, value),
+            // Movement has no state, the input core only drops empty moves
+            InputEvent::Relative { value, .. } => value != 0,
+            InputEvent::Raw {
+                event_type,
+                code,
+                value,
+            } => {
+                if event_type == EV_KEY
+                    && let Some(button) = Button::from_ev_code(code)
+                {
+                    // Autorepeat (2) of a held key always goes through, like in the input core
+                    value == 2 || self.set_button(button, value != 0)
+                } else if event_type == EV_ABS
+                    // The device's own axis, on a motion sensor ABS_X is AccelX
+                    && let Some(&axis) = self.axes.keys().find(|axis| axis.to_ev_code() == code)
+                {
+                    self.set_axis(axis, value)
+                } else {
+                    true
+                }
+            }
+            InputEvent::Sync => true,
+        }
+    }
+
+    /// Forget buttons and axes the device no longer has after its config was updated
+    ///
+    /// Axes added by the update start out at rest.
+    fn retain_layout(&mut self, config: &DeviceConfig) {
+        self.pressed_buttons
+            .retain(|button| config.has_button(*button));
+        self.axes.retain(|axis, _| config.has_axis(*axis));
+        for (axis, rest) in DeviceState::initial(config).axes {
+            self.axes.entry(axis).or_insert(rest);
+        }
+    }
+
+    fn set_button(&mut self, button: Button, pressed: bool) -> bool {
+        if pressed {
+            self.pressed_buttons.insert(button)
+        } else {
+            self.pressed_buttons.remove(&button)
+        }
+    }
+
+    fn set_axis(&mut self, axis: Axis, value: i32) -> bool {
+        // Multitouch values belong to the current slot, they aren't part of the device state
+        if axis.is_multitouch() {
+            return true;
+        }
+
+        // Axes of the layout start out at rest (see `initial`), others at 0
+        self.axes.insert(axis, value).unwrap_or(0) != value
+    }
+
+    /// Events reproducing this state on a fresh reader of a device with `config`, ending with
+    /// a sync
+    ///
+    /// Released buttons and axes at rest are skipped, returns nothing if all are at rest.
+    pub fn to_events(&self, config: &DeviceConfig) -> Vec<InputEvent> {
+        self.diff_events(&DeviceState::initial(config))
+    }
+
+    /// Events taking a reader from `previous` to this state, ending with a sync
+    ///
+    /// Returns nothing if the states are the same.
+    pub fn diff_events(&self, previous: &DeviceState) -> Vec<InputEvent> {
+        let mut events: Vec<InputEvent> = previous
+            .pressed_buttons
+            .difference(&self.pressed_buttons)
+            .map(|&button| InputEvent::Button {
+                button,
+                pressed: false,
+            })
+            .chain(
+                self.pressed_buttons
+                    .difference(&previous.pressed_buttons)
+                    .map(|&button| InputEvent::Button {
+                        button,
+                        pressed: true,
+                    }),
+            )
+            .chain(
+                self.axes
+                    .iter()
+                    .filter(|&(axis, &value)| {
+                        previous.axes.get(axis).copied().unwrap_or(0) != value
+                    })
+                    .map(|(&axis, &value)| InputEvent::Axis { axis, value }),
+            )
+            .collect();
+
+        if !events.is_empty() {
+            events.push(InputEvent::Sync);
+        }
+        events
+    }
+}
+
+/// Events of a report not yet ended by a sync, for suppressing empty reports
+#[derive(Debug, Default)]
+struct PendingReport {
+    /// Events kept for the report
+    events: usize,
+    /// Some events of the report were dropped
+    dropped: bool,
+}
+
+/// Fingers down on a multitouch device, for emitting `BTN_TOUCH` and `BTN_TOOL_*`
+#[derive(Debug, Default)]
+struct TouchTracker {
+    /// Slot selected by the last `ABS_MT_SLOT`
+    slot: i32,
+    /// Slots with a tracking ID assigned
+    active: BTreeSet<i32>,
+    /// Finger count readers were last told about
+    reported: usize,
+}
+impl TouchTracker {
+    /// Follow the slots through some events, adding touch button changes before each sync
+    ///
+    /// `buttons` are `DeviceConfig::touch_buttons`, `BTN_TOUCH` followed by the tools by
+    /// finger count.
+    fn process(&mut self, events: &[InputEvent], buttons: &[Button]) -> Vec<InputEvent> {
+        let mut processed = Vec::with_capacity(events.len());
+        for event in events {
+            let abs = match *event {
+                InputEvent::Axis {
+                    axis: Axis::Custom(code),
+                    value,
+                } => Some((code, value)),
+                InputEvent::Raw {
+                    event_type: EV_ABS,
+                    code,
+                    value,
+                } => Some((code, value)),
+                _ => None,
+            };
+            match abs {
+                Some((ABS_MT_SLOT, slot)) => self.slot = slot,
+                Some((ABS_MT_TRACKING_ID, id)) if id >= 0 => {
+                    self.active.insert(self.slot);
+                }
+                Some((ABS_MT_TRACKING_ID, _)) => {
+                    self.active.remove(&self.slot);
+                }
+                _ => {}
+            }
+
+            if let InputEvent::Sync = event {
+                self.report_fingers(&mut processed, buttons);
+            }
+            processed.push(event.clone());
+        }
+        processed
+    }
+
+    fn report_fingers(&mut self, events: &mut Vec<InputEvent>, buttons: &[Button]) {
+        let count = self.active.len();
+        if count == self.reported {
+            return;
+        }
+        let Some((&touch, tools)) = buttons.split_first() else {
+            return;
+        };
+
+        // More fingers than tools keep the last tool
+        let tool = |count: usize| tools.get(count.min(tools.len()).checked_sub(1)?).copied();
+        if let Some(button) = tool(self.reported)
+            && tool(count) != Some(button)
+        {
+            events.push(InputEvent::Button {
+                button,
+                pressed: false,
+            });
+        }
+        if (self.reported == 0) != (count == 0) {
+            events.push(InputEvent::Button {
+                button: touch,
+                pressed: count > 0,
+            });
+        }
+        if let Some(button) = tool(count)
+            && tool(self.reported) != Some(button)
+        {
+            events.push(InputEvent::Button {
+                button,
+                pressed: true,
+            });
+        }
+
+        self.reported = count;
+    }
+}
+
+/// Small xorshift PRNG, so latency jitter can be reproduced from a seed
+struct JitterRng(u64);
+impl JitterRng {
+    fn new(seed: u64) -> Self {
+        Self((seed ^ 0x9e37_79b9_7f4a_7c15).max(1))
+    }
+
+    /// Uniformly distributed offset in `-jitter..=jitter`
+    fn offset(&mut self, jitter: u64) -> i64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 % (2 * jitter + 1)) as i64 - jitter as i64
+    }
+}
+
+/// Entry of the latency delay queue
+enum LatencyItem {
+    /// Frame to write once due
+    Frame(tokio::time::Instant, Vec<InputEvent>),
+    /// Signalled once all frames queued before it are written
+    Flush(oneshot::Sender<()>),
+}
+
+/// Artificial latency applied to frames before they reach readers
+struct Latency {
+    base_ms: u32,
+    jitter_ms: u32,
+    rng: JitterRng,
+    /// Delay queue, frames are written in order once due
+    queue: mpsc::UnboundedSender<LatencyItem>,
+}
+
+/// Input held back for `DeviceConfig::max_event_hz`, written out by a timer
+struct Coalescing {
+    /// Frames written on the next tick, the last one is being filled
+    frames: Vec<Vec<InputEvent>>,
+    /// Dropped with the coalescing, which stops its timer
+    _stop: watch::Sender<()>,
+}
+impl Coalescing {
+    /// Merge events into the frame being filled
+    ///
+    /// Absolute axes keep their last value and relative ones add up, except multitouch axes
+    /// which depend on the slot before them. Key events are kept, a key already in the frame
+    /// starts the next one, so readers see each press and release.
+    fn push(&mut self, events: &[InputEvent]) {
+        for event in events {
+            let linux = event.to_linux_input_event();
+            if linux.event_type == EV_SYN && linux.code == SYN_REPORT {
+                // Frames end on the timer
+                continue;
+            }
+
+            let frame = self.frames.last_mut().unwrap();
+            let same = frame.iter().rposition(|e| {
+                let other = e.to_linux_input_event();
+                other.event_type == linux.event_type && other.code == linux.code
+            });
+            match (linux.event_type, same) {
+                (EV_ABS, Some(idx)) if !(ABS_MT_SLOT..=ABS_MT_TOOL_Y).contains(&linux.code) => {
+                    frame[idx] = event.clone();
+                }
+                (EV_REL, Some(idx)) => {
+                    let total = frame[idx]
+                        .to_linux_input_event()
+                        .value
+                        .saturating_add(linux.value);
+                    frame[idx] = InputEvent::from_linux_input_event(&LinuxInputEvent::new(
+                        EV_REL, linux.code, total,
+                    ));
+                }
+                (EV_KEY, Some(_)) => self.frames.push(vec![event.clone()]),
+                _ => frame.push(event.clone()),
+            }
+        }
+    }
+
+    /// Take the held back frames, each ended with a sync
+    fn take(&mut self) -> Vec<InputEvent> {
+        let frames = std::mem::replace(&mut self.frames, vec![Vec::new()]);
+        frames
+            .into_iter()
+            .filter(|frame| !frame.is_empty())
+            .flat_map(|frame| frame.into_iter().chain([InputEvent::Sync]))
+            .collect()
+    }
+}
+
+/// Evdev reader of a device
+struct EvdevClient {
+    writer: tokio::net::unix::OwnedWriteHalf,
+    /// Rest of an event its socket only took part of, written before anything else
+    partial: Vec<u8>,
+    /// Events were dropped as its socket was full, the next write starts with SYN_DROPPED
+    dropped: bool,
+    /// Duplicate of the socket, registered on its own so waiting for the reader to take what
+    /// was written leaves the readiness `writer` writes by alone
+    drain: AsyncFd<OwnedFd>,
+}
+impl EvdevClient {
+    fn new(writer: tokio::net::unix::OwnedWriteHalf) -> std::io::Result<Self> {
+        let fd = writer.as_ref().as_fd().try_clone_to_owned()?;
+        Ok(Self {
+            writer,
+            partial: Vec::new(),
+            dropped: false,
+            drain: AsyncFd::with_interest(fd, Interest::WRITABLE)?,
+        })
+    }
+
+    /// Bytes written that the reader didn't take yet
+    fn queued(&self) -> usize {
+        let mut queued: libc::c_int = 0;
+        let fd = self.drain.as_raw_fd();
+        match unsafe { libc::ioctl(fd, libc::TIOCOUTQ, &mut queued) } {
+            0 => queued as usize,
+            _ => 0,
+        }
+    }
+
+    /// Its socket filled up, a cut off event or dropped events are waiting for it
+    fn overflowed(&self) -> bool {
+        self.dropped || !self.partial.is_empty()
+    }
+
+    /// Write without waiting, dropping what doesn't fit into the socket like the kernel does
+    /// when a reader's buffer overflows
+    ///
+    /// Fails only if the reader is gone.
+    fn write(&mut self, data: &[u8]) -> std::io::Result<()> {
+        if !self.partial.is_empty() {
+            let written = self.try_write(&self.partial)?;
+            self.partial.drain(..written);
+            if !self.partial.is_empty() {
+                self.dropped = true;
+                return Ok(());
+            }
+        }
+
+        let resynced: Vec<u8>;
+        let data = if self.dropped {
+            resynced = LinuxInputEvent::new(EV_SYN, SYN_DROPPED, 0)
+                .to_bytes()
+                .into_iter()
+                .chain(data.iter().copied())
+                .collect();
+            &resynced
+        } else {
+            data
+        };
+
+        let written = self.try_write(data)?;
+        if written == 0 {
+            self.dropped = true;
+            return Ok(());
+        }
+
+        // An event can't be cut off, the rest of it goes out first on the next write
+        let event_end = written
+            .next_multiple_of(size_of::<LinuxInputEvent>())
+            .min(data.len());
+        self.partial = data[written..event_end].to_vec();
+        self.dropped = event_end < data.len();
+        Ok(())
+    }
+
+    /// Bytes the socket took, 0 if it's full
+    fn try_write(&self, data: &[u8]) -> std::io::Result<usize> {
+        match self.writer.try_write(data) {
+            Ok(written) => Ok(written),
+            Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => Ok(0),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+/// What the accept loop of a device's evdev socket shares with the device
+struct EvdevSocket {
+    /// Node name for logging, e.g. `event0`
+    event_node: String,
+    clients: Arc<Mutex<Vec<EvdevClient>>>,
+    feedback_clients: Arc<Mutex<Vec<UnixStream>>>,
+    /// Number of connected readers, see `VirtualDevice::evdev_readers`
+    readers: Arc<AtomicUsize>,
+    delivered: Arc<Mutex<DeviceState>>,
+}
+
+/// Joystick interface (`jsN`) of a device, its socket is removed when dropped
+struct JoystickInterface {
+    node: String,
+    socket_path: PathBuf,
+    /// Dropped with the interface, which stops its accept loop
+    _shutdown: watch::Sender<()>,
+}
+impl Drop for JoystickInterface {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.socket_path);
+    }
+}
+
+/// Files of a device being created, removed again if creation fails part way
+struct PartialDevice<'a> {
+    id: DeviceId,
+    node_prefix: &'a str,
+    base_path: &'a Path,
+    sockets: Vec<PathBuf>,
+    complete: bool,
+}
+impl Drop for PartialDevice<'_> {
+    fn drop(&mut self) {
+        if self.complete {
+            return;
+        }
+        for socket in &self.sockets {
+            let _ = std::fs::remove_file(socket);
+        }
+        let _ = SysfsGenerator::remove_device_files(self.id, self.node_prefix, self.base_path);
+    }
+}
+
+/// Filter an axis value like the kernel's `input_defuzz_abs_event`
+///
+/// Changes within half the fuzz are dropped, larger ones up to twice the fuzz are smoothed
+/// towards the previous value.
+fn defuzz(old: i32, value: i32, fuzz: i32) -> i32 {
+    if fuzz > 0 {
+        let within = |range: i32| value > old - range && value < old + range;
+        if within(fuzz / 2) {
+            return old;
+        }
+        if within(fuzz) {
+            return (old * 3 + value) / 4;
+        }
+        if within(fuzz * 2) {
+            return (old + value) / 2;
+        }
+    }
+    value
+}
+
+/// Bind a device socket, naming the path on failure
+fn bind_socket(path: &Path) -> anyhow::Result<UnixListener> {
+    UnixListener::bind(path).with_context(|| format!("Failed to bind {}", path.display()))
+}
+
 pub struct VirtualDevice {
     pub id: DeviceId,
-    pub config: DeviceConfig,
-    pub event_node: String,            // e.g., "event0"
-    pub joystick_node: Option<String>, // e.g., "js0"
+    /// Config of the device and the one presented to readers, swapped by `update_config`
+    layout: std::sync::RwLock<Layout>,
+    /// Handshake of newly connected readers, `advertised` with the ids changed by `set_ids`
+    handshake: watch::Sender<DeviceHandshake>,
+    pub event_node: String, // e.g., "event0"
     socket_path: PathBuf,
-    joystick_socket_path: Option<PathBuf>,
+    /// Joystick interface, if the device has one, see `set_joystick_interface`
+    joystick: std::sync::Mutex<Option<JoystickInterface>>,
     base_path: PathBuf,
-    clients: Arc<Mutex<Vec<tokio::net::unix::OwnedWriteHalf>>>,
+    /// Prefix of the node names, see `event_node_name`
+    node_prefix: String,
+    clients: Arc<Mutex<Vec<EvdevClient>>>,
     joystick_clients: Arc<Mutex<Vec<tokio::net::unix::OwnedWriteHalf>>>,
     feedback_clients: Arc<Mutex<Vec<UnixStream>>>,
     feedback_socket_path: Option<PathBuf>,
+    /// Number of connected evdev readers
+    evdev_readers: Arc<AtomicUsize>,
+    /// Number of connected joystick readers
+    joystick_readers: Arc<AtomicUsize>,
+    /// Time of creation or the last sent input
+    last_activity: std::sync::Mutex<Instant>,
+    /// Button/axis state after all input sent so far, for dropping events that change nothing
+    state: Arc<Mutex<DeviceState>>,
+    /// Button/axis state readers were written, replayed to newly connected readers
+    ///
+    /// Behind `state` while input is paused, held back for the event rate or in the latency
+    /// queue.
+    delivered: Arc<Mutex<DeviceState>>,
+    /// Pause mode and the state readers last saw, while paused
+    paused: Mutex<Option<(PauseMode, DeviceState)>>,
+    /// Artificial latency, once configured
+    latency: std::sync::Mutex<Option<Latency>>,
+    /// Input held back, with `DeviceConfig::max_event_hz`
+    coalescing: std::sync::Mutex<Option<Coalescing>>,
+    /// Frames written to evdev readers, for `DeviceConfig::emit_sequence`
+    sequence: AtomicU32,
+    /// Last left and right trigger values, for `TriggerLayout::CombinedZ`
+    triggers: std::sync::Mutex<(i32, i32)>,
+    /// Fingers down, for multitouch devices
+    touch: std::sync::Mutex<TouchTracker>,
+    /// Report being built since the last sync, which may span several sends
+    report: std::sync::Mutex<PendingReport>,
+    /// Axis values last written to joystick readers, by axis index, for fuzz filtering
+    joystick_axes: std::sync::Mutex<Vec<i32>>,
+    /// Smoothed axis values, by axis index, for `AxisConfig::smoothing`
+    smoothed_axes: std::sync::Mutex<Vec<f32>>,
+    /// Sampling state of the event trace log
+    event_log: std::sync::Mutex<EventLogger>,
+    /// See `ManagerConfig::frame_drain_timeout`
+    frame_drain_timeout: Duration,
+    /// Dropped with the device, which stops its accept loops
+    _shutdown: watch::Sender<()>,
+}
+/// Config of a device along with the config presented to readers
+#[derive(Clone)]
+struct Layout {
+    config: Arc<DeviceConfig>,
+    /// See `DeviceConfig::advertised`
+    advertised: Arc<DeviceConfig>,
 }
+
 impl VirtualDevice {
     /// Create a new virtual device
     pub async fn create(
         id: DeviceId,
         config: DeviceConfig,
         base_path: &Path,
-    ) -> anyhow::Result<Self> {
-        let event_node = format!("event{}", id);
+        node_prefix: &str,
+        event_log: EventLogConfig,
+        frame_drain_timeout: Duration,
+    ) -> anyhow::Result<Arc<Self>> {
+        let event_node = event_node_name(node_prefix, id);
         let socket_path = base_path.join("devices").join(&event_node);
 
         // Remove old socket if exists
         let _ = std::fs::remove_file(&socket_path);
 
+        // Removes whatever was created if a later step fails
+        let mut partial = PartialDevice {
+            id,
+            node_prefix,
+            base_path,
+            sockets: Vec::new(),
+            complete: false,
+        };
+
         // Create device socket
-        let listener = UnixListener::bind(&socket_path)?;
+        let listener = bind_socket(&socket_path)?;
+        partial.sockets.push(socket_path.clone());
+
+        // Readers and sysfs see the device as presented
+        let advertised = config.advertised();
 
         // Create sysfs entries using new generator
-        SysfsGenerator::create_device_files(id, &config, base_path)?;
+        SysfsGenerator::create_device_files(
+            id,
+            node_prefix,
+            &advertised,
+            base_path,
+            config.has_joystick_interface(),
+        )?;
 
         let clients = Arc::new(Mutex::new(Vec::new()));
         let feedback_clients = Arc::new(Mutex::new(Vec::new()));
+        let evdev_readers = Arc::new(AtomicUsize::new(0));
+        let joystick_readers = Arc::new(AtomicUsize::new(0));
+        let state = Arc::new(Mutex::new(DeviceState::initial(&advertised)));
+        let delivered = Arc::new(Mutex::new(DeviceState::initial(&advertised)));
+        let (shutdown, shutdown_rx) = watch::channel(());
 
-        // Start accepting client connections
-        let clients_clone = clients.clone();
-        let feedback_clients_clone = feedback_clients.clone();
-        let config_clone = config.clone();
-        let event_node_clone = event_node.clone();
-        tokio::spawn(async move {
-            Self::accept_clients(
-                id,
-                listener,
-                clients_clone,
-                feedback_clients_clone,
-                config_clone,
-                event_node_clone,
-            )
-            .await;
+        let (handshake, handshake_rx) = watch::channel(DeviceHandshake {
+            device_id: id,
+            config: advertised.clone(),
         });
 
         // Create feedback socket
@@ -66,11 +607,17 @@ impl VirtualDevice {
             .join(format!("{}.feedback", &event_node));
         let _ = std::fs::remove_file(&feedback_socket_path);
 
-        let feedback_listener = UnixListener::bind(&feedback_socket_path)?;
+        let feedback_listener = bind_socket(&feedback_socket_path)?;
+        partial.sockets.push(feedback_socket_path.clone());
         let feedback_clients_clone = Arc::clone(&feedback_clients);
+        let mut shutdown_clone = shutdown_rx.clone();
         tokio::spawn(async move {
             loop {
-                if let Ok((stream, _)) = feedback_listener.accept().await {
+                let accepted = tokio::select! {
+                    accepted = feedback_listener.accept() => accepted,
+                    _ = shutdown_clone.changed() => break,
+                };
+                if let Ok((stream, _)) = accepted {
                     debug!("Client connected to feedback socket");
                     feedback_clients_clone.lock().await.push(stream);
                 }
@@ -78,84 +625,390 @@ impl VirtualDevice {
         });
 
         // Create joystick interface if device has axes or buttons
-        let (joystick_node, joystick_socket_path, joystick_clients) =
-            if !config.buttons.is_empty() || !config.axes.is_empty() {
-                let js_node = format!("js{}", id);
-                let js_socket_path = base_path.join("devices").join(&js_node);
-
-                // Remove old socket if exists
-                let _ = std::fs::remove_file(&js_socket_path);
-
-                // Create joystick socket
-                let js_listener = UnixListener::bind(&js_socket_path)?;
-
-                let js_clients = Arc::new(Mutex::new(Vec::new()));
-                let js_clients_clone = js_clients.clone();
-                let config_clone = config.clone();
-
-                tokio::spawn(async move {
-                    Self::accept_joystick_clients(id, js_listener, js_clients_clone, config_clone)
-                        .await;
-                });
-
-                info!("Created joystick node: {}", js_node);
+        let joystick_clients = Arc::new(Mutex::new(Vec::new()));
+        let joystick = if config.has_joystick_interface() {
+            let joystick = Self::open_joystick(
+                id,
+                base_path,
+                node_prefix,
+                joystick_clients.clone(),
+                joystick_readers.clone(),
+                handshake_rx.clone(),
+            )?;
+            partial.sockets.push(joystick.socket_path.clone());
+            Some(joystick)
+        } else {
+            None
+        };
 
-                (Some(js_node), Some(js_socket_path), js_clients)
-            } else {
-                (None, None, Arc::new(Mutex::new(Vec::new())))
-            };
+        partial.complete = true;
+        let joystick_axes = advertised.axes.iter().map(AxisConfig::rest).collect();
+        let smoothed_axes = advertised.axes.iter().map(|a| a.rest() as f32).collect();
 
-        Ok(Self {
+        let device = Self {
             id,
-            config,
+            layout: std::sync::RwLock::new(Layout {
+                config: Arc::new(config),
+                advertised: Arc::new(advertised),
+            }),
+            handshake,
             event_node,
-            joystick_node,
             socket_path,
-            joystick_socket_path,
+            joystick: std::sync::Mutex::new(joystick),
             base_path: base_path.to_path_buf(),
+            node_prefix: node_prefix.to_string(),
             clients,
             joystick_clients,
             feedback_clients,
             feedback_socket_path: Some(feedback_socket_path),
-        })
+            evdev_readers,
+            joystick_readers,
+            last_activity: std::sync::Mutex::new(Instant::now()),
+            state,
+            delivered,
+            paused: Mutex::new(None),
+            latency: std::sync::Mutex::new(None),
+            coalescing: std::sync::Mutex::new(None),
+            sequence: AtomicU32::new(0),
+            triggers: std::sync::Mutex::new((0, 0)),
+            touch: std::sync::Mutex::new(TouchTracker::default()),
+            report: std::sync::Mutex::new(PendingReport::default()),
+            joystick_axes: std::sync::Mutex::new(joystick_axes),
+            smoothed_axes: std::sync::Mutex::new(smoothed_axes),
+            event_log: std::sync::Mutex::new(EventLogger::new(event_log)),
+            frame_drain_timeout,
+            _shutdown: shutdown,
+        };
+
+        // Readers connecting meanwhile wait in the listen backlog, so the first one accepted
+        // already gets the initial state
+        device.apply_initial_state().await;
+
+        // Started after the initial state, which readers get on connecting anyway
+        let device = Arc::new(device);
+        device.set_max_event_hz(device.config().max_event_hz);
+        if let Some(interval) = event_log.summary_interval {
+            tokio::spawn(Self::run_event_log_summary(
+                Arc::downgrade(&device),
+                interval,
+            ));
+        }
+
+        // Start accepting client connections
+        let socket = EvdevSocket {
+            event_node: device.event_node.clone(),
+            clients: device.clients.clone(),
+            feedback_clients: device.feedback_clients.clone(),
+            readers: device.evdev_readers.clone(),
+            delivered: device.delivered.clone(),
+        };
+        tokio::spawn(async move {
+            Self::accept_clients(handshake_rx, listener, shutdown_rx, socket).await;
+        });
+
+        Ok(device)
+    }
+
+    /// Config the device was created with, or last updated to with `update_config`
+    pub fn config(&self) -> Arc<DeviceConfig> {
+        self.layout.read().unwrap().config.clone()
+    }
+
+    /// Config presented to readers, see `DeviceConfig::advertised`
+    fn advertised(&self) -> Arc<DeviceConfig> {
+        self.layout.read().unwrap().advertised.clone()
+    }
+
+    /// Config with the ids and battery currently presented, see `set_ids` and `set_battery`
+    pub fn current_config(&self) -> DeviceConfig {
+        let handshake = self.handshake.borrow();
+        let presented = &handshake.config;
+        DeviceConfig {
+            bustype: presented.bustype,
+            vendor_id: presented.vendor_id,
+            product_id: presented.product_id,
+            version: presented.version,
+            battery: presented.battery,
+            ..(*self.config()).clone()
+        }
+    }
+
+    /// Swap the layout of the device for that of `config`, keeping its nodes
+    ///
+    /// Readers opening the device from now on and sysfs see the new config, except for the ids
+    /// and battery, which stay as presented, see `set_ids` and `set_battery`. Readers that are
+    /// already connected keep the capabilities from their handshake and have to open the
+    /// device again to see the new ones. Input sent from now on is checked against the new
+    /// layout, the state of buttons and axes the device keeps stays as it is. `initial_state`
+    /// isn't applied again, and the joystick interface stays as it is unless the new layout
+    /// has nothing for it, see `set_joystick_interface`.
+    ///
+    /// Returns whether the joystick node was removed.
+    pub async fn update_config(self: &Arc<Self>, config: DeviceConfig) -> anyhow::Result<bool> {
+        // Sends hold this throughout, so none of them sees part of the old layout and part of
+        // the new
+        let _paused = self.paused.lock().await;
+
+        if config.max_event_hz != self.config().max_event_hz {
+            self.write_coalesced().await?;
+            self.set_max_event_hz(config.max_event_hz);
+        }
+
+        let joystick_removed =
+            !config.has_joystick_interface() && self.set_joystick_interface(false).await?;
+
+        let advertised = {
+            let handshake = self.handshake.borrow();
+            let presented = &handshake.config;
+            DeviceConfig {
+                bustype: presented.bustype,
+                vendor_id: presented.vendor_id,
+                product_id: presented.product_id,
+                version: presented.version,
+                battery: presented.battery,
+                ..config.advertised()
+            }
+        };
+        SysfsGenerator::create_device_files(
+            self.id,
+            &self.node_prefix,
+            &advertised,
+            &self.base_path,
+            self.joystick_node().is_some(),
+        )?;
+
+        self.state.lock().await.retain_layout(&advertised);
+        self.delivered.lock().await.retain_layout(&advertised);
+        *self.joystick_axes.lock().unwrap() =
+            advertised.axes.iter().map(AxisConfig::rest).collect();
+        *self.smoothed_axes.lock().unwrap() =
+            advertised.axes.iter().map(|a| a.rest() as f32).collect();
+        self.handshake.send_replace(DeviceHandshake {
+            device_id: self.id,
+            config: advertised.clone(),
+        });
+
+        info!(
+            "Device {} now has {} buttons and {} axes",
+            self.id,
+            config.buttons.len(),
+            config.axes.len()
+        );
+        *self.layout.write().unwrap() = Layout {
+            config: Arc::new(config),
+            advertised: Arc::new(advertised),
+        };
+        Ok(joystick_removed)
+    }
+
+    /// Present the device with other ids to readers opening it from now on and in sysfs
+    ///
+    /// Readers that are already connected keep the ids from their handshake.
+    pub fn set_ids(
+        &self,
+        bustype: BusType,
+        vendor_id: u16,
+        product_id: u16,
+        version: u16,
+    ) -> anyhow::Result<()> {
+        let mut handshake = self.handshake.borrow().clone();
+        let presented = &mut handshake.config;
+        presented.bustype = bustype;
+        presented.vendor_id = vendor_id;
+        presented.product_id = product_id;
+        presented.version = version;
+
+        SysfsGenerator::create_device_files(
+            self.id,
+            &self.node_prefix,
+            presented,
+            &self.base_path,
+            self.joystick_node().is_some(),
+        )?;
+        self.handshake.send_replace(handshake);
+
+        info!(
+            "Device {} now presents {:04x}:{:04x} (version {:04x}, {:?})",
+            self.id, vendor_id, product_id, version, bustype
+        );
+        Ok(())
+    }
+
+    /// Present another battery state in sysfs and to readers opening the device from now on
+    ///
+    /// Fails for devices created without a battery, wired devices don't gain one.
+    pub fn set_battery(&self, battery: BatteryConfig) -> anyhow::Result<()> {
+        let mut handshake = self.handshake.borrow().clone();
+        if handshake.config.battery.is_none() {
+            anyhow::bail!("Device {} has no battery", self.id);
+        }
+        handshake.config.battery = Some(battery);
+
+        SysfsGenerator::create_device_files(
+            self.id,
+            &self.node_prefix,
+            &handshake.config,
+            &self.base_path,
+            self.joystick_node().is_some(),
+        )?;
+        self.handshake.send_replace(handshake);
+
+        info!(
+            "Device {} battery now at {}% ({:?})",
+            self.id, battery.capacity, battery.status
+        );
+        Ok(())
+    }
+
+    /// Joystick node name while the device has one, e.g. `js0`
+    pub fn joystick_node(&self) -> Option<String> {
+        let joystick = self.joystick.lock().unwrap();
+        joystick.as_ref().map(|joystick| joystick.node.clone())
+    }
+
+    /// Add or remove the joystick interface, leaving the evdev node as it is
+    ///
+    /// Returns false if the interface already was as asked. Removing it ends the streams of
+    /// the connected joystick readers, like unplugging would. Only devices with buttons or
+    /// axes should get one, see `DeviceConfig::has_joystick_interface`.
+    pub async fn set_joystick_interface(&self, enabled: bool) -> anyhow::Result<bool> {
+        if enabled {
+            // Held while opening, so concurrent requests can't both bind the socket
+            let mut joystick = self.joystick.lock().unwrap();
+            if joystick.is_some() {
+                return Ok(false);
+            }
+
+            let opened = Self::open_joystick(
+                self.id,
+                &self.base_path,
+                &self.node_prefix,
+                self.joystick_clients.clone(),
+                self.joystick_readers.clone(),
+                self.handshake.subscribe(),
+            )?;
+            if let Err(e) = SysfsGenerator::create_joystick_files(
+                self.id,
+                &self.node_prefix,
+                &self.handshake.borrow().config,
+                &self.base_path,
+            ) {
+                // Drop the socket again, the interface is either complete or absent
+                drop(opened);
+                let _ = SysfsGenerator::remove_joystick_files(
+                    self.id,
+                    &self.node_prefix,
+                    &self.base_path,
+                );
+                return Err(e);
+            }
+            *joystick = Some(opened);
+        } else {
+            let Some(closed) = self.joystick.lock().unwrap().take() else {
+                return Ok(false);
+            };
+            info!("Removing joystick node: {}", closed.node);
+            drop(closed);
+            SysfsGenerator::remove_joystick_files(self.id, &self.node_prefix, &self.base_path)?;
+
+            // Dropping the write halves shuts them down, so readers see the end of the stream
+            self.joystick_clients.lock().await.clear();
+        }
+
+        Ok(true)
+    }
+
+    /// Snapshot of the current button/axis state
+    pub async fn state(&self) -> DeviceState {
+        self.state.lock().await.clone()
+    }
+
+    /// Event log sampling this device was created with
+    pub fn event_log_config(&self) -> EventLogConfig {
+        self.event_log.lock().unwrap().config()
+    }
+
+    /// How long this device waits for readers to take a frame, see
+    /// `ManagerConfig::frame_drain_timeout`
+    pub fn frame_drain_timeout(&self) -> Duration {
+        self.frame_drain_timeout
+    }
+
+    /// Prefix of the node names this device was created with
+    pub fn node_prefix(&self) -> &str {
+        &self.node_prefix
+    }
+
+    /// Number of currently connected evdev and joystick readers
+    pub fn client_count(&self) -> usize {
+        self.evdev_readers.load(Ordering::Relaxed) + self.joystick_readers.load(Ordering::Relaxed)
+    }
+
+    /// Number of currently connected evdev, joystick and feedback clients
+    pub async fn client_counts(&self) -> ClientCounts {
+        // Feedback clients never write, a read only succeeds once they hung up
+        let mut feedback_clients = self.feedback_clients.lock().await;
+        feedback_clients.retain(|stream| {
+            let read = stream.try_read(&mut [0u8; 1]);
+            matches!(read, Err(e) if e.kind() == std::io::ErrorKind::WouldBlock)
+        });
+
+        ClientCounts {
+            evdev: self.evdev_readers.load(Ordering::Relaxed),
+            joystick: self.joystick_readers.load(Ordering::Relaxed),
+            feedback: feedback_clients.len(),
+        }
+    }
+
+    /// Time since the device was created or last received input
+    pub fn idle_for(&self) -> Duration {
+        self.last_activity.lock().unwrap().elapsed()
     }
 
     /// Accept client connections to device socket
     async fn accept_clients(
-        id: DeviceId,
+        handshake: watch::Receiver<DeviceHandshake>,
         listener: UnixListener,
-        clients: Arc<Mutex<Vec<tokio::net::unix::OwnedWriteHalf>>>,
-        feedback_clients: Arc<Mutex<Vec<UnixStream>>>,
-        config: DeviceConfig,
-        event_node: String,
+        mut shutdown: watch::Receiver<()>,
+        socket: EvdevSocket,
     ) {
+        let EvdevSocket {
+            event_node,
+            clients,
+            feedback_clients,
+            readers,
+            delivered,
+        } = socket;
         loop {
-            match listener.accept().await {
+            let accepted = tokio::select! {
+                accepted = listener.accept() => accepted,
+                // The device was dropped
+                _ = shutdown.changed() => break,
+            };
+            match accepted {
                 Ok((stream, _)) => {
+                    let handshake = handshake.borrow().clone();
                     debug!(
                         "Client connected to device socket: {} ({})",
-                        event_node, config.name
+                        event_node, handshake.config.name
                     );
 
                     let (mut read_half, mut write_half) = stream.into_split();
 
+                    // The reader can send input once it has the handshake, so the state it
+                    // starts from is taken first and it's registered before that input is
+                    // written. Holding the clients lock keeps sends from slipping in between.
+                    let mut clients_lock = clients.lock().await;
+                    let initial_events = delivered.lock().await.to_events(&handshake.config);
+
                     // Send handshake
-                    let handshake = DeviceHandshake {
-                        device_id: id,
-                        config: config.clone(),
-                    };
-                    match serde_json::to_vec(&handshake) {
-                        Ok(config_json) => {
-                            let len = config_json.len() as u32;
-                            if let Err(e) = write_half.write_all(&len.to_le_bytes()).await {
-                                error!("Failed to send config length to client: {}", e);
-                                continue;
-                            }
-                            if let Err(e) = write_half.write_all(&config_json).await {
+                    match handshake.to_bytes() {
+                        Ok(frame) => {
+                            if let Err(e) = write_half.write_all(&frame).await {
                                 error!("Failed to send config to client: {}", e);
                                 continue;
                             }
-                            debug!("Sent device config to client ({} bytes)", config_json.len());
+                            debug!("Sent device config to client ({} bytes)", frame.len());
                         }
                         Err(e) => {
                             error!("Failed to serialize device config: {}", e);
@@ -163,10 +1016,31 @@ impl VirtualDevice {
                         }
                     }
 
-                    clients.lock().await.push(write_half);
+                    // Send current state before registering, so the reader starts in sync
+                    if !initial_events.is_empty() {
+                        let data: Vec<u8> = initial_events
+                            .iter()
+                            .flat_map(|e| e.to_linux_input_event().to_bytes())
+                            .collect();
+                        if let Err(e) = write_half.write_all(&data).await {
+                            error!("Failed to send initial state to client: {}", e);
+                            continue;
+                        }
+                        debug!("Sent initial state ({} events)", initial_events.len());
+                    }
+                    match EvdevClient::new(write_half) {
+                        Ok(client) => clients_lock.push(client),
+                        Err(e) => {
+                            error!("Failed to register client: {}", e);
+                            continue;
+                        }
+                    }
+                    drop(clients_lock);
+                    readers.fetch_add(1, Ordering::Relaxed);
 
                     // Spawn reader for feedback events
                     let feedback_clients = feedback_clients.clone();
+                    let readers = readers.clone();
                     tokio::spawn(async move {
                         let mut buf = [0u8; 24];
                         while read_half.read_exact(&mut buf).await.is_ok() {
@@ -178,25 +1052,12 @@ impl VirtualDevice {
                                     "Received feedback event: type={}, code={}, value={}",
                                     event.event_type, event.code, event.value
                                 );
-                                let mut clients = feedback_clients.lock().await;
-                                debug!("Writing to {} feedback clients", clients.len());
-                                let mut disconnected = Vec::new();
-
-                                for (idx, client) in clients.iter_mut().enumerate() {
-                                    if let Err(e) = client.write_all(&buf).await {
-                                        trace!("Failed to write to feedback client {}: {}", idx, e);
-                                        disconnected.push(idx);
-                                    } else {
-                                        debug!("Wrote feedback to client {}", idx);
-                                    }
-                                }
-
-                                // Remove disconnected clients in reverse order
-                                for idx in disconnected.iter().rev() {
-                                    clients.remove(*idx);
-                                }
+                                Self::write_feedback(&feedback_clients, &buf).await;
                             }
                         }
+
+                        // Reader hung up
+                        readers.fetch_sub(1, Ordering::Relaxed);
                     });
                 }
                 Err(e) => {
@@ -207,30 +1068,91 @@ impl VirtualDevice {
         }
     }
 
-    async fn accept_joystick_clients(
+    /// Write raw feedback events to all feedback clients, dropping disconnected ones
+    async fn write_feedback(feedback_clients: &Mutex<Vec<UnixStream>>, data: &[u8]) {
+        let mut clients = feedback_clients.lock().await;
+        debug!("Writing to {} feedback clients", clients.len());
+        let mut disconnected = Vec::new();
+
+        for (idx, client) in clients.iter_mut().enumerate() {
+            if let Err(e) = client.write_all(data).await {
+                trace!("Failed to write to feedback client {}: {}", idx, e);
+                disconnected.push(idx);
+            } else {
+                debug!("Wrote feedback to client {}", idx);
+            }
+        }
+
+        // Remove disconnected clients in reverse order
+        for idx in disconnected.iter().rev() {
+            clients.remove(*idx);
+        }
+    }
+
+    /// Deliver feedback to the feedback clients as if a reader had written it
+    pub async fn inject_feedback(&self, event: &FeedbackEvent) {
+        let data: Vec<u8> = event
+            .to_linux_input_events()
+            .iter()
+            .flat_map(|e| e.to_bytes())
+            .collect();
+        Self::write_feedback(&self.feedback_clients, &data).await;
+    }
+
+    /// Bind the joystick socket of a device and start accepting its readers
+    fn open_joystick(
         id: DeviceId,
+        base_path: &Path,
+        node_prefix: &str,
+        clients: Arc<Mutex<Vec<tokio::net::unix::OwnedWriteHalf>>>,
+        readers: Arc<AtomicUsize>,
+        handshake: watch::Receiver<DeviceHandshake>,
+    ) -> anyhow::Result<JoystickInterface> {
+        let node = joystick_node_name(node_prefix, id);
+        let socket_path = base_path.join("devices").join(&node);
+
+        // Remove old socket if exists
+        let _ = std::fs::remove_file(&socket_path);
+
+        let listener = bind_socket(&socket_path)?;
+        let (shutdown, shutdown_rx) = watch::channel(());
+        tokio::spawn(async move {
+            Self::accept_joystick_clients(listener, shutdown_rx, clients, readers, handshake).await;
+        });
+
+        info!("Created joystick node: {}", node);
+
+        Ok(JoystickInterface {
+            node,
+            socket_path,
+            _shutdown: shutdown,
+        })
+    }
+
+    async fn accept_joystick_clients(
         listener: UnixListener,
+        mut shutdown: watch::Receiver<()>,
         clients: Arc<Mutex<Vec<tokio::net::unix::OwnedWriteHalf>>>,
-        config: DeviceConfig,
+        readers: Arc<AtomicUsize>,
+        handshake: watch::Receiver<DeviceHandshake>,
     ) {
         loop {
-            match listener.accept().await {
+            let accepted = tokio::select! {
+                accepted = listener.accept() => accepted,
+                // The device or its joystick interface was dropped
+                _ = shutdown.changed() => break,
+            };
+            match accepted {
                 Ok((stream, _)) => {
                     info!("Client connected to joystick socket");
 
-                    let (_, mut write_half) = stream.into_split();
+                    let (mut read_half, mut write_half) = stream.into_split();
 
                     // Send handshake
-                    let handshake = DeviceHandshake {
-                        device_id: id,
-                        config: config.clone(),
-                    };
-                    match serde_json::to_vec(&handshake) {
-                        Ok(config_json) => {
-                            let len = config_json.len() as u32;
-                            if write_half.write_all(&len.to_le_bytes()).await.is_err()
-                                || write_half.write_all(&config_json).await.is_err()
-                            {
+                    let handshake = handshake.borrow().clone();
+                    match handshake.to_bytes() {
+                        Ok(frame) => {
+                            if write_half.write_all(&frame).await.is_err() {
                                 continue;
                             }
                         }
@@ -238,6 +1160,15 @@ impl VirtualDevice {
                     }
 
                     clients.lock().await.push(write_half);
+                    readers.fetch_add(1, Ordering::Relaxed);
+
+                    // Joystick readers never write, so a read only returns once they hang up
+                    let readers = readers.clone();
+                    tokio::spawn(async move {
+                        let mut buf = [0u8; 64];
+                        while matches!(read_half.read(&mut buf).await, Ok(n) if n > 0) {}
+                        readers.fetch_sub(1, Ordering::Relaxed);
+                    });
                 }
                 Err(e) => {
                     error!("Error accepting joystick client: {}", e);
@@ -249,6 +1180,99 @@ impl VirtualDevice {
 
     /// Send input events to all connected clients (both evdev and joystick)
     pub async fn send_events(&self, events: &[InputEvent]) -> anyhow::Result<()> {
+        *self.last_activity.lock().unwrap() = Instant::now();
+
+        // Held for the whole send, so pausing or updating the config can't interleave with it
+        let paused = self.paused.lock().await;
+        if let Some((PauseMode::Discard, _)) = *paused {
+            trace!("Discarding input for paused device {}", self.id);
+            return Ok(());
+        }
+        let Layout { config, advertised } = self.layout.read().unwrap().clone();
+
+        // Apply button remapping from quirks and the button namespace
+        let remapped: Vec<InputEvent>;
+        let events = if config.button_remap.is_empty()
+            && config.button_namespace == ButtonNamespace::Gamepad
+        {
+            events
+        } else {
+            remapped = config.emitted_events(events);
+            &remapped
+        };
+
+        let combined: Vec<InputEvent>;
+        let events = if config.combined_triggers().is_some() {
+            combined = self.combine_triggers(events, &config);
+            &combined
+        } else {
+            events
+        };
+
+        let smoothed: Vec<InputEvent>;
+        let events = if advertised.axes.iter().any(|a| a.smoothing.is_some()) {
+            smoothed = self.smooth_axes(events, &advertised);
+            &smoothed
+        } else {
+            events
+        };
+
+        let touch_buttons = advertised.touch_buttons();
+        let touched: Vec<InputEvent>;
+        let events = if !touch_buttons.is_empty() {
+            touched = self.touch.lock().unwrap().process(events, &touch_buttons);
+            &touched
+        } else {
+            events
+        };
+
+        let events = {
+            let mut state = self.state.lock().await;
+            self.filter_events(&mut state, events, &advertised)
+        };
+        if events.is_empty() || paused.is_some() {
+            return Ok(());
+        }
+
+        self.deliver_events(events).await
+    }
+
+    /// Write events to readers, coalesced and through the latency queue if configured
+    async fn deliver_events(&self, events: Vec<InputEvent>) -> anyhow::Result<()> {
+        if let Some(coalescing) = self.coalescing.lock().unwrap().as_mut() {
+            coalescing.push(&events);
+            return Ok(());
+        }
+
+        self.delay_events(events).await
+    }
+
+    /// Write events to readers, through the latency queue if one is configured
+    async fn delay_events(&self, events: Vec<InputEvent>) -> anyhow::Result<()> {
+        let events = {
+            let mut latency = self.latency.lock().unwrap();
+            match latency.as_mut() {
+                Some(latency) => {
+                    let delay_ms =
+                        latency.base_ms as i64 + latency.rng.offset(latency.jitter_ms as u64);
+                    let due =
+                        tokio::time::Instant::now() + Duration::from_millis(delay_ms.max(0) as u64);
+                    return latency
+                        .queue
+                        .send(LatencyItem::Frame(due, events))
+                        .map_err(|_| anyhow::anyhow!("Latency queue closed"));
+                }
+                None => events,
+            }
+        };
+
+        self.write_events(&events).await
+    }
+
+    /// Write events to evdev and joystick readers right away
+    async fn write_events(&self, events: &[InputEvent]) -> anyhow::Result<()> {
+        self.event_log.lock().unwrap().log_events(self.id, events);
+
         // Send to evdev clients
         self.send_evdev_events(events).await?;
 
@@ -258,34 +1282,366 @@ impl VirtualDevice {
         Ok(())
     }
 
+    /// Delay frames by `base_ms` plus a random `-jitter_ms..=jitter_ms` before they reach readers
+    ///
+    /// Frames stay in order, one delayed more than the next holds that one back.
+    /// The same `seed` reproduces the same jitter sequence.
+    pub fn set_latency(self: &Arc<Self>, base_ms: u32, jitter_ms: u32, seed: Option<u64>) {
+        let seed = seed.unwrap_or_else(|| {
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map_or(0, |d| d.as_nanos() as u64)
+        });
+
+        let mut latency = self.latency.lock().unwrap();
+        match latency.as_mut() {
+            // Keep the existing queue, so frames still in it aren't overtaken
+            Some(latency) => {
+                latency.base_ms = base_ms;
+                latency.jitter_ms = jitter_ms;
+                latency.rng = JitterRng::new(seed);
+            }
+            None => {
+                let (queue, rx) = mpsc::unbounded_channel();
+                tokio::spawn(Self::run_latency_queue(Arc::downgrade(self), rx));
+                *latency = Some(Latency {
+                    base_ms,
+                    jitter_ms,
+                    rng: JitterRng::new(seed),
+                    queue,
+                });
+            }
+        }
+        info!(
+            "Set latency of device {} to {}ms ± {}ms",
+            self.id, base_ms, jitter_ms
+        );
+    }
+
+    /// Hold input back and write it at most `max_event_hz` times a second, or stop with None
+    ///
+    /// Input held back by a previous rate is dropped, it has to be written out before.
+    fn set_max_event_hz(self: &Arc<Self>, max_event_hz: Option<u32>) {
+        let coalescing = max_event_hz.map(|max_event_hz| {
+            let (stop, stop_rx) = watch::channel(());
+            let period = Duration::from_nanos(1_000_000_000 / max_event_hz.max(1) as u64);
+            tokio::spawn(Self::run_coalescing(
+                Arc::downgrade(self),
+                period.max(Duration::from_micros(1)),
+                stop_rx,
+            ));
+            Coalescing {
+                frames: vec![Vec::new()],
+                _stop: stop,
+            }
+        });
+        *self.coalescing.lock().unwrap() = coalescing;
+    }
+
+    /// Write the input held back for `max_event_hz` on each tick, until stopped
+    async fn run_coalescing(device: Weak<Self>, period: Duration, mut stop: watch::Receiver<()>) {
+        let mut interval = tokio::time::interval(period);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {}
+                _ = stop.changed() => break,
+            }
+            let Some(device) = device.upgrade() else {
+                break;
+            };
+
+            // Sends write while holding this, so frames don't interleave
+            let _paused = device.paused.lock().await;
+            if let Err(e) = device.write_coalesced().await {
+                debug!("Failed to write coalesced events: {}", e);
+            }
+        }
+    }
+
+    /// Write the input held back for `max_event_hz` right away, the caller holds `paused`
+    async fn write_coalesced(&self) -> anyhow::Result<()> {
+        let events = match self.coalescing.lock().unwrap().as_mut() {
+            Some(coalescing) => coalescing.take(),
+            None => return Ok(()),
+        };
+        if events.is_empty() {
+            return Ok(());
+        }
+
+        self.delay_events(events).await
+    }
+
+    /// Write queued frames to readers once they're due
+    async fn run_latency_queue(device: Weak<Self>, mut rx: mpsc::UnboundedReceiver<LatencyItem>) {
+        while let Some(item) = rx.recv().await {
+            match item {
+                LatencyItem::Frame(due, events) => {
+                    tokio::time::sleep_until(due).await;
+                    let Some(device) = device.upgrade() else {
+                        break;
+                    };
+                    if let Err(e) = device.write_events(&events).await {
+                        debug!("Failed to write delayed events: {}", e);
+                    }
+                }
+                LatencyItem::Flush(done) => {
+                    let _ = done.send(());
+                }
+            }
+        }
+    }
+
+    /// Log the event summary at the end of each interval, also once input stopped
+    async fn run_event_log_summary(device: Weak<Self>, interval: Duration) {
+        let mut ticks = tokio::time::interval(interval);
+        ticks.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            ticks.tick().await;
+            let Some(device) = device.upgrade() else {
+                break;
+            };
+            device.event_log.lock().unwrap().flush_summary(device.id);
+        }
+    }
+
+    /// Wait until all events sent so far have been written to the readers' sockets
+    ///
+    /// This only waits for the writes to complete, not for readers to process the events.
+    pub async fn flush(&self) {
+        // Sends and pause resyncs write while holding this
+        let _paused = self.paused.lock().await;
+
+        // Input held back for the event rate is written now rather than on the next tick
+        if let Err(e) = self.write_coalesced().await {
+            debug!("Failed to write coalesced events: {}", e);
+        }
+
+        let queue = self
+            .latency
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map(|latency| latency.queue.clone());
+        if let Some(queue) = queue {
+            let (done, wait) = oneshot::channel();
+            if queue.send(LatencyItem::Flush(done)).is_ok() {
+                let _ = wait.await;
+            }
+        }
+    }
+
+    /// Check if the device is paused
+    pub async fn is_paused(&self) -> bool {
+        self.paused.lock().await.is_some()
+    }
+
+    /// Stop or resume forwarding input to readers
+    ///
+    /// On resume, readers are sent whatever changed while paused so they end up in sync.
+    pub async fn set_paused(&self, paused: bool, mode: PauseMode) -> anyhow::Result<()> {
+        let mut current = self.paused.lock().await;
+
+        if paused {
+            if current.is_none() {
+                let snapshot = self.state.lock().await.clone();
+                *current = Some((mode, snapshot));
+                info!("Paused device {} ({:?})", self.id, mode);
+            }
+            return Ok(());
+        }
+
+        let Some((_, snapshot)) = current.take() else {
+            return Ok(());
+        };
+        info!("Resumed device {}", self.id);
+
+        let events = self.state.lock().await.diff_events(&snapshot);
+        if !events.is_empty() {
+            debug!("Resyncing device {} ({} events)", self.id, events.len());
+            self.deliver_events(events).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Start out in `DeviceConfig::initial_state`, before any reader is let in
+    ///
+    /// Set as the state rather than sent, so smoothing starts from it instead of blending it
+    /// with the rest positions.
+    async fn apply_initial_state(&self) {
+        let Layout { config, advertised } = self.layout.read().unwrap().clone();
+        if config.initial_state.is_empty() {
+            return;
+        }
+
+        let mut events = config.emitted_events(&config.initial_state);
+        if config.combined_triggers().is_some() {
+            events = self.combine_triggers(&events, &config);
+        }
+
+        let mut state = self.state.lock().await;
+        for event in &events {
+            state.apply(event);
+        }
+        *self.delivered.lock().await = state.clone();
+
+        let mut smoothed = self.smoothed_axes.lock().unwrap();
+        for (idx, axis) in advertised.axes.iter().enumerate() {
+            if let Some(&value) = state.axes.get(&axis.axis) {
+                smoothed[idx] = value as f32;
+            }
+        }
+    }
+
+    /// Blend axis values with the previous ones, for axes with `AxisConfig::smoothing`
+    fn smooth_axes(&self, events: &[InputEvent], advertised: &DeviceConfig) -> Vec<InputEvent> {
+        let mut smoothed = self.smoothed_axes.lock().unwrap();
+        events
+            .iter()
+            .map(|event| match *event {
+                InputEvent::Axis { axis, value } => {
+                    let Some((idx, alpha)) = advertised
+                        .axes
+                        .iter()
+                        .position(|a| a.axis == axis)
+                        .and_then(|idx| Some((idx, advertised.axes[idx].smoothing?)))
+                    else {
+                        return event.clone();
+                    };
+                    smoothed[idx] = alpha * smoothed[idx] + (1.0 - alpha) * value as f32;
+                    InputEvent::Axis {
+                        axis,
+                        value: smoothed[idx].round() as i32,
+                    }
+                }
+                _ => event.clone(),
+            })
+            .collect()
+    }
+
+    /// Replace trigger events by events of the combined trigger axis
+    fn combine_triggers(&self, events: &[InputEvent], config: &DeviceConfig) -> Vec<InputEvent> {
+        let mut triggers = self.triggers.lock().unwrap();
+        events
+            .iter()
+            .map(|event| match *event {
+                InputEvent::Axis { axis, value }
+                    if matches!(axis, Axis::LowerLeftTrigger | Axis::LowerRightTrigger) =>
+                {
+                    if axis == Axis::LowerLeftTrigger {
+                        triggers.0 = value;
+                    } else {
+                        triggers.1 = value;
+                    }
+                    InputEvent::Axis {
+                        axis: Axis::LowerLeftTrigger,
+                        value: config.combined_trigger_value(triggers.0, triggers.1),
+                    }
+                }
+                ref other => other.clone(),
+            })
+            .collect()
+    }
+
+    /// Apply events to the state, dropping those the kernel would ignore
+    ///
+    /// Like the input core, events for codes the device doesn't advertise or that don't change
+    /// its state are dropped, and so is a sync whose report ends up empty, even if the report
+    /// was sent before the sync in separate calls. A sync with no events before it is kept,
+    /// the caller asked for it explicitly.
+    fn filter_events(
+        &self,
+        state: &mut DeviceState,
+        events: &[InputEvent],
+        advertised: &DeviceConfig,
+    ) -> Vec<InputEvent> {
+        let mut filtered = Vec::with_capacity(events.len());
+        let mut report = self.report.lock().unwrap();
+
+        for event in events {
+            let advertised = match event {
+                InputEvent::Button { button, .. } => advertised.has_button(*button),
+                InputEvent::Axis { axis, .. } => advertised.has_axis(*axis),
+                InputEvent::Relative { axis, .. } => advertised.has_rel_axis(*axis),
+                _ => true,
+            };
+
+            if let InputEvent::Sync = event {
+                if report.events > 0 || !report.dropped {
+                    filtered.push(event.clone());
+                } else {
+                    trace!("Suppressing empty report on device {}", self.id);
+                }
+                *report = PendingReport::default();
+            } else if advertised && state.apply(event) {
+                filtered.push(event.clone());
+                report.events += 1;
+            } else {
+                report.dropped = true;
+            }
+        }
+
+        filtered
+    }
+
     /// Send evdev events
     async fn send_evdev_events(&self, events: &[InputEvent]) -> anyhow::Result<()> {
-        let linux_events: Vec<LinuxInputEvent> =
-            events.iter().map(|e| e.to_linux_input_event()).collect();
+        let config = self.config();
+        let mut linux_events: Vec<LinuxInputEvent> = Vec::with_capacity(events.len() + 1);
+        for event in events {
+            if config.emit_sequence && matches!(event, InputEvent::Sync) {
+                // Counted per written frame, a gap on the reader side means a lost frame
+                let sequence = self.sequence.fetch_add(1, Ordering::Relaxed);
+                linux_events.push(LinuxInputEvent::new(EV_MSC, MSC_RAW, sequence as i32));
+            }
+            linux_events.push(event.to_linux_input_event());
+        }
 
-        // Convert to bytes
-        let mut data = Vec::new();
+        // Convert to bytes, split after each SYN_REPORT when frames are written separately
+        let mut writes = vec![Vec::new()];
         for event in &linux_events {
+            let data = writes.last_mut().unwrap();
             data.extend_from_slice(&event.to_bytes());
+            if config.per_frame_writes && event.event_type == EV_SYN && event.code == SYN_REPORT {
+                writes.push(Vec::new());
+            }
         }
+        writes.retain(|data| !data.is_empty());
 
         // Send to all connected evdev clients
         let mut clients = self.clients.lock().await;
         let mut disconnected = Vec::new();
 
-        for (idx, client) in clients.iter_mut().enumerate() {
-            match client.write_all(&data).await {
-                Ok(()) => {
-                    // Success
+        // Under the clients lock, so a reader accepted meanwhile gets either the state before
+        // these events and then the events, or the state after them
+        let mut delivered = self.delivered.lock().await;
+        for event in events {
+            delivered.apply(event);
+        }
+        drop(delivered);
+
+        for (i, data) in writes.iter().enumerate() {
+            if i > 0 {
+                // Let readers woken by the previous frame take it on its own
+                Self::wait_drained(&clients, self.frame_drain_timeout).await;
+            }
+
+            for (idx, client) in clients.iter_mut().enumerate() {
+                if disconnected.contains(&idx) {
+                    continue;
                 }
-                Err(e) => {
+                // A reader not keeping up misses events rather than holding up the others
+                if let Err(e) = client.write(data) {
                     trace!("Failed to write to evdev client {}: {}", idx, e);
                     disconnected.push(idx);
+                } else if client.dropped {
+                    trace!("Evdev client {} is full, dropping events", idx);
                 }
             }
         }
 
-        // Remove disconnected/slow clients (in reverse order)
+        // Remove disconnected clients (in reverse order)
         for idx in disconnected.iter().rev() {
             clients.remove(*idx);
         }
@@ -293,15 +1649,30 @@ impl VirtualDevice {
         Ok(())
     }
 
+    /// Wait until evdev readers consumed everything written to them, or `timeout`
+    ///
+    /// Readers whose socket overflowed are skipped, they aren't keeping up anyway.
+    async fn wait_drained(clients: &[EvdevClient], timeout: Duration) {
+        let deadline = tokio::time::Instant::now() + timeout;
+        for client in clients.iter().filter(|client| !client.overflowed()) {
+            // The reader taking data wakes the socket's writers, checked again after each wake
+            while client.queued() > 0 {
+                match tokio::time::timeout_at(deadline, client.drain.writable()).await {
+                    Ok(Ok(mut guard)) => guard.clear_ready(),
+                    Ok(Err(_)) => break,
+                    Err(_) => return,
+                }
+            }
+        }
+    }
+
     /// Send joystick events
     async fn send_joystick_events(&self, events: &[InputEvent]) -> anyhow::Result<()> {
-        if self.joystick_node.is_none() {
+        if self.joystick.lock().unwrap().is_none() {
             return Ok(());
         }
 
-        const JS_EVENT_BUTTON: u8 = 0x01;
-        const JS_EVENT_AXIS: u8 = 0x02;
-
+        let advertised = self.advertised();
         let mut js_events = Vec::new();
         let time = std::time::SystemTime::now()
             .duration_since(std::time::UNIX_EPOCH)?
@@ -311,7 +1682,7 @@ impl VirtualDevice {
             match event {
                 InputEvent::Button { button, pressed } => {
                     // Find button index in config
-                    if let Some(button_idx) = self.config.buttons.iter().position(|b| b == button) {
+                    if let Some(button_idx) = advertised.buttons.iter().position(|b| b == button) {
                         js_events.push(LinuxJsEvent {
                             time,
                             value: if *pressed { 1 } else { 0 },
@@ -321,10 +1692,22 @@ impl VirtualDevice {
                     }
                 }
                 InputEvent::Axis { axis, value } => {
-                    if let Some(axis_idx) = self.config.axes.iter().position(|a| a.axis == *axis) {
+                    if let Some(axis_idx) = advertised.axes.iter().position(|a| a.axis == *axis) {
+                        // The kernel defuzzes before joydev sees the value
+                        let value = {
+                            let mut last = self.joystick_axes.lock().unwrap();
+                            let fuzz = advertised.axes[axis_idx].fuzz;
+                            let value = defuzz(last[axis_idx], *value, fuzz);
+                            if value == last[axis_idx] {
+                                continue;
+                            }
+                            last[axis_idx] = value;
+                            value
+                        };
+
                         // Clamp the i32 value to i16 range BEFORE casting
-                        let clamped_value = value.clamp(&(i16::MIN as i32), &(i16::MAX as i32));
-                        let normalized_value = *clamped_value as i16;
+                        let clamped_value = value.clamp(i16::MIN as i32, i16::MAX as i32);
+                        let normalized_value = clamped_value as i16;
                         js_events.push(LinuxJsEvent {
                             time,
                             value: normalized_value,
@@ -374,19 +1757,37 @@ impl Drop for VirtualDevice {
         // Clean up socket file
         let _ = std::fs::remove_file(&self.socket_path);
 
-        // Clean up joystick socket
-        if let Some(js_socket) = &self.joystick_socket_path {
-            let _ = std::fs::remove_file(js_socket);
-        }
-
         // Clean up feedback socket
         if let Some(feedback_socket) = &self.feedback_socket_path {
             let _ = std::fs::remove_file(feedback_socket);
         }
 
         // Clean up sysfs files
-        let _ = SysfsGenerator::remove_device_files(self.id, &self.base_path);
+        let _ = SysfsGenerator::remove_device_files(self.id, &self.node_prefix, &self.base_path);
 
         info!("Device {} cleaned up", self.event_node);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn draining_skips_readers_with_a_cut_off_event() {
+        let (writer, _stalled) = tokio::net::UnixStream::pair().unwrap();
+        let (_, writer) = writer.into_split();
+        let mut client = EvdevClient::new(writer).unwrap();
+        client.writer.writable().await.unwrap();
+        client.write(&[0; 48]).unwrap();
+        assert!(client.queued() > 0);
+
+        // The rest of an event a full socket only took part of
+        client.partial = vec![0; 8];
+        let clients = [client];
+        let drained = VirtualDevice::wait_drained(&clients, Duration::from_secs(60));
+        tokio::time::timeout(Duration::from_secs(1), drained)
+            .await
+            .expect("waited for an overflowed reader");
+    }
+}
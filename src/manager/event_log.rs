@@ -0,0 +1,234 @@
+use crate::protocol::*;
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+use tracing::trace;
+
+/// Tracing target of the per-frame event log
+pub const EVENT_LOG_TARGET: &str = "vimputti::events";
+
+/// How frames written to device readers are traced
+///
+/// Frames are logged at trace level under `EVENT_LOG_TARGET`, e.g. with
+/// `RUST_LOG=vimputti::events=trace`. The options keep high-rate devices from flooding the log.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct EventLogConfig {
+    /// Log only every Nth frame (0 and 1 log every frame)
+    pub sample_every: u32,
+    /// Log at most this many frames per second
+    pub max_per_second: Option<u32>,
+    /// Log event counts per type once per interval instead of individual frames
+    pub summary_interval: Option<Duration>,
+}
+
+/// Per-device rate limiter for the event log
+pub(crate) struct EventLogger {
+    config: EventLogConfig,
+    /// Frames seen, for sampling
+    frames: u64,
+    /// Events of the frame being written, until its sync
+    pending: Vec<InputEvent>,
+    /// Start of the current one second window and frames logged in it
+    window_start: Instant,
+    window_logged: u32,
+    /// Start of the current summary interval, and frames and events per type seen in it
+    summary_start: Instant,
+    summary_frames: u64,
+    summary_counts: BTreeMap<&'static str, u64>,
+}
+impl EventLogger {
+    pub fn new(config: EventLogConfig) -> Self {
+        let now = Instant::now();
+        Self {
+            config,
+            frames: 0,
+            pending: Vec::new(),
+            window_start: now,
+            window_logged: 0,
+            summary_start: now,
+            summary_frames: 0,
+            summary_counts: BTreeMap::new(),
+        }
+    }
+
+    pub fn config(&self) -> EventLogConfig {
+        self.config
+    }
+
+    /// Log events written to the readers of a device, if tracing is enabled
+    pub fn log_events(&mut self, device_id: DeviceId, events: &[InputEvent]) {
+        if tracing::enabled!(target: EVENT_LOG_TARGET, tracing::Level::TRACE) {
+            self.record(device_id, events);
+        }
+    }
+
+    /// Count events and log the frames they complete
+    ///
+    /// A frame ends at its sync, and may have been written over several calls.
+    fn record(&mut self, device_id: DeviceId, events: &[InputEvent]) {
+        let summarize = self.config.summary_interval.is_some();
+        for event in events {
+            let ends_frame = matches!(event, InputEvent::Sync);
+            if summarize {
+                let name = event_type_name(event.to_linux_input_event().event_type);
+                *self.summary_counts.entry(name).or_default() += 1;
+                if ends_frame {
+                    self.summary_frames += 1;
+                }
+                continue;
+            }
+
+            self.pending.push(event.clone());
+            if ends_frame {
+                let frame = std::mem::take(&mut self.pending);
+                if self.should_log() {
+                    trace!(
+                        target: EVENT_LOG_TARGET,
+                        "Device {} frame {}: {:?}",
+                        device_id,
+                        self.frames,
+                        frame
+                    );
+                }
+            }
+        }
+
+        if summarize {
+            self.flush_summary(device_id);
+        }
+    }
+
+    /// Decide whether the current frame is logged, counting it
+    fn should_log(&mut self) -> bool {
+        self.frames += 1;
+        if self.config.sample_every > 1 && self.frames % self.config.sample_every as u64 != 1 {
+            return false;
+        }
+
+        if let Some(max) = self.config.max_per_second {
+            let now = Instant::now();
+            if now.duration_since(self.window_start) >= Duration::from_secs(1) {
+                self.window_start = now;
+                self.window_logged = 0;
+            }
+            if self.window_logged >= max {
+                return false;
+            }
+            self.window_logged += 1;
+        }
+
+        true
+    }
+
+    /// Log the counts of the summary interval once it's over, see `summary_interval`
+    ///
+    /// Also called on a timer, so the last interval before input stops is logged.
+    pub fn flush_summary(&mut self, device_id: DeviceId) {
+        let Some(interval) = self.config.summary_interval else {
+            return;
+        };
+        let now = Instant::now();
+        if now.duration_since(self.summary_start) < interval {
+            return;
+        }
+
+        if !self.summary_counts.is_empty() {
+            trace!(
+                target: EVENT_LOG_TARGET,
+                "Device {}: {} frames in {:?}, events {:?}",
+                device_id,
+                self.summary_frames,
+                now.duration_since(self.summary_start),
+                self.summary_counts
+            );
+        }
+        self.summary_start = now;
+        self.summary_frames = 0;
+        self.summary_counts.clear();
+    }
+}
+
+fn event_type_name(event_type: u16) -> &'static str {
+    match event_type {
+        EV_SYN => "SYN",
+        EV_KEY => "KEY",
+        EV_REL => "REL",
+        EV_ABS => "ABS",
+        EV_MSC => "MSC",
+        EV_FF => "FF",
+        _ => "OTHER",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn logged_of(logger: &mut EventLogger, frames: usize) -> usize {
+        (0..frames).filter(|_| logger.should_log()).count()
+    }
+
+    #[test]
+    fn sampling_logs_one_in_n_frames() {
+        let mut logger = EventLogger::new(EventLogConfig {
+            sample_every: 100,
+            ..Default::default()
+        });
+        assert_eq!(logged_of(&mut logger, 10_000), 100);
+
+        let mut logger = EventLogger::new(EventLogConfig::default());
+        assert_eq!(logged_of(&mut logger, 10), 10);
+    }
+
+    #[test]
+    fn frames_end_at_their_sync() {
+        let axis = InputEvent::Axis {
+            axis: Axis::LeftStickX,
+            value: 1,
+        };
+        let mut logger = EventLogger::new(EventLogConfig::default());
+        logger.record(0, std::slice::from_ref(&axis));
+        logger.record(0, &[axis.clone(), InputEvent::Sync, axis.clone()]);
+        assert_eq!(logger.frames, 1);
+        logger.record(0, &[InputEvent::Sync, axis.clone(), InputEvent::Sync]);
+        assert_eq!(logger.frames, 3);
+
+        let mut logger = EventLogger::new(EventLogConfig {
+            summary_interval: Some(Duration::from_secs(60)),
+            ..Default::default()
+        });
+        logger.record(0, std::slice::from_ref(&axis));
+        logger.record(0, &[InputEvent::Sync, axis, InputEvent::Sync]);
+        assert_eq!(logger.summary_frames, 2);
+        assert_eq!(logger.summary_counts["ABS"], 2);
+    }
+
+    #[test]
+    fn summary_is_flushed_once_its_interval_is_over() {
+        let mut logger = EventLogger::new(EventLogConfig {
+            summary_interval: Some(Duration::from_secs(60)),
+            ..Default::default()
+        });
+        logger.record(0, &[InputEvent::Sync]);
+
+        logger.flush_summary(0);
+        assert_eq!(logger.summary_frames, 1);
+
+        logger.summary_start -= Duration::from_secs(60);
+        logger.flush_summary(0);
+        assert_eq!(logger.summary_frames, 0);
+        assert!(logger.summary_counts.is_empty());
+    }
+
+    #[test]
+    fn rate_cap_limits_frames_per_second() {
+        let mut logger = EventLogger::new(EventLogConfig {
+            max_per_second: Some(5),
+            ..Default::default()
+        });
+        assert_eq!(logged_of(&mut logger, 1000), 5);
+
+        // A new window starts after a second
+        logger.window_start -= Duration::from_secs(1);
+        assert_eq!(logged_of(&mut logger, 1000), 5);
+    }
+}
@@ -0,0 +1,116 @@
+//! Hooks run when devices are added and removed
+//!
+//! Callbacks are registered in-process with `Manager::on_device_event`. Clients can also set a
+//! program to run with `SetHook`, which needs the `hooks` feature: any client that can reach
+//! the control socket can then run programs as the user of the manager, so only enable it
+//! where the socket is limited to trusted users.
+
+use crate::protocol::*;
+use std::collections::HashMap;
+use std::sync::Arc;
+#[cfg(feature = "hooks")]
+use tracing::{info, warn};
+
+/// Device added or removed, as passed to hooks
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceEvent {
+    pub event: HookEvent,
+    pub device_id: DeviceId,
+    /// Event node name, e.g. `event0`
+    pub event_node: String,
+    /// Device name
+    pub name: String,
+}
+
+/// Callback registered with `Manager::on_device_event`
+pub type DeviceEventCallback = Arc<dyn Fn(&DeviceEvent) + Send + Sync>;
+
+/// Callbacks and programs to run on device events
+#[derive(Default)]
+pub(crate) struct Hooks {
+    callbacks: Vec<DeviceEventCallback>,
+    commands: HashMap<HookEvent, Vec<String>>,
+}
+impl Hooks {
+    pub fn add_callback(&mut self, callback: DeviceEventCallback) {
+        self.callbacks.push(callback);
+    }
+
+    /// Set the program run on `event`, or clear it with `None`
+    ///
+    /// Fails if the manager was built without the `hooks` feature.
+    pub fn set_command(
+        &mut self,
+        event: HookEvent,
+        command: Option<Vec<String>>,
+    ) -> Result<(), String> {
+        if !cfg!(feature = "hooks") {
+            return Err("Hook programs aren't enabled in this manager".to_string());
+        }
+
+        match command {
+            Some(command) if command.first().is_none_or(|program| program.is_empty()) => {
+                Err("Hook command has no program".to_string())
+            }
+            Some(command) => {
+                self.commands.insert(event, command);
+                Ok(())
+            }
+            None => {
+                self.commands.remove(&event);
+                Ok(())
+            }
+        }
+    }
+
+    /// What to run for an event, taken out so the hooks aren't locked while they run
+    pub fn for_event(&self, event: HookEvent) -> (Vec<DeviceEventCallback>, Option<Vec<String>>) {
+        (self.callbacks.clone(), self.commands.get(&event).cloned())
+    }
+}
+
+/// Call the callbacks and start the program of an event
+///
+/// The program isn't waited for, its exit is only logged.
+pub(crate) fn run(
+    event: &DeviceEvent,
+    callbacks: &[DeviceEventCallback],
+    command: Option<Vec<String>>,
+) {
+    for callback in callbacks {
+        callback(event);
+    }
+
+    if let Some(command) = command {
+        spawn_command(event, command);
+    }
+}
+
+#[cfg(feature = "hooks")]
+fn spawn_command(event: &DeviceEvent, command: Vec<String>) {
+    let child = tokio::process::Command::new(&command[0])
+        .args(&command[1..])
+        .env("VIMPUTTI_DEVICE_ID", event.device_id.to_string())
+        .env("VIMPUTTI_EVENT_NODE", &event.event_node)
+        .env("VIMPUTTI_DEVICE_NAME", &event.name)
+        .stdin(std::process::Stdio::null())
+        .spawn();
+
+    match child {
+        Ok(mut child) => {
+            info!("Running {:?} hook {:?}", event.event, command);
+            tokio::spawn(async move {
+                match child.wait().await {
+                    Ok(status) if status.success() => {}
+                    Ok(status) => warn!("Hook {:?} exited with {}", command, status),
+                    Err(e) => warn!("Failed to wait for hook {:?}: {}", command, e),
+                }
+            });
+        }
+        Err(e) => warn!("Failed to run hook {:?}: {}", command, e),
+    }
+}
+
+/// Without the feature no program can be set, see `Hooks::set_command`
+#[cfg(not(feature = "hooks"))]
+fn spawn_command(_event: &DeviceEvent, _command: Vec<String>) {}
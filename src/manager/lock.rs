@@ -31,6 +31,28 @@ impl LockFile {
 
         Ok(Self { _file: file })
     }
+
+    /// Check if a manager holds the lock at `path`
+    ///
+    /// A lock file left behind by a crashed manager isn't held, its lock went with the process.
+    pub fn is_held(path: &Path) -> bool {
+        let Ok(file) = File::open(path) else {
+            return false;
+        };
+
+        #[cfg(unix)]
+        {
+            use libc::{LOCK_NB, LOCK_SH, flock};
+            // Taking a shared lock fails while the manager holds its exclusive one, and is
+            // released again as the file is closed
+            unsafe { flock(file.as_raw_fd(), LOCK_SH | LOCK_NB) != 0 }
+        }
+        #[cfg(not(unix))]
+        {
+            drop(file);
+            true
+        }
+    }
 }
 impl Drop for LockFile {
     fn drop(&mut self) {
@@ -1,66 +1,636 @@
 use crate::protocol::*;
-use std::collections::HashMap;
+use crate::quirks::QuirkTable;
+use std::collections::{HashMap, HashSet};
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
 use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
 use tokio::net::{UnixListener, UnixStream};
 use tokio::sync::Mutex;
 use tracing::{debug, error, info, trace, warn};
 
 mod device;
+mod event_log;
+mod hooks;
 mod lock;
 mod netlink;
 mod sysfs;
-mod udev;
+pub mod udev;
 mod uinput;
 
 use crate::manager::netlink::NetlinkBroadcaster;
-pub use device::VirtualDevice;
+pub use device::{DeviceState, VirtualDevice};
+pub use event_log::{EVENT_LOG_TARGET, EventLogConfig};
+pub use hooks::{DeviceEvent, DeviceEventCallback};
 pub use lock::LockFile;
 pub use sysfs::SysfsGenerator;
 pub use udev::UdevBroadcaster;
 pub use uinput::UinputEmulator;
 
-pub struct Manager {
+/// Highest device ID a client may ask for with `CreateDevice`
+///
+/// IDs skipped over by a requested one are kept for reuse, so this bounds that pool.
+pub const MAX_DEVICE_ID: DeviceId = 4095;
+
+/// Tunable manager behaviour
+#[derive(Debug, Clone)]
+pub struct ManagerConfig {
+    /// Maximum number of devices that may exist at once
+    pub max_devices: usize,
+    /// Maximum number of events a single `SendInput` or `SendNamed` may carry
+    ///
+    /// Larger batches are rejected before any of their events are sent, so one command can't
+    /// stall the manager for everyone else.
+    pub max_batch_events: usize,
+    /// Destroy devices that have had no connected readers and no input for this long
+    pub idle_timeout: Option<Duration>,
+    /// Compatibility quirks for devices created with `apply_quirks`
+    pub quirks: QuirkTable,
+    /// Sampling of the per-frame event trace log
+    pub event_log: EventLogConfig,
+    /// Prefix of the device node names, e.g. `b` for `eventb0`, see `event_node_name`
+    ///
+    /// Managers whose `/dev/input` trees are merged, e.g. when nested, need distinct prefixes.
+    pub node_prefix: String,
+    /// Create uinput mirrors with the name and ids of the device they mirror
+    ///
+    /// By default mirrors present the ids their uinput consumer (e.g. Steam Input) set up.
+    pub mirror_source_ids: bool,
+    /// How long a frame written with `DeviceConfig::per_frame_writes` may stay unread before
+    /// the next one is written anyway
+    pub frame_drain_timeout: Duration,
+}
+impl Default for ManagerConfig {
+    fn default() -> Self {
+        Self {
+            max_devices: 64,
+            max_batch_events: 4096,
+            idle_timeout: None,
+            quirks: QuirkTable::builtin(),
+            event_log: EventLogConfig::default(),
+            node_prefix: String::new(),
+            mirror_source_ids: false,
+            frame_drain_timeout: Duration::from_millis(2),
+        }
+    }
+}
+
+/// State shared between the manager and its client handlers
+struct ManagerContext {
+    /// Manager configuration
+    config: ManagerConfig,
     /// Base directory for all vimputti files
     base_path: PathBuf,
     /// Socket path for control commands
-    control_socket_path: PathBuf,
-    /// Lock file to prevent multiple managers with same instance
-    _lock_file: LockFile,
+    socket_path: PathBuf,
     /// Registry of active virtual devices
     devices: Arc<Mutex<HashMap<DeviceId, Arc<VirtualDevice>>>>,
     /// Next device ID to assign
     next_device_id: Arc<Mutex<DeviceId>>,
     /// Pool of device IDs available for reuse
     free_device_ids: Arc<Mutex<Vec<DeviceId>>>,
+    /// Devices being created, counted against `max_devices`, see `reserve_slot`
+    pending_devices: AtomicUsize,
+    /// IDs of devices being created, see `reserve_id`
+    pending_ids: std::sync::Mutex<HashSet<DeviceId>>,
+    /// Devices registered by `EnsureDevice`, by key
+    device_keys: Mutex<HashMap<String, DeviceId>>,
     /// udev event broadcaster
     udev_broadcaster: Arc<UdevBroadcaster>,
     /// netlink event broadcaster
     netlink_broadcaster: Arc<NetlinkBroadcaster>,
     /// uinput emulator
     uinput_emulator: Arc<UinputEmulator>,
+    /// Callbacks and programs run when devices are added and removed
+    hooks: std::sync::Mutex<hooks::Hooks>,
+}
+
+/// A device slot reserved with `ManagerContext::reserve_slot`, released on drop
+struct SlotReservation<'a> {
+    pending: &'a AtomicUsize,
+}
+impl Drop for SlotReservation<'_> {
+    fn drop(&mut self) {
+        self.pending.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// A device ID reserved with `ManagerContext::reserve_id`, released on drop
+struct IdReservation<'a> {
+    pending: &'a std::sync::Mutex<HashSet<DeviceId>>,
+    id: DeviceId,
+}
+impl Drop for IdReservation<'_> {
+    fn drop(&mut self) {
+        self.pending.lock().unwrap().remove(&self.id);
+    }
+}
+
+impl ManagerContext {
+    /// Remove a device, recycle its ID and broadcast its removal
+    ///
+    /// Returns false if the device doesn't exist.
+    async fn destroy_device(&self, device_id: DeviceId) -> bool {
+        self.device_keys
+            .lock()
+            .await
+            .retain(|_, &mut id| id != device_id);
+
+        self.remove_device(device_id).await
+    }
+
+    /// Remove a device without touching its `EnsureDevice` key, see `destroy_device`
+    async fn remove_device(&self, device_id: DeviceId) -> bool {
+        let (device, config) = {
+            let mut devices = self.devices.lock().await;
+            let Some(device) = devices.remove(&device_id) else {
+                return false;
+            };
+            // Under the registry lock, see `UdevBroadcaster::run`
+            let config = device.current_config();
+            self.broadcast_remove(device_id, &config);
+            (device, config)
+        };
+        info!("Destroyed device {}", device_id);
+
+        // Add the ID to the re-usable pool
+        self.free_device_ids.lock().await.push(device_id);
+        debug!("Marking device ID {} as re-usable", device_id);
+
+        self.run_hooks(
+            HookEvent::DeviceRemoved,
+            device_id,
+            &device.event_node,
+            &config,
+        );
+
+        true
+    }
+
+    /// Run the hooks of a device event
+    fn run_hooks(
+        &self,
+        event: HookEvent,
+        device_id: DeviceId,
+        event_node: &str,
+        config: &DeviceConfig,
+    ) {
+        let (callbacks, command) = self.hooks.lock().unwrap().for_event(event);
+        let event = hooks::DeviceEvent {
+            event,
+            device_id,
+            event_node: event_node.to_string(),
+            name: config.name.clone(),
+        };
+        hooks::run(&event, &callbacks, command);
+    }
+
+    /// Broadcast udev and netlink add events of a device
+    fn broadcast_add(&self, device_id: DeviceId, config: &DeviceConfig) {
+        if let Err(e) = self.udev_broadcaster.broadcast_add(device_id, config) {
+            debug!("Failed to broadcast udev add event: {}", e);
+        }
+
+        // Also broadcast via real netlink
+        if let Err(e) = self.netlink_broadcaster.broadcast_add(device_id, config) {
+            debug!("Failed to broadcast netlink add event: {}", e);
+        }
+    }
+
+    /// Broadcast udev and netlink remove events of a device
+    fn broadcast_remove(&self, device_id: DeviceId, config: &DeviceConfig) {
+        if let Err(e) = self.udev_broadcaster.broadcast_remove(device_id, config) {
+            debug!("Failed to broadcast udev remove event: {}", e);
+        }
+
+        // Also broadcast via real netlink
+        if let Err(e) = self.netlink_broadcaster.broadcast_remove(device_id, config) {
+            debug!("Failed to broadcast netlink remove event: {}", e);
+        }
+    }
+
+    /// Return the device registered under a key, creating it if there is none
+    async fn ensure_device(
+        &self,
+        key: String,
+        config: DeviceConfig,
+        on_mismatch: EnsureMismatch,
+    ) -> ControlResult {
+        // Held throughout, so concurrent requests for a key end up with a single device
+        let mut device_keys = self.device_keys.lock().await;
+        if let Some(&device_id) = device_keys.get(&key) {
+            let existing = self.devices.lock().await.get(&device_id).cloned();
+            if let Some(device) = existing {
+                // Compared after quirks, as that's what the existing device was created with
+                let mut requested = config.clone();
+                self.config.quirks.apply(&mut requested);
+
+                if *device.config() == requested {
+                    debug!("Device {} already exists as {}", key, device_id);
+                    return ControlResult::DeviceEnsured {
+                        device_id,
+                        event_node: device.event_node.clone(),
+                        created: false,
+                        config: Some(Box::new(requested)),
+                    };
+                }
+
+                if on_mismatch == EnsureMismatch::Error {
+                    return ControlResult::Error {
+                        code: ErrorCode::AlreadyExists,
+                        message: format!(
+                            "Device {} exists as {} with a different config",
+                            key, device_id
+                        ),
+                    };
+                }
+
+                info!(
+                    "Recreating device {} ({}) with a new config",
+                    key, device_id
+                );
+                drop(device);
+                device_keys.remove(&key);
+                self.remove_device(device_id).await;
+            }
+        }
+
+        match self.create_device(config, None).await {
+            ControlResult::DeviceCreated {
+                device_id,
+                event_node,
+                config,
+            } => {
+                device_keys.insert(key, device_id);
+                ControlResult::DeviceEnsured {
+                    device_id,
+                    event_node,
+                    created: true,
+                    config,
+                }
+            }
+            other => other,
+        }
+    }
+
+    /// Broadcast udev and netlink change events of a device
+    fn broadcast_change(&self, device_id: DeviceId, config: &DeviceConfig) {
+        if let Err(e) = self.udev_broadcaster.broadcast_change(device_id, config) {
+            debug!("Failed to broadcast udev change event: {}", e);
+        }
+
+        // Also broadcast via real netlink
+        if let Err(e) = self.netlink_broadcaster.broadcast_change(device_id, config) {
+            debug!("Failed to broadcast netlink change event: {}", e);
+        }
+    }
+
+    /// Broadcast the joystick node of a device being added or removed on its own
+    fn broadcast_joystick(
+        &self,
+        action: udev::UdevAction,
+        device_id: DeviceId,
+        config: &DeviceConfig,
+    ) {
+        if let Err(e) = self
+            .udev_broadcaster
+            .broadcast_joystick(action, device_id, config)
+        {
+            debug!("Failed to broadcast udev joystick event: {}", e);
+        }
+
+        // Also broadcast via real netlink
+        if let Err(e) = self
+            .netlink_broadcaster
+            .broadcast_joystick(action, device_id, config)
+        {
+            debug!("Failed to broadcast netlink joystick event: {}", e);
+        }
+    }
+
+    /// Error for input batches over `ManagerConfig::max_batch_events`
+    fn check_batch_size(&self, len: usize) -> Option<ControlResult> {
+        (len > self.config.max_batch_events).then(|| ControlResult::Error {
+            code: ErrorCode::InvalidInput,
+            message: format!(
+                "Batch of {} events exceeds the limit of {}",
+                len, self.config.max_batch_events
+            ),
+        })
+    }
+
+    /// Look up a device, or the error for a missing one
+    async fn device(&self, device_id: DeviceId) -> Result<Arc<VirtualDevice>, ControlResult> {
+        self.devices
+            .lock()
+            .await
+            .get(&device_id)
+            .cloned()
+            .ok_or_else(|| ControlResult::Error {
+                code: ErrorCode::NotFound,
+                message: format!("Device {} not found", device_id),
+            })
+    }
+
+    /// Send input to a device and mirror it to uinput
+    ///
+    /// `events` builds the input from the device's config, its error is returned as
+    /// invalid input.
+    async fn send_to_device(
+        &self,
+        device_id: DeviceId,
+        events: impl FnOnce(&DeviceConfig) -> Result<Vec<InputEvent>, String>,
+    ) -> ControlResult {
+        let device = match self.device(device_id).await {
+            Ok(device) => device,
+            Err(error) => return error,
+        };
+
+        let config = device.config();
+        if config.passive {
+            return ControlResult::Error {
+                code: ErrorCode::InvalidInput,
+                message: format!("Device {} is passive and takes no input", device_id),
+            };
+        }
+        let events = match events(&config) {
+            Ok(events) => events,
+            Err(message) => {
+                return ControlResult::Error {
+                    code: ErrorCode::InvalidInput,
+                    message,
+                };
+            }
+        };
+
+        let send_result = device.send_events(&events).await;
+
+        // Also mirror to uinput devices if any
+        if !device.is_paused().await {
+            let _ = self
+                .uinput_emulator
+                .mirror_to_uinput_devices(&device, &events)
+                .await;
+        }
+
+        match send_result {
+            Ok(()) => ControlResult::InputSent,
+            Err(e) => ControlResult::Error {
+                code: ErrorCode::Internal,
+                message: format!("Failed to send input: {}", e),
+            },
+        }
+    }
+
+    /// Create a device, at the requested ID if given
+    async fn create_device(
+        &self,
+        mut config: DeviceConfig,
+        requested_id: Option<DeviceId>,
+    ) -> ControlResult {
+        self.config.quirks.apply(&mut config);
+
+        if let Err(message) = config.validate() {
+            return ControlResult::Error {
+                code: ErrorCode::InvalidInput,
+                message,
+            };
+        }
+        // Applied at once, like a batch of input
+        if let Some(error) = self.check_batch_size(config.initial_state.len()) {
+            return error;
+        }
+
+        let slot = match self.reserve_slot().await {
+            Ok(slot) => slot,
+            Err(error) => return error,
+        };
+
+        let reservation = match self.reserve_id(requested_id).await {
+            Ok(reservation) => reservation,
+            Err(error) => return error,
+        };
+        let device_id = reservation.id;
+
+        debug!(
+            "Creating device {} with config: name={}, vendor_id=0x{:04x}, product_id=0x{:04x}",
+            device_id, config.name, config.vendor_id, config.product_id
+        );
+        match VirtualDevice::create(
+            device_id,
+            config.clone(),
+            &self.base_path,
+            &self.config.node_prefix,
+            self.config.event_log,
+            self.config.frame_drain_timeout,
+        )
+        .await
+        {
+            Ok(device) => {
+                let event_node = device.event_node.clone();
+                let created_config = device.config();
+                {
+                    let mut devices = self.devices.lock().await;
+                    devices.insert(device_id, device);
+                    // Released while still holding the lock, so the slot is never counted twice
+                    // and the ID is never free to take in between
+                    drop(slot);
+                    drop(reservation);
+                    // Broadcast add events (after device is ready), under the registry lock
+                    // like removals, see `UdevBroadcaster::run`
+                    self.broadcast_add(device_id, &config);
+                }
+
+                info!("Created device {} as {}", device_id, event_node);
+
+                self.run_hooks(HookEvent::DeviceAdded, device_id, &event_node, &config);
+
+                ControlResult::DeviceCreated {
+                    device_id,
+                    event_node,
+                    config: Some(Box::new((*created_config).clone())),
+                }
+            }
+            Err(e) => {
+                // Nothing of the device is left behind, so its ID can be handed out again
+                self.free_device_ids.lock().await.push(device_id);
+                drop(reservation);
+
+                ControlResult::Error {
+                    code: ErrorCode::Internal,
+                    message: format!("Failed to create device: {:#}", e),
+                }
+            }
+        }
+    }
+
+    /// Reserve a slot for a new device under the `max_devices` limit
+    ///
+    /// Checked under the registry lock, counting creations still in progress, so concurrent
+    /// creations can't overshoot the limit. Insert the device under the same lock before
+    /// dropping the reservation.
+    async fn reserve_slot(&self) -> Result<SlotReservation<'_>, ControlResult> {
+        let devices = self.devices.lock().await;
+        let taken = devices.len() + self.pending_devices.load(Ordering::Relaxed);
+        if taken >= self.config.max_devices {
+            return Err(ControlResult::Error {
+                code: ErrorCode::LimitExceeded,
+                message: format!(
+                    "Device limit reached ({} of {})",
+                    taken, self.config.max_devices
+                ),
+            });
+        }
+
+        self.pending_devices.fetch_add(1, Ordering::Relaxed);
+        Ok(SlotReservation {
+            pending: &self.pending_devices,
+        })
+    }
+
+    /// Pick the ID of a new device and reserve it until the device is inserted
+    ///
+    /// A requested ID must be at most `MAX_DEVICE_ID` and neither exist nor be in creation,
+    /// automatic IDs are reused from the free pool first. Insert the device under the registry
+    /// lock before dropping the reservation.
+    async fn reserve_id(
+        &self,
+        requested_id: Option<DeviceId>,
+    ) -> Result<IdReservation<'_>, ControlResult> {
+        let mut free_ids = self.free_device_ids.lock().await;
+        let mut next_id = self.next_device_id.lock().await;
+        let id = match requested_id {
+            Some(id) => {
+                if id > MAX_DEVICE_ID {
+                    return Err(ControlResult::Error {
+                        code: ErrorCode::InvalidInput,
+                        message: format!(
+                            "Device ID {} is above the highest one ({})",
+                            id, MAX_DEVICE_ID
+                        ),
+                    });
+                }
+                let taken = self.devices.lock().await.contains_key(&id)
+                    || self.pending_ids.lock().unwrap().contains(&id);
+                if taken {
+                    return Err(ControlResult::Error {
+                        code: ErrorCode::AlreadyExists,
+                        message: format!("Device {} already exists", id),
+                    });
+                }
+
+                // Keep automatic IDs from handing out this one, IDs skipped over become free
+                free_ids.retain(|&free| free != id);
+                if id >= *next_id {
+                    free_ids.extend(*next_id..id);
+                    *next_id = id + 1;
+                }
+                debug!("Using requested device ID: {}", id);
+                id
+            }
+            None => {
+                // Try to reuse an ID first, otherwise next
+                if let Some(id) = free_ids.pop() {
+                    debug!("Re-using device ID: {}", id);
+                    id
+                } else {
+                    let id = *next_id;
+                    *next_id += 1;
+                    debug!("Using next device ID: {}", id);
+                    id
+                }
+            }
+        };
+
+        self.pending_ids.lock().unwrap().insert(id);
+        Ok(IdReservation {
+            pending: &self.pending_ids,
+            id,
+        })
+    }
+
+    /// Swap the config of a device, see `VirtualDevice::update_config`
+    async fn update_device(&self, device_id: DeviceId, mut config: DeviceConfig) -> ControlResult {
+        self.config.quirks.apply(&mut config);
+
+        if let Err(message) = config.validate() {
+            return ControlResult::Error {
+                code: ErrorCode::InvalidInput,
+                message,
+            };
+        }
+
+        let device = match self.device(device_id).await {
+            Ok(device) => device,
+            Err(error) => return error,
+        };
+
+        match device.update_config(config).await {
+            Ok(joystick_removed) => {
+                let config = device.current_config();
+                if joystick_removed {
+                    self.broadcast_joystick(udev::UdevAction::Remove, device_id, &config);
+                }
+                self.broadcast_change(device_id, &config);
+                ControlResult::DeviceUpdated
+            }
+            Err(e) => ControlResult::Error {
+                code: ErrorCode::Internal,
+                message: format!("Failed to update device: {:#}", e),
+            },
+        }
+    }
+}
+
+pub struct Manager {
+    /// Socket path for control commands
+    control_socket_path: PathBuf,
+    /// Lock file to prevent multiple managers with same instance
+    ///
+    /// Not needed for abstract sockets, binding one that's in use fails.
+    _lock_file: Option<LockFile>,
+    /// State shared with client handlers
+    context: Arc<ManagerContext>,
 }
 impl Manager {
-    /// Create a new manager instance
+    /// Create a new manager instance with default configuration
     pub fn new(socket_path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        Self::with_config(socket_path, ManagerConfig::default())
+    }
+
+    /// Create a new manager instance
+    pub fn with_config(
+        socket_path: impl AsRef<Path>,
+        config: ManagerConfig,
+    ) -> anyhow::Result<Self> {
         let socket_path = socket_path.as_ref();
-        let base_path = socket_path.parent().unwrap().join("vimputti");
+        let base_path = base_path_for_socket(socket_path);
+
+        if !valid_node_prefix(&config.node_prefix) {
+            anyhow::bail!(
+                "Invalid node prefix {:?}, only ASCII letters, '-' and '_' are allowed",
+                config.node_prefix
+            );
+        }
 
         // Create base directory structure
         std::fs::create_dir_all(&base_path)?;
         std::fs::create_dir_all(base_path.join("devices"))?;
         std::fs::create_dir_all(base_path.join("sysfs/class/input"))?;
+        std::fs::create_dir_all(base_path.join("sysfs/class/power_supply"))?;
         std::fs::create_dir_all(base_path.join("sysfs/devices/virtual/input"))?;
 
         // Acquire lock file
-        let lock_path = socket_path.with_extension("lock");
-        let lock_file = LockFile::acquire(&lock_path)?;
+        let lock_file = match abstract_socket_name(socket_path) {
+            Some(_) => None,
+            None => Some(LockFile::acquire(&socket_path.with_extension("lock"))?),
+        };
 
         // Create udev broadcaster
-        let udev_broadcaster = Arc::new(UdevBroadcaster::new(&base_path)?);
+        let udev_broadcaster = Arc::new(UdevBroadcaster::new(&base_path, &config.node_prefix)?);
         // Create netlink broadcaster
-        let netlink_broadcaster = Arc::new(NetlinkBroadcaster::new()?);
+        let netlink_broadcaster = Arc::new(NetlinkBroadcaster::new(&config.node_prefix)?);
 
         let devices: Arc<Mutex<HashMap<DeviceId, Arc<VirtualDevice>>>> =
             Arc::new(Mutex::new(HashMap::new()));
@@ -72,40 +642,76 @@ impl Manager {
             &base_path,
             devices.clone(),
             next_device_id.clone(),
+            config.mirror_source_ids,
         )?);
 
         info!("Manager initialized at {}", socket_path.display());
 
         Ok(Self {
-            base_path,
             control_socket_path: socket_path.to_path_buf(),
             _lock_file: lock_file,
-            next_device_id,
-            free_device_ids,
-            devices,
-            udev_broadcaster,
-            netlink_broadcaster,
-            uinput_emulator,
+            context: Arc::new(ManagerContext {
+                config,
+                base_path,
+                socket_path: socket_path.to_path_buf(),
+                devices,
+                next_device_id,
+                free_device_ids,
+                pending_devices: AtomicUsize::new(0),
+                pending_ids: std::sync::Mutex::new(HashSet::new()),
+                device_keys: Mutex::new(HashMap::new()),
+                udev_broadcaster,
+                netlink_broadcaster,
+                uinput_emulator,
+                hooks: std::sync::Mutex::new(hooks::Hooks::default()),
+            }),
         })
     }
 
+    /// Call `callback` whenever a device is added or removed
+    ///
+    /// Called from the manager's tasks, so it should return quickly. Devices created as
+    /// uinput mirrors aren't included.
+    pub fn on_device_event(&self, callback: impl Fn(&DeviceEvent) + Send + Sync + 'static) {
+        self.context
+            .hooks
+            .lock()
+            .unwrap()
+            .add_callback(Arc::new(callback));
+    }
+
     /// Run the manager main loop
     pub async fn run(&mut self) -> anyhow::Result<()> {
-        // Remove existing socket if present
-        let _ = std::fs::remove_file(&self.control_socket_path);
+        let listener = match abstract_socket_name(&self.control_socket_path) {
+            Some(name) => {
+                use std::os::linux::net::SocketAddrExt;
+
+                // Abstract sockets have no file to clean up or set permissions on
+                let addr = std::os::unix::net::SocketAddr::from_abstract_name(name)?;
+                let listener = std::os::unix::net::UnixListener::bind_addr(&addr)?;
+                listener.set_nonblocking(true)?;
+                UnixListener::from_std(listener)?
+            }
+            None => {
+                // Remove existing socket if present
+                let _ = std::fs::remove_file(&self.control_socket_path);
 
-        // Bind control socket
-        let listener = UnixListener::bind(&self.control_socket_path)?;
+                // Bind control socket
+                let listener = UnixListener::bind(&self.control_socket_path)?;
 
-        // Set socket permissions to allow all users in container
-        #[cfg(unix)]
-        {
-            use std::os::unix::fs::PermissionsExt;
-            std::fs::set_permissions(
-                &self.control_socket_path,
-                std::fs::Permissions::from_mode(0o666),
-            )?;
-        }
+                // Set socket permissions to allow all users in container
+                #[cfg(unix)]
+                {
+                    use std::os::unix::fs::PermissionsExt;
+                    std::fs::set_permissions(
+                        &self.control_socket_path,
+                        std::fs::Permissions::from_mode(0o666),
+                    )?;
+                }
+
+                listener
+            }
+        };
 
         info!(
             "Manager listening on {}",
@@ -113,43 +719,35 @@ impl Manager {
         );
 
         // Start udev broadcaster
-        let udev_broadcaster = self.udev_broadcaster.clone();
+        let udev_broadcaster = self.context.udev_broadcaster.clone();
+        let devices = self.context.devices.clone();
         tokio::spawn(async move {
-            udev_broadcaster.run().await;
+            udev_broadcaster.run(devices).await;
         });
 
         // Start uinput emulator
-        let uinput_emulator = self.uinput_emulator.clone();
+        let uinput_emulator = self.context.uinput_emulator.clone();
         tokio::spawn(async move {
             if let Err(e) = uinput_emulator.run().await {
                 error!("uinput emulator error: {}", e);
             }
         });
 
+        // Start idle device reaper if enabled
+        if let Some(idle_timeout) = self.context.config.idle_timeout {
+            let context = self.context.clone();
+            tokio::spawn(async move {
+                Self::reap_idle_devices(context, idle_timeout).await;
+            });
+        }
+
         loop {
             match listener.accept().await {
                 Ok((stream, _addr)) => {
-                    let devices = self.devices.clone();
-                    let next_device_id = self.next_device_id.clone();
-                    let free_device_ids = self.free_device_ids.clone();
-                    let base_path = self.base_path.clone();
-                    let udev_broadcaster = self.udev_broadcaster.clone();
-                    let netlink_broadcaster = self.netlink_broadcaster.clone();
-                    let uinput_emulator = self.uinput_emulator.clone();
+                    let context = self.context.clone();
 
                     tokio::spawn(async move {
-                        if let Err(e) = Self::handle_client(
-                            stream,
-                            devices,
-                            next_device_id,
-                            free_device_ids,
-                            base_path,
-                            udev_broadcaster,
-                            netlink_broadcaster,
-                            uinput_emulator,
-                        )
-                        .await
-                        {
+                        if let Err(e) = Self::handle_client(stream, context).await {
                             error!("Client handler error: {}", e);
                         }
                     });
@@ -161,17 +759,40 @@ impl Manager {
         }
     }
 
+    /// Periodically destroy devices with no connected readers and no recent input
+    async fn reap_idle_devices(context: Arc<ManagerContext>, idle_timeout: Duration) {
+        let period = (idle_timeout / 2).clamp(Duration::from_millis(100), Duration::from_secs(5));
+        let mut interval = tokio::time::interval(period);
+
+        loop {
+            interval.tick().await;
+
+            let idle_devices: Vec<DeviceId> = {
+                let devices = context.devices.lock().await;
+                devices
+                    .values()
+                    .filter(|d| d.client_count() == 0 && d.idle_for() >= idle_timeout)
+                    .map(|d| d.id)
+                    .collect()
+            };
+
+            for device_id in idle_devices {
+                // Mirror devices belong to their uinput session
+                if context.uinput_emulator.is_mirror_device(device_id).await {
+                    continue;
+                }
+
+                info!(
+                    "Reaping device {} (no clients or input for {:?})",
+                    device_id, idle_timeout
+                );
+                context.destroy_device(device_id).await;
+            }
+        }
+    }
+
     /// Handle a single client connection
-    async fn handle_client(
-        stream: UnixStream,
-        devices: Arc<Mutex<HashMap<DeviceId, Arc<VirtualDevice>>>>,
-        next_device_id: Arc<Mutex<DeviceId>>,
-        free_device_ids: Arc<Mutex<Vec<DeviceId>>>,
-        base_path: PathBuf,
-        udev_broadcaster: Arc<UdevBroadcaster>,
-        netlink_broadcaster: Arc<NetlinkBroadcaster>,
-        uinput_emulator: Arc<UinputEmulator>,
-    ) -> anyhow::Result<()> {
+    async fn handle_client(stream: UnixStream, context: Arc<ManagerContext>) -> anyhow::Result<()> {
         let (reader, mut writer) = stream.into_split();
         let mut reader = BufReader::new(reader);
         let mut line = String::new();
@@ -194,17 +815,7 @@ impl Manager {
 
                     trace!("Received command: {:?}", message.command);
 
-                    let response = Self::process_command(
-                        message.command,
-                        &devices,
-                        &next_device_id,
-                        &free_device_ids,
-                        &base_path,
-                        &udev_broadcaster,
-                        &netlink_broadcaster,
-                        &uinput_emulator,
-                    )
-                    .await;
+                    let response = Self::process_command(message.command, &context).await;
 
                     let response = ControlResponse {
                         id: message.id,
@@ -241,136 +852,298 @@ impl Manager {
     }
 
     /// Process a control command
-    async fn process_command(
-        command: ControlCommand,
-        devices: &Arc<Mutex<HashMap<DeviceId, Arc<VirtualDevice>>>>,
-        next_device_id: &Arc<Mutex<DeviceId>>,
-        free_device_ids: &Arc<Mutex<Vec<DeviceId>>>,
-        base_path: &Path,
-        udev_broadcaster: &Arc<UdevBroadcaster>,
-        netlink_broadcaster: &Arc<NetlinkBroadcaster>,
-        uinput_emulator: &Arc<UinputEmulator>,
-    ) -> ControlResult {
+    async fn process_command(command: ControlCommand, context: &ManagerContext) -> ControlResult {
+        let devices = &context.devices;
+
         match command {
-            ControlCommand::CreateDevice { config } => {
-                // Try to reuse an ID first, otherwise next
-                let device_id = {
-                    let mut free_ids = free_device_ids.lock().await;
-                    if let Some(id) = free_ids.pop() {
-                        debug!("Re-using device ID: {}", id);
-                        id
-                    } else {
-                        let mut next_id = next_device_id.lock().await;
-                        let id = *next_id;
-                        *next_id += 1;
-                        debug!("Using next device ID: {}", id);
-                        id
+            ControlCommand::CreateDevice { config } => context.create_device(config, None).await,
+            ControlCommand::CreateDeviceWithId { device_id, config } => {
+                context.create_device(config, Some(device_id)).await
+            }
+            ControlCommand::EnsureDevice {
+                key,
+                config,
+                on_mismatch,
+            } => context.ensure_device(key, config, on_mismatch).await,
+            ControlCommand::DestroyDevice { device_id } => {
+                if context.destroy_device(device_id).await {
+                    ControlResult::DeviceDestroyed
+                } else {
+                    ControlResult::Error {
+                        code: ErrorCode::NotFound,
+                        message: format!("Device {} not found", device_id),
                     }
-                };
-
-                debug!(
-                    "Creating device {} with config: name={}, vendor_id=0x{:04x}, product_id=0x{:04x}",
-                    device_id, config.name, config.vendor_id, config.product_id
-                );
-                match VirtualDevice::create(device_id, config.clone(), base_path).await {
-                    Ok(device) => {
-                        let event_node = device.event_node.clone();
-                        devices.lock().await.insert(device_id, Arc::new(device));
-
-                        info!("Created device {} as {}", device_id, event_node);
-
-                        // Broadcast udev add event (after device is ready)
-                        if let Err(e) = udev_broadcaster.broadcast_add(device_id, &config) {
-                            debug!("Failed to broadcast udev add event: {}", e);
-                        }
+                }
+            }
+            ControlCommand::SendInput { device_id, events } => {
+                if let Some(error) = context.check_batch_size(events.len()) {
+                    return error;
+                }
 
-                        // Also broadcast via real netlink
-                        if let Err(e) = netlink_broadcaster.broadcast_add(device_id, &config) {
-                            debug!("Failed to broadcast netlink add event: {}", e);
-                        }
+                context.send_to_device(device_id, |_| Ok(events)).await
+            }
+            ControlCommand::SendNamed { device_id, inputs } => {
+                if let Some(error) = context.check_batch_size(inputs.len()) {
+                    return error;
+                }
 
-                        ControlResult::DeviceCreated {
-                            device_id,
-                            event_node,
-                        }
+                context
+                    .send_to_device(device_id, |config| {
+                        let mut events = inputs
+                            .iter()
+                            .map(|input| input.resolve(config))
+                            .collect::<Result<Vec<_>, _>>()?;
+                        events.push(InputEvent::Sync);
+                        Ok(events)
+                    })
+                    .await
+            }
+            ControlCommand::SetDevicePaused {
+                device_id,
+                paused,
+                mode,
+            } => {
+                let device = match context.device(device_id).await {
+                    Ok(device) => device,
+                    Err(error) => return error,
+                };
+                match device.set_paused(paused, mode).await {
+                    Ok(()) => ControlResult::DevicePaused { paused },
+                    Err(e) => ControlResult::Error {
+                        code: ErrorCode::Internal,
+                        message: format!("Failed to resync device: {}", e),
+                    },
+                }
+            }
+            ControlCommand::InjectFeedback { device_id, event } => {
+                let device = match context.device(device_id).await {
+                    Ok(device) => device,
+                    Err(error) => return error,
+                };
+                device.inject_feedback(&event).await;
+                ControlResult::FeedbackInjected
+            }
+            ControlCommand::SetLatency {
+                device_id,
+                base_ms,
+                jitter_ms,
+                seed,
+            } => {
+                let device = match context.device(device_id).await {
+                    Ok(device) => device,
+                    Err(error) => return error,
+                };
+                device.set_latency(base_ms, jitter_ms, seed);
+                ControlResult::LatencySet
+            }
+            ControlCommand::SetDeviceId {
+                device_id,
+                vendor_id,
+                product_id,
+                version,
+                bustype,
+            } => {
+                let device = match context.device(device_id).await {
+                    Ok(device) => device,
+                    Err(error) => return error,
+                };
+                let current = device.current_config();
+                match device.set_ids(
+                    bustype.unwrap_or(current.bustype),
+                    vendor_id,
+                    product_id,
+                    version.unwrap_or(current.version),
+                ) {
+                    Ok(()) => {
+                        context.broadcast_change(device_id, &device.current_config());
+                        ControlResult::DeviceIdSet
                     }
                     Err(e) => ControlResult::Error {
-                        message: format!("Failed to create device: {}", e),
+                        code: ErrorCode::Internal,
+                        message: format!("Failed to update sysfs: {:#}", e),
                     },
                 }
             }
-            ControlCommand::DestroyDevice { device_id } => {
-                let device = devices.lock().await.remove(&device_id);
-                match device {
-                    Some(device) => {
-                        info!("Destroyed device {}", device_id);
-
-                        // Add the ID to the re-usable pool
-                        free_device_ids.lock().await.push(device_id);
-                        debug!("Marking device ID {} as re-usable", device_id);
-
-                        // Broadcast udev remove event
-                        if let Err(e) = udev_broadcaster.broadcast_remove(device_id, &device.config)
-                        {
-                            debug!("Failed to broadcast udev remove event: {}", e);
-                        }
+            ControlCommand::SetJoystickInterface { device_id, enabled } => {
+                let device = match context.device(device_id).await {
+                    Ok(device) => device,
+                    Err(error) => return error,
+                };
+                if enabled && !device.config().has_joystick_interface() {
+                    return ControlResult::Error {
+                        code: ErrorCode::InvalidInput,
+                        message: format!(
+                            "Device {} has no joystick buttons or axes for a joystick interface",
+                            device_id
+                        ),
+                    };
+                }
 
-                        // Also broadcast via real netlink
-                        if let Err(e) =
-                            netlink_broadcaster.broadcast_remove(device_id, &device.config)
-                        {
-                            debug!("Failed to broadcast netlink remove event: {}", e);
+                match device.set_joystick_interface(enabled).await {
+                    Ok(changed) => {
+                        if changed {
+                            let action = if enabled {
+                                udev::UdevAction::Add
+                            } else {
+                                udev::UdevAction::Remove
+                            };
+                            context.broadcast_joystick(action, device_id, &device.current_config());
+                        }
+                        ControlResult::JoystickInterfaceSet {
+                            joystick_node: device.joystick_node(),
                         }
-
-                        ControlResult::DeviceDestroyed
                     }
-                    None => ControlResult::Error {
-                        message: format!("Device {} not found", device_id),
+                    Err(e) => ControlResult::Error {
+                        code: ErrorCode::Internal,
+                        message: format!("Failed to set joystick interface: {:#}", e),
                     },
                 }
             }
-            ControlCommand::SendInput { device_id, events } => {
-                let device = {
-                    let devices = devices.lock().await;
-                    devices.get(&device_id).cloned()
+            ControlCommand::SetBattery {
+                device_id,
+                capacity,
+                status,
+            } => {
+                let device = match context.device(device_id).await {
+                    Ok(device) => device,
+                    Err(error) => return error,
                 };
+                if device.current_config().battery.is_none() {
+                    return ControlResult::Error {
+                        code: ErrorCode::InvalidInput,
+                        message: format!("Device {} has no battery", device_id),
+                    };
+                }
+                if capacity > 100 {
+                    return ControlResult::Error {
+                        code: ErrorCode::InvalidInput,
+                        message: format!("Battery capacity {} is over 100 percent", capacity),
+                    };
+                }
 
-                match device {
-                    Some(device) => {
-                        let send_result = device.send_events(&events).await;
-
-                        // Also mirror to uinput devices if any
-                        let _ = uinput_emulator
-                            .mirror_to_uinput_devices(device_id, &events)
-                            .await;
-
-                        match send_result {
-                            Ok(()) => ControlResult::InputSent,
-                            Err(e) => ControlResult::Error {
-                                message: format!("Failed to send input: {}", e),
-                            },
-                        }
+                match device.set_battery(BatteryConfig { capacity, status }) {
+                    Ok(()) => {
+                        context.broadcast_change(device_id, &device.current_config());
+                        ControlResult::BatterySet
                     }
-                    None => ControlResult::Error {
-                        message: format!("Device {} not found", device_id),
+                    Err(e) => ControlResult::Error {
+                        code: ErrorCode::Internal,
+                        message: format!("Failed to update sysfs: {:#}", e),
                     },
                 }
             }
-            ControlCommand::ListDevices => {
+            ControlCommand::Flush { device_id } => {
+                let device = match context.device(device_id).await {
+                    Ok(device) => device,
+                    Err(error) => return error,
+                };
+                device.flush().await;
+                ControlResult::Flushed
+            }
+            ControlCommand::GetDeviceClients { device_id } => {
+                let device = match context.device(device_id).await {
+                    Ok(device) => device,
+                    Err(error) => return error,
+                };
+                ControlResult::DeviceClients(device.client_counts().await)
+            }
+            ControlCommand::UpdateDevice { device_id, config } => {
+                context.update_device(device_id, config).await
+            }
+            ControlCommand::GetDeviceConfig { device_id } => {
+                match context.device(device_id).await {
+                    Ok(device) => ControlResult::DeviceConfig(Box::new(device.current_config())),
+                    Err(error) => error,
+                }
+            }
+            ControlCommand::ListDevices { filter } => {
                 let devices = devices.lock().await;
                 let device_list: Vec<DeviceInfo> = devices
                     .values()
-                    .map(|d| DeviceInfo {
+                    .map(|d| (d, d.current_config()))
+                    .filter(|(d, config)| {
+                        filter
+                            .as_ref()
+                            .is_none_or(|f| f.matches(config, d.joystick_node().is_some()))
+                    })
+                    .map(|(d, config)| DeviceInfo {
                         device_id: d.id,
-                        name: d.config.name.clone(),
+                        name: config.name,
                         event_node: d.event_node.clone(),
-                        joystick_node: d.joystick_node.clone(),
-                        vendor_id: d.config.vendor_id,
-                        product_id: d.config.product_id,
+                        joystick_node: d.joystick_node(),
+                        vendor_id: config.vendor_id,
+                        product_id: config.product_id,
                     })
                     .collect();
                 ControlResult::DeviceList(device_list)
             }
+            ControlCommand::RebroadcastDevices => {
+                let mut existing: Vec<_> = devices.lock().await.values().cloned().collect();
+                existing.sort_by_key(|d| d.id);
+                for device in &existing {
+                    context.broadcast_add(device.id, &device.current_config());
+                }
+                info!("Rebroadcast add events for {} devices", existing.len());
+                ControlResult::DevicesRebroadcast {
+                    count: existing.len(),
+                }
+            }
+            ControlCommand::SetHook { event, command } => {
+                match context.hooks.lock().unwrap().set_command(event, command) {
+                    Ok(()) => ControlResult::HookSet,
+                    Err(message) => ControlResult::Error {
+                        code: ErrorCode::InvalidInput,
+                        message,
+                    },
+                }
+            }
+            ControlCommand::GetManagerInfo => {
+                let mut features: Vec<String> = [
+                    "send_named",
+                    "quirks",
+                    "uinput",
+                    "netlink",
+                    "pause",
+                    "inject_feedback",
+                    "latency",
+                    "flush",
+                    "set_device_id",
+                    "create_with_id",
+                    "ensure",
+                    "list_filter",
+                    "rebroadcast",
+                    "node_prefix",
+                    "device_clients",
+                    "joystick_interface",
+                    "battery",
+                    "device_config",
+                    "update_device",
+                    "max_event_hz",
+                ]
+                .into_iter()
+                .map(String::from)
+                .collect();
+                if context.config.idle_timeout.is_some() {
+                    features.push("idle_reaper".to_string());
+                }
+                if cfg!(feature = "hooks") {
+                    features.push("hooks".to_string());
+                }
+
+                ControlResult::ManagerInfo(ManagerInfo {
+                    version: env!("CARGO_PKG_VERSION").to_string(),
+                    base_path: std::fs::canonicalize(&context.base_path)
+                        .unwrap_or_else(|_| context.base_path.clone())
+                        .to_string_lossy()
+                        .to_string(),
+                    socket_path: context.socket_path.to_string_lossy().to_string(),
+                    device_count: devices.lock().await.len(),
+                    max_devices: context.config.max_devices,
+                    max_batch_events: context.config.max_batch_events,
+                    features,
+                    node_prefix: context.config.node_prefix.clone(),
+                })
+            }
             ControlCommand::Ping => ControlResult::Pong,
         }
     }
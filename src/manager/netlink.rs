@@ -1,13 +1,17 @@
-use crate::manager::udev::{UdevAction, UdevDeviceInfo, UdevEvent};
+use crate::manager::sysfs::battery_properties;
+use crate::manager::udev::{UdevAction, UdevDeviceInfo, UdevEvent, joystick_event, wire};
+use crate::protocol::{event_node_name, input_node_name, joystick_node_name};
 use crate::{BusType, DeviceConfig, DeviceId};
 use anyhow::Result;
 use tracing::info;
 
 pub struct NetlinkBroadcaster {
     socket: i32,
+    /// Prefix of the device node names
+    node_prefix: String,
 }
 impl NetlinkBroadcaster {
-    pub fn new() -> Result<Self> {
+    pub fn new(node_prefix: &str) -> Result<Self> {
         const AF_NETLINK: i32 = 16;
         const NETLINK_KOBJECT_UEVENT: i32 = 15;
         const SOCK_RAW: i32 = 3;
@@ -18,43 +22,19 @@ impl NetlinkBroadcaster {
         }
 
         info!("netlink broadcaster created");
-        Ok(Self { socket: sock })
+        Ok(Self {
+            socket: sock,
+            node_prefix: node_prefix.to_string(),
+        })
     }
 
     /// Send a udev event via real netlink
     pub fn send_event(&self, event: &UdevEvent) -> Result<()> {
-        let action = match event.action {
-            UdevAction::Add => "add",
-            UdevAction::Remove => "remove",
-            UdevAction::Change => "change",
-        };
-
-        // First line is special: action@devpath WITHOUT null terminator
-        let mut message = Vec::new();
-        message.extend_from_slice(format!("{}@{}", action, event.device_info.devpath).as_bytes());
-
-        // Then null-terminated key=value pairs
-        message.extend_from_slice(format!("\0ACTION={}\0", action).as_bytes());
-        message.extend_from_slice(format!("DEVPATH={}\0", event.device_info.devpath).as_bytes());
-        message
-            .extend_from_slice(format!("SUBSYSTEM={}\0", event.device_info.subsystem).as_bytes());
-
-        // Only add DEVNAME if it's not empty
-        if !event.device_info.devname.is_empty() {
-            message
-                .extend_from_slice(format!("DEVNAME={}\0", event.device_info.devname).as_bytes());
-        }
-
         // Add sequence number (udevadm expects this)
         let seq = std::time::SystemTime::now()
             .duration_since(std::time::UNIX_EPOCH)?
             .as_secs();
-        message.extend_from_slice(format!("SEQNUM={}\0", seq).as_bytes());
-
-        // Add properties
-        for (key, value) in &event.device_info.properties {
-            message.extend_from_slice(format!("{}={}\0", key, value).as_bytes());
-        }
+        let message = wire::encode_kernel_event(event, seq);
 
         let message_bytes = message.as_slice();
 
@@ -91,15 +71,58 @@ impl NetlinkBroadcaster {
 
     /// Broadcast a device add event via netlink
     pub fn broadcast_add(&self, device_id: DeviceId, config: &DeviceConfig) -> Result<()> {
-        let event_node = format!("event{}", device_id);
-        let input_node = format!("input{}", device_id);
+        self.send_event(&self.add_event(device_id, config))?;
+        info!(
+            "Sent netlink add event for {}",
+            event_node_name(&self.node_prefix, device_id)
+        );
+        Ok(())
+    }
+
+    /// Broadcast a device change event via netlink, e.g. after its ids changed
+    pub fn broadcast_change(&self, device_id: DeviceId, config: &DeviceConfig) -> Result<()> {
+        let mut event = self.add_event(device_id, config);
+        event.action = UdevAction::Change;
+        self.send_event(&event)?;
+        info!(
+            "Sent netlink change event for {}",
+            event_node_name(&self.node_prefix, device_id)
+        );
+        Ok(())
+    }
+
+    /// Broadcast the joystick node of a device being added or removed on its own via netlink
+    pub fn broadcast_joystick(
+        &self,
+        action: UdevAction,
+        device_id: DeviceId,
+        config: &DeviceConfig,
+    ) -> Result<()> {
+        self.send_event(&joystick_event(
+            &self.node_prefix,
+            action,
+            device_id,
+            config,
+        ))?;
+        info!(
+            "Sent netlink joystick {} event for {}",
+            action.as_str(),
+            joystick_node_name(&self.node_prefix, device_id)
+        );
+        Ok(())
+    }
+
+    /// Add event of a device
+    fn add_event(&self, device_id: DeviceId, config: &DeviceConfig) -> UdevEvent {
+        let event_node = event_node_name(&self.node_prefix, device_id);
+        let input_node = input_node_name(&self.node_prefix, device_id);
 
         let mut properties = vec![
             ("ID_INPUT".to_string(), "1".to_string()),
             ("ID_INPUT_JOYSTICK".to_string(), "1".to_string()),
             (
                 "ID_MODEL".to_string(),
-                format!("{}_{}", config.name.replace(' ', "_"), device_id),
+                format!("{}_{}", config.udev_model().replace(' ', "_"), device_id),
             ),
             (
                 "ID_VENDOR_ID".to_string(),
@@ -109,20 +132,16 @@ impl NetlinkBroadcaster {
                 "ID_MODEL_ID".to_string(),
                 format!("{:04x}", config.product_id),
             ),
-            (
-                "ID_BUS".to_string(),
-                match config.bustype {
-                    BusType::Usb => "usb".to_string(),
-                    BusType::Bluetooth => "bluetooth".to_string(),
-                    BusType::Virtual => "virtual".to_string(),
-                },
-            ),
+            ("ID_BUS".to_string(), config.udev_bus_name().to_string()),
             ("NAME".to_string(), format!("\"{}\"", config.name)),
             (
                 "PRODUCT".to_string(),
                 format!(
                     "{:x}/{:x}/{:x}/{:x}",
-                    config.bustype as u16, config.vendor_id, config.product_id, config.version
+                    config.bustype.to_u16(),
+                    config.vendor_id,
+                    config.product_id,
+                    config.version
                 ),
             ),
         ];
@@ -131,8 +150,12 @@ impl NetlinkBroadcaster {
             properties.push(("BUSNUM".to_string(), "253".to_string()));
             properties.push(("DEVNUM".to_string(), format!("{:03}", device_id + 1)));
         }
+        if let Some(battery) = &config.battery {
+            properties.extend(battery_properties(&self.node_prefix, device_id, battery));
+        }
+        config.merge_extra_udev_properties(&mut properties);
 
-        let event = UdevEvent {
+        UdevEvent {
             action: UdevAction::Add,
             device_info: UdevDeviceInfo {
                 subsystem: "input".to_string(),
@@ -142,17 +165,13 @@ impl NetlinkBroadcaster {
                 syspath: format!("/sys/devices/virtual/input/{}/{}", input_node, event_node),
                 properties,
             },
-        };
-
-        self.send_event(&event)?;
-        info!("Sent netlink add event for {}", event_node);
-        Ok(())
+        }
     }
 
     /// Broadcast a device remove event via netlink
     pub fn broadcast_remove(&self, device_id: DeviceId, config: &DeviceConfig) -> Result<()> {
-        let event_node = format!("event{}", device_id);
-        let input_node = format!("input{}", device_id);
+        let event_node = event_node_name(&self.node_prefix, device_id);
+        let input_node = input_node_name(&self.node_prefix, device_id);
 
         let mut event = UdevEvent {
             action: UdevAction::Remove,
@@ -166,7 +185,7 @@ impl NetlinkBroadcaster {
                     ("NAME".to_string(), format!("\"{}\"", config.name)),
                     (
                         "ID_MODEL".to_string(),
-                        format!("{}_{}", config.name.replace(' ', "_"), device_id),
+                        format!("{}_{}", config.udev_model().replace(' ', "_"), device_id),
                     ),
                     ("ID_SERIAL".to_string(), format!("vimputti_{}", event_node)),
                     ("ID_SERIAL_SHORT".to_string(), event_node.clone()),
@@ -1,45 +1,174 @@
 use crate::protocol::*;
-use anyhow::Result;
+use anyhow::{Context, Result};
 use std::path::Path;
 
+/// Name of a device's power supply, e.g. `vimputti-battery-event0`
+pub(crate) fn power_supply_name(node_prefix: &str, id: DeviceId) -> String {
+    format!("vimputti-battery-{}", event_node_name(node_prefix, id))
+}
+
+/// `POWER_SUPPLY_*` properties of a battery, as in its uevent and udev events
+pub(crate) fn battery_properties(
+    node_prefix: &str,
+    id: DeviceId,
+    battery: &BatteryConfig,
+) -> Vec<(String, String)> {
+    vec![
+        (
+            "POWER_SUPPLY_NAME".to_string(),
+            power_supply_name(node_prefix, id),
+        ),
+        ("POWER_SUPPLY_TYPE".to_string(), "Battery".to_string()),
+        // A device's own battery, not powering the system
+        ("POWER_SUPPLY_SCOPE".to_string(), "Device".to_string()),
+        (
+            "POWER_SUPPLY_STATUS".to_string(),
+            battery.status.sysfs_name().to_string(),
+        ),
+        ("POWER_SUPPLY_PRESENT".to_string(), "1".to_string()),
+        (
+            "POWER_SUPPLY_CAPACITY".to_string(),
+            battery.capacity.to_string(),
+        ),
+    ]
+}
+
 /// Enhanced sysfs file generator
 pub struct SysfsGenerator;
 impl SysfsGenerator {
-    /// Create complete sysfs structure for a device
+    /// Create complete sysfs structure for a device, with its joystick node if `joystick`
     pub fn create_device_files(
         id: DeviceId,
+        node_prefix: &str,
         config: &DeviceConfig,
         base_path: &Path,
+        joystick: bool,
     ) -> Result<()> {
-        let event_node = format!("event{}", id);
-        let input_node = format!("input{}", id);
+        let event_node = event_node_name(node_prefix, id);
+        let input_node = input_node_name(node_prefix, id);
         Self::create_devices_virtual(&input_node, &event_node, config, base_path)?;
-        Self::create_class_input_symlink(&event_node, &input_node, base_path)?;
-        Self::create_udev_data_file(id, config, base_path)?;
-        // Create joystick udev data if device has buttons or axes
-        if !config.buttons.is_empty() || !config.axes.is_empty() {
-            Self::create_joystick_udev_data_file(id, config, base_path)?;
+        Self::create_class_input_symlink(&input_node, &input_node, base_path)?;
+        Self::create_class_input_symlink(
+            &event_node,
+            &format!("{}/{}", input_node, event_node),
+            base_path,
+        )?;
+        Self::create_udev_data_file(id, node_prefix, config, base_path)?;
+        if joystick {
+            Self::create_joystick_files(id, node_prefix, config, base_path)?;
+        }
+        if let Some(battery) = &config.battery {
+            Self::create_power_supply(id, node_prefix, battery, base_path)?;
         }
         Ok(())
     }
 
-    fn create_class_input_symlink(
-        event_node: &str,
-        input_node: &str,
+    /// Create the power supply of a device's battery under its input node
+    ///
+    /// Linked from /sys/class/power_supply, where UPower and games look for batteries.
+    fn create_power_supply(
+        id: DeviceId,
+        node_prefix: &str,
+        battery: &BatteryConfig,
         base_path: &Path,
     ) -> Result<()> {
+        let name = power_supply_name(node_prefix, id);
+        let input_node = input_node_name(node_prefix, id);
+        let supply_path = base_path
+            .join("sysfs/devices/virtual/input")
+            .join(&input_node)
+            .join("power_supply")
+            .join(&name);
+        create_dir(&supply_path)?;
+
+        write_file(supply_path.join("type"), "Battery\n")?;
+        write_file(supply_path.join("scope"), "Device\n")?;
+        write_file(
+            supply_path.join("status"),
+            format!("{}\n", battery.status.sysfs_name()),
+        )?;
+        write_file(supply_path.join("present"), "1\n")?;
+        write_file(
+            supply_path.join("capacity"),
+            format!("{}\n", battery.capacity),
+        )?;
+
+        let uevent: String = battery_properties(node_prefix, id, battery)
+            .into_iter()
+            .map(|(key, value)| format!("{}={}\n", key, value))
+            .collect();
+        write_file(supply_path.join("uevent"), uevent)?;
+
+        let subsystem_link = supply_path.join("subsystem");
+        let _ = std::fs::remove_file(&subsystem_link);
+        symlink("../../../../../../class/power_supply", &subsystem_link)?;
+
+        // The input device the battery belongs to
+        let device_link = supply_path.join("device");
+        let _ = std::fs::remove_file(&device_link);
+        symlink("../..", &device_link)?;
+
+        let class_dir = base_path.join("sysfs/class/power_supply");
+        create_dir(&class_dir)?;
+        let class_link = class_dir.join(&name);
+        let _ = std::fs::remove_file(&class_link);
+        symlink(
+            format!(
+                "../../devices/virtual/input/{}/power_supply/{}",
+                input_node, name
+            ),
+            &class_link,
+        )?;
+
+        Ok(())
+    }
+
+    /// Create the joystick node and its udev data of a device
+    pub fn create_joystick_files(
+        id: DeviceId,
+        node_prefix: &str,
+        config: &DeviceConfig,
+        base_path: &Path,
+    ) -> Result<()> {
+        let input_node = input_node_name(node_prefix, id);
+        let js_node = joystick_node_name(node_prefix, id);
+        Self::create_joystick_node(id, &js_node, &input_node, base_path)?;
+        Self::create_class_input_symlink(
+            &js_node,
+            &format!("{}/{}", input_node, js_node),
+            base_path,
+        )?;
+        Self::create_joystick_udev_data_file(id, node_prefix, config, base_path)
+    }
+
+    /// Remove the joystick node of a device, keeping the rest of it
+    pub fn remove_joystick_files(id: DeviceId, node_prefix: &str, base_path: &Path) -> Result<()> {
+        let js_node = joystick_node_name(node_prefix, id);
+        let _ = std::fs::remove_file(base_path.join("sysfs/class/input").join(&js_node));
+        let _ = std::fs::remove_dir_all(
+            base_path
+                .join("sysfs/devices/virtual/input")
+                .join(input_node_name(node_prefix, id))
+                .join(&js_node),
+        );
+        let _ = std::fs::remove_file(base_path.join("udev_data").join(format!("c81:{}", id)));
+        Ok(())
+    }
+
+    /// Create /sys/class/input/<name> pointing at `target` under devices/virtual/input
+    fn create_class_input_symlink(name: &str, target: &str, base_path: &Path) -> Result<()> {
         let class_input_dir = base_path.join("sysfs/class/input");
-        std::fs::create_dir_all(&class_input_dir)?;
+        create_dir(&class_input_dir)?;
 
-        let symlink_path = class_input_dir.join(event_node);
-        let target = format!("../../devices/virtual/input/{}/{}", input_node, event_node);
+        let symlink_path = class_input_dir.join(name);
+        let target = format!("../../devices/virtual/input/{}", target);
 
         // Remove if exists
         let _ = std::fs::remove_file(&symlink_path);
         let _ = std::fs::remove_dir_all(&symlink_path);
 
         // Create symlink
-        std::os::unix::fs::symlink(&target, &symlink_path)?;
+        symlink(&target, &symlink_path)?;
 
         tracing::debug!("Created symlink: {} -> {}", symlink_path.display(), target);
 
@@ -60,10 +189,10 @@ impl SysfsGenerator {
         let event_path = input_base.join(event_node);
 
         // Create ALL directory structure
-        std::fs::create_dir_all(&input_base)?;
-        std::fs::create_dir_all(&event_path)?;
-        std::fs::create_dir_all(input_base.join("id"))?;
-        std::fs::create_dir_all(input_base.join("capabilities"))?;
+        create_dir(&input_base)?;
+        create_dir(&event_path)?;
+        create_dir(input_base.join("id"))?;
+        create_dir(input_base.join("capabilities"))?;
 
         // Add unique name identifier
         let unique_name = format!("{} ({})", config.name, event_node);
@@ -71,34 +200,34 @@ impl SysfsGenerator {
             "Sysfs for device event_path={:?}: unique_name={}, product={:x}/{:x}/{:x}/{:x}",
             event_path,
             unique_name,
-            config.bustype as u16,
+            config.bustype.to_u16(),
             config.vendor_id,
             config.product_id,
             config.version
         );
 
         // Write input device properties
-        std::fs::write(input_base.join("name"), format!("{}\n", unique_name))?;
-        std::fs::write(
+        write_file(input_base.join("name"), format!("{}\n", unique_name))?;
+        write_file(
             input_base.join("phys"),
             format!("vimputti-{}\n", event_node),
         )?;
-        std::fs::write(input_base.join("uniq"), format!("{}\n", event_node))?;
+        write_file(input_base.join("uniq"), format!("{}\n", event_node))?;
 
         // Write IDs
-        std::fs::write(
+        write_file(
             input_base.join("id/bustype"),
-            format!("{:04x}\n", config.bustype as u16),
+            format!("{:04x}\n", config.bustype.to_u16()),
         )?;
-        std::fs::write(
+        write_file(
             input_base.join("id/vendor"),
             format!("{:04x}\n", config.vendor_id),
         )?;
-        std::fs::write(
+        write_file(
             input_base.join("id/product"),
             format!("{:04x}\n", config.product_id),
         )?;
-        std::fs::write(
+        write_file(
             input_base.join("id/version"),
             format!("{:04x}\n", config.version),
         )?;
@@ -107,37 +236,53 @@ impl SysfsGenerator {
         Self::write_capabilities(&input_base, config)?;
 
         // Write modalias
-        std::fs::write(
-            input_base.join("modalias"),
-            format!(
-                "input:b{:04X}v{:04X}p{:04X}e{:04X}\n",
-                config.bustype as u16, config.vendor_id, config.product_id, config.version
-            ),
-        )?;
+        let modalias = format!(
+            "input:b{:04X}v{:04X}p{:04X}e{:04X}",
+            config.bustype.to_u16(),
+            config.vendor_id,
+            config.product_id,
+            config.version
+        );
+        write_file(input_base.join("modalias"), format!("{}\n", modalias))?;
+        let props = capabilities::prop_bitmap(config).to_sysfs_string();
+        write_file(input_base.join("properties"), format!("{}\n", props))?;
 
         // Write uevent
+        let bitmaps = capabilities::ev_bitmap(config);
+        // Like the kernel, only listed for devices with relative axes
+        let rel_line = if config.rel_axes.is_empty() {
+            String::new()
+        } else {
+            format!("REL={}\n", bitmaps.rel.to_sysfs_string())
+        };
         let mut uevent_content = format!(
             "PRODUCT={:x}/{:x}/{:x}/{:x}\n\
              NAME=\"{}\"\n\
              PHYS=\"vimputti-{}\"\n\
              UNIQ=\"{}\"\n\
+             PROP={}\n\
              EV={}\n\
              KEY={}\n\
-             ABS={}\n",
-            config.bustype as u16,
+             {}ABS={}\n\
+             MODALIAS={}\n",
+            config.bustype.to_u16(),
             config.vendor_id,
             config.product_id,
             config.version,
             unique_name,
             event_node,
             event_node,
-            Self::calculate_ev_bits(config),
-            Self::calculate_key_bits(config),
-            Self::calculate_abs_bits(config),
+            props,
+            bitmaps.ev.to_sysfs_string(),
+            bitmaps.key.to_sysfs_string(),
+            rel_line,
+            bitmaps.abs.to_sysfs_string(),
+            modalias,
         );
 
+        // Node prefixes have no digits, the id is all of them
         let device_id = event_node
-            .trim_start_matches("event")
+            .trim_start_matches(|c: char| !c.is_ascii_digit())
             .parse::<u64>()
             .unwrap_or(0);
         if matches!(config.bustype, BusType::Usb) {
@@ -145,39 +290,73 @@ impl SysfsGenerator {
             uevent_content.push_str(&format!("DEVNUM={}\n", device_id + 1));
         }
 
-        std::fs::write(input_base.join("uevent"), uevent_content)?;
+        write_file(input_base.join("uevent"), uevent_content)?;
 
-        // Event node properties
-        std::fs::write(
-            event_path.join("dev"),
-            format!("13:{}\n", event_node.trim_start_matches("event")),
-        )?;
+        // Input device subsystem symlink
+        let subsystem_link = input_base.join("subsystem");
+        let _ = std::fs::remove_file(&subsystem_link);
+        symlink("../../../../class/input", &subsystem_link)?;
+
+        // Event node properties (event0 = minor 64, event1 = 65, etc.)
+        let event_minor = 64 + device_id;
+        write_file(event_path.join("dev"), format!("13:{}\n", event_minor))?;
 
         // Create subsystem symlink
         let subsystem_link = event_path.join("subsystem");
         let _ = std::fs::remove_file(&subsystem_link);
-        std::os::unix::fs::symlink("../../../../class/input", &subsystem_link)?;
+        symlink("../../../../../class/input", &subsystem_link)?;
 
         // Create device symlink: eventX/device -> ..
         let device_link = event_path.join("device");
         let _ = std::fs::remove_file(&device_link);
         let _ = std::fs::remove_dir_all(&device_link); // Remove if it's a directory
-        std::os::unix::fs::symlink("..", &device_link)?;
+        symlink("..", &device_link)?;
 
         // Write event uevent
         let event_uevent = format!(
             "MAJOR=13\n\
              MINOR={}\n\
              DEVNAME=input/{}\n",
-            event_node.trim_start_matches("event"),
-            event_node
+            event_minor, event_node
         );
-        std::fs::write(event_path.join("uevent"), event_uevent)?;
+        write_file(event_path.join("uevent"), event_uevent)?;
+
+        Ok(())
+    }
+
+    /// Create /sys/devices/virtual/input/inputX/jsX
+    fn create_joystick_node(
+        id: DeviceId,
+        js_node: &str,
+        input_node: &str,
+        base_path: &Path,
+    ) -> Result<()> {
+        let js_path = base_path
+            .join("sysfs/devices/virtual/input")
+            .join(input_node)
+            .join(js_node);
+        create_dir(&js_path)?;
+
+        // js0 = minor 0, js1 = 1, etc.
+        write_file(js_path.join("dev"), format!("81:{}\n", id))?;
+        write_file(
+            js_path.join("uevent"),
+            format!("MAJOR=81\nMINOR={}\nDEVNAME=input/{}\n", id, js_node),
+        )?;
+
+        let subsystem_link = js_path.join("subsystem");
+        let _ = std::fs::remove_file(&subsystem_link);
+        symlink("../../../../../class/input", &subsystem_link)?;
+
+        let device_link = js_path.join("device");
+        let _ = std::fs::remove_file(&device_link);
+        symlink("..", &device_link)?;
 
         Ok(())
     }
     pub fn create_udev_data_file(
         id: DeviceId,
+        node_prefix: &str,
         config: &DeviceConfig,
         base_path: &Path,
     ) -> Result<()> {
@@ -185,7 +364,7 @@ impl SysfsGenerator {
         let data_file = format!("c13:{}", minor); // char device major 13
 
         let udev_data_dir = base_path.join("udev_data");
-        std::fs::create_dir_all(&udev_data_dir)?;
+        create_dir(&udev_data_dir)?;
 
         // Generate unique initialization timestamp (microseconds)
         let init_time = std::time::SystemTime::now()
@@ -197,7 +376,7 @@ impl SysfsGenerator {
         let mut content = String::new();
 
         // main props..
-        let event_node = format!("event{}", id);
+        let event_node = event_node_name(node_prefix, id);
         content.push_str(&format!(
             "S:input/by-path/platform-vimputti-device{}-event-joystick\n",
             id
@@ -212,12 +391,7 @@ impl SysfsGenerator {
         content.push_str(&format!("E:ID_VENDOR_ID={:04x}\n", config.vendor_id));
         content.push_str(&format!("E:ID_MODEL_ID={:04x}\n", config.product_id));
 
-        let bus_name = match config.bustype {
-            BusType::Usb => "usb",
-            BusType::Bluetooth => "bluetooth",
-            BusType::Virtual => "virtual",
-        };
-        content.push_str(&format!("E:ID_BUS={}\n", bus_name));
+        content.push_str(&format!("E:ID_BUS={}\n", config.udev_bus_name()));
 
         // Vendor/Model info
         let vendor_name = match config.vendor_id {
@@ -228,10 +402,13 @@ impl SysfsGenerator {
         };
         content.push_str(&format!("E:ID_VENDOR={}\n", vendor_name));
         content.push_str(&format!("E:ID_VENDOR_ENC={}\n", vendor_name));
-        content.push_str(&format!("E:ID_MODEL={}\n", config.name.replace(' ', "_")));
+        content.push_str(&format!(
+            "E:ID_MODEL={}\n",
+            config.udev_model().replace(' ', "_")
+        ));
         content.push_str(&format!(
             "E:ID_MODEL_ENC={}\n",
-            config.name.replace(' ', "\\x20")
+            config.udev_model().replace(' ', "\\x20")
         ));
 
         // usb props..
@@ -243,11 +420,11 @@ impl SysfsGenerator {
             content.push_str(&format!("E:ID_USB_VENDOR_ID={:04x}\n", config.vendor_id));
             content.push_str(&format!(
                 "E:ID_USB_MODEL={}\n",
-                config.name.replace(' ', "_")
+                config.udev_model().replace(' ', "_")
             ));
             content.push_str(&format!(
                 "E:ID_USB_MODEL_ENC={}\n",
-                config.name.replace(' ', "\\x20")
+                config.udev_model().replace(' ', "\\x20")
             ));
             content.push_str(&format!("E:ID_USB_MODEL_ID={:04x}\n", config.product_id));
             content.push_str(&format!("E:ID_USB_REVISION={:04x}\n", config.version));
@@ -268,6 +445,16 @@ impl SysfsGenerator {
             id
         ));
 
+        // battery props..
+        if let Some(battery) = &config.battery {
+            for (key, value) in battery_properties(node_prefix, id, battery) {
+                content.push_str(&format!("E:{}={}\n", key, value));
+            }
+        }
+
+        // extra props..
+        Self::push_extra_udev_properties(&mut content, config);
+
         // tags..
         content.push_str("E:TAGS=:seat:uaccess:\n");
         content.push_str("G:seat\n");
@@ -278,13 +465,14 @@ impl SysfsGenerator {
         // version..
         content.push_str("V:1\n");
 
-        std::fs::write(udev_data_dir.join(&data_file), content)?;
+        write_file(udev_data_dir.join(&data_file), content)?;
 
         Ok(())
     }
 
     pub fn create_joystick_udev_data_file(
         id: DeviceId,
+        node_prefix: &str,
         config: &DeviceConfig,
         base_path: &Path,
     ) -> Result<()> {
@@ -292,7 +480,7 @@ impl SysfsGenerator {
         let data_file = format!("c81:{}", js_minor); // char device major 81
 
         let udev_data_dir = base_path.join("udev_data");
-        std::fs::create_dir_all(&udev_data_dir)?;
+        create_dir(&udev_data_dir)?;
 
         // Generate unique initialization timestamp
         let init_time = std::time::SystemTime::now()
@@ -302,7 +490,7 @@ impl SysfsGenerator {
             + 500; // Slightly different from event device
 
         let mut content = String::new();
-        let js_node = format!("js{}", id);
+        let js_node = joystick_node_name(node_prefix, id);
 
         // Symlinks
         content.push_str(&format!(
@@ -320,12 +508,7 @@ impl SysfsGenerator {
         content.push_str(&format!("E:ID_VENDOR_ID={:04x}\n", config.vendor_id));
         content.push_str(&format!("E:ID_MODEL_ID={:04x}\n", config.product_id));
 
-        let bus_name = match config.bustype {
-            BusType::Usb => "usb",
-            BusType::Bluetooth => "bluetooth",
-            BusType::Virtual => "virtual",
-        };
-        content.push_str(&format!("E:ID_BUS={}\n", bus_name));
+        content.push_str(&format!("E:ID_BUS={}\n", config.udev_bus_name()));
 
         let vendor_name = match config.vendor_id {
             0x045e => "Microsoft",
@@ -336,10 +519,13 @@ impl SysfsGenerator {
 
         content.push_str(&format!("E:ID_VENDOR={}\n", vendor_name));
         content.push_str(&format!("E:ID_VENDOR_ENC={}\n", vendor_name));
-        content.push_str(&format!("E:ID_MODEL={}\n", config.name.replace(' ', "_")));
+        content.push_str(&format!(
+            "E:ID_MODEL={}\n",
+            config.udev_model().replace(' ', "_")
+        ));
         content.push_str(&format!(
             "E:ID_MODEL_ENC={}\n",
-            config.name.replace(' ', "\\x20")
+            config.udev_model().replace(' ', "\\x20")
         ));
 
         if matches!(config.bustype, BusType::Usb) {
@@ -350,11 +536,11 @@ impl SysfsGenerator {
             content.push_str(&format!("E:ID_USB_VENDOR_ID={:04x}\n", config.vendor_id));
             content.push_str(&format!(
                 "E:ID_USB_MODEL={}\n",
-                config.name.replace(' ', "_")
+                config.udev_model().replace(' ', "_")
             ));
             content.push_str(&format!(
                 "E:ID_USB_MODEL_ENC={}\n",
-                config.name.replace(' ', "\\x20")
+                config.udev_model().replace(' ', "\\x20")
             ));
             content.push_str(&format!("E:ID_USB_MODEL_ID={:04x}\n", config.product_id));
             content.push_str(&format!("E:ID_USB_REVISION={:04x}\n", config.version));
@@ -374,6 +560,8 @@ impl SysfsGenerator {
             id
         ));
 
+        Self::push_extra_udev_properties(&mut content, config);
+
         content.push_str("E:TAGS=:seat:uaccess:\n");
         content.push_str("G:seat\n");
         content.push_str("G:uaccess\n");
@@ -381,131 +569,82 @@ impl SysfsGenerator {
         content.push_str("Q:uaccess\n");
         content.push_str("V:1\n");
 
-        std::fs::write(udev_data_dir.join(&data_file), content)?;
+        write_file(udev_data_dir.join(&data_file), content)?;
 
         Ok(())
     }
 
+    /// Append `extra_udev_properties` to udev database content, see
+    /// `DeviceConfig::merge_extra_udev_properties`
+    fn push_extra_udev_properties(content: &mut String, config: &DeviceConfig) {
+        for (key, value) in &config.extra_udev_properties {
+            let prefix = format!("E:{}=", key);
+            if content.lines().any(|line| line.starts_with(&prefix)) {
+                if !config.override_udev_properties {
+                    continue;
+                }
+                *content = content
+                    .lines()
+                    .filter(|line| !line.starts_with(&prefix))
+                    .map(|line| format!("{}\n", line))
+                    .collect();
+            }
+            content.push_str(&format!("{}{}\n", prefix, value));
+        }
+    }
+
     /// Write capability bitmasks
     fn write_capabilities(base_path: &Path, config: &DeviceConfig) -> Result<()> {
         let caps_dir = base_path.join("capabilities");
 
         // Ensure capabilities directory exists
-        std::fs::create_dir_all(&caps_dir)?;
-
-        // EV capabilities
-        std::fs::write(
-            caps_dir.join("ev"),
-            format!("{}\n", Self::calculate_ev_bits(config)),
-        )?;
-
-        // Key capabilities (buttons)
-        std::fs::write(
-            caps_dir.join("key"),
-            format!("{}\n", Self::calculate_key_bits(config)),
-        )?;
-
-        // Absolute axis capabilities
-        std::fs::write(
-            caps_dir.join("abs"),
-            format!("{}\n", Self::calculate_abs_bits(config)),
-        )?;
-
-        // Relative axis capabilities (none for controllers)
-        std::fs::write(caps_dir.join("rel"), "0\n")?;
-
-        // MSC capabilities
-        std::fs::write(caps_dir.join("msc"), "0\n")?;
+        create_dir(&caps_dir)?;
+
+        // Same bitmaps the shim answers EVIOCGBIT with
+        let bitmaps = capabilities::ev_bitmap(config);
+        for (name, bitmap) in [
+            ("ev", &bitmaps.ev),
+            ("key", &bitmaps.key),
+            ("rel", &bitmaps.rel),
+            ("abs", &bitmaps.abs),
+            ("msc", &bitmaps.msc),
+            ("ff", &bitmaps.ff),
+        ] {
+            write_file(
+                caps_dir.join(name),
+                format!("{}\n", bitmap.to_sysfs_string()),
+            )?;
+        }
 
         // LED capabilities
-        std::fs::write(caps_dir.join("led"), "0\n")?;
+        write_file(caps_dir.join("led"), "0\n")?;
 
         // Sound capabilities
-        std::fs::write(caps_dir.join("snd"), "0\n")?;
-
-        // Force feedback capabilities (none for now)
-        std::fs::write(caps_dir.join("ff"), "0\n")?;
+        write_file(caps_dir.join("snd"), "0\n")?;
 
         // Switch capabilities
-        std::fs::write(caps_dir.join("sw"), "0\n")?;
+        write_file(caps_dir.join("sw"), "0\n")?;
 
         Ok(())
     }
 
-    /// Calculate EV bitmask (supported event types)
-    fn calculate_ev_bits(config: &DeviceConfig) -> String {
-        let mut bits = 1u64; // EV_SYN is always supported
-
-        if !config.buttons.is_empty() {
-            bits |= 1 << EV_KEY; // Button events
-        }
-
-        if !config.axes.is_empty() {
-            bits |= 1 << EV_ABS; // Absolute axis events
-        }
-
-        format!("{:x}", bits)
-    }
-
-    /// Calculate KEY bitmask (supported buttons)
-    fn calculate_key_bits(config: &DeviceConfig) -> String {
-        if config.buttons.is_empty() {
-            return "0".to_string();
-        }
-
-        let mut bits = [0u64; 12]; // 768 bits / 64 = 12 u64s
-
-        for button in &config.buttons {
-            let code = button.to_ev_code() as usize;
-            let index = code / 64;
-            let bit = code % 64;
-            if index < bits.len() {
-                bits[index] |= 1u64 << bit;
-            }
-        }
-
-        // Format as hex string (filter out leading zeros)
-        let formatted: Vec<String> = bits
-            .iter()
-            .rev()
-            .skip_while(|&&b| b == 0)
-            .map(|b| format!("{:x}", b))
-            .collect();
-
-        if formatted.is_empty() {
-            "0".to_string()
-        } else {
-            formatted.join(" ")
-        }
-    }
-
-    /// Calculate ABS bitmask (supported axes)
-    fn calculate_abs_bits(config: &DeviceConfig) -> String {
-        if config.axes.is_empty() {
-            return "0".to_string();
-        }
-
-        let mut bits = [0u64; 1]; // 64 bits for now (covers standard axes)
-
-        for axis_config in &config.axes {
-            let code = axis_config.axis.to_ev_code() as usize;
-            if code < 64 {
-                bits[0] |= 1u64 << code;
-            }
-        }
-
-        format!("{:x}", bits[0])
-    }
-
     /// Remove sysfs files for a device
-    pub fn remove_device_files(id: DeviceId, base_path: &Path) -> Result<()> {
-        let event_node = format!("event{}", id);
-        let input_node = format!("input{}", id);
+    pub fn remove_device_files(id: DeviceId, node_prefix: &str, base_path: &Path) -> Result<()> {
+        let event_node = event_node_name(node_prefix, id);
+        let input_node = input_node_name(node_prefix, id);
         let event_minor = 64 + id;
         let js_minor = id;
 
-        // Remove class/input/eventX
-        let _ = std::fs::remove_dir_all(base_path.join("sysfs/class/input").join(&event_node));
+        // Remove class/input/{eventX,inputX,jsX}
+        let class_input_dir = base_path.join("sysfs/class/input");
+        let _ = std::fs::remove_dir_all(class_input_dir.join(&event_node));
+        let _ = std::fs::remove_file(class_input_dir.join(&input_node));
+        let _ = std::fs::remove_file(class_input_dir.join(joystick_node_name(node_prefix, id)));
+        let _ = std::fs::remove_file(
+            base_path
+                .join("sysfs/class/power_supply")
+                .join(power_supply_name(node_prefix, id)),
+        );
 
         // Remove devices/virtual/input/inputX
         let _ = std::fs::remove_dir_all(
@@ -529,3 +668,22 @@ impl SysfsGenerator {
         Ok(())
     }
 }
+
+// File operations naming the path on failure, e.g. on a full or read-only filesystem
+
+fn write_file(path: impl AsRef<Path>, contents: impl AsRef<[u8]>) -> Result<()> {
+    let path = path.as_ref();
+    std::fs::write(path, contents).with_context(|| format!("Failed to write {}", path.display()))
+}
+
+fn create_dir(path: impl AsRef<Path>) -> Result<()> {
+    let path = path.as_ref();
+    std::fs::create_dir_all(path)
+        .with_context(|| format!("Failed to create directory {}", path.display()))
+}
+
+fn symlink(target: impl AsRef<Path>, link: impl AsRef<Path>) -> Result<()> {
+    let link = link.as_ref();
+    std::os::unix::fs::symlink(target, link)
+        .with_context(|| format!("Failed to create symlink {}", link.display()))
+}
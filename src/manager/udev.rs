@@ -1,66 +1,15 @@
+use crate::manager::device::VirtualDevice;
+use crate::manager::sysfs::battery_properties;
 use crate::protocol::*;
 use anyhow::Result;
-use std::mem::size_of;
+use std::collections::HashMap;
 use std::path::Path;
+use std::sync::Arc;
 use tokio::net::{UnixListener, UnixStream};
-use tokio::sync::broadcast;
+use tokio::sync::{Mutex, broadcast};
 use tracing::{debug, error, info};
 
-#[repr(C)]
-struct MonitorNetlinkHeader {
-    prefix: [u8; 8], // "libudev\0"
-    magic: u32,      // 0xfeedcafe (big-endian)
-    header_size: u32,
-    properties_off: u32,
-    properties_len: u32,
-    filter_subsystem_hash: u32,
-    filter_devtype_hash: u32,
-    filter_tag_bloom_hi: u32,
-    filter_tag_bloom_lo: u32,
-}
-
-/// MurmurHash2 - needed for subsystem/devtype hashing
-fn murmur_hash2(data: &[u8], seed: u32) -> u32 {
-    const M: u32 = 0x5bd1e995;
-    const R: i32 = 24;
-
-    let mut h: u32 = seed ^ (data.len() as u32);
-    let mut chunks = data.chunks_exact(4);
-
-    for chunk in &mut chunks {
-        let mut k = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
-        k = k.wrapping_mul(M);
-        k ^= k >> R;
-        k = k.wrapping_mul(M);
-        h = h.wrapping_mul(M);
-        h ^= k;
-    }
-
-    let remainder = chunks.remainder();
-    match remainder.len() {
-        3 => {
-            h ^= (remainder[2] as u32) << 16;
-            h ^= (remainder[1] as u32) << 8;
-            h ^= remainder[0] as u32;
-            h = h.wrapping_mul(M);
-        }
-        2 => {
-            h ^= (remainder[1] as u32) << 8;
-            h ^= remainder[0] as u32;
-            h = h.wrapping_mul(M);
-        }
-        1 => {
-            h ^= remainder[0] as u32;
-            h = h.wrapping_mul(M);
-        }
-        _ => {}
-    }
-
-    h ^= h >> 13;
-    h = h.wrapping_mul(M);
-    h ^= h >> 15;
-    h
-}
+pub mod wire;
 
 /// A udev event (hotplug notification)
 #[derive(Debug, Clone)]
@@ -69,12 +18,32 @@ pub struct UdevEvent {
     pub device_info: UdevDeviceInfo,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum UdevAction {
     Add,
     Remove,
     Change,
 }
+impl UdevAction {
+    /// Action name as used on the wire
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            UdevAction::Add => "add",
+            UdevAction::Remove => "remove",
+            UdevAction::Change => "change",
+        }
+    }
+
+    /// Parse an action name
+    pub fn parse(action: &str) -> Option<Self> {
+        match action {
+            "add" => Some(UdevAction::Add),
+            "remove" => Some(UdevAction::Remove),
+            "change" => Some(UdevAction::Change),
+            _ => None,
+        }
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct UdevDeviceInfo {
@@ -86,14 +55,58 @@ pub struct UdevDeviceInfo {
     pub properties: Vec<(String, String)>,
 }
 
+/// Event of a device's joystick node alone, for it being added or removed at runtime
+pub(crate) fn joystick_event(
+    node_prefix: &str,
+    action: UdevAction,
+    device_id: DeviceId,
+    config: &DeviceConfig,
+) -> UdevEvent {
+    let js_node = joystick_node_name(node_prefix, device_id);
+    let input_node = input_node_name(node_prefix, device_id);
+
+    let mut properties = vec![
+        ("MAJOR".to_string(), "81".to_string()),
+        ("MINOR".to_string(), device_id.to_string()),
+        ("ID_INPUT".to_string(), "1".to_string()),
+        ("ID_INPUT_JOYSTICK".to_string(), "1".to_string()),
+        (
+            "ID_VENDOR_ID".to_string(),
+            format!("{:04x}", config.vendor_id),
+        ),
+        (
+            "ID_MODEL_ID".to_string(),
+            format!("{:04x}", config.product_id),
+        ),
+        ("ID_BUS".to_string(), config.udev_bus_name().to_string()),
+    ];
+    if action != UdevAction::Remove {
+        config.merge_extra_udev_properties(&mut properties);
+    }
+
+    UdevEvent {
+        action,
+        device_info: UdevDeviceInfo {
+            subsystem: "input".to_string(),
+            devtype: "".to_string(),
+            devname: format!("/dev/input/{}", js_node),
+            devpath: format!("/devices/virtual/input/{}/{}", input_node, js_node),
+            syspath: format!("/sys/devices/virtual/input/{}/{}", input_node, js_node),
+            properties,
+        },
+    }
+}
+
 /// Udev event broadcaster
 pub struct UdevBroadcaster {
     listener: UnixListener,
     event_tx: broadcast::Sender<UdevEvent>,
+    /// Prefix of the device node names
+    node_prefix: String,
 }
 impl UdevBroadcaster {
     /// Create a new udev broadcaster
-    pub fn new(base_path: &Path) -> Result<Self> {
+    pub fn new(base_path: &Path, node_prefix: &str) -> Result<Self> {
         let socket_path = base_path.join("udev");
 
         // Remove old socket if exists
@@ -106,11 +119,18 @@ impl UdevBroadcaster {
         // Create broadcast channel for events
         let (event_tx, _) = broadcast::channel(100);
 
-        Ok(Self { listener, event_tx })
+        Ok(Self {
+            listener,
+            event_tx,
+            node_prefix: node_prefix.to_string(),
+        })
     }
 
     /// Start accepting udev monitor connections
-    pub async fn run(&self) {
+    ///
+    /// A new monitor first gets add events for the devices that already exist, like an
+    /// application enumerating right after setting up its monitor would find them.
+    pub async fn run(&self, devices: Arc<Mutex<HashMap<DeviceId, Arc<VirtualDevice>>>>) {
         let listener = &self.listener;
         let event_tx = self.event_tx.clone();
 
@@ -119,10 +139,23 @@ impl UdevBroadcaster {
                 Ok((stream, _addr)) => {
                     info!("udev monitor connected");
 
-                    let mut event_rx = event_tx.subscribe();
+                    // Devices are added and removed along with their events under the registry
+                    // lock, so subscribing under it too gets each device either in the snapshot
+                    // or as an event, never missed or twice
+                    let (mut event_rx, initial) = {
+                        let devices = devices.lock().await;
+                        let event_rx = event_tx.subscribe();
+                        let mut existing: Vec<_> = devices.values().cloned().collect();
+                        existing.sort_by_key(|d| d.id);
+                        let initial: Vec<_> = existing
+                            .iter()
+                            .map(|d| self.add_event(d.id, &d.current_config()))
+                            .collect();
+                        (event_rx, initial)
+                    };
 
                     tokio::spawn(async move {
-                        if let Err(e) = Self::handle_monitor(stream, &mut event_rx).await {
+                        if let Err(e) = Self::handle_monitor(stream, initial, &mut event_rx).await {
                             debug!("udev monitor disconnected: {}", e);
                         }
                     });
@@ -137,6 +170,7 @@ impl UdevBroadcaster {
     /// Handle a single udev monitor connection
     async fn handle_monitor(
         stream: UnixStream,
+        initial: Vec<UdevEvent>,
         event_rx: &mut broadcast::Receiver<UdevEvent>,
     ) -> Result<()> {
         use tokio::io::{AsyncReadExt, AsyncWriteExt};
@@ -163,7 +197,14 @@ impl UdevBroadcaster {
             }
         });
 
-        // WRITE events to monitor
+        // WRITE events to monitor, existing devices first
+        for event in &initial {
+            write_half
+                .write_all(&Self::format_udev_message(event))
+                .await?;
+        }
+        write_half.flush().await?;
+
         loop {
             match event_rx.recv().await {
                 Ok(event) => {
@@ -183,67 +224,27 @@ impl UdevBroadcaster {
 
     /// Format a udev event
     pub(crate) fn format_udev_message(event: &UdevEvent) -> Vec<u8> {
-        let action = match event.action {
-            UdevAction::Add => "add",
-            UdevAction::Remove => "remove",
-            UdevAction::Change => "change",
-        };
-
-        // Build properties string (null-separated, double-null terminated)
-        let mut properties = String::new();
-        properties.push_str(&format!("ACTION={}\0", action));
-        properties.push_str(&format!("DEVPATH={}\0", event.device_info.devpath));
-        properties.push_str(&format!("SUBSYSTEM={}\0", event.device_info.subsystem));
-        properties.push_str(&format!("DEVNAME={}\0", event.device_info.devname));
-
-        for (key, value) in &event.device_info.properties {
-            properties.push_str(&format!("{}={}\0", key, value));
-        }
-        properties.push('\0'); // Double null terminator
-
-        // Calculate hashes for filtering
-        let subsystem_hash = murmur_hash2(event.device_info.subsystem.as_bytes(), 0);
-        let devtype_hash = if !event.device_info.devtype.is_empty() {
-            murmur_hash2(event.device_info.devtype.as_bytes(), 0)
-        } else {
-            0
-        };
-
-        // Build header
-        let header = MonitorNetlinkHeader {
-            prefix: *b"libudev\0",
-            magic: 0xfeedcafe_u32.to_be(),
-            header_size: size_of::<MonitorNetlinkHeader>() as u32,
-            properties_off: size_of::<MonitorNetlinkHeader>() as u32,
-            properties_len: properties.len() as u32,
-            filter_subsystem_hash: subsystem_hash.to_be(),
-            filter_devtype_hash: devtype_hash.to_be(),
-            filter_tag_bloom_hi: 0,
-            filter_tag_bloom_lo: 0,
-        };
-
-        // Combine header + properties
-        let mut message = Vec::new();
+        wire::encode_event(event)
+    }
 
-        // Copy header as bytes
-        unsafe {
-            let header_bytes = std::slice::from_raw_parts(
-                &header as *const _ as *const u8,
-                size_of::<MonitorNetlinkHeader>(),
-            );
-            message.extend_from_slice(header_bytes);
-        }
+    /// Broadcast a device add event
+    pub fn broadcast_add(&self, device_id: DeviceId, config: &DeviceConfig) -> Result<()> {
+        self.event_tx
+            .send(self.add_event(device_id, config))
+            .map_err(|_| anyhow::anyhow!("No receivers"))?;
 
-        // Add properties
-        message.extend_from_slice(properties.as_bytes());
+        info!(
+            "Broadcasted device add event for {}",
+            event_node_name(&self.node_prefix, device_id)
+        );
 
-        message
+        Ok(())
     }
 
-    /// Broadcast a device add event
-    pub fn broadcast_add(&self, device_id: DeviceId, config: &DeviceConfig) -> Result<()> {
-        let event_node = format!("event{}", device_id);
-        let input_node = format!("input{}", device_id);
+    /// Add event of a device
+    fn add_event(&self, device_id: DeviceId, config: &DeviceConfig) -> UdevEvent {
+        let event_node = event_node_name(&self.node_prefix, device_id);
+        let input_node = input_node_name(&self.node_prefix, device_id);
 
         let unique_name = format!("{} ({})", config.name, event_node);
 
@@ -252,7 +253,7 @@ impl UdevBroadcaster {
             ("ID_INPUT_JOYSTICK".to_string(), "1".to_string()),
             (
                 "ID_MODEL".to_string(),
-                format!("{}_{}", config.name.replace(' ', "_"), device_id),
+                format!("{}_{}", config.udev_model().replace(' ', "_"), device_id),
             ),
             (
                 "ID_VENDOR_ID".to_string(),
@@ -262,20 +263,16 @@ impl UdevBroadcaster {
                 "ID_MODEL_ID".to_string(),
                 format!("{:04x}", config.product_id),
             ),
-            (
-                "ID_BUS".to_string(),
-                match config.bustype {
-                    BusType::Usb => "usb".to_string(),
-                    BusType::Bluetooth => "bluetooth".to_string(),
-                    BusType::Virtual => "virtual".to_string(),
-                },
-            ),
+            ("ID_BUS".to_string(), config.udev_bus_name().to_string()),
             ("NAME".to_string(), format!("\"{}\"", unique_name)),
             (
                 "PRODUCT".to_string(),
                 format!(
                     "{:x}/{:x}/{:x}/{:x}",
-                    config.bustype as u16, config.vendor_id, config.product_id, config.version
+                    config.bustype.to_u16(),
+                    config.vendor_id,
+                    config.product_id,
+                    config.version
                 ),
             ),
             ("ID_SERIAL".to_string(), format!("vimputti_{}", event_node)),
@@ -287,8 +284,12 @@ impl UdevBroadcaster {
             properties.push(("BUSNUM".to_string(), "253".to_string()));
             properties.push(("DEVNUM".to_string(), format!("{:03}", device_id + 1)));
         }
+        if let Some(battery) = &config.battery {
+            properties.extend(battery_properties(&self.node_prefix, device_id, battery));
+        }
+        config.merge_extra_udev_properties(&mut properties);
 
-        let event = UdevEvent {
+        UdevEvent {
             action: UdevAction::Add,
             device_info: UdevDeviceInfo {
                 subsystem: "input".to_string(),
@@ -298,21 +299,29 @@ impl UdevBroadcaster {
                 syspath: format!("/sys/devices/virtual/input/{}/{}", input_node, event_node),
                 properties,
             },
-        };
+        }
+    }
 
+    /// Broadcast a device change event, e.g. after its ids changed
+    pub fn broadcast_change(&self, device_id: DeviceId, config: &DeviceConfig) -> Result<()> {
+        let mut event = self.add_event(device_id, config);
+        event.action = UdevAction::Change;
         self.event_tx
             .send(event)
             .map_err(|_| anyhow::anyhow!("No receivers"))?;
 
-        info!("Broadcasted device add event for {}", event_node);
+        info!(
+            "Broadcasted device change event for {}",
+            event_node_name(&self.node_prefix, device_id)
+        );
 
         Ok(())
     }
 
     /// Broadcast a device remove event
     pub fn broadcast_remove(&self, device_id: DeviceId, config: &DeviceConfig) -> Result<()> {
-        let event_node = format!("event{}", device_id);
-        let input_node = format!("input{}", device_id);
+        let event_node = event_node_name(&self.node_prefix, device_id);
+        let input_node = input_node_name(&self.node_prefix, device_id);
 
         let unique_name = format!("{} ({})", config.name, event_node);
 
@@ -328,7 +337,7 @@ impl UdevBroadcaster {
                     ("NAME".to_string(), format!("\"{}\"", unique_name)),
                     (
                         "ID_MODEL".to_string(),
-                        format!("{}_{}", config.name.replace(' ', "_"), device_id),
+                        format!("{}_{}", config.udev_model().replace(' ', "_"), device_id),
                     ),
                     ("ID_SERIAL".to_string(), format!("vimputti_{}", event_node)),
                     ("ID_SERIAL_SHORT".to_string(), event_node.clone()),
@@ -357,6 +366,26 @@ impl UdevBroadcaster {
         Ok(())
     }
 
+    /// Broadcast the joystick node of a device being added or removed on its own
+    pub fn broadcast_joystick(
+        &self,
+        action: UdevAction,
+        device_id: DeviceId,
+        config: &DeviceConfig,
+    ) -> Result<()> {
+        self.event_tx
+            .send(joystick_event(&self.node_prefix, action, device_id, config))
+            .map_err(|_| anyhow::anyhow!("No receivers"))?;
+
+        info!(
+            "Broadcasted joystick {} event for {}",
+            action.as_str(),
+            joystick_node_name(&self.node_prefix, device_id)
+        );
+
+        Ok(())
+    }
+
     /// Get a clone of the event sender (for other components to broadcast events)
     pub fn event_sender(&self) -> broadcast::Sender<UdevEvent> {
         self.event_tx.clone()
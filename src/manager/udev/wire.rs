@@ -0,0 +1,312 @@
+//! udev monitor wire format
+//!
+//! Shared by the broadcasters and the shim, so the encoder and the decoder can't drift apart.
+
+use super::{UdevAction, UdevDeviceInfo, UdevEvent};
+use std::mem::size_of;
+
+/// "libudev\0" prefix of monitor messages
+const LIBUDEV_PREFIX: &[u8; 8] = b"libudev\0";
+/// Monitor header magic
+const LIBUDEV_MAGIC: u32 = 0xfeedcafe;
+
+#[repr(C)]
+struct MonitorNetlinkHeader {
+    prefix: [u8; 8], // "libudev\0"
+    magic: u32,      // 0xfeedcafe (big-endian)
+    header_size: u32,
+    properties_off: u32,
+    properties_len: u32,
+    filter_subsystem_hash: u32,
+    filter_devtype_hash: u32,
+    filter_tag_bloom_hi: u32,
+    filter_tag_bloom_lo: u32,
+}
+
+/// MurmurHash2 - needed for subsystem/devtype hashing
+pub fn murmur_hash2(data: &[u8], seed: u32) -> u32 {
+    const M: u32 = 0x5bd1e995;
+    const R: i32 = 24;
+
+    let mut h: u32 = seed ^ (data.len() as u32);
+    let mut chunks = data.chunks_exact(4);
+
+    for chunk in &mut chunks {
+        let mut k = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+        k = k.wrapping_mul(M);
+        k ^= k >> R;
+        k = k.wrapping_mul(M);
+        h = h.wrapping_mul(M);
+        h ^= k;
+    }
+
+    let remainder = chunks.remainder();
+    match remainder.len() {
+        3 => {
+            h ^= (remainder[2] as u32) << 16;
+            h ^= (remainder[1] as u32) << 8;
+            h ^= remainder[0] as u32;
+            h = h.wrapping_mul(M);
+        }
+        2 => {
+            h ^= (remainder[1] as u32) << 8;
+            h ^= remainder[0] as u32;
+            h = h.wrapping_mul(M);
+        }
+        1 => {
+            h ^= remainder[0] as u32;
+            h = h.wrapping_mul(M);
+        }
+        _ => {}
+    }
+
+    h ^= h >> 13;
+    h = h.wrapping_mul(M);
+    h ^= h >> 15;
+    h
+}
+
+/// Subsystem/devtype filter hash as libudev computes it
+pub fn filter_hash(value: &str) -> u32 {
+    if value.is_empty() {
+        0
+    } else {
+        murmur_hash2(value.as_bytes(), 0)
+    }
+}
+
+/// Null-separated KEY=value properties of an event, in the order libudev expects
+fn encode_properties(event: &UdevEvent, message: &mut Vec<u8>) {
+    let info = &event.device_info;
+    message.extend_from_slice(format!("ACTION={}\0", event.action.as_str()).as_bytes());
+    message.extend_from_slice(format!("DEVPATH={}\0", info.devpath).as_bytes());
+    message.extend_from_slice(format!("SUBSYSTEM={}\0", info.subsystem).as_bytes());
+    if !info.devtype.is_empty() {
+        message.extend_from_slice(format!("DEVTYPE={}\0", info.devtype).as_bytes());
+    }
+    if !info.devname.is_empty() {
+        message.extend_from_slice(format!("DEVNAME={}\0", info.devname).as_bytes());
+    }
+
+    for (key, value) in &info.properties {
+        message.extend_from_slice(format!("{}={}\0", key, value).as_bytes());
+    }
+}
+
+/// Encode an event as a libudev monitor message (header + properties)
+pub fn encode_event(event: &UdevEvent) -> Vec<u8> {
+    let mut properties = Vec::new();
+    encode_properties(event, &mut properties);
+    properties.push(0); // Double null terminator
+
+    let header = MonitorNetlinkHeader {
+        prefix: *LIBUDEV_PREFIX,
+        magic: LIBUDEV_MAGIC.to_be(),
+        header_size: size_of::<MonitorNetlinkHeader>() as u32,
+        properties_off: size_of::<MonitorNetlinkHeader>() as u32,
+        properties_len: properties.len() as u32,
+        filter_subsystem_hash: filter_hash(&event.device_info.subsystem).to_be(),
+        filter_devtype_hash: filter_hash(&event.device_info.devtype).to_be(),
+        filter_tag_bloom_hi: 0,
+        filter_tag_bloom_lo: 0,
+    };
+
+    let mut message = Vec::with_capacity(size_of::<MonitorNetlinkHeader>() + properties.len());
+
+    // Copy header as bytes
+    unsafe {
+        let header_bytes = std::slice::from_raw_parts(
+            &header as *const _ as *const u8,
+            size_of::<MonitorNetlinkHeader>(),
+        );
+        message.extend_from_slice(header_bytes);
+    }
+
+    message.extend_from_slice(&properties);
+    message
+}
+
+/// Encode an event in the kernel uevent format ("action@devpath\0KEY=value\0...")
+pub fn encode_kernel_event(event: &UdevEvent, seqnum: u64) -> Vec<u8> {
+    let mut message = Vec::new();
+    message.extend_from_slice(
+        format!("{}@{}\0", event.action.as_str(), event.device_info.devpath).as_bytes(),
+    );
+
+    let mut properties = Vec::new();
+    encode_properties(event, &mut properties);
+    message.extend_from_slice(&properties);
+
+    // udevadm expects a sequence number
+    message.extend_from_slice(format!("SEQNUM={}\0", seqnum).as_bytes());
+    message
+}
+
+/// Field of a libudev monitor message header, in the sender's byte order
+fn header_u32(data: &[u8], offset: usize) -> Option<u32> {
+    let bytes = data.get(offset..offset + 4)?;
+    Some(u32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+/// Check for a complete libudev monitor message header at the start of `data`
+fn has_header(data: &[u8]) -> bool {
+    data.starts_with(LIBUDEV_PREFIX)
+        && data.len() >= size_of::<MonitorNetlinkHeader>()
+        && header_u32(data, 8).map(u32::from_be) == Some(LIBUDEV_MAGIC)
+}
+
+/// Length of the libudev monitor message at the start of `data`
+///
+/// Messages arrive back to back on a stream, this tells where the next one begins. Returns
+/// None while the header is incomplete or the data isn't a monitor message.
+pub fn message_len(data: &[u8]) -> Option<usize> {
+    if !has_header(data) {
+        return None;
+    }
+    let properties_off = header_u32(data, 16)? as usize;
+    let properties_len = header_u32(data, 20)? as usize;
+    properties_off.checked_add(properties_len)
+}
+
+/// Subsystem and devtype filter hashes from the header of a libudev monitor message
+///
+/// Lets monitors skip events of other subsystems without decoding them, as libudev's socket
+/// filter does. A devtype hash of 0 means the event has no devtype.
+pub fn filter_hashes(data: &[u8]) -> Option<(u32, u32)> {
+    if !has_header(data) {
+        return None;
+    }
+    Some((
+        u32::from_be(header_u32(data, 24)?),
+        u32::from_be(header_u32(data, 28)?),
+    ))
+}
+
+/// Decode a libudev monitor message or a kernel uevent back into an event
+///
+/// The syspath isn't part of the wire format, it is derived from DEVPATH.
+pub fn decode_event(data: &[u8]) -> Option<UdevEvent> {
+    let properties = if data.starts_with(LIBUDEV_PREFIX) {
+        let properties_off = header_u32(data, 16)? as usize;
+        data.get(properties_off..message_len(data)?)?
+    } else {
+        // Kernel format, skip the "action@devpath" summary
+        let summary_end = data.iter().position(|&b| b == 0)?;
+        if !data[..summary_end].contains(&b'@') {
+            return None;
+        }
+        &data[summary_end + 1..]
+    };
+
+    let mut action = None;
+    let mut info = UdevDeviceInfo {
+        subsystem: String::new(),
+        devtype: String::new(),
+        devname: String::new(),
+        devpath: String::new(),
+        syspath: String::new(),
+        properties: Vec::new(),
+    };
+
+    for entry in properties.split(|&b| b == 0) {
+        if entry.is_empty() {
+            continue;
+        }
+        let entry = String::from_utf8_lossy(entry);
+        let Some((key, value)) = entry.split_once('=') else {
+            continue;
+        };
+        match key {
+            "ACTION" => action = UdevAction::parse(value),
+            "DEVPATH" => info.devpath = value.to_string(),
+            "SUBSYSTEM" => info.subsystem = value.to_string(),
+            "DEVTYPE" => info.devtype = value.to_string(),
+            "DEVNAME" => info.devname = value.to_string(),
+            _ => info.properties.push((key.to_string(), value.to_string())),
+        }
+    }
+
+    if !info.devpath.is_empty() {
+        info.syspath = format!("/sys{}", info.devpath);
+    }
+
+    Some(UdevEvent {
+        action: action?,
+        device_info: info,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn add_event() -> UdevEvent {
+        UdevEvent {
+            action: UdevAction::Add,
+            device_info: UdevDeviceInfo {
+                subsystem: "input".to_string(),
+                devtype: String::new(),
+                devname: "/dev/input/event0".to_string(),
+                devpath: "/devices/virtual/input/input0/event0".to_string(),
+                syspath: "/sys/devices/virtual/input/input0/event0".to_string(),
+                properties: vec![
+                    ("MAJOR".to_string(), "13".to_string()),
+                    ("MINOR".to_string(), "64".to_string()),
+                    ("ID_INPUT_JOYSTICK".to_string(), "1".to_string()),
+                ],
+            },
+        }
+    }
+
+    fn assert_same(decoded: &UdevEvent, event: &UdevEvent) {
+        assert_eq!(decoded.action, event.action);
+        let (decoded, info) = (&decoded.device_info, &event.device_info);
+        assert_eq!(decoded.subsystem, info.subsystem);
+        assert_eq!(decoded.devtype, info.devtype);
+        assert_eq!(decoded.devname, info.devname);
+        assert_eq!(decoded.devpath, info.devpath);
+        assert_eq!(decoded.syspath, info.syspath);
+        assert_eq!(decoded.properties, info.properties);
+    }
+
+    #[test]
+    fn murmur_hash2_known_values() {
+        assert_eq!(murmur_hash2(b"input", 0), 0xc1a28470);
+        assert_eq!(murmur_hash2(b"hidraw", 0), 0xc2caf397);
+        assert_eq!(murmur_hash2(b"usb_device", 0), 0x27f8f50c);
+        assert_eq!(filter_hash(""), 0);
+    }
+
+    #[test]
+    fn monitor_message_round_trip() {
+        let event = add_event();
+        let message = encode_event(&event);
+
+        assert_eq!(message_len(&message), Some(message.len()));
+        assert_eq!(filter_hashes(&message), Some((filter_hash("input"), 0)));
+        assert_same(&decode_event(&message).unwrap(), &event);
+    }
+
+    #[test]
+    fn kernel_message_round_trip() {
+        let event = add_event();
+        let message = encode_kernel_event(&event, 42);
+        assert!(message.starts_with(b"add@/devices/virtual/input/input0/event0\0"));
+
+        let decoded = decode_event(&message).unwrap();
+        let mut expected = event.clone();
+        expected
+            .device_info
+            .properties
+            .push(("SEQNUM".to_string(), "42".to_string()));
+        assert_same(&decoded, &expected);
+    }
+
+    #[test]
+    fn truncated_messages_are_rejected() {
+        let message = encode_event(&add_event());
+        assert_eq!(message_len(&message[..20]), None);
+        assert!(decode_event(&message[..message.len() - 10]).is_none());
+        assert!(decode_event(b"not a udev message").is_none());
+    }
+}
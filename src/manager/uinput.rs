@@ -34,7 +34,8 @@ impl UinputDeviceState {
             .clone()
             .unwrap_or_else(|| "virtual uinput Device".to_string());
 
-        // Convert keys to buttons
+        // Convert keys to buttons. Keys and axes are looked up separately, so BTN_TL2 (the
+        // L2 click) and ABS_Z (the analog L2) end up as the button and the axis they are.
         let buttons = self
             .keys
             .iter()
@@ -46,28 +47,37 @@ impl UinputDeviceState {
             .abs_axes
             .iter()
             .filter_map(|(&code, info)| {
-                Axis::from_ev_code(code).map(|axis| AxisConfig {
-                    axis,
-                    min: info.minimum,
-                    max: info.maximum,
-                    fuzz: info.fuzz,
-                    flat: info.flat,
-                })
+                Axis::from_ev_code(code)
+                    .or_else(|| Axis::custom_abs(code))
+                    .map(|axis| AxisConfig {
+                        axis,
+                        min: info.minimum,
+                        max: info.maximum,
+                        fuzz: info.fuzz,
+                        flat: info.flat,
+                        center: None,
+                        smoothing: None,
+                        resolution: info.resolution,
+                    })
             })
             .collect();
 
+        let rel_axes = self
+            .rel_axes
+            .iter()
+            .filter_map(|&code| RelAxis::from_ev_code(code))
+            .collect();
+
         DeviceConfig {
             name,
             vendor_id: self.vendor_id,
             product_id: self.product_id,
             version: self.version,
-            bustype: match self.bustype {
-                0x03 => BusType::Usb,
-                0x05 => BusType::Bluetooth,
-                _ => BusType::Virtual,
-            },
+            bustype: BusType::from_u16(self.bustype),
             buttons,
             axes,
+            rel_axes,
+            ..Default::default()
         }
     }
 }
@@ -78,12 +88,15 @@ pub struct UinputEmulator {
     devices: Arc<Mutex<HashMap<DeviceId, Arc<VirtualDevice>>>>,
     next_device_id: Arc<Mutex<DeviceId>>,
     mirror_map: Arc<Mutex<HashMap<DeviceId, DeviceId>>>,
+    /// Whether mirrors take the name and ids of their source device instead of the consumer's
+    inherit_source_ids: bool,
 }
 impl UinputEmulator {
     pub fn new(
         base_path: impl AsRef<Path>,
         devices: Arc<Mutex<HashMap<DeviceId, Arc<VirtualDevice>>>>,
         next_device_id: Arc<Mutex<DeviceId>>,
+        inherit_source_ids: bool,
     ) -> Result<Self> {
         let base_path = base_path.as_ref().to_path_buf();
         let socket_path = base_path.join("uinput");
@@ -94,6 +107,7 @@ impl UinputEmulator {
             devices,
             next_device_id,
             mirror_map: Arc::new(Mutex::new(HashMap::new())),
+            inherit_source_ids,
         })
     }
 
@@ -119,21 +133,19 @@ impl UinputEmulator {
         loop {
             match listener.accept().await {
                 Ok((stream, _)) => {
-                    let devices = devices.clone();
-                    let next_device_id = self.next_device_id.clone();
-                    let base_path = self.base_path.clone();
-                    let mirror_map = self.mirror_map.clone();
+                    let session = UinputSession {
+                        state: UinputDeviceState::default(),
+                        bound_device_id: None,
+                        created_device_id: None,
+                        devices: devices.clone(),
+                        next_device_id: self.next_device_id.clone(),
+                        base_path: self.base_path.clone(),
+                        mirror_map: self.mirror_map.clone(),
+                        inherit_source_ids: self.inherit_source_ids,
+                    };
 
                     tokio::spawn(async move {
-                        if let Err(e) = Self::handle_client(
-                            stream,
-                            &devices,
-                            &next_device_id,
-                            &base_path,
-                            &mirror_map,
-                        )
-                        .await
-                        {
+                        if let Err(e) = Self::handle_client(stream, session).await {
                             error!("uinput client error: {}", e);
                         }
                     });
@@ -145,14 +157,27 @@ impl UinputEmulator {
         }
     }
 
+    /// Check if a device was created by a uinput session as a mirror
+    pub async fn is_mirror_device(&self, device_id: DeviceId) -> bool {
+        self.mirror_map
+            .lock()
+            .await
+            .values()
+            .any(|&mirror| mirror == device_id)
+    }
+
+    /// Send events sent to `source` to its uinput mirror, if it has one
+    ///
+    /// The mirror gets the events the source's readers see, after its button remapping.
     pub async fn mirror_to_uinput_devices(
         &self,
-        source_device_id: DeviceId,
-        events: &Vec<InputEvent>,
+        source: &VirtualDevice,
+        events: &[InputEvent],
     ) -> Result<()> {
         if events.is_empty() {
             return Ok(());
         }
+        let source_device_id = source.id;
 
         // Get mirror_id without holding lock
         let mirror_id = {
@@ -175,7 +200,8 @@ impl UinputEmulator {
             };
 
             if let Some(mirror_device) = mirror_device {
-                match mirror_device.send_events(events).await {
+                let events = source.config().emitted_events(events);
+                match mirror_device.send_events(&events).await {
                     Ok(()) => trace!("Mirrored successfully"),
                     Err(e) => warn!("Mirror send failed: {}", e),
                 }
@@ -187,95 +213,75 @@ impl UinputEmulator {
         Ok(())
     }
 
-    async fn handle_client(
-        mut stream: UnixStream,
-        devices: &Arc<Mutex<HashMap<DeviceId, Arc<VirtualDevice>>>>,
-        next_device_id: &Arc<Mutex<DeviceId>>,
-        base_path: &PathBuf,
-        mirror_map: &Arc<Mutex<HashMap<DeviceId, DeviceId>>>,
-    ) -> Result<()> {
-        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    async fn handle_client(mut stream: UnixStream, mut session: UinputSession) -> Result<()> {
+        use tokio::io::AsyncWriteExt;
 
         let session_id = ulid::Ulid::new();
         debug!("New uinput session {}", session_id);
 
-        let mut state = UinputDeviceState::default();
-        state.session_id = Some(session_id);
-        let mut bound_device_id: Option<DeviceId> = None;
-        let mut created_device_id: Option<DeviceId> = None;
+        session.state.session_id = Some(session_id);
 
         loop {
-            // Read 4-byte length prefix
-            let mut len_buf = [0u8; 4];
-            match stream.read_exact(&mut len_buf).await {
-                Ok(_) => {}
+            // A bad frame means the stream is out of sync, so the session can't continue
+            let (msg_buf, framing) = match framing::read_frame_with_framing_async(&mut stream).await
+            {
+                Ok(frame) => frame,
                 Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
                     debug!("uinput session {} disconnected", session_id);
                     break;
                 }
                 Err(e) => {
-                    error!("Error reading length from session {}: {}", session_id, e);
+                    error!("Error reading frame from session {}: {}", session_id, e);
                     break;
                 }
-            }
+            };
 
-            let msg_len = u32::from_le_bytes(len_buf) as usize;
-            if msg_len == 0 || msg_len > 1_000_000 {
-                error!(
-                    "Invalid message length {} from session {}",
-                    msg_len, session_id
-                );
-                break;
-            }
+            let response = match UinputRequest::from_bytes(&msg_buf) {
+                Ok(request) => {
+                    // Check if this is WriteEvents (fire-and-forget)
+                    let is_write_events = matches!(request, UinputRequest::WriteEvents { .. });
 
-            // Read message body
-            let mut msg_buf = vec![0u8; msg_len];
-            match stream.read_exact(&mut msg_buf).await {
-                Ok(_) => {}
-                Err(e) => {
-                    error!("Error reading message from session {}: {}", session_id, e);
-                    break;
-                }
-            }
+                    trace!("Session {}: request {:?}", session_id, request);
+
+                    let response = session.process_request(request).await;
 
-            let request: UinputRequest = match UinputRequest::from_bytes(&msg_buf) {
-                Ok(req) => req,
+                    // For WriteEvents, don't bother sending response (client won't read it anyway)
+                    if is_write_events {
+                        trace!(
+                            "Session {}: WriteEvents processed (no response sent)",
+                            session_id
+                        );
+                        continue; // Skip response sending
+                    }
+                    response
+                }
                 Err(e) => {
-                    error!("Failed to parse request from session {}: {}", session_id, e);
-                    continue;
+                    // Likely a request of a newer shim, the frame was read whole so the
+                    // session can go on. The shim waits for an answer unless it's WriteEvents.
+                    let name = UinputRequest::name_of(&msg_buf);
+                    warn!(
+                        "Unsupported request {:?} from session {}: {}",
+                        name, session_id, e
+                    );
+                    if name.as_deref() == Some("WriteEvents") {
+                        continue;
+                    }
+                    UinputResponse {
+                        success: false,
+                        device_id: None,
+                        error: Some(format!(
+                            "Unsupported request {}",
+                            name.as_deref().unwrap_or("(unnamed)")
+                        )),
+                    }
                 }
             };
 
-            // Check if this is WriteEvents (fire-and-forget)
-            let is_write_events = matches!(request, UinputRequest::WriteEvents { .. });
-
-            trace!("Session {}: request {:?}", session_id, request);
-
-            let response = Self::process_request(
-                request,
-                &mut state,
-                &mut bound_device_id,
-                &mut created_device_id,
-                devices,
-                next_device_id,
-                base_path,
-                mirror_map,
-            )
-            .await;
-
-            // For WriteEvents, don't bother sending response (client won't read it anyway)
-            if is_write_events {
-                trace!(
-                    "Session {}: WriteEvents processed (no response sent)",
-                    session_id
-                );
-                continue; // Skip response sending
-            }
-
             // For other requests (setup ioctls), send response normally
             trace!("Session {}: response {:?}", session_id, response);
 
-            let response_bytes = match response.to_bytes() {
+            // Shims from before the frame header only read answers in the legacy framing
+            let response_bytes = match response.to_bytes_in(framing) {
                 Ok(b) => b,
                 Err(e) => {
                     error!("Failed to serialize response: {}", e);
@@ -301,33 +307,39 @@ impl UinputEmulator {
         }
 
         // Cleanup
-        if let Some(device_id) = created_device_id {
+        if let Some(device_id) = session.created_device_id {
             info!(
                 "Session {} cleanup: removing device {}",
                 session_id, device_id
             );
-            devices.lock().await.remove(&device_id);
+            session.devices.lock().await.remove(&device_id);
         }
 
         debug!("uinput session {} exiting", session_id);
         Ok(())
     }
+}
 
-    async fn process_request(
-        request: UinputRequest,
-        state: &mut UinputDeviceState,
-        created_device_id: &mut Option<DeviceId>,
-        bound_device_id: &mut Option<DeviceId>,
-        devices: &Arc<Mutex<HashMap<DeviceId, Arc<VirtualDevice>>>>,
-        next_device_id: &Arc<Mutex<DeviceId>>,
-        base_path: &Path,
-        mirror_map: &Arc<Mutex<HashMap<DeviceId, DeviceId>>>,
-    ) -> UinputResponse {
+/// A connection to the uinput socket, setting up and then writing to one device
+struct UinputSession {
+    state: UinputDeviceState,
+    /// Device the session's events go to
+    bound_device_id: Option<DeviceId>,
+    /// Device the session created, removed when the session ends
+    created_device_id: Option<DeviceId>,
+    devices: Arc<Mutex<HashMap<DeviceId, Arc<VirtualDevice>>>>,
+    next_device_id: Arc<Mutex<DeviceId>>,
+    base_path: PathBuf,
+    mirror_map: Arc<Mutex<HashMap<DeviceId, DeviceId>>>,
+    inherit_source_ids: bool,
+}
+impl UinputSession {
+    async fn process_request(&mut self, request: UinputRequest) -> UinputResponse {
         match request {
             UinputRequest::SetEvBit { ev_type } => {
                 trace!("SetEvBit: {}", ev_type);
-                if !state.ev_types.contains(&ev_type) {
-                    state.ev_types.push(ev_type);
+                if !self.state.ev_types.contains(&ev_type) {
+                    self.state.ev_types.push(ev_type);
                 }
                 UinputResponse {
                     success: true,
@@ -338,8 +350,8 @@ impl UinputEmulator {
 
             UinputRequest::SetKeyBit { key_code } => {
                 trace!("SetKeyBit: {}", key_code);
-                if !state.keys.contains(&key_code) {
-                    state.keys.push(key_code);
+                if !self.state.keys.contains(&key_code) {
+                    self.state.keys.push(key_code);
                 }
                 UinputResponse {
                     success: true,
@@ -351,14 +363,17 @@ impl UinputEmulator {
             UinputRequest::SetAbsBit { abs_code } => {
                 trace!("SetAbsBit: {}", abs_code);
                 // Add with default range if not already configured
-                state.abs_axes.entry(abs_code).or_insert(LinuxAbsEvent {
-                    value: 0,
-                    minimum: -32768,
-                    maximum: 32767,
-                    fuzz: 16,
-                    flat: 128,
-                    resolution: 0,
-                });
+                self.state
+                    .abs_axes
+                    .entry(abs_code)
+                    .or_insert(LinuxAbsEvent {
+                        value: 0,
+                        minimum: -32768,
+                        maximum: 32767,
+                        fuzz: 16,
+                        flat: 128,
+                        resolution: 0,
+                    });
                 UinputResponse {
                     success: true,
                     device_id: None,
@@ -368,8 +383,8 @@ impl UinputEmulator {
 
             UinputRequest::SetRelBit { rel_code } => {
                 trace!("SetRelBit: {}", rel_code);
-                if !state.rel_axes.contains(&rel_code) {
-                    state.rel_axes.push(rel_code);
+                if !self.state.rel_axes.contains(&rel_code) {
+                    self.state.rel_axes.push(rel_code);
                 }
                 UinputResponse {
                     success: true,
@@ -383,7 +398,7 @@ impl UinputEmulator {
                     "AbsSetup: code={}, range=[{}, {}]",
                     code, absinfo.minimum, absinfo.maximum
                 );
-                state.abs_axes.insert(code, absinfo);
+                self.state.abs_axes.insert(code, absinfo);
                 UinputResponse {
                     success: true,
                     device_id: None,
@@ -393,11 +408,11 @@ impl UinputEmulator {
 
             UinputRequest::DevSetup { setup } => {
                 trace!("DevSetup: {}", setup.name);
-                state.name = Some(setup.name);
-                state.vendor_id = setup.vendor_id;
-                state.product_id = setup.product_id;
-                state.version = setup.version;
-                state.bustype = setup.bustype;
+                self.state.name = Some(setup.name);
+                self.state.vendor_id = setup.vendor_id;
+                self.state.product_id = setup.product_id;
+                self.state.version = setup.version;
+                self.state.bustype = setup.bustype;
                 UinputResponse {
                     success: true,
                     device_id: None,
@@ -406,16 +421,16 @@ impl UinputEmulator {
             }
 
             UinputRequest::DevCreate {} => {
-                let config = state.to_device_config();
+                let mut config = self.state.to_device_config();
                 info!(
                     "DevCreate session {:?}: Creating mirror device for Steam Input",
-                    state.session_id
+                    self.state.session_id
                 );
 
                 // Get the next unmirrored device
                 let source_device_id = {
-                    let devices_lock = devices.lock().await;
-                    let map = mirror_map.lock().await;
+                    let devices_lock = self.devices.lock().await;
+                    let map = self.mirror_map.lock().await;
 
                     // Find first device that doesn't have a mirror mapping yet
                     devices_lock
@@ -436,33 +451,61 @@ impl UinputEmulator {
 
                 // Create new device for Steam's output
                 let mirror_device_id = {
-                    let mut next_id = next_device_id.lock().await;
+                    let mut next_id = self.next_device_id.lock().await;
                     let id = *next_id;
                     *next_id += 1;
                     id
                 };
 
-                match VirtualDevice::create(mirror_device_id, config.clone(), base_path).await {
+                // Mirrors log, are named and pace frames like the device they mirror
+                let (event_log, node_prefix, frame_drain_timeout) =
+                    match self.devices.lock().await.get(&source_device_id) {
+                        Some(source) => {
+                            if self.inherit_source_ids {
+                                // Games re-detecting the mirror by id see the original controller
+                                let source_config = source.current_config();
+                                config.name = source_config.name;
+                                config.bustype = source_config.bustype;
+                                config.vendor_id = source_config.vendor_id;
+                                config.product_id = source_config.product_id;
+                                config.version = source_config.version;
+                            }
+                            (
+                                source.event_log_config(),
+                                source.node_prefix().to_string(),
+                                source.frame_drain_timeout(),
+                            )
+                        }
+                        None => Default::default(),
+                    };
+
+                match VirtualDevice::create(
+                    mirror_device_id,
+                    config.clone(),
+                    &self.base_path,
+                    &node_prefix,
+                    event_log,
+                    frame_drain_timeout,
+                )
+                .await
+                {
                     Ok(device) => {
                         let event_node = device.event_node.clone();
-                        devices
-                            .lock()
-                            .await
-                            .insert(mirror_device_id, Arc::new(device));
+                        self.devices.lock().await.insert(mirror_device_id, device);
 
                         // Set up mirroring: source_device -> mirror_device
-                        mirror_map
+                        self.mirror_map
                             .lock()
                             .await
                             .insert(source_device_id, mirror_device_id);
 
                         info!(
                             "Session {:?}: Created mirror device {} as {} (mirrors device {})",
-                            state.session_id, mirror_device_id, event_node, source_device_id
+                            self.state.session_id, mirror_device_id, event_node, source_device_id
                         );
 
-                        *bound_device_id = Some(mirror_device_id);
-                        *created_device_id = Some(mirror_device_id);
+                        self.bound_device_id = Some(mirror_device_id);
+                        self.created_device_id = Some(mirror_device_id);
 
                         UinputResponse {
                             success: true,
@@ -482,18 +525,18 @@ impl UinputEmulator {
             }
 
             UinputRequest::DevDestroy {} => {
-                if let Some(device_id) = created_device_id.take() {
+                if let Some(device_id) = self.created_device_id.take() {
                     info!(
                         "Session {:?}: Destroying mirror device {}",
-                        state.session_id, device_id
+                        self.state.session_id, device_id
                     );
 
                     // Remove from devices first
-                    devices.lock().await.remove(&device_id);
+                    self.devices.lock().await.remove(&device_id);
 
                     // Remove mirror mapping
                     {
-                        let mut map = mirror_map.lock().await;
+                        let mut map = self.mirror_map.lock().await;
                         let to_remove: Vec<_> = map
                             .iter()
                             .filter(|&(_, &mirror)| mirror == device_id)
@@ -506,7 +549,7 @@ impl UinputEmulator {
                         }
                     }
                 }
-                *bound_device_id = None;
+                self.bound_device_id = None;
 
                 UinputResponse {
                     success: true,
@@ -518,22 +561,22 @@ impl UinputEmulator {
             UinputRequest::WriteEvents { events } => {
                 trace!(
                     "WriteEvents: session {:?}, {} events",
-                    state.session_id,
+                    self.state.session_id,
                     events.len()
                 );
-                if events.is_empty() || bound_device_id.is_none() {
+                if events.is_empty() || self.bound_device_id.is_none() {
                     return UinputResponse {
                         success: true,
-                        device_id: *bound_device_id,
+                        device_id: self.bound_device_id,
                         error: None,
                     };
                 }
 
-                let device_id = bound_device_id.unwrap();
+                let device_id = self.bound_device_id.unwrap();
 
                 trace!(
                     "Session {:?}: Forwarding {} remapped events to device {}",
-                    state.session_id,
+                    self.state.session_id,
                     events.len(),
                     device_id
                 );
@@ -541,18 +584,8 @@ impl UinputEmulator {
                 // Convert to InputEvents and forward to the mirror device
                 let input_events: Vec<InputEvent> = events
                     .iter()
-                    .filter_map(|e| match e.event_type {
-                        EV_KEY => Button::from_ev_code(e.code).map(|button| InputEvent::Button {
-                            button,
-                            pressed: e.value != 0,
-                        }),
-                        EV_ABS => Axis::from_ev_code(e.code).map(|axis| InputEvent::Axis {
-                            axis,
-                            value: e.value,
-                        }),
-                        EV_SYN => Some(InputEvent::Sync),
-                        _ => None,
-                    })
+                    .map(InputEvent::from_linux_input_event)
+                    .filter(|e| !matches!(e, InputEvent::Raw { .. }))
                     .collect();
 
                 if input_events.is_empty() {
@@ -565,7 +598,7 @@ impl UinputEmulator {
 
                 // Forward to mirror device (device1)
                 let device = {
-                    let devices_lock = devices.lock().await;
+                    let devices_lock = self.devices.lock().await;
                     devices_lock.get(&device_id).cloned()
                 };
 
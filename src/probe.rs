@@ -0,0 +1,221 @@
+//! Probing real input devices of the host
+//!
+//! Reads the identity and capabilities of an evdev node into a `DeviceConfig`, so a virtual
+//! device mirroring a real one can be created for reproducing issues. Devices are opened and
+//! queried through raw syscalls, bypassing the shim if it's loaded into the process.
+
+use crate::protocol::linux::{
+    EVIOCGID, EVIOCGVERSION, EVIOCSCLOCKID, eviocgabs, eviocgbit, eviocgname, eviocgprop,
+};
+use crate::protocol::*;
+use anyhow::{Context, Result, bail};
+use std::ffi::CString;
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+
+const NAME_LEN: usize = 256;
+
+/// Read the config of a real evdev device, e.g. `/dev/input/event3`
+///
+/// The device's buttons and axes are taken over as they are, codes without a `Button` or
+/// `Axis` variant as `Custom`. Needs read access to the node, usually membership in the
+/// `input` group.
+pub fn probe_device(path: &Path) -> Result<DeviceConfig> {
+    let device = RawDevice::open(path)?;
+
+    let mut id = [0u16; 4];
+    device
+        .ioctl(EVIOCGID, id.as_mut_ptr().cast())
+        .with_context(|| format!("EVIOCGID failed on {}", path.display()))?;
+
+    let mut version: i32 = 0;
+    let evdev_version = device
+        .ioctl(EVIOCGVERSION, (&mut version as *mut i32).cast())
+        .ok()
+        .map(|_| version);
+
+    let mut name = [0u8; NAME_LEN];
+    device
+        .ioctl(eviocgname(NAME_LEN), name.as_mut_ptr().cast())
+        .with_context(|| format!("EVIOCGNAME failed on {}", path.display()))?;
+    let name_len = name.iter().position(|&b| b == 0).unwrap_or(NAME_LEN);
+
+    // Axes of motion sensors are acceleration and angular velocity, not sticks
+    let mut props = [0u8; INPUT_PROP_MAX as usize / 8 + 1];
+    let motion_sensor = device
+        .ioctl(eviocgprop(props.len()), props.as_mut_ptr().cast())
+        .is_ok()
+        && props[INPUT_PROP_ACCELEROMETER as usize / 8] & (1 << (INPUT_PROP_ACCELEROMETER % 8))
+            != 0;
+    let axis_from_ev_code = if motion_sensor {
+        Axis::from_motion_ev_code
+    } else {
+        Axis::from_ev_code
+    };
+
+    let buttons = device
+        .bits(EV_KEY, KEY_MAX)?
+        .into_iter()
+        .filter_map(Button::from_ev_code)
+        .collect();
+
+    let rel_axes = device
+        .bits(EV_REL, REL_MAX)?
+        .into_iter()
+        .filter_map(RelAxis::from_ev_code)
+        .collect();
+
+    let mut axes = Vec::new();
+    for code in device.bits(EV_ABS, ABS_MAX)? {
+        let mut absinfo = LinuxAbsEvent {
+            value: 0,
+            minimum: 0,
+            maximum: 0,
+            fuzz: 0,
+            flat: 0,
+            resolution: 0,
+        };
+        device
+            .ioctl(eviocgabs(code), (&mut absinfo as *mut LinuxAbsEvent).cast())
+            .with_context(|| format!("EVIOCGABS({}) failed on {}", code, path.display()))?;
+
+        axes.push(AxisConfig {
+            axis: axis_from_ev_code(code).unwrap_or(Axis::Custom(code)),
+            min: absinfo.minimum,
+            max: absinfo.maximum,
+            fuzz: absinfo.fuzz,
+            flat: absinfo.flat,
+            center: None,
+            smoothing: None,
+            resolution: absinfo.resolution,
+        });
+    }
+
+    // udev tells motion sensors from joysticks the same way, for readers going by udev
+    let extra_udev_properties = if motion_sensor {
+        vec![
+            ("ID_INPUT_JOYSTICK".to_string(), "0".to_string()),
+            ("ID_INPUT_ACCELEROMETER".to_string(), "1".to_string()),
+        ]
+    } else {
+        Vec::new()
+    };
+
+    Ok(DeviceConfig {
+        name: String::from_utf8_lossy(&name[..name_len]).into_owned(),
+        bustype: BusType::from_u16(id[0]),
+        vendor_id: id[1],
+        product_id: id[2],
+        version: id[3],
+        buttons,
+        axes,
+        rel_axes,
+        evdev_version,
+        extra_udev_properties,
+        override_udev_properties: motion_sensor,
+        ..Default::default()
+    })
+}
+
+/// Evdev node opened with raw syscalls, closed on drop
+pub(crate) struct RawDevice {
+    fd: i32,
+}
+impl RawDevice {
+    pub(crate) fn open(path: &Path) -> Result<Self> {
+        let c_path = CString::new(path.as_os_str().as_bytes())
+            .with_context(|| format!("Invalid device path {}", path.display()))?;
+
+        let fd = unsafe {
+            libc::syscall(
+                libc::SYS_openat,
+                libc::AT_FDCWD,
+                c_path.as_ptr(),
+                libc::O_RDONLY | libc::O_NONBLOCK | libc::O_CLOEXEC,
+            )
+        };
+        if fd < 0 {
+            let error = std::io::Error::last_os_error();
+            if error.kind() == std::io::ErrorKind::PermissionDenied {
+                bail!(
+                    "No permission to open {}, the user may need to be in the input group",
+                    path.display()
+                );
+            }
+            return Err(error).with_context(|| format!("Failed to open {}", path.display()));
+        }
+
+        Ok(Self { fd: fd as i32 })
+    }
+
+    fn ioctl(&self, request: u32, arg: *mut libc::c_void) -> std::io::Result<()> {
+        let result =
+            unsafe { libc::syscall(libc::SYS_ioctl, self.fd, request as libc::c_ulong, arg) };
+        if result < 0 {
+            Err(std::io::Error::last_os_error())
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Have the kernel timestamp events read from this fd with `clock`, e.g. `CLOCK_MONOTONIC`
+    pub(crate) fn set_clock(&self, mut clock: libc::clockid_t) -> std::io::Result<()> {
+        self.ioctl(EVIOCSCLOCKID, (&mut clock as *mut libc::clockid_t).cast())
+    }
+
+    /// Wait up to `timeout` for the device to become readable
+    pub(crate) fn wait_readable(&self, timeout: std::time::Duration) -> std::io::Result<bool> {
+        let mut pollfd = libc::pollfd {
+            fd: self.fd,
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        let timeout = libc::timespec {
+            tv_sec: timeout.as_secs() as libc::time_t,
+            tv_nsec: timeout.subsec_nanos() as libc::c_long,
+        };
+        let result = unsafe {
+            libc::syscall(
+                libc::SYS_ppoll,
+                &mut pollfd as *mut libc::pollfd,
+                1,
+                &timeout as *const libc::timespec,
+                std::ptr::null::<libc::sigset_t>(),
+                0,
+            )
+        };
+        if result < 0 {
+            Err(std::io::Error::last_os_error())
+        } else {
+            Ok(result > 0)
+        }
+    }
+
+    /// Read what's available, 0 bytes if nothing is
+    pub(crate) fn read(&self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let result = unsafe { libc::syscall(libc::SYS_read, self.fd, buf.as_mut_ptr(), buf.len()) };
+        if result >= 0 {
+            return Ok(result as usize);
+        }
+        match std::io::Error::last_os_error() {
+            e if e.kind() == std::io::ErrorKind::WouldBlock => Ok(0),
+            e => Err(e),
+        }
+    }
+
+    /// Codes set in the EVIOCGBIT bitmap of an event type
+    fn bits(&self, ev_type: u16, max: u16) -> Result<Vec<u16>> {
+        let mut bitmap = vec![0u8; max as usize / 8 + 1];
+        self.ioctl(eviocgbit(ev_type, bitmap.len()), bitmap.as_mut_ptr().cast())
+            .with_context(|| format!("EVIOCGBIT({}) failed", ev_type))?;
+
+        Ok((0..=max)
+            .filter(|&code| bitmap[code as usize / 8] & (1 << (code % 8)) != 0)
+            .collect())
+    }
+}
+impl Drop for RawDevice {
+    fn drop(&mut self) {
+        unsafe { libc::syscall(libc::SYS_close, self.fd) };
+    }
+}
@@ -0,0 +1,249 @@
+//! Per-controller compatibility fix-ups
+//!
+//! Different games expect slightly different presentations of the same controller,
+//! e.g. triggers as `0..=255` rather than the full `i16` range, or Nintendo's A/B swap.
+//! A `QuirkTable` maps vendor/product IDs to such adjustments, which the manager applies
+//! to devices created with `DeviceConfig::apply_quirks` set.
+
+use crate::protocol::*;
+use serde::{Deserialize, Serialize};
+
+/// Adjustments applied to a matching device config
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Quirk {
+    pub vendor_id: u16,
+    /// Product to match, or any product of the vendor if None
+    #[serde(default)]
+    pub product_id: Option<u16>,
+    /// Range (min, max) to present analog triggers with
+    #[serde(default)]
+    pub trigger_range: Option<(i32, i32)>,
+    /// Buttons to emit as other buttons, as (from, to) pairs
+    #[serde(default)]
+    pub button_remap: Vec<(Button, Button)>,
+    /// Additional udev properties for the device
+    #[serde(default)]
+    pub extra_udev_properties: Vec<(String, String)>,
+}
+impl Quirk {
+    /// Check if this quirk applies to a vendor/product pair
+    pub fn matches(&self, vendor_id: u16, product_id: u16) -> bool {
+        self.vendor_id == vendor_id && self.product_id.is_none_or(|p| p == product_id)
+    }
+
+    /// Apply the adjustments to a config
+    pub fn apply(&self, config: &mut DeviceConfig) {
+        if let Some((min, max)) = self.trigger_range {
+            for axis in config
+                .axes
+                .iter_mut()
+                .filter(|a| matches!(a.axis, Axis::LowerLeftTrigger | Axis::LowerRightTrigger))
+            {
+                axis.min = min;
+                axis.max = max;
+            }
+        }
+
+        if !self.button_remap.is_empty() {
+            // Advertise the buttons that will actually be emitted
+            for button in config.buttons.iter_mut() {
+                if let Some((_, to)) = self.button_remap.iter().find(|(from, _)| from == button) {
+                    *button = *to;
+                }
+            }
+            config
+                .button_remap
+                .extend(self.button_remap.iter().copied());
+        }
+
+        config
+            .extra_udev_properties
+            .extend(self.extra_udev_properties.iter().cloned());
+    }
+}
+
+/// Lookup table of quirks keyed by vendor/product
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct QuirkTable {
+    quirks: Vec<Quirk>,
+}
+impl QuirkTable {
+    /// Table without any quirks
+    pub fn empty() -> Self {
+        Self::default()
+    }
+
+    /// Built-in quirks for the controllers provided by `ControllerTemplates`
+    pub fn builtin() -> Self {
+        Self {
+            quirks: vec![
+                // xpad reports 8-bit triggers for the 360 pad
+                Quirk {
+                    vendor_id: 0x045e,
+                    product_id: Some(0x028e),
+                    trigger_range: Some((0, 255)),
+                    ..Default::default()
+                },
+                // ..and 10-bit triggers for the One pad
+                Quirk {
+                    vendor_id: 0x045e,
+                    product_id: Some(0x02ea),
+                    trigger_range: Some((0, 1023)),
+                    ..Default::default()
+                },
+                // hid-sony/hid-playstation report 8-bit triggers
+                Quirk {
+                    vendor_id: 0x054c,
+                    product_id: None,
+                    trigger_range: Some((0, 255)),
+                    ..Default::default()
+                },
+                // Label-based A/B and X/Y, as Nintendo prints them
+                Quirk {
+                    vendor_id: 0x057e,
+                    product_id: Some(0x2009),
+                    button_remap: vec![
+                        (Button::A, Button::B),
+                        (Button::B, Button::A),
+                        (Button::X, Button::Y),
+                        (Button::Y, Button::X),
+                    ],
+                    ..Default::default()
+                },
+            ],
+        }
+    }
+
+    /// Load a table from a JSON file (a list of quirks)
+    pub fn load(path: impl AsRef<std::path::Path>) -> anyhow::Result<Self> {
+        let content = std::fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&content)?)
+    }
+
+    /// Add a quirk, replacing any existing one for the same vendor/product
+    pub fn insert(&mut self, quirk: Quirk) {
+        self.quirks
+            .retain(|q| !(q.vendor_id == quirk.vendor_id && q.product_id == quirk.product_id));
+        self.quirks.push(quirk);
+    }
+
+    /// Merge another table over this one, its entries taking precedence
+    pub fn extend(&mut self, other: QuirkTable) {
+        for quirk in other.quirks {
+            self.insert(quirk);
+        }
+    }
+
+    /// Find the quirk for a vendor/product, preferring exact product matches over vendor-wide ones
+    pub fn lookup(&self, vendor_id: u16, product_id: u16) -> Option<&Quirk> {
+        self.quirks
+            .iter()
+            .filter(|q| q.matches(vendor_id, product_id))
+            .max_by_key(|q| q.product_id.is_some())
+    }
+
+    /// Apply the matching quirk to a config if it asks for quirks
+    ///
+    /// Clears `apply_quirks` afterwards so the config isn't adjusted twice.
+    pub fn apply(&self, config: &mut DeviceConfig) {
+        if !config.apply_quirks {
+            return;
+        }
+        if let Some(quirk) = self.lookup(config.vendor_id, config.product_id) {
+            tracing::debug!(
+                "Applying quirk for {:04x}:{:04x} to {}",
+                config.vendor_id,
+                config.product_id,
+                config.name
+            );
+            quirk.apply(config);
+        }
+        config.apply_quirks = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ControllerTemplates;
+
+    #[test]
+    fn quirks_only_apply_when_asked() {
+        let table = QuirkTable::builtin();
+        let mut config = ControllerTemplates::xbox360();
+        config.apply_quirks = false;
+        let original = config.clone();
+
+        table.apply(&mut config);
+        assert_eq!(config, original);
+    }
+
+    #[test]
+    fn trigger_range_quirk() {
+        let table = QuirkTable::builtin();
+        let mut config = ControllerTemplates::xbox360();
+        config.apply_quirks = true;
+
+        table.apply(&mut config);
+        for axis in config.trigger_axes() {
+            assert_eq!((axis.min, axis.max), (0, 255));
+        }
+        let left_x = config.axis(Axis::LeftStickX).unwrap();
+        assert_eq!((left_x.min, left_x.max), (-32768, 32767));
+        assert!(!config.apply_quirks);
+    }
+
+    #[test]
+    fn button_remap_quirk() {
+        let table = QuirkTable::builtin();
+        let mut config = ControllerTemplates::switch_pro();
+        config.apply_quirks = true;
+
+        table.apply(&mut config);
+        assert_eq!(config.remapped_button(Button::A), Button::B);
+        assert_eq!(config.remapped_button(Button::X), Button::Y);
+        assert_eq!(config.remapped_button(Button::Start), Button::Start);
+    }
+
+    #[test]
+    fn switch_pro_template_is_swapped_once() {
+        let table = QuirkTable::builtin();
+        let mut config = ControllerTemplates::switch_pro();
+        assert_eq!(
+            config.buttons[..4],
+            [Button::A, Button::B, Button::X, Button::Y]
+        );
+
+        table.apply(&mut config);
+        assert_eq!(
+            config.buttons[..4],
+            [Button::B, Button::A, Button::Y, Button::X]
+        );
+        assert_eq!(config.remapped_button(Button::A), Button::B);
+        assert_eq!(config.remapped_button(Button::Y), Button::X);
+    }
+
+    #[test]
+    fn exact_product_quirk_wins() {
+        let mut table = QuirkTable::empty();
+        table.insert(Quirk {
+            vendor_id: 0x1234,
+            product_id: None,
+            trigger_range: Some((0, 255)),
+            ..Default::default()
+        });
+        table.insert(Quirk {
+            vendor_id: 0x1234,
+            product_id: Some(0x0001),
+            extra_udev_properties: vec![("ID_QUIRK".into(), "1".into())],
+            ..Default::default()
+        });
+
+        let exact = table.lookup(0x1234, 0x0001).unwrap();
+        assert_eq!(exact.product_id, Some(0x0001));
+        let vendor_wide = table.lookup(0x1234, 0x0002).unwrap();
+        assert_eq!(vendor_wide.trigger_range, Some((0, 255)));
+        assert!(table.lookup(0x4321, 0x0001).is_none());
+    }
+}
@@ -1,9 +1,192 @@
 use crate::protocol::*;
+use serde::Serialize;
 
 /// Pre-configured controller templates
 pub struct ControllerTemplates;
 
+/// Entry of `TEMPLATES`
+struct TemplateName {
+    /// Canonical name
+    name: &'static str,
+    /// Human readable name, see `TemplateInfo::display_name`
+    display_name: &'static str,
+    /// Other names `ControllerTemplates::from_name` accepts
+    aliases: &'static [&'static str],
+    config: fn() -> DeviceConfig,
+}
+
+/// Names of every template, in the order of `ControllerTemplates::all_names`
+const TEMPLATES: &[TemplateName] = &[
+    TemplateName {
+        name: "xbox360",
+        display_name: "Xbox 360 Controller",
+        aliases: &["xbox_360", "x360", "xbox"],
+        config: ControllerTemplates::xbox360,
+    },
+    TemplateName {
+        name: "xbox_one",
+        display_name: "Xbox One Controller",
+        aliases: &["xboxone"],
+        config: ControllerTemplates::xbox_one,
+    },
+    TemplateName {
+        name: "ps4",
+        display_name: "DualShock 4",
+        aliases: &["dualshock4", "dualshock_4", "ds4"],
+        config: ControllerTemplates::ps4,
+    },
+    TemplateName {
+        name: "ps5",
+        display_name: "DualSense",
+        aliases: &["dualsense", "ds5"],
+        config: ControllerTemplates::ps5,
+    },
+    TemplateName {
+        name: "ps5_motion",
+        display_name: "DualSense Motion Sensors",
+        aliases: &["dualsense_motion"],
+        config: ControllerTemplates::ps5_motion,
+    },
+    TemplateName {
+        name: "dualsense_edge",
+        display_name: "DualSense Edge",
+        aliases: &["ps5_edge", "edge"],
+        config: ControllerTemplates::dualsense_edge,
+    },
+    TemplateName {
+        name: "switch_pro",
+        display_name: "Switch Pro Controller",
+        aliases: &["switchpro", "switch"],
+        config: ControllerTemplates::switch_pro,
+    },
+    TemplateName {
+        name: "switch_pro_motion",
+        display_name: "Switch Pro Controller IMU",
+        aliases: &["switch_pro_imu"],
+        config: ControllerTemplates::switch_pro_motion,
+    },
+    TemplateName {
+        name: "joycon_left",
+        display_name: "Joy-Con (L)",
+        aliases: &["joycon_l", "left_joycon"],
+        config: ControllerTemplates::joycon_left,
+    },
+    TemplateName {
+        name: "joycon_right",
+        display_name: "Joy-Con (R)",
+        aliases: &["joycon_r", "right_joycon"],
+        config: ControllerTemplates::joycon_right,
+    },
+    TemplateName {
+        name: "generic_gamepad",
+        display_name: "Generic Gamepad",
+        aliases: &["generic"],
+        config: ControllerTemplates::generic_gamepad,
+    },
+    TemplateName {
+        name: "arcade_stick",
+        display_name: "Arcade Stick",
+        aliases: &["arcade"],
+        config: ControllerTemplates::arcade_stick,
+    },
+    TemplateName {
+        name: "flight_stick",
+        display_name: "Flight Stick",
+        aliases: &["hotas", "joystick"],
+        config: ControllerTemplates::flight_stick,
+    },
+    TemplateName {
+        name: "keyboard",
+        display_name: "Keyboard",
+        aliases: &["kbd"],
+        config: ControllerTemplates::keyboard,
+    },
+    TemplateName {
+        name: "mouse",
+        display_name: "Mouse",
+        aliases: &[],
+        config: ControllerTemplates::mouse,
+    },
+];
+
+/// Summary of a template, e.g. for a UI to offer it, see `ControllerTemplates::catalog`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TemplateInfo {
+    /// Canonical name, as accepted by `ControllerTemplates::from_name`
+    pub name: &'static str,
+    /// Human readable name, e.g. "DualSense"
+    pub display_name: &'static str,
+    pub vendor_id: u16,
+    pub product_id: u16,
+    pub button_count: usize,
+    pub axis_count: usize,
+    /// Reports motion, i.e. has gyroscope and accelerometer axes
+    pub motion: bool,
+    /// Has a multitouch touchpad
+    pub touchpad: bool,
+}
+impl TemplateInfo {
+    fn new(template: &TemplateName) -> Self {
+        let config = (template.config)();
+        Self {
+            name: template.name,
+            display_name: template.display_name,
+            vendor_id: config.vendor_id,
+            product_id: config.product_id,
+            button_count: config.buttons.len(),
+            axis_count: config.axes.len(),
+            motion: config.is_motion_sensor(),
+            touchpad: config.is_multitouch(),
+        }
+    }
+}
+
 impl ControllerTemplates {
+    /// Look up a template by name, e.g. "xbox360" or "switch_pro"
+    ///
+    /// Case-insensitive, `-` and spaces are treated as `_`. Also accepts a few
+    /// aliases such as "xbox", "dualshock4" and "dualsense".
+    pub fn from_name(name: &str) -> Option<DeviceConfig> {
+        let name = name.trim().to_lowercase().replace(['-', ' '], "_");
+        let template = TEMPLATES
+            .iter()
+            .find(|template| template.name == name || template.aliases.contains(&name.as_str()))?;
+        Some((template.config)())
+    }
+
+    /// Template named by an environment variable, e.g. `VIMPUTTI_CONTROLLER=ps5`
+    ///
+    /// Lets CI run the same tests against several controllers. Falls back to `default` when
+    /// the variable is unset or empty, and with a warning when it names no template.
+    pub fn from_env(var: &str, default: fn() -> DeviceConfig) -> DeviceConfig {
+        let name = match std::env::var(var) {
+            Ok(name) if !name.trim().is_empty() => name,
+            _ => return default(),
+        };
+
+        Self::from_name(&name).unwrap_or_else(|| {
+            tracing::warn!(
+                "Unknown controller template {:?} in {}, expected one of {}",
+                name,
+                var,
+                Self::all_names().join(", ")
+            );
+            default()
+        })
+    }
+
+    /// Canonical names of all templates accepted by `from_name`
+    pub fn all_names() -> Vec<&'static str> {
+        TEMPLATES.iter().map(|template| template.name).collect()
+    }
+
+    /// Summaries of all templates, in the order of `all_names`
+    ///
+    /// Computed from the templates' configs, so front-ends don't have to keep their own list.
+    pub fn catalog() -> Vec<TemplateInfo> {
+        TEMPLATES.iter().map(TemplateInfo::new).collect()
+    }
+
     /// Xbox 360 Controller
     pub fn xbox360() -> DeviceConfig {
         DeviceConfig {
@@ -35,6 +218,8 @@ impl ControllerTemplates {
                 AxisConfig::new(Axis::DPadX, -1, 1),
                 AxisConfig::new(Axis::DPadY, -1, 1),
             ],
+            dpad_style: Some(DPadStyle::Hat),
+            ..Default::default()
         }
     }
 
@@ -69,6 +254,8 @@ impl ControllerTemplates {
                 AxisConfig::new(Axis::DPadX, -1, 1),
                 AxisConfig::new(Axis::DPadY, -1, 1),
             ],
+            dpad_style: Some(DPadStyle::Hat),
+            ..Default::default()
         }
     }
 
@@ -105,10 +292,14 @@ impl ControllerTemplates {
                 AxisConfig::new(Axis::DPadX, -1, 1),
                 AxisConfig::new(Axis::DPadY, -1, 1),
             ],
+            dpad_style: Some(DPadStyle::Hat),
+            ..Default::default()
         }
     }
 
     /// PlayStation 5 Controller (DualSense)
+    ///
+    /// Its gyroscope and accelerometer are a device of their own, see `ps5_motion`.
     pub fn ps5() -> DeviceConfig {
         DeviceConfig {
             name: "Sony Interactive Entertainment DualSense Wireless Controller".to_string(),
@@ -141,10 +332,49 @@ impl ControllerTemplates {
                 AxisConfig::new(Axis::DPadX, -1, 1),
                 AxisConfig::new(Axis::DPadY, -1, 1),
             ],
+            dpad_style: Some(DPadStyle::Hat),
+            ..Default::default()
         }
     }
 
+    /// Motion sensors of the DualSense, created next to `ps5`
+    ///
+    /// Like the kernel driver's, acceleration is in units of 1/8192 g and angular velocity in
+    /// units of 1/1024 degrees per second, as the axes' resolutions tell.
+    pub fn ps5_motion() -> DeviceConfig {
+        DeviceConfig {
+            name: "Sony Interactive Entertainment DualSense Wireless Controller Motion Sensors"
+                .to_string(),
+            axes: Self::motion_axes((32768, 8192), (2048 * 1024, 1024), 16),
+            ..Self::motion_sensor(Self::ps5())
+        }
+    }
+
+    /// PlayStation 5 DualSense Edge Controller
+    ///
+    /// The DualSense with the Edge's product ID, which games detect it by, and its four
+    /// extra buttons. The kernel has no dedicated codes for them, so they are
+    /// `Button::TriggerHappy`: 1 and 2 for the left and right back buttons, 3 and 4 for
+    /// the left and right function (Fn) buttons.
+    pub fn dualsense_edge() -> DeviceConfig {
+        let mut config = Self::ps5();
+        config.name =
+            "Sony Interactive Entertainment DualSense Edge Wireless Controller".to_string();
+        config.product_id = 0x0df2;
+        config.buttons.extend([
+            Button::TriggerHappy(1), // Left back button
+            Button::TriggerHappy(2), // Right back button
+            Button::TriggerHappy(3), // Left Fn
+            Button::TriggerHappy(4), // Right Fn
+        ]);
+        config
+    }
+
     /// Nintendo Switch Pro Controller
+    ///
+    /// Its gyroscope and accelerometer are a device of their own, see `switch_pro_motion`.
+    /// Buttons are named as printed on the controller, the built-in quirk swaps A/B and X/Y
+    /// to the positions the kernel driver reports them at.
     pub fn switch_pro() -> DeviceConfig {
         DeviceConfig {
             name: "Nintendo Switch Pro Controller".to_string(),
@@ -153,10 +383,10 @@ impl ControllerTemplates {
             version: 0x8111,
             bustype: BusType::Usb,
             buttons: vec![
-                Button::B,                 // A (Nintendo)
-                Button::A,                 // B (Nintendo)
-                Button::Y,                 // X (Nintendo)
-                Button::X,                 // Y (Nintendo)
+                Button::A,
+                Button::B,
+                Button::X,
+                Button::Y,
                 Button::UpperLeftBumper,   // L
                 Button::UpperRightBumper,  // R
                 Button::LowerLeftTrigger,  // ZL
@@ -176,9 +406,119 @@ impl ControllerTemplates {
                 AxisConfig::new(Axis::DPadX, -1, 1),
                 AxisConfig::new(Axis::DPadY, -1, 1),
             ],
+            dpad_style: Some(DPadStyle::Hat),
+            apply_quirks: true,
+            ..Default::default()
+        }
+    }
+
+    /// Motion sensors (IMU) of the Switch Pro Controller, created next to `switch_pro`
+    ///
+    /// Like the kernel driver's, acceleration is in units of 1/4096 g and angular velocity in
+    /// units of 1/14247 degrees per second, as the axes' resolutions tell.
+    pub fn switch_pro_motion() -> DeviceConfig {
+        DeviceConfig {
+            name: "Nintendo Switch Pro Controller (IMU)".to_string(),
+            axes: Self::motion_axes((32767, 4096), (32767000, 14247), 10),
+            ..Self::motion_sensor(Self::switch_pro())
+        }
+    }
+
+    /// Left Nintendo Switch Joy-Con, on its own as hid-nintendo presents it
+    ///
+    /// Has the left half of a Switch Pro Controller, with its D-pad as buttons. Capture is
+    /// `BTN_Z` (`Button::Custom(0x135)`) like in the kernel driver, as `BTN_THUMBL` is the
+    /// stick click. Joy-Cons used as a pair are two devices, see `joycon_right`.
+    pub fn joycon_left() -> DeviceConfig {
+        DeviceConfig {
+            name: "Nintendo Switch Left Joy-Con".to_string(),
+            vendor_id: 0x057e,
+            product_id: 0x2006,
+            version: 0x8001,
+            bustype: BusType::Bluetooth,
+            buttons: vec![
+                Button::Select,           // Minus
+                Button::Custom(0x135),    // Capture
+                Button::LeftStick,        // Stick click
+                Button::UpperLeftBumper,  // L
+                Button::LowerLeftTrigger, // ZL
+                Button::DPadUp,
+                Button::DPadDown,
+                Button::DPadLeft,
+                Button::DPadRight,
+            ],
+            axes: vec![
+                AxisConfig::new(Axis::LeftStickX, -32768, 32767),
+                AxisConfig::new(Axis::LeftStickY, -32768, 32767),
+            ],
+            dpad_style: Some(DPadStyle::Buttons),
+            ..Default::default()
+        }
+    }
+
+    /// Right Nintendo Switch Joy-Con, on its own as hid-nintendo presents it
+    ///
+    /// Has the right half of a Switch Pro Controller, its stick reporting on `ABS_RX`/`ABS_RY`
+    /// as with the kernel driver, so its joystick axes 0 and 1 are those.
+    pub fn joycon_right() -> DeviceConfig {
+        DeviceConfig {
+            name: "Nintendo Switch Right Joy-Con".to_string(),
+            vendor_id: 0x057e,
+            product_id: 0x2007,
+            version: 0x8001,
+            bustype: BusType::Bluetooth,
+            buttons: vec![
+                Button::Start,             // Plus
+                Button::Guide,             // Home
+                Button::RightStick,        // Stick click
+                Button::B,                 // A (Nintendo)
+                Button::A,                 // B (Nintendo)
+                Button::Y,                 // X (Nintendo)
+                Button::X,                 // Y (Nintendo)
+                Button::UpperRightBumper,  // R
+                Button::LowerRightTrigger, // ZR
+            ],
+            axes: vec![
+                AxisConfig::new(Axis::RightStickX, -32768, 32767),
+                AxisConfig::new(Axis::RightStickY, -32768, 32767),
+            ],
+            ..Default::default()
+        }
+    }
+
+    /// Motion sensor device of a controller, with its IDs but none of its inputs
+    fn motion_sensor(controller: DeviceConfig) -> DeviceConfig {
+        DeviceConfig {
+            vendor_id: controller.vendor_id,
+            product_id: controller.product_id,
+            version: controller.version,
+            bustype: controller.bustype,
+            extra_udev_properties: vec![
+                ("ID_INPUT_JOYSTICK".to_string(), "0".to_string()),
+                ("ID_INPUT_ACCELEROMETER".to_string(), "1".to_string()),
+            ],
+            override_udev_properties: true,
+            ..Default::default()
         }
     }
 
+    /// Accelerometer and gyroscope axes, each ranging over `-range..=range` with a resolution
+    fn motion_axes(accel: (i32, i32), gyro: (i32, i32), fuzz: i32) -> Vec<AxisConfig> {
+        let axis = |axis, (range, resolution): (i32, i32)| AxisConfig {
+            fuzz,
+            ..AxisConfig::new(axis, -range, range).with_resolution(resolution)
+        };
+
+        vec![
+            axis(Axis::AccelX, accel),
+            axis(Axis::AccelY, accel),
+            axis(Axis::AccelZ, accel),
+            axis(Axis::GyroX, gyro),
+            axis(Axis::GyroY, gyro),
+            axis(Axis::GyroZ, gyro),
+        ]
+    }
+
     /// Generic USB gamepad (basic configuration)
     pub fn generic_gamepad() -> DeviceConfig {
         DeviceConfig {
@@ -203,6 +543,132 @@ impl ControllerTemplates {
                 AxisConfig::new(Axis::RightStickX, -32768, 32767),
                 AxisConfig::new(Axis::RightStickY, -32768, 32767),
             ],
+            ..Default::default()
+        }
+    }
+
+    /// Arcade stick (Zero Delay style USB encoder with extra buttons)
+    pub fn arcade_stick() -> DeviceConfig {
+        let mut buttons = vec![
+            Button::A,
+            Button::B,
+            Button::X,
+            Button::Y,
+            Button::UpperLeftBumper,
+            Button::UpperRightBumper,
+            Button::LowerLeftTrigger,
+            Button::LowerRightTrigger,
+            Button::Select,
+            Button::Start,
+            Button::Guide,
+        ];
+        // Turbo, clear, macro etc. buttons wired to the encoder
+        buttons.extend((1..=16).map(Button::TriggerHappy));
+
+        DeviceConfig {
+            name: "Generic Arcade Stick".to_string(),
+            vendor_id: 0x0079,
+            product_id: 0x0006,
+            version: 0x0110,
+            bustype: BusType::Usb,
+            buttons,
+            axes: vec![
+                // Lever as a hat
+                AxisConfig::new(Axis::DPadX, -1, 1),
+                AxisConfig::new(Axis::DPadY, -1, 1),
+            ],
+            dpad_style: Some(DPadStyle::Hat),
+            ..Default::default()
+        }
+    }
+
+    /// Flight stick with twist rudder, throttle lever and hat, like HOTAS sticks on hid-generic
+    ///
+    /// Buttons are reported in the legacy joystick range (`BTN_TRIGGER` to `BTN_BASE6`).
+    pub fn flight_stick() -> DeviceConfig {
+        DeviceConfig {
+            name: "Generic Flight Stick".to_string(),
+            vendor_id: 0x1209,
+            product_id: 0x0001,
+            version: 0x0100,
+            bustype: BusType::Usb,
+            buttons: vec![
+                Button::A,
+                Button::B,
+                Button::X,
+                Button::Y,
+                Button::UpperLeftBumper,
+                Button::UpperRightBumper,
+                Button::LowerLeftTrigger,
+                Button::LowerRightTrigger,
+                Button::Select,
+                Button::Start,
+                Button::LeftStick,
+                Button::RightStick,
+            ],
+            axes: vec![
+                AxisConfig::new(Axis::LeftStickX, 0, 1023).with_center(512),
+                AxisConfig::new(Axis::LeftStickY, 0, 1023).with_center(512),
+                AxisConfig::new(Axis::Rudder, 0, 255).with_center(128),
+                AxisConfig::new(Axis::Throttle, 0, 255),
+                AxisConfig::new(Axis::DPadX, -1, 1),
+                AxisConfig::new(Axis::DPadY, -1, 1),
+            ],
+            button_namespace: ButtonNamespace::Joystick,
+            ..Default::default()
+        }
+    }
+
+    /// Full-size (105 key) USB keyboard
+    ///
+    /// Keys are `Button::Custom` with their KEY_* codes, e.g. `Button::Custom(30)` for KEY_A.
+    /// Presented through udev as a keyboard rather than a joystick, and without a `jsN` node.
+    pub fn keyboard() -> DeviceConfig {
+        let keys = [
+            1..=83,    // KEY_ESC to KEY_KPDOT: main block, F1-F10, modifiers and keypad
+            86..=88,   // KEY_102ND, KEY_F11, KEY_F12
+            96..=100,  // KEY_KPENTER, KEY_RIGHTCTRL, KEY_KPSLASH, KEY_SYSRQ, KEY_RIGHTALT
+            102..=111, // KEY_HOME to KEY_DELETE: navigation and arrows
+            119..=119, // KEY_PAUSE
+            125..=127, // KEY_LEFTMETA, KEY_RIGHTMETA, KEY_COMPOSE
+        ];
+
+        DeviceConfig {
+            name: "Generic USB Keyboard".to_string(),
+            vendor_id: 0x1209,
+            product_id: 0x0002,
+            version: 0x0100,
+            bustype: BusType::Usb,
+            buttons: keys.into_iter().flatten().map(Button::Custom).collect(),
+            extra_udev_properties: vec![
+                ("ID_INPUT_JOYSTICK".to_string(), "0".to_string()),
+                ("ID_INPUT_KEY".to_string(), "1".to_string()),
+                ("ID_INPUT_KEYBOARD".to_string(), "1".to_string()),
+            ],
+            override_udev_properties: true,
+            ..Default::default()
+        }
+    }
+
+    /// Five button USB mouse with a vertical and a horizontal wheel
+    ///
+    /// Buttons are `Button::Custom` with their codes, `BTN_LEFT` (`BTN_MOUSE`) to `BTN_EXTRA`.
+    /// Presented through udev as a mouse rather than a joystick, and without a `jsN` node.
+    pub fn mouse() -> DeviceConfig {
+        DeviceConfig {
+            name: "Generic USB Mouse".to_string(),
+            vendor_id: 0x1209,
+            product_id: 0x0003,
+            version: 0x0100,
+            bustype: BusType::Usb,
+            buttons: (BTN_MOUSE..BTN_MOUSE + 5).map(Button::Custom).collect(),
+            rel_axes: vec![RelAxis::X, RelAxis::Y, RelAxis::WheelV, RelAxis::WheelH],
+            extra_udev_properties: vec![
+                ("ID_INPUT_JOYSTICK".to_string(), "0".to_string()),
+                ("ID_INPUT_MOUSE".to_string(), "1".to_string()),
+            ],
+            override_udev_properties: true,
+            ..Default::default()
         }
     }
 }
@@ -218,12 +684,7 @@ impl ControllerBuilder {
         Self {
             config: DeviceConfig {
                 name: name.into(),
-                vendor_id: 0x0000,
-                product_id: 0x0000,
-                version: 0x0100,
-                bustype: BusType::Virtual,
-                buttons: Vec::new(),
-                axes: Vec::new(),
+                ..Default::default()
             },
         }
     }
@@ -252,6 +713,80 @@ impl ControllerBuilder {
         self
     }
 
+    /// Present a different model name through udev than the evdev name
+    pub fn udev_model_name(mut self, name: impl Into<String>) -> Self {
+        self.config.udev_model_name = Some(name.into());
+        self
+    }
+
+    /// Override the udev `ID_BUS` presented to applications
+    pub fn udev_bus(mut self, bus: impl Into<String>) -> Self {
+        self.config.udev_bus = Some(bus.into());
+        self
+    }
+
+    /// Add a custom udev property, e.g. one a udev rule or game matches on
+    pub fn udev_property(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.config
+            .extra_udev_properties
+            .push((key.into(), value.into()));
+        self
+    }
+
+    /// Let custom udev properties replace the generated ones of the same key
+    pub fn override_udev_properties(mut self, allow: bool) -> Self {
+        self.config.override_udev_properties = allow;
+        self
+    }
+
+    /// Apply the manager's compatibility quirks for this vendor/product
+    pub fn apply_quirks(mut self, apply: bool) -> Self {
+        self.config.apply_quirks = apply;
+        self
+    }
+
+    /// Number frames with an `MSC_RAW` sequence counter, for debugging lost input
+    pub fn emit_sequence(mut self, emit: bool) -> Self {
+        self.config.emit_sequence = emit;
+        self
+    }
+
+    /// Write every frame to readers separately instead of a batch at once
+    pub fn per_frame_writes(mut self, enable: bool) -> Self {
+        self.config.per_frame_writes = enable;
+        self
+    }
+
+    /// Present the triggers separately or on a single combined axis
+    pub fn trigger_layout(mut self, layout: TriggerLayout) -> Self {
+        self.config.trigger_layout = layout;
+        self
+    }
+
+    /// Emit buttons in the gamepad or the legacy joystick `BTN_*` range
+    pub fn button_namespace(mut self, namespace: ButtonNamespace) -> Self {
+        self.config.button_namespace = namespace;
+        self
+    }
+
+    /// Only register the device for enumeration, rejecting any input sent to it
+    pub fn passive(mut self, passive: bool) -> Self {
+        self.config.passive = passive;
+        self
+    }
+
+    /// Override the evdev driver version reported by EVIOCGVERSION
+    pub fn evdev_version(mut self, version: i32) -> Self {
+        self.config.evdev_version = Some(version);
+        self
+    }
+
+    /// Override the joystick driver version reported by JSIOCGVERSION
+    pub fn js_version(mut self, version: i32) -> Self {
+        self.config.js_version = Some(version);
+        self
+    }
+
     /// Add a button
     pub fn button(mut self, button: Button) -> Self {
         self.config.buttons.push(button);
@@ -282,6 +817,36 @@ impl ControllerBuilder {
         self
     }
 
+    /// Add a relative axis, e.g. `RelAxis::X` for a pointer
+    pub fn rel_axis(mut self, axis: RelAxis) -> Self {
+        self.config.rel_axes.push(axis);
+        self
+    }
+
+    /// Add multitouch (type B) axes for `slots` fingers on a `width` by `height` surface
+    ///
+    /// The manager emits `BTN_TOUCH` and `BTN_TOOL_*` along with them, see
+    /// `DeviceConfig::touch_buttons`.
+    pub fn multitouch(mut self, slots: u8, width: i32, height: i32) -> Self {
+        self.config.axes.extend([
+            AxisConfig::new(Axis::Custom(ABS_MT_SLOT), 0, slots.max(1) as i32 - 1),
+            AxisConfig::new(Axis::Custom(ABS_MT_TRACKING_ID), 0, u16::MAX as i32),
+            AxisConfig::new(Axis::Custom(ABS_MT_POSITION_X), 0, width),
+            AxisConfig::new(Axis::Custom(ABS_MT_POSITION_Y), 0, height),
+        ]);
+        self
+    }
+
+    /// Set the resolution of an added axis in units per mm, e.g. for touch surfaces
+    ///
+    /// See `AxisConfig::with_dpi` for setting it from DPI.
+    pub fn axis_resolution(mut self, axis: Axis, units_per_mm: i32) -> Self {
+        for config in self.config.axes.iter_mut().filter(|a| a.axis == axis) {
+            config.resolution = units_per_mm;
+        }
+        self
+    }
+
     /// Build the configuration
     pub fn build(self) -> DeviceConfig {
         self.config
@@ -315,8 +880,10 @@ impl ControllerBuilder {
         self.buttons([Button::LeftStick, Button::RightStick])
     }
 
-    /// Add D-pad buttons
-    pub fn dpad_buttons(self) -> Self {
+    /// Report the D-pad as buttons, replacing D-pad axes added before
+    pub fn dpad_buttons(mut self) -> Self {
+        self.clear_dpad();
+        self.config.dpad_style = Some(DPadStyle::Buttons);
         self.buttons([
             Button::DPadUp,
             Button::DPadDown,
@@ -343,11 +910,169 @@ impl ControllerBuilder {
         ])
     }
 
-    /// Add D-pad as axes
-    pub fn dpad_axes(self) -> Self {
+    /// Report the D-pad as hat axes, replacing D-pad buttons added before
+    pub fn dpad_axes(mut self) -> Self {
+        self.clear_dpad();
+        self.config.dpad_style = Some(DPadStyle::Hat);
         self.axes([
             AxisConfig::new(Axis::DPadX, -1, 1),
             AxisConfig::new(Axis::DPadY, -1, 1),
         ])
     }
+
+    /// Remove the D-pad in either style, so only the last style chosen is reported
+    fn clear_dpad(&mut self) {
+        self.config.buttons.retain(|b| {
+            !matches!(
+                b,
+                Button::DPadUp | Button::DPadDown | Button::DPadLeft | Button::DPadRight
+            )
+        });
+        self.config
+            .axes
+            .retain(|a| !matches!(a.axis, Axis::DPadX | Axis::DPadY));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn xbox360_capability_accessors() {
+        let config = ControllerTemplates::xbox360();
+
+        assert!(config.has_button(Button::A));
+        assert!(config.has_button(Button::Guide));
+        assert!(!config.has_button(Button::DPadUp));
+
+        let left_x = config.axis(Axis::LeftStickX).unwrap();
+        assert_eq!((left_x.min, left_x.max), (-32768, 32767));
+        assert!(config.axis(Axis::Throttle).is_none());
+        assert!(config.has_axis(Axis::DPadX));
+
+        let triggers: Vec<Axis> = config.trigger_axes().map(|a| a.axis).collect();
+        assert_eq!(triggers, [Axis::LowerLeftTrigger, Axis::LowerRightTrigger]);
+
+        let sticks: Vec<Axis> = config.stick_axes().map(|a| a.axis).collect();
+        assert_eq!(
+            sticks,
+            [
+                Axis::LeftStickX,
+                Axis::LeftStickY,
+                Axis::RightStickX,
+                Axis::RightStickY
+            ]
+        );
+    }
+
+    #[test]
+    fn dualsense_edge_has_back_and_fn_buttons() {
+        let edge = ControllerTemplates::dualsense_edge();
+        let ps5 = ControllerTemplates::ps5();
+        assert_eq!((edge.vendor_id, edge.product_id), (0x054c, 0x0df2));
+
+        // BTN_TRIGGER_HAPPY1..4 on top of the DualSense's buttons
+        let extra: Vec<u16> = edge
+            .buttons
+            .iter()
+            .filter(|b| !ps5.has_button(**b))
+            .map(|b| b.to_ev_code())
+            .collect();
+        assert_eq!(extra, [0x2c0, 0x2c1, 0x2c2, 0x2c3]);
+        assert_eq!(edge.axes, ps5.axes);
+    }
+
+    #[test]
+    fn from_env_picks_the_named_template() {
+        // A variable of its own, tests run in parallel
+        const VAR: &str = "VIMPUTTI_TEST_FROM_ENV";
+        let from_env = || ControllerTemplates::from_env(VAR, ControllerTemplates::xbox360);
+
+        unsafe { std::env::set_var(VAR, "ps5") };
+        assert_eq!(from_env(), ControllerTemplates::ps5());
+        unsafe { std::env::set_var(VAR, "Switch-Pro") };
+        assert_eq!(from_env(), ControllerTemplates::switch_pro());
+
+        // Unknown, empty and unset give the default
+        for value in [Some("gamecube"), Some(""), None] {
+            match value {
+                Some(value) => unsafe { std::env::set_var(VAR, value) },
+                None => unsafe { std::env::remove_var(VAR) },
+            }
+            assert_eq!(from_env(), ControllerTemplates::xbox360(), "{:?}", value);
+        }
+    }
+
+    #[test]
+    fn every_template_name_resolves() {
+        for name in ControllerTemplates::all_names() {
+            let config = ControllerTemplates::from_name(name)
+                .unwrap_or_else(|| panic!("{} doesn't resolve", name));
+            assert_eq!(config.validate(), Ok(()), "{}", name);
+        }
+        assert_eq!(ControllerTemplates::from_name("gamecube"), None);
+        assert_eq!(ControllerTemplates::from_name(""), None);
+    }
+
+    #[test]
+    fn template_names_are_normalized() {
+        assert_eq!(
+            ControllerTemplates::from_name(" DualSense "),
+            Some(ControllerTemplates::ps5())
+        );
+        assert_eq!(
+            ControllerTemplates::from_name("Switch-Pro"),
+            Some(ControllerTemplates::switch_pro())
+        );
+        assert_eq!(
+            ControllerTemplates::from_name("xbox"),
+            Some(ControllerTemplates::xbox360())
+        );
+        assert_eq!(
+            ControllerTemplates::from_name("dualshock4"),
+            Some(ControllerTemplates::ps4())
+        );
+    }
+
+    #[test]
+    fn catalog_covers_every_template() {
+        let catalog = ControllerTemplates::catalog();
+        let names: Vec<_> = catalog.iter().map(|info| info.name).collect();
+        assert_eq!(names, ControllerTemplates::all_names());
+
+        for info in &catalog {
+            let config = ControllerTemplates::from_name(info.name).unwrap();
+            assert_ne!(info.display_name, info.name);
+            assert_eq!(
+                (info.vendor_id, info.product_id),
+                (config.vendor_id, config.product_id)
+            );
+            assert_eq!(info.button_count, config.buttons.len(), "{}", info.name);
+            assert_eq!(info.axis_count, config.axes.len(), "{}", info.name);
+        }
+
+        let info = |name: &str| catalog.iter().find(|info| info.name == name).unwrap();
+        assert!(info("ps5_motion").motion && !info("ps5").motion);
+    }
+
+    #[test]
+    fn joycons_have_one_stick_each() {
+        let left = ControllerTemplates::joycon_left();
+        let axes: Vec<_> = left.axes.iter().map(|a| a.axis).collect();
+        assert_eq!(axes, [Axis::LeftStickX, Axis::LeftStickY]);
+        // Capture is BTN_Z, BTN_THUMBL (317) being the stick click
+        assert!(left.has_button(Button::Custom(0x135)));
+        assert_eq!(Button::LeftStick.to_ev_code(), 317);
+        assert!(!left.has_button(Button::Start));
+
+        let right = ControllerTemplates::joycon_right();
+        let codes: Vec<_> = right.axes.iter().map(|a| a.axis.to_ev_code()).collect();
+        // ABS_RX and ABS_RY, which the joystick node maps to axes 0 and 1
+        assert_eq!(codes, [0x03, 0x04]);
+        assert!(right.has_button(Button::Guide) && !right.has_button(Button::Select));
+        assert_eq!((left.vendor_id, left.product_id), (0x057e, 0x2006));
+        assert_eq!((right.vendor_id, right.product_id), (0x057e, 0x2007));
+        assert!(left.validate().is_ok() && right.validate().is_ok());
+    }
 }
@@ -0,0 +1,64 @@
+//! Allocations made by the client for sending input
+//!
+//! A test binary of its own, as it counts allocations with its own global allocator. Only
+//! allocations on the test's thread are counted, the manager runs on threads of its own.
+
+mod common;
+
+use common::TestManager;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use vimputti::protocol::*;
+
+struct CountingAllocator;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// Fewest allocations `send` made in any of a number of calls
+async fn fewest_allocations(mut send: impl AsyncFnMut()) -> usize {
+    let mut fewest = usize::MAX;
+    for _ in 0..200 {
+        let before = ALLOCATIONS.with(Cell::get);
+        send().await;
+        fewest = fewest.min(ALLOCATIONS.with(Cell::get) - before);
+    }
+    fewest
+}
+
+#[tokio::test]
+async fn single_events_need_no_vec() {
+    let manager = TestManager::start().await;
+    let client = manager.client().await;
+    let device = client.create_device(common::gamepad()).await.unwrap();
+    let event = InputEvent::Button {
+        button: Button::A,
+        pressed: true,
+    };
+
+    let one = fewest_allocations(async || device.send_one(event.clone()).await.unwrap()).await;
+    let vec =
+        fewest_allocations(async || device.send_events(vec![event.clone()]).await.unwrap()).await;
+    assert_eq!(
+        one + 1,
+        vec,
+        "send_one: {} allocations, send_events(vec![..]): {}",
+        one,
+        vec
+    );
+}
@@ -0,0 +1,347 @@
+//! Helpers shared by the integration tests: a manager in a temporary directory, and readers
+//! and uinput sessions on its sockets
+
+#![allow(dead_code)]
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+use tokio::io::AsyncReadExt;
+use tokio::net::UnixStream;
+use tokio::runtime::Runtime;
+use vimputti::manager::{Manager, ManagerConfig};
+use vimputti::protocol::framing::read_frame_async;
+use vimputti::protocol::*;
+use vimputti::{ControllerTemplates, VimputtiClient};
+
+/// How long a test waits for anything before failing
+pub const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Run on a manager before it starts, see `TestManager::with_setup`
+type Setup = Arc<dyn Fn(&Manager) + Send + Sync>;
+
+/// A manager running in its own temporary directory, removed on drop
+///
+/// The manager has a runtime of its own, so stopping it closes every socket it had open like
+/// stopping the process would.
+pub struct TestManager {
+    dir: PathBuf,
+    socket_path: PathBuf,
+    config: ManagerConfig,
+    /// Run on each manager before it starts, e.g. registering callbacks
+    setup: Option<Setup>,
+    runtime: Option<Runtime>,
+}
+impl TestManager {
+    pub async fn start() -> Self {
+        Self::with_config(ManagerConfig::default()).await
+    }
+
+    pub async fn with_config(config: ManagerConfig) -> Self {
+        Self::new(config, None).await
+    }
+
+    /// A manager that `setup` is run on before it starts, also after restarts
+    pub async fn with_setup(
+        config: ManagerConfig,
+        setup: impl Fn(&Manager) + Send + Sync + 'static,
+    ) -> Self {
+        Self::new(config, Some(Arc::new(setup))).await
+    }
+
+    /// A manager on the socket of `--instance <instance>`, where clients discover it
+    pub async fn on_instance(instance: u32) -> Self {
+        let socket_path = instance_socket_path(instance);
+        Self::at(
+            base_path_for_socket(&socket_path),
+            socket_path,
+            ManagerConfig::default(),
+            None,
+        )
+        .await
+    }
+
+    async fn new(config: ManagerConfig, setup: Option<Setup>) -> Self {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+        let dir = std::env::temp_dir().join(format!(
+            "vimputti-test-{}-{}",
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        let socket_path = dir.join("control.sock");
+        Self::at(dir, socket_path, config, setup).await
+    }
+
+    async fn at(
+        dir: PathBuf,
+        socket_path: PathBuf,
+        config: ManagerConfig,
+        setup: Option<Setup>,
+    ) -> Self {
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let mut manager = Self {
+            dir,
+            socket_path,
+            config,
+            setup,
+            runtime: None,
+        };
+        manager.run().await;
+        manager
+    }
+
+    /// Stop the manager and start a new one on the same socket, without any devices
+    pub async fn restart(&mut self) {
+        // Waited for, the new manager can't start while the old one holds the instance lock
+        if let Some(runtime) = self.runtime.take() {
+            tokio::task::spawn_blocking(move || runtime.shutdown_timeout(TIMEOUT))
+                .await
+                .unwrap();
+        }
+        let _ = std::fs::remove_file(&self.socket_path);
+        self.run().await;
+    }
+
+    async fn run(&mut self) {
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(2)
+            .enable_all()
+            .build()
+            .unwrap();
+        let mut manager = {
+            let _runtime = runtime.enter();
+            Manager::with_config(&self.socket_path, self.config.clone()).unwrap()
+        };
+        if let Some(setup) = &self.setup {
+            setup(&manager);
+        }
+        runtime.spawn(async move {
+            manager.run().await.unwrap();
+        });
+        self.runtime = Some(runtime);
+
+        // Wait for the control socket to come up
+        tokio::time::timeout(TIMEOUT, async {
+            while UnixStream::connect(&self.socket_path).await.is_err() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("manager didn't start");
+    }
+
+    pub fn socket_path(&self) -> &Path {
+        &self.socket_path
+    }
+
+    /// Base directory of the device sockets and sysfs
+    pub fn base_path(&self) -> PathBuf {
+        base_path_for_socket(&self.socket_path)
+    }
+
+    /// Socket of a device node, e.g. `event0`
+    pub fn node_path(&self, node: &str) -> PathBuf {
+        self.base_path().join("devices").join(node)
+    }
+
+    /// Number of tasks alive on the manager's runtime
+    pub fn alive_tasks(&self) -> usize {
+        self.runtime.as_ref().unwrap().metrics().num_alive_tasks()
+    }
+
+    pub async fn client(&self) -> VimputtiClient {
+        VimputtiClient::connect(&self.socket_path).await.unwrap()
+    }
+
+    /// Open a reader on a device's event node
+    pub async fn open(&self, node: &str) -> EvdevReader {
+        EvdevReader::connect(&self.node_path(node)).await
+    }
+
+    /// Open a reader on a device's joystick node
+    pub async fn open_joystick(&self, node: &str) -> JoystickReader {
+        JoystickReader::connect(&self.node_path(node)).await
+    }
+}
+impl Drop for TestManager {
+    fn drop(&mut self) {
+        if let Some(runtime) = self.runtime.take() {
+            runtime.shutdown_background();
+        }
+        let _ = std::fs::remove_dir_all(&self.dir);
+        // Outside of the directory for managers on an instance's socket
+        let _ = std::fs::remove_file(&self.socket_path);
+        let _ = std::fs::remove_file(self.socket_path.with_extension("lock"));
+    }
+}
+
+/// Reader on a device socket, like the shim opening `/dev/input/eventN`
+pub struct EvdevReader {
+    stream: UnixStream,
+    pub handshake: DeviceHandshake,
+}
+impl EvdevReader {
+    pub async fn connect(path: &Path) -> Self {
+        let mut stream = UnixStream::connect(path).await.unwrap();
+        let payload = tokio::time::timeout(TIMEOUT, read_frame_async(&mut stream))
+            .await
+            .expect("no handshake")
+            .unwrap();
+        let handshake = DeviceHandshake::from_bytes(&payload).unwrap();
+        Self { stream, handshake }
+    }
+
+    /// Next event, as (type, code, value)
+    pub async fn next_event(&mut self) -> (u16, u16, i32) {
+        tokio::time::timeout(TIMEOUT, self.read_event())
+            .await
+            .expect("no event")
+    }
+
+    /// Next event if one arrives within `wait`
+    pub async fn try_next_event(&mut self, wait: Duration) -> Option<(u16, u16, i32)> {
+        tokio::time::timeout(wait, self.read_event()).await.ok()
+    }
+
+    /// Events up to and including the next SYN_REPORT
+    pub async fn next_frame(&mut self) -> Vec<(u16, u16, i32)> {
+        let mut frame = Vec::new();
+        loop {
+            let event = self.next_event().await;
+            frame.push(event);
+            if event.0 == EV_SYN && event.1 == SYN_REPORT {
+                return frame;
+            }
+        }
+    }
+
+    async fn read_event(&mut self) -> (u16, u16, i32) {
+        let mut bytes = [0u8; 24];
+        self.stream.read_exact(&mut bytes).await.unwrap();
+        (
+            u16::from_ne_bytes([bytes[16], bytes[17]]),
+            u16::from_ne_bytes([bytes[18], bytes[19]]),
+            i32::from_ne_bytes([bytes[20], bytes[21], bytes[22], bytes[23]]),
+        )
+    }
+}
+
+/// Reader on a joystick socket, like the shim opening `/dev/input/jsN`
+pub struct JoystickReader {
+    stream: UnixStream,
+}
+impl JoystickReader {
+    pub async fn connect(path: &Path) -> Self {
+        let mut stream = UnixStream::connect(path).await.unwrap();
+        tokio::time::timeout(TIMEOUT, read_frame_async(&mut stream))
+            .await
+            .expect("no handshake")
+            .unwrap();
+        Self { stream }
+    }
+
+    /// Next event if one arrives within `wait`, as (type, number, value)
+    pub async fn try_next_event(&mut self, wait: Duration) -> Option<(u8, u8, i16)> {
+        let mut bytes = [0u8; 8];
+        tokio::time::timeout(wait, self.stream.read_exact(&mut bytes))
+            .await
+            .ok()?
+            .unwrap();
+        Some((bytes[6], bytes[7], i16::from_ne_bytes([bytes[4], bytes[5]])))
+    }
+
+    /// Wait for the stream to end, as when the joystick node is removed
+    pub async fn wait_closed(&mut self) {
+        let mut rest = Vec::new();
+        tokio::time::timeout(TIMEOUT, self.stream.read_to_end(&mut rest))
+            .await
+            .expect("joystick stream didn't end")
+            .unwrap();
+    }
+}
+
+/// A plain gamepad config
+pub fn gamepad() -> DeviceConfig {
+    ControllerTemplates::xbox360()
+}
+
+/// Session on the manager's uinput socket, like the shim's for `/dev/uinput`
+pub struct UinputSession {
+    stream: UnixStream,
+}
+impl UinputSession {
+    pub async fn connect(manager: &TestManager) -> Self {
+        let stream = UnixStream::connect(manager.base_path().join("uinput"))
+            .await
+            .unwrap();
+        Self { stream }
+    }
+
+    pub async fn request(&mut self, request: UinputRequest) -> UinputResponse {
+        use tokio::io::AsyncWriteExt;
+
+        self.stream
+            .write_all(&request.to_bytes().unwrap())
+            .await
+            .unwrap();
+        let payload = tokio::time::timeout(TIMEOUT, read_frame_async(&mut self.stream))
+            .await
+            .expect("no uinput response")
+            .unwrap();
+        UinputResponse::from_bytes(&payload).unwrap()
+    }
+
+    /// Write events to the session's device, which gets no response
+    pub async fn write_events(&mut self, events: &[(u16, u16, i32)]) {
+        use tokio::io::AsyncWriteExt;
+
+        let events = events
+            .iter()
+            .map(|&(event_type, code, value)| LinuxInputEvent::new(event_type, code, value))
+            .collect();
+        let request = UinputRequest::WriteEvents { events };
+        self.stream
+            .write_all(&request.to_bytes().unwrap())
+            .await
+            .unwrap();
+    }
+
+    /// Create a USB device with the given buttons, returning its ID
+    pub async fn create(&mut self, name: &str, buttons: &[Button]) -> DeviceId {
+        self.create_on_bus(name, buttons, 0x03).await
+    }
+
+    /// `create` with a Linux `BUS_*` number
+    pub async fn create_on_bus(
+        &mut self,
+        name: &str,
+        buttons: &[Button],
+        bustype: u16,
+    ) -> DeviceId {
+        self.request(UinputRequest::SetEvBit { ev_type: EV_KEY })
+            .await;
+        for button in buttons {
+            self.request(UinputRequest::SetKeyBit {
+                key_code: button.to_ev_code(),
+            })
+            .await;
+        }
+        self.request(UinputRequest::DevSetup {
+            setup: DeviceSetup {
+                name: name.to_string(),
+                vendor_id: 0x28de,
+                product_id: 0x11ff,
+                version: 1,
+                bustype,
+            },
+        })
+        .await;
+
+        let response = self.request(UinputRequest::DevCreate {}).await;
+        assert!(response.success, "{:?}", response.error);
+        response.device_id.unwrap()
+    }
+}
@@ -0,0 +1,74 @@
+//! The control protocol, spoken raw the way non-Rust clients do
+
+mod common;
+
+use common::TestManager;
+use serde_json::{Value, json};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::UnixStream;
+use vimputti::protocol::*;
+
+/// Send one command as raw JSON and return the result
+async fn raw_command(manager: &TestManager, command: Value) -> Value {
+    let mut stream = UnixStream::connect(manager.socket_path()).await.unwrap();
+    let message = json!({ "id": "raw", "command": command });
+    stream
+        .write_all(format!("{}\n", message).as_bytes())
+        .await
+        .unwrap();
+
+    let mut line = String::new();
+    BufReader::new(stream).read_line(&mut line).await.unwrap();
+    let mut response: Value = serde_json::from_str(&line).unwrap();
+    assert_eq!(response["id"], "raw");
+    response["result"].take()
+}
+
+#[tokio::test]
+async fn send_named_inputs() {
+    let manager = TestManager::start().await;
+    let client = manager.client().await;
+    let device = client.create_device(common::gamepad()).await.unwrap();
+    let mut reader = manager.open(device.event_node()).await;
+
+    let result = raw_command(
+        &manager,
+        json!({ "SendNamed": {
+            "device_id": device.device_id(),
+            "inputs": [
+                { "button": "A", "pressed": true },
+                { "axis": "LeftStickX", "value": 12000 },
+            ],
+        }}),
+    )
+    .await;
+    assert_eq!(result, json!("InputSent"));
+
+    assert_eq!(
+        reader.next_frame().await,
+        vec![
+            (EV_KEY, Button::A.to_ev_code(), 1),
+            (EV_ABS, Axis::LeftStickX.to_ev_code(), 12000),
+            (EV_SYN, SYN_REPORT, 0),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn send_named_rejects_unknown_names() {
+    let manager = TestManager::start().await;
+    let client = manager.client().await;
+    let device = client.create_device(common::gamepad()).await.unwrap();
+
+    for input in [
+        json!({ "button": "Jump", "pressed": true }),
+        json!({ "axis": "Throttle", "value": 1 }),
+    ] {
+        let result = raw_command(
+            &manager,
+            json!({ "SendNamed": { "device_id": device.device_id(), "inputs": [input] } }),
+        )
+        .await;
+        assert_eq!(result["Error"]["code"], "InvalidInput", "{}", result);
+    }
+}
@@ -0,0 +1,1008 @@
+//! Events readers of the device sockets get
+
+mod common;
+
+use common::TestManager;
+use std::time::Duration;
+use vimputti::manager::ManagerConfig;
+use vimputti::protocol::*;
+use vimputti::{ControllerBuilder, ControllerTemplates};
+
+/// How long to wait for events that shouldn't come
+const QUIET: Duration = Duration::from_millis(100);
+
+#[tokio::test]
+async fn new_reader_gets_current_state() {
+    let manager = TestManager::start().await;
+    let client = manager.client().await;
+    let device = client.create_device(common::gamepad()).await.unwrap();
+
+    device.axis(Axis::LeftStickX, 20000).await.unwrap();
+    device.button(Button::B, true).await.unwrap();
+    device.sync().await.unwrap();
+    device.flush().await.unwrap();
+
+    let mut reader = manager.open(device.event_node()).await;
+    let mut frame = reader.next_frame().await;
+    frame.sort();
+    assert_eq!(
+        frame,
+        vec![
+            (EV_SYN, SYN_REPORT, 0),
+            (EV_KEY, Button::B.to_ev_code(), 1),
+            (EV_ABS, Axis::LeftStickX.to_ev_code(), 20000),
+        ]
+    );
+    assert_eq!(reader.try_next_event(QUIET).await, None);
+}
+
+#[tokio::test]
+async fn new_reader_gets_no_state_at_rest() {
+    let manager = TestManager::start().await;
+    let client = manager.client().await;
+    let device = client.create_device(common::gamepad()).await.unwrap();
+
+    let mut reader = manager.open(device.event_node()).await;
+    assert_eq!(reader.try_next_event(QUIET).await, None);
+}
+
+#[tokio::test]
+async fn new_reader_gets_axis_held_off_center_at_zero() {
+    let manager = TestManager::start().await;
+    let client = manager.client().await;
+    let device = client
+        .create_device(
+            ControllerBuilder::new("Centered")
+                .axis_config(AxisConfig::new(Axis::LeftStickX, 0, 32767).with_center(16384))
+                .build(),
+        )
+        .await
+        .unwrap();
+
+    device.axis(Axis::LeftStickX, 0).await.unwrap();
+    device.sync().await.unwrap();
+    device.flush().await.unwrap();
+
+    let mut reader = manager.open(device.event_node()).await;
+    assert_eq!(
+        reader.next_frame().await,
+        vec![
+            (EV_ABS, Axis::LeftStickX.to_ev_code(), 0),
+            (EV_SYN, SYN_REPORT, 0),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn initial_state_is_seen_by_the_first_reader() {
+    let manager = TestManager::start().await;
+    let client = manager.client().await;
+    let mut config = common::gamepad();
+    config.initial_state = vec![InputEvent::Axis {
+        axis: Axis::LeftStickX,
+        value: -20000,
+    }];
+    // Not blended with the rest position
+    let stick = config.axes.iter_mut().find(|a| a.axis == Axis::LeftStickX);
+    stick.unwrap().smoothing = Some(0.5);
+    let device = client.create_device(config.clone()).await.unwrap();
+
+    let mut reader = manager.open(device.event_node()).await;
+    assert_eq!(
+        reader.next_frame().await,
+        vec![
+            (EV_ABS, Axis::LeftStickX.to_ev_code(), -20000),
+            (EV_SYN, SYN_REPORT, 0),
+        ]
+    );
+
+    // Only with what the device has
+    config.initial_state = vec![InputEvent::Button {
+        button: Button::LowerLeftTrigger,
+        pressed: true,
+    }];
+    client
+        .create_device(config)
+        .await
+        .err()
+        .expect("created a device with a button it doesn't have held");
+}
+
+#[tokio::test]
+async fn frame_without_changes_sends_no_report() {
+    let manager = TestManager::start().await;
+    let client = manager.client().await;
+    let device = client.create_device(common::gamepad()).await.unwrap();
+    let mut reader = manager.open(device.event_node()).await;
+
+    device.axis(Axis::LeftStickX, 1000).await.unwrap();
+    device.sync().await.unwrap();
+    device.flush().await.unwrap();
+    assert_eq!(
+        reader.next_frame().await,
+        vec![
+            (EV_ABS, Axis::LeftStickX.to_ev_code(), 1000),
+            (EV_SYN, SYN_REPORT, 0),
+        ]
+    );
+
+    // Repeating the value, or a button the device doesn't have, changes nothing
+    device
+        .send_events([
+            InputEvent::Axis {
+                axis: Axis::LeftStickX,
+                value: 1000,
+            },
+            InputEvent::Button {
+                button: Button::Custom(0x2c0),
+                pressed: true,
+            },
+            InputEvent::Sync,
+        ])
+        .await
+        .unwrap();
+    device.flush().await.unwrap();
+    assert_eq!(reader.try_next_event(QUIET).await, None);
+
+    // A sync on its own is still forwarded
+    device.sync().await.unwrap();
+    device.flush().await.unwrap();
+    assert_eq!(reader.next_event().await, (EV_SYN, SYN_REPORT, 0));
+}
+
+#[tokio::test]
+async fn paused_device_forwards_no_input() {
+    let manager = TestManager::start().await;
+    let client = manager.client().await;
+    let device = client.create_device(common::gamepad()).await.unwrap();
+    let mut reader = manager.open(device.event_node()).await;
+
+    device.pause().await.unwrap();
+    device.press_button(Button::A).await.unwrap();
+    device.flush().await.unwrap();
+    assert_eq!(reader.try_next_event(QUIET).await, None);
+
+    // Discarded input is gone, input after resuming gets through
+    device.resume().await.unwrap();
+    assert_eq!(reader.try_next_event(QUIET).await, None);
+    device.press_button(Button::B).await.unwrap();
+    device.flush().await.unwrap();
+    assert_eq!(
+        reader.next_frame().await,
+        vec![(EV_KEY, Button::B.to_ev_code(), 1), (EV_SYN, SYN_REPORT, 0)]
+    );
+}
+
+#[tokio::test]
+async fn tracking_pause_catches_readers_up_on_resume() {
+    let manager = TestManager::start().await;
+    let client = manager.client().await;
+    let device = client.create_device(common::gamepad()).await.unwrap();
+    let mut reader = manager.open(device.event_node()).await;
+
+    device.pause_with(PauseMode::Track).await.unwrap();
+    device.press_button(Button::A).await.unwrap();
+    device.axis(Axis::LeftStickY, -5000).await.unwrap();
+    device.sync().await.unwrap();
+    device.flush().await.unwrap();
+    assert_eq!(reader.try_next_event(QUIET).await, None);
+
+    device.resume().await.unwrap();
+    let mut frame = reader.next_frame().await;
+    frame.sort();
+    assert_eq!(
+        frame,
+        vec![
+            (EV_SYN, SYN_REPORT, 0),
+            (EV_KEY, Button::A.to_ev_code(), 1),
+            (EV_ABS, Axis::LeftStickY.to_ev_code(), -5000),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn latency_delays_frames_in_order() {
+    let manager = TestManager::start().await;
+    let client = manager.client().await;
+    let device = client.create_device(common::gamepad()).await.unwrap();
+    let mut reader = manager.open(device.event_node()).await;
+
+    device.set_latency_seeded(100, 20, 7).await.unwrap();
+
+    let buttons = [Button::A, Button::B, Button::X, Button::Y];
+    let mut sent = Vec::new();
+    for button in buttons {
+        sent.push(std::time::Instant::now());
+        device.press_button(button).await.unwrap();
+    }
+
+    for (button, sent) in buttons.into_iter().zip(sent) {
+        let frame = reader.next_frame().await;
+        let delay = sent.elapsed();
+        assert_eq!(frame[0], (EV_KEY, button.to_ev_code(), 1));
+        assert!(
+            delay >= Duration::from_millis(80),
+            "{:?} arrived after {:?}",
+            button,
+            delay
+        );
+        assert!(
+            delay < Duration::from_millis(120 + 200),
+            "{:?} arrived after {:?}",
+            button,
+            delay
+        );
+    }
+
+    // Without latency, frames come right away again
+    device.set_latency(0, 0).await.unwrap();
+    let sent = std::time::Instant::now();
+    device.release_button(Button::A).await.unwrap();
+    reader.next_frame().await;
+    assert!(sent.elapsed() < Duration::from_millis(80));
+}
+
+#[tokio::test]
+async fn reader_connecting_during_latency_gets_delayed_input_once() {
+    let manager = TestManager::start().await;
+    let client = manager.client().await;
+    let device = client.create_device(common::gamepad()).await.unwrap();
+    device.set_latency_seeded(200, 0, 7).await.unwrap();
+
+    // Connected while the press is still queued, so it isn't part of the state yet
+    device.press_button(Button::A).await.unwrap();
+    let mut reader = manager.open(device.event_node()).await;
+    assert_eq!(
+        reader.next_frame().await,
+        vec![(EV_KEY, Button::A.to_ev_code(), 1), (EV_SYN, SYN_REPORT, 0)]
+    );
+    device.flush().await.unwrap();
+    assert_eq!(reader.try_next_event(QUIET).await, None);
+
+    // Delivered input is replayed to readers connecting after it
+    let mut late_reader = manager.open(device.event_node()).await;
+    assert_eq!(
+        late_reader.next_frame().await,
+        vec![(EV_KEY, Button::A.to_ev_code(), 1), (EV_SYN, SYN_REPORT, 0)]
+    );
+}
+
+#[tokio::test]
+async fn set_axes_sends_a_single_frame() {
+    let manager = TestManager::start().await;
+    let client = manager.client().await;
+    let device = client.create_device(common::gamepad()).await.unwrap();
+    let mut reader = manager.open(device.event_node()).await;
+
+    device
+        .set_axes(&[
+            (Axis::LeftStickX, 1000),
+            (Axis::LeftStickY, -2000),
+            (Axis::RightStickX, 3000),
+            (Axis::RightStickY, 100_000),
+            (Axis::LowerLeftTrigger, 500),
+            (Axis::LowerRightTrigger, 600),
+        ])
+        .await
+        .unwrap();
+    device.flush().await.unwrap();
+
+    // Out of range values are clamped
+    assert_eq!(
+        reader.next_frame().await,
+        vec![
+            (EV_ABS, Axis::LeftStickX.to_ev_code(), 1000),
+            (EV_ABS, Axis::LeftStickY.to_ev_code(), -2000),
+            (EV_ABS, Axis::RightStickX.to_ev_code(), 3000),
+            (EV_ABS, Axis::RightStickY.to_ev_code(), 32767),
+            (EV_ABS, Axis::LowerLeftTrigger.to_ev_code(), 500),
+            (EV_ABS, Axis::LowerRightTrigger.to_ev_code(), 600),
+            (EV_SYN, SYN_REPORT, 0),
+        ]
+    );
+    assert_eq!(reader.try_next_event(QUIET).await, None);
+}
+
+#[tokio::test]
+async fn set_axes_normalized_maps_to_ranges() {
+    let manager = TestManager::start().await;
+    let client = manager.client().await;
+    let device = client
+        .create_device(
+            ControllerBuilder::new("Normalized")
+                .axis(Axis::LeftStickX, -32768, 32767)
+                .axis(Axis::LowerLeftTrigger, 0, 255)
+                .build(),
+        )
+        .await
+        .unwrap();
+    let mut reader = manager.open(device.event_node()).await;
+
+    device
+        .set_axes_normalized(&[(Axis::LeftStickX, -1.0), (Axis::LowerLeftTrigger, 1.0)])
+        .await
+        .unwrap();
+    device.flush().await.unwrap();
+    assert_eq!(
+        reader.next_frame().await,
+        vec![
+            (EV_ABS, Axis::LeftStickX.to_ev_code(), -32768),
+            (EV_ABS, Axis::LowerLeftTrigger.to_ev_code(), 255),
+            (EV_SYN, SYN_REPORT, 0),
+        ]
+    );
+
+    // An axis the device doesn't have can't be normalized
+    assert!(
+        device
+            .set_axes_normalized(&[(Axis::RightStickX, 0.5)])
+            .await
+            .is_err()
+    );
+}
+
+#[tokio::test]
+async fn normalized_zero_is_the_configured_center() {
+    let manager = TestManager::start().await;
+    let client = manager.client().await;
+    let throttle = AxisConfig::new(Axis::Throttle, 0, 1000).with_center(200);
+    let device = client
+        .create_device(
+            ControllerBuilder::new("Throttle")
+                .axis_config(throttle)
+                .build(),
+        )
+        .await
+        .unwrap();
+    let code = Axis::Throttle.to_ev_code();
+
+    // The device starts at rest, which isn't a change to replay
+    let mut reader = manager.open(device.event_node()).await;
+    assert_eq!(reader.try_next_event(QUIET).await, None);
+
+    for (normalized, value) in [(-1.0, 0), (-0.5, 100), (0.5, 600), (1.0, 1000), (0.0, 200)] {
+        device
+            .set_axes_normalized(&[(Axis::Throttle, normalized)])
+            .await
+            .unwrap();
+        assert_eq!(
+            reader.next_frame().await,
+            [(EV_ABS, code, value), (EV_SYN, SYN_REPORT, 0)],
+            "{}",
+            normalized
+        );
+    }
+
+    let outside = ControllerBuilder::new("Throttle")
+        .axis_config(AxisConfig::new(Axis::Throttle, 0, 1000).with_center(1001))
+        .build();
+    assert!(outside.validate().is_err());
+}
+
+/// Frames alternately pressing and releasing `button`
+fn press_release_frames(button: Button, frames: usize) -> Vec<InputEvent> {
+    (0..frames)
+        .flat_map(|i| {
+            [
+                InputEvent::Button {
+                    button,
+                    pressed: i % 2 == 0,
+                },
+                InputEvent::Sync,
+            ]
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn sequence_numbers_count_frames_and_show_gaps() {
+    let manager = TestManager::start().await;
+    let client = manager.client().await;
+    let device = client
+        .create_device(
+            ControllerBuilder::new("Sequenced")
+                .button(Button::A)
+                .emit_sequence(true)
+                .build(),
+        )
+        .await
+        .unwrap();
+    let mut reader = manager.open(device.event_node()).await;
+
+    device
+        .send_events(press_release_frames(Button::A, 4))
+        .await
+        .unwrap();
+    device.flush().await.unwrap();
+    let mut sequence = Vec::new();
+    for _ in 0..4 {
+        let frame = reader.next_frame().await;
+        assert_eq!(frame.len(), 3, "{:?}", frame);
+        assert_eq!((frame[1].0, frame[1].1), (EV_MSC, MSC_RAW));
+        sequence.push(frame[1].2);
+    }
+    assert_eq!(sequence, [0, 1, 2, 3]);
+
+    // Overflow the reader's socket without reading, frames that don't fit are dropped
+    for _ in 0..100 {
+        device
+            .send_events(press_release_frames(Button::A, 50))
+            .await
+            .unwrap();
+    }
+    device.flush().await.unwrap();
+    let mut last = 3;
+    while let Some(event) = reader.try_next_event(QUIET).await {
+        if let (EV_MSC, MSC_RAW, value) = event {
+            assert_eq!(value, last + 1);
+            last = value;
+        }
+    }
+    assert!(last < 4 + 100 * 50 - 1, "nothing was dropped");
+
+    // The next frame tells the reader, and its sequence number shows the gap
+    device.press_button(Button::A).await.unwrap();
+    device.flush().await.unwrap();
+    let frame = reader.next_frame().await;
+    assert_eq!(frame[0], (EV_SYN, SYN_DROPPED, 0));
+    assert_eq!(frame[frame.len() - 2], (EV_MSC, MSC_RAW, 4 + 100 * 50));
+}
+
+#[tokio::test]
+async fn combined_z_triggers_share_one_axis() {
+    let manager = TestManager::start().await;
+    let client = manager.client().await;
+    let device = client
+        .create_device(
+            ControllerBuilder::new("DirectInput Pad")
+                .axis(Axis::LowerLeftTrigger, 0, 255)
+                .axis(Axis::LowerRightTrigger, 0, 255)
+                .trigger_layout(TriggerLayout::CombinedZ)
+                .build(),
+        )
+        .await
+        .unwrap();
+    let mut reader = manager.open(device.event_node()).await;
+    let abs_z = Axis::LowerLeftTrigger.to_ev_code();
+
+    // One axis is advertised, spanning both triggers
+    let advertised = &reader.handshake.config;
+    assert_eq!(advertised.axes.len(), 1);
+    let z = advertised.axis(Axis::LowerLeftTrigger).unwrap();
+    assert_eq!((z.min, z.max), (-255, 255));
+
+    device.axis(Axis::LowerRightTrigger, 200).await.unwrap();
+    device.sync().await.unwrap();
+    assert_eq!(
+        reader.next_frame().await,
+        [(EV_ABS, abs_z, 200), (EV_SYN, SYN_REPORT, 0)]
+    );
+
+    // Both pressed, the right trigger minus the left
+    device.axis(Axis::LowerLeftTrigger, 50).await.unwrap();
+    device.sync().await.unwrap();
+    assert_eq!(
+        reader.next_frame().await,
+        [(EV_ABS, abs_z, 150), (EV_SYN, SYN_REPORT, 0)]
+    );
+
+    device.axis(Axis::LowerRightTrigger, 0).await.unwrap();
+    device.sync().await.unwrap();
+    assert_eq!(
+        reader.next_frame().await,
+        [(EV_ABS, abs_z, -50), (EV_SYN, SYN_REPORT, 0)]
+    );
+}
+
+#[tokio::test]
+async fn legacy_namespace_advertises_btn_trigger() {
+    let manager = TestManager::start().await;
+    let client = manager.client().await;
+    let device = client
+        .create_device(
+            ControllerBuilder::new("HOTAS")
+                .buttons([Button::A, Button::B])
+                .button_namespace(ButtonNamespace::Joystick)
+                .build(),
+        )
+        .await
+        .unwrap();
+    let mut reader = manager.open(device.event_node()).await;
+    const BTN_TRIGGER: u16 = 0x120;
+    const BTN_THUMB: u16 = 0x121;
+
+    let advertised: Vec<u16> = reader
+        .handshake
+        .config
+        .buttons
+        .iter()
+        .map(|button| button.to_ev_code())
+        .collect();
+    assert_eq!(advertised, [BTN_TRIGGER, BTN_THUMB]);
+
+    let sysfs = manager
+        .base_path()
+        .join("sysfs/devices/virtual/input")
+        .join(input_node_name("", device.device_id()))
+        .join("capabilities/key");
+    let bits = capabilities::Bitmap::from_sysfs_string(
+        std::fs::read_to_string(sysfs).unwrap().trim_end(),
+        KEY_MAX,
+    )
+    .unwrap();
+    assert_eq!(bits.codes().collect::<Vec<_>>(), [BTN_TRIGGER, BTN_THUMB]);
+
+    device.button(Button::A, true).await.unwrap();
+    device.sync().await.unwrap();
+    assert_eq!(
+        reader.next_frame().await,
+        [(EV_KEY, BTN_TRIGGER, 1), (EV_SYN, SYN_REPORT, 0)]
+    );
+}
+
+/// Sizes of the reads a blocking reader of `node` makes until it got `frames` frames of two
+/// events each
+fn read_sizes(
+    manager: &TestManager,
+    node: &str,
+    frames: usize,
+) -> std::thread::JoinHandle<Vec<usize>> {
+    use std::io::Read;
+
+    let mut stream = std::os::unix::net::UnixStream::connect(manager.node_path(node)).unwrap();
+    stream.set_read_timeout(Some(common::TIMEOUT)).unwrap();
+    framing::read_frame(&mut stream).unwrap();
+    std::thread::spawn(move || {
+        let mut sizes = Vec::new();
+        let mut buf = [0u8; 4096];
+        while sizes.iter().sum::<usize>() < frames * 2 * 24 {
+            sizes.push(stream.read(&mut buf).unwrap());
+        }
+        sizes
+    })
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn per_frame_writes_wake_readers_per_frame() {
+    // Each frame waits for the reader however slow it is
+    let manager = TestManager::with_config(ManagerConfig {
+        frame_drain_timeout: common::TIMEOUT,
+        ..ManagerConfig::default()
+    })
+    .await;
+    let client = manager.client().await;
+    const BATCHES: usize = 20;
+
+    for per_frame in [false, true] {
+        let device = client
+            .create_device(
+                ControllerBuilder::new("Pad")
+                    .button(Button::A)
+                    .per_frame_writes(per_frame)
+                    .build(),
+            )
+            .await
+            .unwrap();
+        let reader = read_sizes(&manager, device.event_node(), BATCHES * 2);
+        for _ in 0..BATCHES {
+            // Two frames sent at once, with the reader waiting in read()
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            device
+                .send_events(press_release_frames(Button::A, 2))
+                .await
+                .unwrap();
+        }
+
+        let sizes = tokio::task::spawn_blocking(move || reader.join().unwrap())
+            .await
+            .unwrap();
+        if per_frame {
+            assert_eq!(sizes, [2 * 24; BATCHES * 2], "{:?}", sizes);
+        } else {
+            assert_eq!(sizes, [4 * 24; BATCHES], "{:?}", sizes);
+        }
+        device.close().await.unwrap();
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn per_frame_writes_dont_wait_for_a_stalled_reader() {
+    let manager = TestManager::with_config(ManagerConfig {
+        frame_drain_timeout: common::TIMEOUT,
+        ..ManagerConfig::default()
+    })
+    .await;
+    let client = manager.client().await;
+    const BATCHES: usize = 20;
+    let device = client
+        .create_device(
+            ControllerBuilder::new("Pad")
+                .button(Button::A)
+                .axis(Axis::LeftStickX, -32768, 32767)
+                .per_frame_writes(true)
+                .build(),
+        )
+        .await
+        .unwrap();
+    let _stalled = manager.open(device.event_node()).await;
+
+    // Frames of their own are written without waiting, these overflow the stalled reader's
+    // socket. The stick ends up at rest, so readers opening later get no state.
+    let jitter: Vec<InputEvent> = (0..4000)
+        .map(|i| InputEvent::Axis {
+            axis: Axis::LeftStickX,
+            value: (i + 1) % 2,
+        })
+        .chain([InputEvent::Sync])
+        .collect();
+    for _ in 0..10 {
+        device.send_events(jitter.clone()).await.unwrap();
+    }
+    device.flush().await.unwrap();
+
+    let reader = read_sizes(&manager, device.event_node(), BATCHES * 2);
+    let start = std::time::Instant::now();
+    for _ in 0..BATCHES {
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        device
+            .send_events(press_release_frames(Button::A, 2))
+            .await
+            .unwrap();
+    }
+    let sizes = tokio::task::spawn_blocking(move || reader.join().unwrap())
+        .await
+        .unwrap();
+    assert_eq!(sizes, [2 * 24; BATCHES * 2], "{:?}", sizes);
+    assert!(
+        start.elapsed() < common::TIMEOUT,
+        "frames waited for the stalled reader, took {:?}",
+        start.elapsed()
+    );
+}
+
+#[tokio::test]
+async fn touches_emit_btn_touch_and_finger_count() {
+    let manager = TestManager::start().await;
+    let client = manager.client().await;
+    let device = client
+        .create_device(
+            ControllerBuilder::new("Touchpad")
+                .multitouch(2, 1000, 1000)
+                .build(),
+        )
+        .await
+        .unwrap();
+    let mut reader = manager.open(device.event_node()).await;
+    let touch = |slot: i32, tracking_id: i32| {
+        [ABS_MT_SLOT, ABS_MT_TRACKING_ID]
+            .into_iter()
+            .zip([slot, tracking_id])
+            .map(|(code, value)| InputEvent::Axis {
+                axis: Axis::Custom(code),
+                value,
+            })
+            .chain([InputEvent::Sync])
+            .collect::<Vec<_>>()
+    };
+    let mut key_events = async |events: Vec<InputEvent>| {
+        device.send_events(events).await.unwrap();
+        let frame = reader.next_frame().await;
+        frame
+            .into_iter()
+            .filter(|event| event.0 == EV_KEY)
+            .map(|(_, code, value)| (code, value))
+            .collect::<Vec<_>>()
+    };
+
+    // One finger, then a second one
+    assert_eq!(
+        key_events(touch(0, 1)).await,
+        [(BTN_TOUCH, 1), (BTN_TOOL_FINGER, 1)]
+    );
+    assert_eq!(
+        key_events(touch(1, 2)).await,
+        [(BTN_TOOL_FINGER, 0), (BTN_TOOL_DOUBLETAP, 1)]
+    );
+
+    // Lifting both
+    assert_eq!(
+        key_events(touch(1, -1)).await,
+        [(BTN_TOOL_DOUBLETAP, 0), (BTN_TOOL_FINGER, 1)]
+    );
+    assert_eq!(
+        key_events(touch(0, -1)).await,
+        [(BTN_TOOL_FINGER, 0), (BTN_TOUCH, 0)]
+    );
+}
+
+#[tokio::test]
+async fn joystick_node_drops_changes_within_the_fuzz() {
+    let manager = TestManager::start().await;
+    let client = manager.client().await;
+    let mut x = AxisConfig::new(Axis::LeftStickX, -32768, 32767);
+    x.fuzz = 16;
+    let device = client
+        .create_device(ControllerBuilder::new("Stick").axis_config(x).build())
+        .await
+        .unwrap();
+    let node = device.set_joystick_interface(true).await.unwrap().unwrap();
+    let mut reader = manager.open_joystick(&node).await;
+    let mut send = async |value: i32| {
+        device
+            .send_one(InputEvent::Axis {
+                axis: Axis::LeftStickX,
+                value,
+            })
+            .await
+            .unwrap();
+        reader
+            .try_next_event(Duration::from_millis(100))
+            .await
+            .map(|(_, _, value)| value)
+    };
+
+    assert_eq!(send(1000).await, Some(1000));
+    // Jitter within half the fuzz
+    assert_eq!(send(1005).await, None);
+    assert_eq!(send(994).await, None);
+    assert_eq!(send(2000).await, Some(2000));
+}
+
+#[tokio::test]
+async fn raw_events_of_a_motion_sensor_update_its_motion_axes() {
+    let manager = TestManager::start().await;
+    let client = manager.client().await;
+    let device = client
+        .create_device(ControllerTemplates::ps5_motion())
+        .await
+        .unwrap();
+    assert!(!manager.node_path("js0").exists());
+    let mut reader = manager.open(device.event_node()).await;
+    let abs_x = Axis::AccelX.to_ev_code();
+
+    device.set_axis(Axis::AccelX, 500).await.unwrap();
+    assert_eq!(
+        reader.next_frame().await,
+        [(EV_ABS, abs_x, 500), (EV_SYN, SYN_REPORT, 0)]
+    );
+
+    // ABS_X is AccelX here, resending its value changes nothing
+    device.raw_event(EV_ABS, abs_x, 500).await.unwrap();
+    device.sync().await.unwrap();
+    assert_eq!(reader.try_next_event(QUIET).await, None);
+
+    device.raw_event(EV_ABS, abs_x, 600).await.unwrap();
+    device.sync().await.unwrap();
+    assert_eq!(
+        reader.next_frame().await,
+        [(EV_ABS, abs_x, 600), (EV_SYN, SYN_REPORT, 0)]
+    );
+    let mut fresh = manager.open(device.event_node()).await;
+    assert_eq!(
+        fresh.next_frame().await,
+        [(EV_ABS, abs_x, 600), (EV_SYN, SYN_REPORT, 0)]
+    );
+}
+
+#[tokio::test]
+async fn smoothing_ramps_towards_a_step() {
+    let manager = TestManager::start().await;
+    let client = manager.client().await;
+    let device = client
+        .create_device(
+            ControllerBuilder::new("Smoothed")
+                .axis_config(AxisConfig::new(Axis::LeftStickX, -32768, 32767).with_smoothing(0.5))
+                .build(),
+        )
+        .await
+        .unwrap();
+    let mut reader = manager.open(device.event_node()).await;
+
+    // Each frame of the same step moves halfway from the previous value
+    let mut ramp = Vec::new();
+    for _ in 0..4 {
+        device.set_axis(Axis::LeftStickX, 1000).await.unwrap();
+        ramp.push(reader.next_frame().await[0]);
+    }
+    assert_eq!(
+        ramp,
+        [500, 750, 875, 938].map(|value| (EV_ABS, Axis::LeftStickX.to_ev_code(), value))
+    );
+}
+
+#[tokio::test]
+async fn keyboard_sends_key_codes() {
+    let manager = TestManager::start().await;
+    let client = manager.client().await;
+    let device = client
+        .create_device(ControllerTemplates::keyboard())
+        .await
+        .unwrap();
+    let mut reader = manager.open(device.event_node()).await;
+    // KEY_A
+    let key_a = Button::Custom(30);
+    assert!(reader.handshake.config.buttons.contains(&key_a));
+
+    device.press_button(key_a).await.unwrap();
+    assert_eq!(
+        reader.next_frame().await,
+        vec![(EV_KEY, 30, 1), (EV_SYN, SYN_REPORT, 0)]
+    );
+    device.release_button(key_a).await.unwrap();
+    assert_eq!(
+        reader.next_frame().await,
+        vec![(EV_KEY, 30, 0), (EV_SYN, SYN_REPORT, 0)]
+    );
+}
+
+#[tokio::test]
+async fn button_helpers_send_what_manual_events_would() {
+    let manager = TestManager::start().await;
+    let client = manager.client().await;
+    let helped = client.create_device(common::gamepad()).await.unwrap();
+    let manual = client.create_device(common::gamepad()).await.unwrap();
+    let mut helped_reader = manager.open(helped.event_node()).await;
+    let mut manual_reader = manager.open(manual.event_node()).await;
+
+    helped.press_button(Button::A).await.unwrap();
+    helped.release_button(Button::A).await.unwrap();
+    helped.tap_button(Button::B, Duration::ZERO).await.unwrap();
+    // Clamped to the range
+    helped.set_axis(Axis::LeftStickX, 40000).await.unwrap();
+
+    let button = |button, pressed| InputEvent::Button { button, pressed };
+    for events in [
+        [button(Button::A, true)],
+        [button(Button::A, false)],
+        [button(Button::B, true)],
+        [button(Button::B, false)],
+        [InputEvent::Axis {
+            axis: Axis::LeftStickX,
+            value: 32767,
+        }],
+    ] {
+        manual
+            .send_events(events.into_iter().chain([InputEvent::Sync]))
+            .await
+            .unwrap();
+    }
+
+    for _ in 0..5 {
+        assert_eq!(
+            helped_reader.next_frame().await,
+            manual_reader.next_frame().await
+        );
+    }
+}
+
+#[tokio::test]
+async fn mouse_sends_relative_deltas() {
+    let manager = TestManager::start().await;
+    let client = manager.client().await;
+    let device = client
+        .create_device(ControllerTemplates::mouse())
+        .await
+        .unwrap();
+    let mut reader = manager.open(device.event_node()).await;
+    let (x, y) = (RelAxis::X.to_ev_code(), RelAxis::Y.to_ev_code());
+
+    device.relative(RelAxis::X, 5).await.unwrap();
+    device.sync().await.unwrap();
+    assert_eq!(
+        reader.next_frame().await,
+        vec![(EV_REL, x, 5), (EV_SYN, SYN_REPORT, 0)]
+    );
+    device.move_pointer(-3, 7).await.unwrap();
+    assert_eq!(
+        reader.next_frame().await,
+        vec![(EV_REL, x, -3), (EV_REL, y, 7), (EV_SYN, SYN_REPORT, 0)]
+    );
+
+    // Deltas aren't state, a new reader gets nothing
+    let mut late = manager.open(device.event_node()).await;
+    assert_eq!(late.try_next_event(QUIET).await, None);
+}
+
+#[tokio::test]
+async fn rapid_axis_updates_are_coalesced() {
+    let manager = TestManager::start().await;
+    let client = manager.client().await;
+    let mut config = common::gamepad();
+    config.max_event_hz = Some(60);
+    let device = client.create_device(config).await.unwrap();
+    let mut reader = manager.open(device.event_node()).await;
+    let x = Axis::LeftStickX.to_ev_code();
+
+    for i in 1..=1000 {
+        device.set_axis(Axis::LeftStickX, i * 30).await.unwrap();
+    }
+    device.flush().await.unwrap();
+
+    let mut updates = 0;
+    loop {
+        let frame = reader.next_frame().await;
+        assert_eq!(frame.len(), 2, "{:?}", frame);
+        assert_eq!(frame[0].1, x);
+        updates += 1;
+        if frame[0].2 == 30000 {
+            break;
+        }
+    }
+    assert!(updates < 100, "{} frames for 1000 updates", updates);
+    assert_eq!(reader.try_next_event(QUIET).await, None);
+
+    // Presses and releases within a frame all come through
+    let a = Button::A.to_ev_code();
+    device.press_button(Button::A).await.unwrap();
+    device.release_button(Button::A).await.unwrap();
+    assert_eq!(
+        reader.next_frame().await,
+        [(EV_KEY, a, 1), (EV_SYN, SYN_REPORT, 0)]
+    );
+    assert_eq!(
+        reader.next_frame().await,
+        [(EV_KEY, a, 0), (EV_SYN, SYN_REPORT, 0)]
+    );
+}
+
+#[tokio::test]
+async fn stalled_reader_gets_syn_dropped() {
+    let manager = TestManager::start().await;
+    let client = manager.client().await;
+    let device = client.create_device(common::gamepad()).await.unwrap();
+    let mut stalled = manager.open(device.event_node()).await;
+
+    // Sends go through while the reader's socket is full
+    for _ in 0..100 {
+        device
+            .send_events(press_release_frames(Button::A, 50))
+            .await
+            .unwrap();
+    }
+    device.flush().await.unwrap();
+    let mut received = 0;
+    while let Some(event) = stalled.try_next_event(QUIET).await {
+        assert_ne!(event, (EV_SYN, SYN_DROPPED, 0));
+        received += 1;
+    }
+    assert!(received < 100 * 50 * 2, "nothing was dropped");
+
+    // Once it reads again, the next frame starts with SYN_DROPPED
+    device.press_button(Button::B).await.unwrap();
+    assert_eq!(
+        stalled.next_frame().await,
+        [
+            (EV_SYN, SYN_DROPPED, 0),
+            (EV_KEY, Button::B.to_ev_code(), 1),
+            (EV_SYN, SYN_REPORT, 0)
+        ]
+    );
+    device.release_button(Button::B).await.unwrap();
+    assert_eq!(
+        stalled.next_frame().await,
+        [(EV_KEY, Button::B.to_ev_code(), 0), (EV_SYN, SYN_REPORT, 0)]
+    );
+}
+
+#[tokio::test]
+async fn report_emptied_across_calls_sends_no_sync() {
+    let manager = TestManager::start().await;
+    let client = manager.client().await;
+    let device = client.create_device(common::gamepad()).await.unwrap();
+    device.axis(Axis::LeftStickX, 1000).await.unwrap();
+    device.sync().await.unwrap();
+    device.flush().await.unwrap();
+    let mut reader = manager.open(device.event_node()).await;
+    reader.next_frame().await;
+
+    // The repeated value is dropped in one call, its sync comes in the next
+    device.axis(Axis::LeftStickX, 1000).await.unwrap();
+    device.sync().await.unwrap();
+    device.flush().await.unwrap();
+    assert_eq!(reader.try_next_event(QUIET).await, None);
+
+    // The dropped report is done with, a sync after it is explicit again
+    device.sync().await.unwrap();
+    device.flush().await.unwrap();
+    assert_eq!(reader.next_event().await, (EV_SYN, SYN_REPORT, 0));
+}
@@ -0,0 +1,48 @@
+//! Feedback from applications to the client's feedback stream
+
+mod common;
+
+use common::{TIMEOUT, TestManager};
+use std::time::Duration;
+use vimputti::protocol::*;
+
+#[tokio::test]
+async fn injected_rumble_reaches_feedback_stream() {
+    let manager = TestManager::start().await;
+    let client = manager.client().await;
+    let mut device = client.create_device(common::gamepad()).await.unwrap();
+
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+    let _rumble = device
+        .on_rumble(move |strong, weak, duration| {
+            let _ = tx.send((strong, weak, duration));
+        })
+        .await
+        .unwrap();
+
+    // Feedback is only sent to connected clients
+    tokio::time::timeout(TIMEOUT, async {
+        while device.client_counts().await.unwrap().feedback == 0 {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("feedback client didn't connect");
+
+    device
+        .inject_feedback(FeedbackEvent::Rumble {
+            strong_magnitude: 0x8000,
+            weak_magnitude: 0x1234,
+            duration_ms: 250,
+        })
+        .await
+        .unwrap();
+    device
+        .inject_feedback(FeedbackEvent::RumbleStop)
+        .await
+        .unwrap();
+
+    let mut next = async || tokio::time::timeout(TIMEOUT, rx.recv()).await.unwrap();
+    assert_eq!(next().await, Some((0x8000, 0x1234, 250)));
+    assert_eq!(next().await, Some((0, 0, 0)));
+}
@@ -0,0 +1,915 @@
+//! Manager limits and device lifecycle
+
+mod common;
+
+use common::TestManager;
+use std::time::Duration;
+use vimputti::manager::{DeviceEvent, MAX_DEVICE_ID, ManagerConfig};
+use vimputti::protocol::*;
+use vimputti::{ControllerBuilder, ControllerTemplates, VimputtiClient};
+
+fn limited(max_devices: usize) -> ManagerConfig {
+    ManagerConfig {
+        max_devices,
+        ..Default::default()
+    }
+}
+
+#[tokio::test]
+async fn max_devices_limit() {
+    let manager = TestManager::with_config(limited(2)).await;
+    let client = manager.client().await;
+
+    let first = client.create_device(common::gamepad()).await.unwrap();
+    let _second = client.create_device(common::gamepad()).await.unwrap();
+
+    let error = client
+        .create_device(common::gamepad())
+        .await
+        .err()
+        .expect("created a device over the limit");
+    assert!(
+        error.to_string().contains("Device limit reached (2 of 2)"),
+        "{}",
+        error
+    );
+
+    first.close().await.unwrap();
+    client.create_device(common::gamepad()).await.unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn max_devices_limit_holds_for_concurrent_creations() {
+    let manager = TestManager::with_config(limited(2)).await;
+
+    let mut tasks = Vec::new();
+    for _ in 0..16 {
+        let client = manager.client().await;
+        tasks.push(tokio::spawn(async move {
+            let device = client.create_device(common::gamepad()).await;
+            // Keep the device alive until every creation is done
+            (client, device)
+        }));
+    }
+
+    let mut created = 0;
+    let mut results = Vec::new();
+    for task in tasks {
+        let result = task.await.unwrap();
+        if result.1.is_ok() {
+            created += 1;
+        }
+        results.push(result);
+    }
+    assert_eq!(created, 2);
+    assert_eq!(
+        manager.client().await.list_devices().await.unwrap().len(),
+        2
+    );
+}
+
+#[tokio::test]
+async fn idle_devices_are_reaped() {
+    let manager = TestManager::with_config(ManagerConfig {
+        idle_timeout: Some(Duration::from_millis(300)),
+        ..Default::default()
+    })
+    .await;
+    let client = manager.client().await;
+
+    let idle = client.create_device(common::gamepad()).await.unwrap();
+    let with_input = client.create_device(common::gamepad()).await.unwrap();
+    let with_reader = client.create_device(common::gamepad()).await.unwrap();
+    let _reader = manager.open(with_reader.event_node()).await;
+
+    for _ in 0..10 {
+        with_input.button(Button::A, true).await.unwrap();
+        with_input.sync().await.unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+
+    assert!(!idle.exists().await);
+    assert!(with_input.exists().await);
+    assert!(with_reader.exists().await);
+}
+
+#[tokio::test]
+async fn manager_info_reports_paths_and_version() {
+    let manager = TestManager::with_config(limited(3)).await;
+    let client = manager.client().await;
+    let _device = client.create_device(common::gamepad()).await.unwrap();
+
+    let info = client.manager_info().await.unwrap();
+    assert_eq!(info.version, env!("CARGO_PKG_VERSION"));
+    assert_eq!(
+        std::path::Path::new(&info.base_path),
+        manager.base_path().canonicalize().unwrap()
+    );
+    assert_eq!(
+        std::path::Path::new(&info.socket_path),
+        manager.socket_path()
+    );
+    assert_eq!(info.device_count, 1);
+    assert_eq!(info.max_devices, 3);
+    for feature in ["device_config", "update_device", "max_event_hz"] {
+        assert!(info.has_feature(feature), "{:?}", info.features);
+    }
+}
+
+#[tokio::test]
+async fn features_cover_every_command() {
+    let manager = TestManager::start().await;
+    let info = manager.client().await.manager_info().await.unwrap();
+
+    let config = DeviceConfig::default;
+    let commands = [
+        ControlCommand::CreateDevice { config: config() },
+        ControlCommand::CreateDeviceWithId {
+            device_id: 0,
+            config: config(),
+        },
+        ControlCommand::EnsureDevice {
+            key: "pad".to_string(),
+            config: config(),
+            on_mismatch: EnsureMismatch::default(),
+        },
+        ControlCommand::DestroyDevice { device_id: 0 },
+        ControlCommand::SendInput {
+            device_id: 0,
+            events: Vec::new(),
+        },
+        ControlCommand::SendNamed {
+            device_id: 0,
+            inputs: Vec::new(),
+        },
+        ControlCommand::SetDevicePaused {
+            device_id: 0,
+            paused: true,
+            mode: PauseMode::default(),
+        },
+        ControlCommand::InjectFeedback {
+            device_id: 0,
+            event: FeedbackEvent::RumbleStop,
+        },
+        ControlCommand::SetLatency {
+            device_id: 0,
+            base_ms: 0,
+            jitter_ms: 0,
+            seed: None,
+        },
+        ControlCommand::SetDeviceId {
+            device_id: 0,
+            vendor_id: 0,
+            product_id: 0,
+            version: None,
+            bustype: None,
+        },
+        ControlCommand::SetJoystickInterface {
+            device_id: 0,
+            enabled: true,
+        },
+        ControlCommand::SetBattery {
+            device_id: 0,
+            capacity: 0,
+            status: BatteryStatus::default(),
+        },
+        ControlCommand::Flush { device_id: 0 },
+        ControlCommand::GetDeviceClients { device_id: 0 },
+        ControlCommand::UpdateDevice {
+            device_id: 0,
+            config: config(),
+        },
+        ControlCommand::GetDeviceConfig { device_id: 0 },
+        ControlCommand::ListDevices { filter: None },
+        ControlCommand::ListDevices {
+            filter: Some(DeviceFilter::default()),
+        },
+        ControlCommand::RebroadcastDevices,
+        ControlCommand::SetHook {
+            event: HookEvent::DeviceAdded,
+            command: None,
+        },
+        ControlCommand::GetManagerInfo,
+        ControlCommand::Ping,
+    ];
+    for command in commands {
+        let Some(feature) = command.feature() else {
+            continue;
+        };
+        // Programs can only be set with the feature
+        let expected = feature != "hooks" || cfg!(feature = "hooks");
+        assert_eq!(
+            info.has_feature(feature),
+            expected,
+            "{} of {:?} in {:?}",
+            feature,
+            command,
+            info.features
+        );
+    }
+}
+
+#[tokio::test]
+async fn managers_on_different_instances_are_discovered() {
+    // Instances unlikely to be taken, next to each other
+    let instance = 10_000 + std::process::id() % 10_000 * 3;
+    let first = TestManager::on_instance(instance).await;
+    let second = TestManager::on_instance(instance + 1).await;
+
+    // A socket left behind by a crashed manager, nothing listens on it
+    let stale = instance_socket_path(instance + 2);
+    let _ = std::fs::remove_file(&stale);
+    drop(std::os::unix::net::UnixListener::bind(&stale).unwrap());
+
+    let managers = VimputtiClient::discover().await;
+    std::fs::remove_file(&stale).unwrap();
+    assert!(managers.iter().any(|path| path == first.socket_path()));
+    assert!(managers.iter().any(|path| path == second.socket_path()));
+    assert!(!managers.contains(&stale));
+
+    // Clients reach each by its instance number
+    let client = VimputtiClient::connect_instance(instance + 1)
+        .await
+        .unwrap();
+    let info = client.manager_info().await.unwrap();
+    assert_eq!(
+        std::path::Path::new(&info.socket_path),
+        second.socket_path()
+    );
+}
+
+#[tokio::test]
+async fn client_connects_over_abstract_socket() {
+    let socket_path = format!("@vimputti-test-{}", std::process::id());
+    let mut manager = vimputti::manager::Manager::new(&socket_path).unwrap();
+    let task = tokio::spawn(async move { manager.run().await });
+
+    let client = tokio::time::timeout(common::TIMEOUT, async {
+        loop {
+            match vimputti::VimputtiClient::connect(&socket_path).await {
+                Ok(client) => break client,
+                Err(_) => tokio::time::sleep(Duration::from_millis(10)).await,
+            }
+        }
+    })
+    .await
+    .expect("manager didn't start");
+
+    let info = client.manager_info().await.unwrap();
+    assert_eq!(info.socket_path, socket_path);
+    assert!(client.list_devices().await.unwrap().is_empty());
+    task.abort();
+}
+
+#[tokio::test]
+async fn device_is_created_at_a_chosen_id() {
+    let manager = TestManager::start().await;
+    let client = manager.client().await;
+
+    let device = client
+        .create_device_with_id(7, common::gamepad())
+        .await
+        .unwrap();
+    assert_eq!(device.device_id(), 7);
+    assert_eq!(device.event_node(), "event7");
+    assert!(manager.node_path("event7").exists());
+
+    let error = client
+        .create_device_with_id(7, common::gamepad())
+        .await
+        .err()
+        .expect("created a device over an existing one");
+    assert!(error.to_string().contains("already exists"), "{}", error);
+
+    // Automatic IDs fill the ones skipped over, never the chosen one
+    let mut others = Vec::new();
+    for _ in 0..8 {
+        let other = client.create_device(common::gamepad()).await.unwrap();
+        assert_ne!(other.device_id(), 7);
+        others.push(other);
+    }
+}
+
+#[tokio::test]
+async fn chosen_ids_are_capped_and_never_shared() {
+    let manager = TestManager::start().await;
+    let client = manager.client().await;
+
+    for id in [MAX_DEVICE_ID + 1, DeviceId::MAX] {
+        let error = client
+            .create_device_with_id(id, common::gamepad())
+            .await
+            .err()
+            .expect("created a device above the highest ID");
+        assert!(error.to_string().contains("above"), "{}", error);
+    }
+
+    // Automatic creations racing ones at the same, skipped over IDs each get a device of their own
+    let _chosen = client
+        .create_device_with_id(7, common::gamepad())
+        .await
+        .unwrap();
+    let mut creations = Vec::new();
+    for id in 0..7 {
+        let automatic = manager.client().await;
+        let chosen = manager.client().await;
+        creations.push(tokio::spawn(async move {
+            automatic.create_device(common::gamepad()).await
+        }));
+        creations.push(tokio::spawn(async move {
+            chosen.create_device_with_id(id, common::gamepad()).await
+        }));
+    }
+    let mut created = Vec::new();
+    for creation in creations {
+        if let Ok(device) = creation.await.unwrap() {
+            created.push(device);
+        }
+    }
+
+    let mut ids: Vec<_> = created.iter().map(|device| device.device_id()).collect();
+    ids.sort();
+    ids.dedup();
+    assert_eq!(ids.len(), created.len(), "an ID was handed out twice");
+    assert_eq!(
+        client.list_devices().await.unwrap().len(),
+        created.len() + 1
+    );
+}
+
+#[tokio::test]
+async fn destroyed_devices_leave_no_tasks_behind() {
+    let manager = TestManager::start().await;
+    let client = manager.client().await;
+    client.ping().await.unwrap();
+    let baseline = manager.alive_tasks();
+
+    for _ in 0..20 {
+        let device = client.create_device(common::gamepad()).await.unwrap();
+        assert!(manager.alive_tasks() > baseline);
+        device.close().await.unwrap();
+    }
+
+    tokio::time::timeout(common::TIMEOUT, async {
+        while manager.alive_tasks() > baseline {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .unwrap_or_else(|_| panic!("{} tasks left of {}", manager.alive_tasks(), baseline));
+}
+
+#[tokio::test]
+async fn failed_creation_names_the_path_and_leaves_nothing_behind() {
+    let manager = TestManager::start().await;
+    let client = manager.client().await;
+
+    // A file where the sysfs tree goes, failing like an unwritable filesystem would even for root
+    let sysfs = manager.base_path().join("sysfs");
+    std::fs::remove_dir_all(&sysfs).unwrap();
+    std::fs::write(&sysfs, "").unwrap();
+
+    let error = client
+        .create_device(common::gamepad())
+        .await
+        .err()
+        .expect("created a device without sysfs");
+    let message = error.to_string();
+    assert!(message.contains(sysfs.to_str().unwrap()), "{}", message);
+    assert!(message.contains("Not a directory"), "{}", message);
+
+    assert!(client.list_devices().await.unwrap().is_empty());
+    assert!(!manager.node_path("event0").exists());
+    assert!(!manager.node_path("event0.feedback").exists());
+
+    // The ID wasn't used up
+    std::fs::remove_file(&sysfs).unwrap();
+    let device = client.create_device(common::gamepad()).await.unwrap();
+    assert_eq!(device.event_node(), "event0");
+}
+
+#[tokio::test]
+async fn read_only_sysfs_fails_with_permission_denied() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let manager = TestManager::start().await;
+    let client = manager.client().await;
+
+    let sysfs = manager.base_path().join("sysfs");
+    std::fs::remove_dir_all(&sysfs).unwrap();
+    std::fs::create_dir(&sysfs).unwrap();
+    std::fs::set_permissions(&sysfs, std::fs::Permissions::from_mode(0o555)).unwrap();
+    if std::fs::create_dir(sysfs.join("probe")).is_ok() {
+        eprintln!("skipped, directory permissions aren't enforced for this user");
+        return;
+    }
+
+    let error = client
+        .create_device(common::gamepad())
+        .await
+        .err()
+        .expect("created a device in a read-only sysfs");
+    let message = error.to_string();
+    assert!(message.contains(sysfs.to_str().unwrap()), "{}", message);
+    assert!(message.contains("Permission denied"), "{}", message);
+
+    assert!(client.list_devices().await.unwrap().is_empty());
+    assert!(!manager.node_path("event0").exists());
+    assert!(!manager.node_path("event0.feedback").exists());
+
+    // The ID wasn't used up
+    std::fs::set_permissions(&sysfs, std::fs::Permissions::from_mode(0o755)).unwrap();
+    let device = client.create_device(common::gamepad()).await.unwrap();
+    assert_eq!(device.event_node(), "event0");
+}
+
+#[tokio::test]
+async fn device_no_longer_exists_after_destroy() {
+    let mut manager = TestManager::start().await;
+    let client = manager.client().await;
+
+    // Two handles of the same device
+    let device = client
+        .ensure_device("pad", common::gamepad())
+        .await
+        .unwrap();
+    let other = client
+        .ensure_device("pad", common::gamepad())
+        .await
+        .unwrap();
+    assert!(other.exists().await);
+    assert!(other.is_connected().await);
+
+    device.close().await.unwrap();
+    assert!(!other.exists().await);
+    assert!(other.is_connected().await);
+
+    // Without a manager nothing exists or is connected
+    let device = client.create_device(common::gamepad()).await.unwrap();
+    manager.restart().await;
+    assert!(!device.is_connected().await);
+    assert!(!device.exists().await);
+}
+
+#[tokio::test]
+async fn mixed_devices_are_filtered_to_gamepads() {
+    let manager = TestManager::start().await;
+    let client = manager.client().await;
+    let mut devices = Vec::new();
+    for config in [
+        ControllerTemplates::xbox360(),
+        ControllerTemplates::keyboard(),
+        ControllerTemplates::ps4(),
+        ControllerTemplates::mouse(),
+    ] {
+        devices.push(client.create_device(config).await.unwrap());
+    }
+    // In creation order, the manager lists them in no particular one
+    let names = |mut list: Vec<DeviceInfo>| {
+        list.sort_by_key(|d| d.device_id);
+        list.into_iter().map(|d| d.name).collect::<Vec<_>>()
+    };
+
+    assert_eq!(client.list_devices().await.unwrap().len(), 4);
+    let gamepads = client
+        .find_devices_by(DeviceFilter {
+            has_joystick: Some(true),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    assert_eq!(
+        names(gamepads),
+        [
+            ControllerTemplates::xbox360().name,
+            ControllerTemplates::ps4().name
+        ]
+    );
+
+    let by_name = client
+        .find_devices_by(DeviceFilter {
+            name: Some(ControllerTemplates::keyboard().name.to_uppercase()),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    assert_eq!(names(by_name), [ControllerTemplates::keyboard().name]);
+
+    let by_button = client
+        .find_devices_by(DeviceFilter {
+            button: Some(Button::A),
+            vendor_id: Some(ControllerTemplates::ps4().vendor_id),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    assert_eq!(names(by_button), [ControllerTemplates::ps4().name]);
+}
+
+#[tokio::test]
+async fn ensure_device_returns_the_same_device() {
+    let manager = TestManager::start().await;
+    let client = manager.client().await;
+
+    let first = client
+        .ensure_device("pad", common::gamepad())
+        .await
+        .unwrap();
+    let again = client
+        .ensure_device("pad", common::gamepad())
+        .await
+        .unwrap();
+    assert_eq!(first.device_id(), again.device_id());
+
+    // Even from another connection, as from the next run
+    let rerun = manager
+        .client()
+        .await
+        .ensure_device("pad", common::gamepad())
+        .await
+        .unwrap();
+    assert_eq!(rerun.device_id(), first.device_id());
+    assert_eq!(client.list_devices().await.unwrap().len(), 1);
+
+    // A different config under the key
+    let error = client
+        .ensure_device("pad", ControllerTemplates::ps4())
+        .await
+        .err()
+        .expect("ensured a device with a different config");
+    assert!(
+        error.to_string().contains("with a different config"),
+        "{}",
+        error
+    );
+    let recreated = client
+        .ensure_device_with("pad", ControllerTemplates::ps4(), EnsureMismatch::Recreate)
+        .await
+        .unwrap();
+    let config = client
+        .get_device_config(recreated.device_id())
+        .await
+        .unwrap();
+    assert_eq!(config.product_id, ControllerTemplates::ps4().product_id);
+    assert_eq!(client.list_devices().await.unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn client_count_follows_readers() {
+    let manager = TestManager::start().await;
+    let client = manager.client().await;
+    let device = client.create_device(common::gamepad()).await.unwrap();
+    let wait_for_count = async |count: usize| {
+        tokio::time::timeout(common::TIMEOUT, async {
+            while device.client_count().await.unwrap() != count {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap_or_else(|_| panic!("client count didn't become {}", count));
+    };
+    assert_eq!(device.client_count().await.unwrap(), 0);
+
+    let reader = manager.open(device.event_node()).await;
+    wait_for_count(1).await;
+    let counts = device.client_counts().await.unwrap();
+    assert_eq!(
+        counts,
+        ClientCounts {
+            evdev: 1,
+            joystick: 0,
+            feedback: 0
+        }
+    );
+
+    drop(reader);
+    wait_for_count(0).await;
+}
+
+#[tokio::test]
+async fn wait_for_device_sees_a_later_device() {
+    let manager = TestManager::start().await;
+    let waiter = manager.client().await;
+    let creator = manager.client().await;
+
+    let create = async {
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        creator.create_device(common::gamepad()).await.unwrap()
+    };
+    let wait = waiter.wait_for_device(
+        |device| device.name == common::gamepad().name,
+        common::TIMEOUT,
+    );
+    let (device, found) = tokio::join!(create, wait);
+    assert_eq!(found.unwrap().device_id, device.device_id());
+}
+
+#[tokio::test]
+async fn wait_for_device_times_out() {
+    let manager = TestManager::start().await;
+    let client = manager.client().await;
+    let _device = client.create_device(common::gamepad()).await.unwrap();
+
+    let error = client
+        .wait_for_device(
+            |device| device.name == "Missing",
+            Duration::from_millis(200),
+        )
+        .await
+        .expect_err("found a device that doesn't exist");
+    assert!(error.to_string().contains("200ms"), "{}", error);
+
+    // The connection is still usable
+    client.ping().await.unwrap();
+}
+
+#[tokio::test]
+async fn wait_for_device_times_out_on_a_manager_not_answering() {
+    let path = std::env::temp_dir().join(format!("vimputti-silent-{}.sock", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let listener = tokio::net::UnixListener::bind(&path).unwrap();
+    let client = VimputtiClient::connect(&path).await.unwrap();
+    let _connection = listener.accept().await.unwrap();
+
+    let wait = client.wait_for_device(|_| true, Duration::from_millis(200));
+    let error = tokio::time::timeout(common::TIMEOUT, wait)
+        .await
+        .expect("kept waiting for the manager past the timeout")
+        .expect_err("found a device on a manager that never answered");
+    std::fs::remove_file(&path).unwrap();
+    assert!(error.to_string().contains("200ms"), "{}", error);
+}
+
+#[tokio::test]
+async fn closed_device_is_no_longer_listed() {
+    let manager = TestManager::start().await;
+    let client = manager.client().await;
+    let kept = client.create_device(common::gamepad()).await.unwrap();
+    let closed = client.create_device(common::gamepad()).await.unwrap();
+    let closed_node = closed.event_node().to_string();
+
+    // No waiting, close returns once the device is gone
+    closed.close().await.unwrap();
+    let ids: Vec<_> = client
+        .list_devices()
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|device| device.device_id)
+        .collect();
+    assert_eq!(ids, [kept.device_id()]);
+    assert!(!manager.node_path(&closed_node).exists());
+}
+
+#[tokio::test]
+async fn device_event_callback_fires_on_create_and_destroy() {
+    let (sender, events) = std::sync::mpsc::channel();
+    let manager = TestManager::with_setup(ManagerConfig::default(), move |manager| {
+        let sender = sender.clone();
+        manager.on_device_event(move |event: &DeviceEvent| {
+            let _ = sender.send(event.clone());
+        });
+    })
+    .await;
+    let client = manager.client().await;
+    let next_event = || {
+        events
+            .recv_timeout(common::TIMEOUT)
+            .expect("no device event")
+    };
+
+    let device = client.create_device(common::gamepad()).await.unwrap();
+    let added = DeviceEvent {
+        event: HookEvent::DeviceAdded,
+        device_id: device.device_id(),
+        event_node: device.event_node().to_string(),
+        name: common::gamepad().name,
+    };
+    assert_eq!(next_event(), added);
+
+    device.close().await.unwrap();
+    let removed = DeviceEvent {
+        event: HookEvent::DeviceRemoved,
+        ..added
+    };
+    assert_eq!(next_event(), removed);
+}
+
+#[tokio::test]
+async fn oversized_input_batches_are_rejected() {
+    let manager = TestManager::with_config(ManagerConfig {
+        max_batch_events: 16,
+        ..Default::default()
+    })
+    .await;
+    let client = manager.client().await;
+    let device = client.create_device(common::gamepad()).await.unwrap();
+    let mut reader = manager.open(device.event_node()).await;
+    let batch = |len: usize| {
+        (0..len).map(|i| InputEvent::Axis {
+            axis: Axis::LeftStickX,
+            value: i as i32 + 1,
+        })
+    };
+
+    let error = device
+        .send_events(batch(17))
+        .await
+        .expect_err("sent a batch over the limit");
+    assert!(
+        error
+            .to_string()
+            .contains("Batch of 17 events exceeds the limit of 16"),
+        "{}",
+        error
+    );
+    // None of it was sent
+    assert_eq!(
+        reader.try_next_event(Duration::from_millis(100)).await,
+        None
+    );
+
+    device.send_events(batch(16)).await.unwrap();
+    device.sync().await.unwrap();
+    let frame = reader.next_frame().await;
+    assert_eq!(frame.len(), 17, "{:?}", frame);
+    assert_eq!(frame[15], (EV_ABS, Axis::LeftStickX.to_ev_code(), 16));
+
+    // The initial state of a device is applied at once too
+    let mut config = common::gamepad();
+    config.initial_state = batch(17).collect();
+    let error = client
+        .create_device(config)
+        .await
+        .err()
+        .expect("created a device with an initial state over the limit");
+    assert!(
+        error
+            .to_string()
+            .contains("Batch of 17 events exceeds the limit of 16"),
+        "{}",
+        error
+    );
+}
+
+#[tokio::test]
+async fn created_devices_keep_the_quirked_config() {
+    let manager = TestManager::start().await;
+    let client = manager.client().await;
+    let mut config = ControllerTemplates::xbox360();
+    config.apply_quirks = true;
+    let device = client.create_device(config).await.unwrap();
+
+    // xpad's 8-bit triggers from the built-in quirks
+    let trigger = device.config().axis(Axis::LowerLeftTrigger).unwrap();
+    assert_eq!((trigger.min, trigger.max), (0, 255));
+    assert_eq!(
+        &client.get_device_config(device.device_id()).await.unwrap(),
+        device.config()
+    );
+
+    let ensured = client
+        .ensure_device("pad", ControllerTemplates::switch_pro())
+        .await
+        .unwrap();
+    assert_eq!(ensured.config().remapped_button(Button::A), Button::B);
+    ensured.close().await.unwrap();
+}
+
+#[tokio::test]
+async fn fetched_config_matches_the_created_one() {
+    let manager = TestManager::start().await;
+    let client = manager.client().await;
+    let config = ControllerTemplates::ps4();
+    let device = client.create_device(config.clone()).await.unwrap();
+
+    assert_eq!(device.config(), &config);
+    assert_eq!(
+        client.get_device_config(device.device_id()).await.unwrap(),
+        config
+    );
+
+    // Changes since creation are only known to the manager
+    device.set_device_id(0x1234, 0x5678).await.unwrap();
+    let fetched = client.get_device_config(device.device_id()).await.unwrap();
+    assert_eq!((fetched.vendor_id, fetched.product_id), (0x1234, 0x5678));
+    assert_eq!(fetched.buttons, config.buttons);
+    assert_eq!(device.config(), &config);
+
+    let missing = client.get_device_config(DeviceId::MAX).await;
+    assert!(missing.is_err());
+}
+
+#[tokio::test]
+async fn update_keeps_the_presented_ids_and_battery() {
+    let manager = TestManager::start().await;
+    let client = manager.client().await;
+    let mut config = ControllerBuilder::new("Wireless Pad")
+        .button(Button::A)
+        .build();
+    config.battery = Some(BatteryConfig {
+        capacity: 100,
+        status: BatteryStatus::Full,
+    });
+    let mut device = client.create_device(config.clone()).await.unwrap();
+    device
+        .set_device_ids(0x1234, 0x5678, Some(2), Some(BusType::Bluetooth))
+        .await
+        .unwrap();
+    device
+        .set_battery(40, BatteryStatus::Discharging)
+        .await
+        .unwrap();
+
+    config.buttons.push(Button::B);
+    device.update_config(config).await.unwrap();
+
+    let fetched = client.get_device_config(device.device_id()).await.unwrap();
+    assert_eq!(fetched.buttons, [Button::A, Button::B]);
+    let ids = |config: &DeviceConfig| {
+        (
+            config.bustype,
+            config.vendor_id,
+            config.product_id,
+            config.version,
+        )
+    };
+    assert_eq!(ids(&fetched), (BusType::Bluetooth, 0x1234, 0x5678, 2));
+    assert_eq!(fetched.battery.unwrap().capacity, 40);
+
+    let reader = manager.open(device.event_node()).await;
+    assert_eq!(reader.handshake.config.buttons, [Button::A, Button::B]);
+    assert_eq!(
+        ids(&reader.handshake.config),
+        (BusType::Bluetooth, 0x1234, 0x5678, 2)
+    );
+
+    let sysfs = manager.base_path().join("sysfs/class");
+    let read = |path: &str| std::fs::read_to_string(sysfs.join(path)).unwrap();
+    assert_eq!(read("input/input0/id/vendor"), "1234\n");
+    assert_eq!(read("input/input0/id/product"), "5678\n");
+    assert_eq!(
+        read("power_supply/vimputti-battery-event0/capacity"),
+        "40\n"
+    );
+}
+
+#[tokio::test]
+async fn battery_is_only_set_on_devices_with_one() {
+    let manager = TestManager::start().await;
+    let client = manager.client().await;
+    let device = client.create_device(common::gamepad()).await.unwrap();
+
+    let error = device
+        .set_battery(50, BatteryStatus::Discharging)
+        .await
+        .unwrap_err();
+    assert!(error.to_string().contains("has no battery"), "{:#}", error);
+    let fetched = client.get_device_config(device.device_id()).await.unwrap();
+    assert_eq!(fetched.battery, None);
+    assert!(
+        !manager
+            .base_path()
+            .join("sysfs/class/power_supply/vimputti-battery-event0")
+            .exists()
+    );
+}
+
+#[tokio::test]
+async fn exported_devices_are_reimported_with_their_layouts() {
+    let manager = TestManager::start().await;
+    let client = manager.client().await;
+    let by_name = |mut configs: Vec<DeviceConfig>| {
+        configs.sort_by(|a, b| a.name.cmp(&b.name));
+        configs
+    };
+    let created = [ControllerTemplates::xbox360(), ControllerTemplates::ps4()];
+    let devices = client.import_devices(&created).await.unwrap();
+
+    let path = manager.base_path().join("devices.json");
+    let exported = client.export_devices().await.unwrap();
+    DeviceConfig::to_json_file(&path, &exported).unwrap();
+    for device in devices {
+        device.close().await.unwrap();
+    }
+    assert!(client.list_devices().await.unwrap().is_empty());
+
+    let loaded = DeviceConfig::from_json_file(&path).unwrap();
+    assert_eq!(by_name(loaded.clone()), by_name(created.to_vec()));
+    let imported = client.import_devices(&loaded).await.unwrap();
+    assert_eq!(
+        by_name(client.export_devices().await.unwrap()),
+        by_name(exported)
+    );
+
+    // Importing next to devices of the same names adds further ones
+    let again = client.import_devices(&loaded).await.unwrap();
+    let mut ids: Vec<_> = imported
+        .iter()
+        .chain(&again)
+        .map(|device| device.device_id())
+        .collect();
+    ids.sort();
+    ids.dedup();
+    assert_eq!(ids.len(), 4);
+}
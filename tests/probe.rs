@@ -0,0 +1,301 @@
+//! Probing and capturing real devices, created through the kernel's uinput
+//!
+//! Tests needing one are skipped where `/dev/uinput` isn't available or writable.
+
+#![cfg(all(feature = "probe", target_os = "linux"))]
+
+mod common;
+
+use common::TestManager;
+use std::ffi::CString;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+use vimputti::ControllerBuilder;
+use vimputti::capture::{CAPTURE_FORMAT_VERSION, Capture, CapturedEvent, FrameLayout};
+use vimputti::probe::probe_device;
+use vimputti::protocol::linux::*;
+use vimputti::protocol::*;
+
+/// A device on the kernel's uinput, destroyed on drop
+struct KernelDevice {
+    fd: i32,
+}
+impl KernelDevice {
+    /// Create a gamepad with A, B and a left stick, None if uinput can't be used
+    fn create(name: &str) -> Option<Self> {
+        let path = CString::new("/dev/uinput").unwrap();
+        let fd = unsafe { libc::open(path.as_ptr(), libc::O_WRONLY | libc::O_NONBLOCK) };
+        if fd < 0 {
+            return None;
+        }
+        let device = Self { fd };
+
+        device.ioctl(UI_SET_EVBIT, EV_KEY as usize);
+        for button in [Button::A, Button::B] {
+            device.ioctl(UI_SET_KEYBIT, button.to_ev_code() as usize);
+        }
+        device.ioctl(UI_SET_EVBIT, EV_ABS as usize);
+        let mut abs: libc::uinput_abs_setup = unsafe { std::mem::zeroed() };
+        abs.code = Axis::LeftStickX.to_ev_code();
+        abs.absinfo.minimum = -32768;
+        abs.absinfo.maximum = 32767;
+        abs.absinfo.fuzz = 16;
+        abs.absinfo.flat = 128;
+        device.ioctl(UI_ABS_SETUP, &abs as *const _ as usize);
+
+        let mut setup: libc::uinput_setup = unsafe { std::mem::zeroed() };
+        setup.id.bustype = 0x03;
+        setup.id.vendor = 0x045e;
+        setup.id.product = 0x028e;
+        setup.id.version = 0x0110;
+        for (dst, &src) in setup.name.iter_mut().zip(name.as_bytes()) {
+            *dst = src as libc::c_char;
+        }
+        device.ioctl(UI_DEV_SETUP, &setup as *const _ as usize);
+        device.ioctl(UI_DEV_CREATE, 0);
+        Some(device)
+    }
+
+    fn ioctl(&self, request: u32, arg: usize) {
+        let result = unsafe { libc::ioctl(self.fd, request as _, arg) };
+        assert!(
+            result >= 0,
+            "uinput ioctl {:x} failed: {}",
+            request,
+            std::io::Error::last_os_error()
+        );
+    }
+
+    /// Write events to the device, as (type, code, value)
+    fn emit(&self, events: &[(u16, u16, i32)]) {
+        for &(event_type, code, value) in events {
+            let bytes = LinuxInputEvent::new(event_type, code, value).to_bytes();
+            let written = unsafe { libc::write(self.fd, bytes.as_ptr().cast(), bytes.len()) };
+            assert_eq!(written, bytes.len() as isize);
+        }
+    }
+
+    /// The device's `/dev/input/eventN`, once it appeared
+    fn event_node(&self) -> PathBuf {
+        let mut sysname = [0u8; 64];
+        self.ioctl(ui_get_sysname(sysname.len()), sysname.as_mut_ptr() as usize);
+        let len = sysname.iter().position(|&b| b == 0).unwrap();
+        let sysfs = PathBuf::from("/sys/devices/virtual/input")
+            .join(std::str::from_utf8(&sysname[..len]).unwrap());
+
+        let start = Instant::now();
+        loop {
+            let event = std::fs::read_dir(&sysfs)
+                .unwrap()
+                .filter_map(|entry| entry.ok())
+                .map(|entry| entry.file_name().to_string_lossy().into_owned())
+                .find(|name| name.starts_with("event"));
+            if let Some(event) = event {
+                let node = PathBuf::from("/dev/input").join(event);
+                if node.exists() {
+                    return node;
+                }
+            }
+            assert!(start.elapsed() < Duration::from_secs(5), "no event node");
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    }
+}
+impl Drop for KernelDevice {
+    fn drop(&mut self) {
+        unsafe {
+            libc::ioctl(self.fd, UI_DEV_DESTROY as _);
+            libc::close(self.fd);
+        }
+    }
+}
+
+#[test]
+fn probed_config_matches_uinput_device() {
+    let Some(device) = KernelDevice::create("vimputti probe test") else {
+        eprintln!("skipped, /dev/uinput isn't available");
+        return;
+    };
+
+    let config = match probe_device(&device.event_node()) {
+        Ok(config) => config,
+        Err(e) if format!("{:#}", e).contains("input group") => {
+            eprintln!("skipped, the event node can't be read: {:#}", e);
+            return;
+        }
+        Err(e) => panic!("{:#}", e),
+    };
+
+    assert_eq!(config.name, "vimputti probe test");
+    assert_eq!(config.bustype, BusType::Usb);
+    assert_eq!((config.vendor_id, config.product_id), (0x045e, 0x028e));
+    assert_eq!(config.version, 0x0110);
+    assert_eq!(config.buttons, [Button::A, Button::B]);
+
+    assert_eq!(config.axes.len(), 1);
+    let x = &config.axes[0];
+    assert_eq!(x.axis, Axis::LeftStickX);
+    assert_eq!((x.min, x.max, x.fuzz, x.flat), (-32768, 32767, 16, 128));
+    assert!(config.validate().is_ok());
+}
+
+/// Events of the frames a virtual device created from `capture` gets when it's replayed
+async fn replayed_frames(capture: &Capture, frames: usize) -> Vec<(u16, u16, i32)> {
+    let manager = TestManager::start().await;
+    let client = manager.client().await;
+    let device = client.create_device(capture.config.clone()).await.unwrap();
+    let mut reader = manager.open(device.event_node()).await;
+
+    capture.replay(&device).await.unwrap();
+    let mut events = Vec::new();
+    for _ in 0..frames {
+        events.extend(reader.next_frame().await);
+    }
+    events
+}
+
+#[tokio::test]
+async fn raw_dumps_of_either_layout_replay_the_same_frames() {
+    let a = Button::A.to_ev_code();
+    let x = Axis::LeftStickX.to_ev_code();
+    let events: [(i64, (u16, u16, i32)); 5] = [
+        (1_000_000, (EV_KEY, a, 1)),
+        (1_000_000, (EV_ABS, x, 1000)),
+        (1_000_000, (EV_SYN, SYN_REPORT, 0)),
+        (1_020_000, (EV_KEY, a, 0)),
+        (1_020_000, (EV_SYN, SYN_REPORT, 0)),
+    ];
+    // Each event as its timeval's seconds and microseconds, then type, code and value
+    let mut time64 = Vec::new();
+    let mut time32 = Vec::new();
+    for (time_us, (event_type, code, value)) in events {
+        let (sec, usec) = (time_us / 1_000_000, time_us % 1_000_000);
+        let rest = [
+            &event_type.to_ne_bytes()[..],
+            &code.to_ne_bytes(),
+            &value.to_ne_bytes(),
+        ]
+        .concat();
+        time64.extend([&sec.to_ne_bytes()[..], &usec.to_ne_bytes(), &rest].concat());
+        time32.extend(
+            [
+                &(sec as i32).to_ne_bytes()[..],
+                &(usec as i32).to_ne_bytes(),
+                &rest,
+            ]
+            .concat(),
+        );
+    }
+    let config = ControllerBuilder::new("Captured")
+        .button(Button::A)
+        .axis(Axis::LeftStickX, -32768, 32767)
+        .build();
+
+    let capture = Capture::from_raw(config.clone(), &time64, FrameLayout::Time64).unwrap();
+    let from_time32 = Capture::from_raw(config, &time32, FrameLayout::Time32).unwrap();
+    assert_eq!(capture.events, from_time32.events);
+    assert_eq!(capture.duration(), Duration::from_millis(20));
+
+    let events: Vec<_> = events.iter().map(|&(_, event)| event).collect();
+    assert_eq!(replayed_frames(&capture, 2).await, events);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn captured_uinput_device_replays_the_same_frames() {
+    let Some(device) = KernelDevice::create("vimputti capture test") else {
+        eprintln!("skipped, /dev/uinput isn't available");
+        return;
+    };
+    let node = device.event_node();
+    let recording =
+        tokio::task::spawn_blocking(move || Capture::record(&node, Duration::from_secs(1)));
+
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    let a = Button::A.to_ev_code();
+    let b = Button::B.to_ev_code();
+    let x = Axis::LeftStickX.to_ev_code();
+    let events = [
+        (EV_KEY, a, 1),
+        (EV_ABS, x, 12000),
+        (EV_SYN, SYN_REPORT, 0),
+        (EV_KEY, b, 1),
+        (EV_KEY, a, 0),
+        (EV_SYN, SYN_REPORT, 0),
+    ];
+    device.emit(&events);
+
+    let capture = match recording.await.unwrap() {
+        Ok(capture) => capture,
+        Err(e) if format!("{:#}", e).contains("input group") => {
+            eprintln!("skipped, the event node can't be read: {:#}", e);
+            return;
+        }
+        Err(e) => panic!("{:#}", e),
+    };
+    let captured: Vec<_> = capture
+        .events
+        .iter()
+        .map(|event| (event.event_type, event.code, event.value))
+        .collect();
+    assert_eq!(captured, events);
+    assert_eq!(replayed_frames(&capture, 2).await, events);
+}
+
+#[tokio::test]
+async fn replay_keeps_the_captured_deltas() {
+    let a = Button::A.to_ev_code();
+    let start_us = 1_700_000_000_000_000;
+    let event = |time_us: u64, event_type: u16, code: u16, value: i32| CapturedEvent {
+        time_us,
+        wall_time_us: Some(start_us + time_us),
+        event_type,
+        code,
+        value,
+    };
+    let mut events = Vec::new();
+    for (time_ms, pressed) in [(0, 1), (50, 0), (120, 1), (200, 0)] {
+        events.push(event(time_ms * 1000, EV_KEY, a, pressed));
+        events.push(event(time_ms * 1000, EV_SYN, SYN_REPORT, 0));
+    }
+    let capture = Capture {
+        format_version: CAPTURE_FORMAT_VERSION,
+        config: ControllerBuilder::new("Captured").button(Button::A).build(),
+        events,
+    };
+
+    // Saved and loaded with the wall-clock times
+    let path = std::env::temp_dir().join(format!("vimputti-capture-{}.json", std::process::id()));
+    capture.save(&path).unwrap();
+    let capture = Capture::load(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(
+        capture.started_at(),
+        Some(std::time::UNIX_EPOCH + Duration::from_micros(start_us))
+    );
+
+    let manager = TestManager::start().await;
+    let client = manager.client().await;
+    let device = client.create_device(capture.config.clone()).await.unwrap();
+    let mut reader = manager.open(device.event_node()).await;
+    let replay = capture.replay(&device);
+    let arrivals = async {
+        let mut arrivals = Vec::new();
+        for _ in 0..4 {
+            reader.next_frame().await;
+            arrivals.push(Instant::now());
+        }
+        arrivals
+    };
+    let (replayed, arrivals) = tokio::join!(replay, arrivals);
+    replayed.unwrap();
+
+    for (arrived, captured_ms) in arrivals[1..].iter().zip([50, 120, 200]) {
+        let delta = arrived.duration_since(arrivals[0]).as_millis() as i64;
+        assert!(
+            (delta - captured_ms).abs() < 25,
+            "frame captured at {}ms arrived at {}ms",
+            captured_ms,
+            delta
+        );
+    }
+}
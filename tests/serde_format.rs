@@ -0,0 +1,95 @@
+//! Serialized formats, pinned by the frozen fixtures in `tests/fixtures`
+//!
+//! Saved configs and recordings are read back by later versions, so a failure here means the
+//! format changed. A template's fixture is only updated along with the template itself, any
+//! other change needs a new `DEVICE_CONFIG_SCHEMA_VERSION`.
+
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use std::fmt::Debug;
+use vimputti::ControllerTemplates;
+use vimputti::protocol::*;
+
+fn fixture(name: &str) -> String {
+    let path = format!("{}/tests/fixtures/{}", env!("CARGO_MANIFEST_DIR"), name);
+    std::fs::read_to_string(&path).unwrap_or_else(|e| panic!("{}: {}", path, e))
+}
+
+/// Check that `fixture` deserializes to `value`, and `value` serializes to exactly `fixture`
+fn assert_round_trip<T>(fixture_name: &str, value: &T)
+where
+    T: Serialize + DeserializeOwned + PartialEq + Debug,
+{
+    let json = fixture(fixture_name);
+    let parsed: T = serde_json::from_str(&json).unwrap();
+    assert_eq!(&parsed, value, "{} deserialized differently", fixture_name);
+
+    let expected: serde_json::Value = serde_json::from_str(&json).unwrap();
+    assert_eq!(
+        serde_json::to_value(value).unwrap(),
+        expected,
+        "{} serialized differently",
+        fixture_name
+    );
+}
+
+#[test]
+fn buttons_match_fixture() {
+    let mut buttons = Button::all_standard().to_vec();
+    buttons.extend([
+        Button::TriggerHappy(1),
+        Button::TriggerHappy(40),
+        Button::Custom(317),
+    ]);
+    assert_round_trip("buttons.json", &buttons);
+}
+
+#[test]
+fn axes_match_fixture() {
+    let axes = vec![
+        Axis::LeftStickX,
+        Axis::LeftStickY,
+        Axis::RightStickX,
+        Axis::RightStickY,
+        Axis::LowerLeftTrigger,
+        Axis::LowerRightTrigger,
+        Axis::DPadX,
+        Axis::DPadY,
+        Axis::Throttle,
+        Axis::Rudder,
+        Axis::Wheel,
+        Axis::Gas,
+        Axis::Brake,
+        Axis::Misc,
+        Axis::AccelX,
+        Axis::AccelY,
+        Axis::AccelZ,
+        Axis::GyroX,
+        Axis::GyroY,
+        Axis::GyroZ,
+        Axis::Custom(40),
+    ];
+    assert_round_trip("axes.json", &axes);
+}
+
+#[test]
+fn templates_match_fixtures() {
+    for name in ControllerTemplates::all_names() {
+        let template = ControllerTemplates::from_name(name).unwrap();
+        assert_round_trip(&format!("templates/{}.json", name), &template);
+    }
+}
+
+#[test]
+fn unversioned_config_reads_as_first_format() {
+    let config: DeviceConfig =
+        serde_json::from_str(&fixture("device_config_unversioned.json")).unwrap();
+
+    assert_eq!(config.schema_version, 1);
+    assert_eq!(config.validate(), Ok(()));
+    assert_eq!(config.name, "Saved Pad");
+    assert_eq!(config.bustype, BusType::Usb);
+    assert_eq!(config.buttons, [Button::A, Button::B, Button::Custom(704)]);
+    assert_eq!(config.axes[1].axis, Axis::Custom(40));
+    assert_eq!((config.axes[0].fuzz, config.axes[0].flat), (16, 128));
+}
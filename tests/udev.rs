@@ -0,0 +1,506 @@
+//! Sysfs and udev presentation of devices
+
+mod common;
+
+use common::TestManager;
+use vimputti::ControllerBuilder;
+use vimputti::protocol::*;
+
+/// Properties of a udev data file, e.g. `c13:64`
+fn udev_properties(manager: &TestManager, data_file: &str) -> Vec<String> {
+    std::fs::read_to_string(manager.base_path().join("udev_data").join(data_file))
+        .unwrap()
+        .lines()
+        .map(str::to_string)
+        .collect()
+}
+
+#[tokio::test]
+async fn virtual_bus_device_presents_as_joystick() {
+    let manager = TestManager::start().await;
+    let client = manager.client().await;
+    let device = client
+        .create_device(
+            ControllerBuilder::new("Custom Pad")
+                .buttons([Button::A, Button::B])
+                .axis(Axis::LeftStickX, -32768, 32767)
+                .build(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(device.config().bustype, BusType::Virtual);
+
+    let properties = udev_properties(&manager, "c13:64");
+    for expected in [
+        "E:ID_INPUT=1",
+        "E:ID_INPUT_JOYSTICK=1",
+        "E:ID_BUS=usb",
+        "E:TAGS=:seat:uaccess:",
+    ] {
+        assert!(
+            properties.iter().any(|p| p == expected),
+            "{} missing from {:?}",
+            expected,
+            properties
+        );
+    }
+
+    // The real bustype stays in sysfs
+    let bustype = std::fs::read_to_string(
+        manager
+            .base_path()
+            .join("sysfs/class/input/event0/device/id/bustype"),
+    )
+    .unwrap();
+    assert_eq!(bustype, "0006\n");
+}
+
+#[tokio::test]
+async fn udev_bus_overrides_presented_bus() {
+    let manager = TestManager::start().await;
+    let client = manager.client().await;
+    let _device = client
+        .create_device(
+            ControllerBuilder::new("Custom Pad")
+                .button(Button::A)
+                .udev_bus("bluetooth")
+                .build(),
+        )
+        .await
+        .unwrap();
+
+    let properties = udev_properties(&manager, "c13:64");
+    assert!(properties.iter().any(|p| p == "E:ID_BUS=bluetooth"));
+}
+
+#[tokio::test]
+async fn sysfs_input_tree_matches_config() {
+    let manager = TestManager::start().await;
+    let client = manager.client().await;
+    let _device = client
+        .create_device(
+            ControllerBuilder::new("Sysfs Pad")
+                .button(Button::A)
+                .axis(Axis::LeftStickX, -32768, 32767)
+                .vendor_id(0x045e)
+                .product_id(0x028e)
+                .build(),
+        )
+        .await
+        .unwrap();
+
+    let class = manager.base_path().join("sysfs/class/input");
+    let read = |path: &str| std::fs::read_to_string(class.join(path)).unwrap();
+
+    // eventN/device is the inputN node, which class/input also links to
+    assert_eq!(
+        class.join("event0/device").canonicalize().unwrap(),
+        class.join("input0").canonicalize().unwrap()
+    );
+    assert_eq!(read("event0/device/name"), "Sysfs Pad (event0)\n");
+    assert_eq!(read("input0/phys"), "vimputti-event0\n");
+    assert_eq!(read("input0/uniq"), "event0\n");
+    assert_eq!(read("input0/id/bustype"), "0006\n");
+    assert_eq!(read("input0/id/vendor"), "045e\n");
+    assert_eq!(read("input0/id/product"), "028e\n");
+
+    let uevent = read("input0/uevent");
+    assert!(uevent.contains("PRODUCT=6/45e/28e/"), "{}", uevent);
+    assert!(
+        uevent.contains("NAME=\"Sysfs Pad (event0)\"\n"),
+        "{}",
+        uevent
+    );
+    assert!(
+        uevent.contains("MODALIAS=input:b0006v045Ep028E"),
+        "{}",
+        uevent
+    );
+
+    assert_eq!(read("event0/dev"), "13:64\n");
+    assert!(read("event0/uevent").contains("DEVNAME=input/event0\n"));
+    assert_eq!(read("js0/dev"), "81:0\n");
+    assert_eq!(
+        class.join("js0/device").canonicalize().unwrap(),
+        class.join("input0").canonicalize().unwrap()
+    );
+    for node in ["input0", "event0", "js0"] {
+        assert_eq!(
+            class.join(node).join("subsystem").canonicalize().unwrap(),
+            class.canonicalize().unwrap(),
+            "{}",
+            node
+        );
+    }
+}
+
+/// A gamepad with a battery, as a wireless controller has
+fn wireless_gamepad(capacity: u8, status: BatteryStatus) -> DeviceConfig {
+    let mut config = common::gamepad();
+    config.battery = Some(BatteryConfig { capacity, status });
+    config
+}
+
+#[tokio::test]
+async fn battery_is_a_power_supply_of_the_device() {
+    let manager = TestManager::start().await;
+    let client = manager.client().await;
+    let _device = client
+        .create_device(wireless_gamepad(80, BatteryStatus::Charging))
+        .await
+        .unwrap();
+
+    let class = manager.base_path().join("sysfs/class");
+    let supply = class.join("power_supply/vimputti-battery-event0");
+    let read = |file: &str| std::fs::read_to_string(supply.join(file)).unwrap();
+    assert_eq!(read("type"), "Battery\n");
+    assert_eq!(read("scope"), "Device\n");
+    assert_eq!(read("status"), "Charging\n");
+    assert_eq!(read("present"), "1\n");
+    assert_eq!(read("capacity"), "80\n");
+    let uevent = read("uevent");
+    for expected in [
+        "POWER_SUPPLY_NAME=vimputti-battery-event0\n",
+        "POWER_SUPPLY_TYPE=Battery\n",
+        "POWER_SUPPLY_CAPACITY=80\n",
+    ] {
+        assert!(
+            uevent.contains(expected),
+            "{} missing from {}",
+            expected,
+            uevent
+        );
+    }
+
+    // Under the input device it belongs to
+    assert_eq!(
+        supply.join("device").canonicalize().unwrap(),
+        class.join("input/input0").canonicalize().unwrap()
+    );
+    assert_eq!(
+        supply.join("subsystem").canonicalize().unwrap(),
+        class.join("power_supply").canonicalize().unwrap()
+    );
+
+    let properties = udev_properties(&manager, "c13:64");
+    for expected in [
+        "E:POWER_SUPPLY_SCOPE=Device",
+        "E:POWER_SUPPLY_STATUS=Charging",
+        "E:POWER_SUPPLY_CAPACITY=80",
+    ] {
+        assert!(
+            properties.iter().any(|p| p == expected),
+            "{} missing from {:?}",
+            expected,
+            properties
+        );
+    }
+}
+
+#[tokio::test]
+async fn wired_device_has_no_power_supply() {
+    let manager = TestManager::start().await;
+    let client = manager.client().await;
+    let _device = client.create_device(common::gamepad()).await.unwrap();
+
+    let class = manager.base_path().join("sysfs/class/power_supply");
+    assert_eq!(std::fs::read_dir(class).unwrap().count(), 0);
+    let properties = udev_properties(&manager, "c13:64");
+    assert!(
+        !properties.iter().any(|p| p.starts_with("E:POWER_SUPPLY_")),
+        "{:?}",
+        properties
+    );
+}
+
+#[tokio::test]
+async fn passive_device_is_enumerated_but_takes_no_input() {
+    let manager = TestManager::start().await;
+    let client = manager.client().await;
+    let device = client
+        .create_device(
+            ControllerBuilder::new("Detect Me")
+                .button(Button::A)
+                .passive(true)
+                .build(),
+        )
+        .await
+        .unwrap();
+
+    let properties = udev_properties(&manager, "c13:64");
+    assert!(properties.iter().any(|p| p == "E:ID_INPUT_JOYSTICK=1"));
+    let name = std::fs::read_to_string(
+        manager
+            .base_path()
+            .join("sysfs/class/input/event0/device/name"),
+    )
+    .unwrap();
+    assert_eq!(name, "Detect Me (event0)\n");
+
+    // Opening it works like any other device
+    let reader = manager.open("event0").await;
+    assert!(reader.handshake.config.passive);
+
+    let error = device
+        .button(Button::A, true)
+        .await
+        .expect_err("sent input to a passive device");
+    assert!(error.to_string().contains("passive"), "{}", error);
+}
+
+#[cfg(feature = "udev-monitor")]
+#[tokio::test]
+async fn late_monitor_gets_existing_devices() {
+    use common::TIMEOUT;
+    use vimputti::UdevMonitorClient;
+    use vimputti::manager::udev::{UdevAction, UdevEvent};
+
+    let manager = TestManager::start().await;
+    let client = manager.client().await;
+    let _first = client.create_device(common::gamepad()).await.unwrap();
+    let _second = client.create_device(common::gamepad()).await.unwrap();
+
+    let mut monitor = UdevMonitorClient::for_client(&client).await.unwrap();
+    let mut next_event = async || -> UdevEvent {
+        tokio::time::timeout(TIMEOUT, monitor.next_event())
+            .await
+            .expect("no udev event")
+            .unwrap()
+    };
+
+    // Added when connecting, then again on request
+    for round in 0..2 {
+        if round == 1 {
+            assert_eq!(client.rebroadcast_devices().await.unwrap(), 2);
+        }
+        let mut added = Vec::new();
+        while added.len() < 2 {
+            let event = next_event().await;
+            assert_eq!(event.action, UdevAction::Add);
+            if event.device_info.devname.contains("event") {
+                added.push(event.device_info.devname);
+            }
+        }
+        assert!(added[0].ends_with("event0"), "{:?}", added);
+        assert!(added[1].ends_with("event1"), "{:?}", added);
+    }
+}
+
+#[cfg(feature = "udev-monitor")]
+#[tokio::test(flavor = "multi_thread")]
+async fn monitors_connecting_during_creation_get_each_device_once() {
+    use common::TIMEOUT;
+    use vimputti::UdevMonitorClient;
+    use vimputti::manager::udev::UdevAction;
+
+    const DEVICES: usize = 8;
+    let manager = TestManager::start().await;
+    let client = manager.client().await;
+    let creator = manager.client().await;
+
+    let creating = tokio::spawn(async move {
+        let mut devices = Vec::new();
+        for _ in 0..DEVICES {
+            devices.push(creator.create_device(common::gamepad()).await.unwrap());
+        }
+        devices
+    });
+    let mut monitors = Vec::new();
+    loop {
+        monitors.push(UdevMonitorClient::for_client(&client).await.unwrap());
+        if creating.is_finished() || monitors.len() == 64 {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(1)).await;
+    }
+    let _devices = creating.await.unwrap();
+
+    // Adds of the evdev nodes, until the one of `until` if given
+    let read_adds = async |monitor: &mut UdevMonitorClient, until: Option<&str>| {
+        let mut added: Vec<String> = Vec::new();
+        while until.is_some() || added.len() < DEVICES {
+            let event = tokio::time::timeout(TIMEOUT, monitor.next_event())
+                .await
+                .expect("no udev event")
+                .unwrap();
+            let devname = event.device_info.devname;
+            assert_eq!(event.action, UdevAction::Add);
+            if until.is_some_and(|node| devname.ends_with(node)) {
+                break;
+            }
+            if devname.contains("event") {
+                assert!(!added.contains(&devname), "{} added twice", devname);
+                added.push(devname);
+            }
+        }
+        added
+    };
+    for monitor in &mut monitors {
+        assert_eq!(read_adds(monitor, None).await.len(), DEVICES);
+    }
+
+    // Every monitor is subscribed by now, so a duplicate would come before the next device
+    let marker = client.create_device(common::gamepad()).await.unwrap();
+    for monitor in &mut monitors {
+        let duplicates = read_adds(monitor, Some(marker.event_node())).await;
+        assert!(duplicates.is_empty(), "{:?} added twice", duplicates);
+    }
+}
+
+#[cfg(feature = "udev-monitor")]
+#[tokio::test]
+async fn monitor_sees_add_and_remove_of_a_device() {
+    use common::TIMEOUT;
+    use vimputti::UdevMonitorClient;
+    use vimputti::manager::udev::UdevAction;
+
+    let manager = TestManager::start().await;
+    let client = manager.client().await;
+    let mut monitor = UdevMonitorClient::for_client(&client).await.unwrap();
+    let config = common::gamepad();
+
+    // The evdev node's event, skipping the joystick node's
+    let mut next_event_node_event = async || loop {
+        let event = tokio::time::timeout(TIMEOUT, monitor.next_event())
+            .await
+            .expect("no udev event")
+            .unwrap();
+        if event.device_info.devname.ends_with("event0") {
+            return event;
+        }
+    };
+
+    let device = client.create_device(config.clone()).await.unwrap();
+    let added = next_event_node_event().await;
+    assert_eq!(added.action, UdevAction::Add);
+    assert_eq!(added.device_info.subsystem, "input");
+    let property = |key: &str| {
+        let properties = &added.device_info.properties;
+        properties
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.clone())
+    };
+    assert_eq!(
+        property("ID_VENDOR_ID"),
+        Some(format!("{:04x}", config.vendor_id))
+    );
+    assert_eq!(
+        property("ID_MODEL_ID"),
+        Some(format!("{:04x}", config.product_id))
+    );
+    assert_eq!(property("ID_INPUT_JOYSTICK").as_deref(), Some("1"));
+
+    device.close().await.unwrap();
+    let removed = next_event_node_event().await;
+    assert_eq!(removed.action, UdevAction::Remove);
+    assert_eq!(removed.device_info.devpath, added.device_info.devpath);
+}
+
+#[cfg(feature = "udev-monitor")]
+#[tokio::test]
+async fn joystick_interface_comes_and_goes_in_udev() {
+    use common::TIMEOUT;
+    use vimputti::UdevMonitorClient;
+    use vimputti::manager::udev::UdevAction;
+
+    let manager = TestManager::start().await;
+    let client = manager.client().await;
+    let device = client.create_device(common::gamepad()).await.unwrap();
+    let mut reader = manager.open_joystick("js0").await;
+    let mut monitor = UdevMonitorClient::for_client(&client).await.unwrap();
+
+    // The joystick node's events, skipping the ones of connecting
+    let mut next_joystick_event = async |action: UdevAction| loop {
+        let event = tokio::time::timeout(TIMEOUT, monitor.next_event())
+            .await
+            .expect("no udev event")
+            .unwrap();
+        if event.device_info.devname.ends_with("js0") && event.action == action {
+            return event;
+        }
+    };
+
+    assert_eq!(device.set_joystick_interface(false).await.unwrap(), None);
+    next_joystick_event(UdevAction::Remove).await;
+    reader.wait_closed().await;
+    assert!(!manager.node_path("js0").exists());
+    // The evdev node stays
+    manager.open(device.event_node()).await;
+
+    assert_eq!(
+        device
+            .set_joystick_interface(true)
+            .await
+            .unwrap()
+            .as_deref(),
+        Some("js0")
+    );
+    next_joystick_event(UdevAction::Add).await;
+    manager.open_joystick("js0").await;
+}
+
+#[cfg(feature = "udev-monitor")]
+#[tokio::test]
+async fn battery_changes_are_announced_in_udev() {
+    use common::TIMEOUT;
+    use vimputti::UdevMonitorClient;
+    use vimputti::manager::udev::UdevAction;
+
+    let manager = TestManager::start().await;
+    let client = manager.client().await;
+    let mut monitor = UdevMonitorClient::for_client(&client).await.unwrap();
+
+    // The evdev node's event, skipping the joystick node's
+    let mut next_event_node_event = async || loop {
+        let event = tokio::time::timeout(TIMEOUT, monitor.next_event())
+            .await
+            .expect("no udev event")
+            .unwrap();
+        if event.device_info.devname.ends_with("event0") {
+            return event;
+        }
+    };
+    let property = |event: &vimputti::manager::udev::UdevEvent, key: &str| {
+        let properties = &event.device_info.properties;
+        properties
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.clone())
+    };
+
+    let device = client
+        .create_device(wireless_gamepad(100, BatteryStatus::Full))
+        .await
+        .unwrap();
+    let added = next_event_node_event().await;
+    assert_eq!(added.action, UdevAction::Add);
+    assert_eq!(
+        property(&added, "POWER_SUPPLY_NAME").as_deref(),
+        Some("vimputti-battery-event0")
+    );
+    assert_eq!(
+        property(&added, "POWER_SUPPLY_CAPACITY").as_deref(),
+        Some("100")
+    );
+    assert_eq!(
+        property(&added, "POWER_SUPPLY_STATUS").as_deref(),
+        Some("Full")
+    );
+
+    device
+        .set_battery(15, BatteryStatus::Discharging)
+        .await
+        .unwrap();
+    let changed = next_event_node_event().await;
+    assert_eq!(changed.action, UdevAction::Change);
+    assert_eq!(changed.device_info.devpath, added.device_info.devpath);
+    assert_eq!(
+        property(&changed, "POWER_SUPPLY_CAPACITY").as_deref(),
+        Some("15")
+    );
+    assert_eq!(
+        property(&changed, "POWER_SUPPLY_STATUS").as_deref(),
+        Some("Discharging")
+    );
+}
@@ -0,0 +1,265 @@
+//! Devices created through the manager's uinput emulation
+
+mod common;
+
+use common::{TestManager, UinputSession};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use vimputti::manager::ManagerConfig;
+use vimputti::protocol::framing::read_frame_async;
+use vimputti::protocol::*;
+use vimputti::{ControllerBuilder, ControllerTemplates};
+
+#[tokio::test]
+async fn mirror_gets_remapped_buttons() {
+    let manager = TestManager::start().await;
+    let client = manager.client().await;
+    // Nintendo's label-based A/B swap from the built-in quirks
+    let source = client
+        .create_device(
+            ControllerBuilder::new("Pro Controller")
+                .vendor_id(0x057e)
+                .product_id(0x2009)
+                .buttons([Button::A, Button::B])
+                .apply_quirks(true)
+                .build(),
+        )
+        .await
+        .unwrap();
+
+    let mut session = UinputSession::connect(&manager).await;
+    let mirror_id = session
+        .create("Steam Virtual Gamepad", &[Button::A, Button::B])
+        .await;
+    let mut source_reader = manager.open(source.event_node()).await;
+    let mut mirror_reader = manager.open(&event_node_name("", mirror_id)).await;
+
+    source.button(Button::A, true).await.unwrap();
+    source.sync().await.unwrap();
+
+    let expected = vec![(EV_KEY, Button::B.to_ev_code(), 1), (EV_SYN, SYN_REPORT, 0)];
+    assert_eq!(source_reader.next_frame().await, expected);
+    assert_eq!(mirror_reader.next_frame().await, expected);
+}
+
+#[tokio::test]
+async fn bus_type_is_preserved() {
+    let manager = TestManager::start().await;
+    let client = manager.client().await;
+    let mut session = UinputSession::connect(&manager).await;
+
+    // Each uinput device mirrors one of these
+    let mut sources = Vec::new();
+    for (bus, expected) in [
+        (0x18, BusType::I2c),
+        (0x1c, BusType::Spi),
+        (0x19, BusType::Other(0x19)),
+    ] {
+        sources.push(client.create_device(common::gamepad()).await.unwrap());
+        let device_id = session.create_on_bus("Sensor", &[Button::A], bus).await;
+        let config = client.get_device_config(device_id).await.unwrap();
+        assert_eq!(config.bustype, expected);
+        let reader = manager.open(&event_node_name("", device_id)).await;
+        assert_eq!(reader.handshake.config.bustype.to_u16(), bus);
+    }
+}
+
+/// Read a frame as shims from before the frame header do, a bare length and the payload
+async fn read_legacy_frame(stream: &mut tokio::net::UnixStream) -> Vec<u8> {
+    let mut len = [0u8; 4];
+    let mut payload = Vec::new();
+    tokio::time::timeout(common::TIMEOUT, async {
+        stream.read_exact(&mut len).await.unwrap();
+        let len = u32::from_le_bytes(len) as usize;
+        assert!(
+            len <= framing::MAX_FRAME_LEN,
+            "length {} of a current header",
+            len
+        );
+        payload.resize(len, 0);
+        stream.read_exact(&mut payload).await.unwrap();
+    })
+    .await
+    .expect("no legacy frame");
+    payload
+}
+
+#[tokio::test]
+async fn legacy_framing_is_still_understood() {
+    let manager = TestManager::start().await;
+    let mut stream = tokio::net::UnixStream::connect(manager.base_path().join("uinput"))
+        .await
+        .unwrap();
+
+    let request = serde_json::to_vec(&UinputRequest::SetEvBit { ev_type: EV_KEY }).unwrap();
+    stream
+        .write_all(&(request.len() as u32).to_le_bytes())
+        .await
+        .unwrap();
+    stream.write_all(&request).await.unwrap();
+
+    // Answered in kind
+    let payload = read_legacy_frame(&mut stream).await;
+    assert!(UinputResponse::from_bytes(&payload).unwrap().success);
+
+    // The handshake comes before the reader sends anything, so it's legacy for every reader
+    let client = manager.client().await;
+    let _device = client.create_device(common::gamepad()).await.unwrap();
+    let mut reader = tokio::net::UnixStream::connect(manager.node_path("event0"))
+        .await
+        .unwrap();
+    let payload = read_legacy_frame(&mut reader).await;
+    assert_eq!(DeviceHandshake::from_bytes(&payload).unwrap().device_id, 0);
+}
+
+#[tokio::test]
+async fn unknown_requests_are_answered_as_unsupported() {
+    let manager = TestManager::start().await;
+    let mut stream = tokio::net::UnixStream::connect(manager.base_path().join("uinput"))
+        .await
+        .unwrap();
+    let mut exchange = async |frame: Vec<u8>| {
+        stream.write_all(&frame).await.unwrap();
+        let payload = tokio::time::timeout(common::TIMEOUT, read_frame_async(&mut stream))
+            .await
+            .expect("no response")
+            .unwrap();
+        UinputResponse::from_bytes(&payload).unwrap()
+    };
+
+    // A request of a newer shim
+    let response = exchange(framing::encode_frame(br#"{"SetLedState":{"led":1}}"#)).await;
+    assert!(!response.success);
+    assert_eq!(
+        response.error.as_deref(),
+        Some("Unsupported request SetLedState")
+    );
+
+    // The session goes on
+    let set_ev_bit = UinputRequest::SetEvBit { ev_type: EV_KEY };
+    assert!(exchange(set_ev_bit.to_bytes().unwrap()).await.success);
+}
+
+/// Wait for the manager to close a session, with EOF or a reset if it left data unread
+async fn assert_closed(stream: &mut tokio::net::UnixStream) {
+    let mut rest = Vec::new();
+    let closed = tokio::time::timeout(common::TIMEOUT, stream.read_to_end(&mut rest))
+        .await
+        .expect("session wasn't closed");
+    match closed {
+        Ok(read) => assert_eq!(read, 0),
+        Err(e) => assert_eq!(e.kind(), std::io::ErrorKind::ConnectionReset),
+    }
+}
+
+#[tokio::test]
+async fn corrupted_framing_closes_the_session() {
+    let manager = TestManager::start().await;
+    let uinput = manager.base_path().join("uinput");
+
+    // A current header with a broken checksum
+    let mut stream = tokio::net::UnixStream::connect(&uinput).await.unwrap();
+    let mut frame = UinputRequest::SetEvBit { ev_type: EV_KEY }
+        .to_bytes()
+        .unwrap();
+    frame[8] ^= 0xff;
+    stream.write_all(&frame).await.unwrap();
+    assert_closed(&mut stream).await;
+
+    // Text a desynced writer might send, the tail of one request and start of the next
+    let mut stream = tokio::net::UnixStream::connect(&uinput).await.unwrap();
+    stream.write_all(b"ents\":[]}}{\"Wri").await.unwrap();
+    assert_closed(&mut stream).await;
+}
+
+#[tokio::test]
+async fn trigger_click_and_analog_stay_apart() {
+    let manager = TestManager::start().await;
+    let client = manager.client().await;
+    let _source = client.create_device(common::gamepad()).await.unwrap();
+    let mut session = UinputSession::connect(&manager).await;
+
+    // BTN_TL2/BTN_TR2 and ABS_Z/ABS_RZ, where ABS_Z shares its number with KEY_1
+    let (clicks, analogs) = (
+        [Button::LowerLeftTrigger, Button::LowerRightTrigger],
+        [Axis::LowerLeftTrigger, Axis::LowerRightTrigger],
+    );
+    session
+        .request(UinputRequest::SetEvBit { ev_type: EV_ABS })
+        .await;
+    for axis in analogs {
+        let absinfo = LinuxAbsEvent {
+            value: 0,
+            minimum: 0,
+            maximum: 255,
+            fuzz: 0,
+            flat: 0,
+            resolution: 0,
+        };
+        let code = axis.to_ev_code();
+        session
+            .request(UinputRequest::SetAbsBit { abs_code: code })
+            .await;
+        session
+            .request(UinputRequest::AbsSetup { code, absinfo })
+            .await;
+    }
+    let device_id = session.create("Triggers", &clicks).await;
+
+    let config = client.get_device_config(device_id).await.unwrap();
+    assert_eq!(config.buttons, clicks);
+    let mut axes: Vec<_> = config.axes.iter().map(|a| (a.axis, a.min, a.max)).collect();
+    axes.sort_by_key(|(axis, ..)| axis.to_ev_code());
+    assert_eq!(
+        axes,
+        [
+            (Axis::LowerLeftTrigger, 0, 255),
+            (Axis::LowerRightTrigger, 0, 255)
+        ]
+    );
+
+    let mut reader = manager.open(&event_node_name("", device_id)).await;
+    let frame = [
+        (EV_KEY, clicks[0].to_ev_code(), 1),
+        (EV_ABS, analogs[0].to_ev_code(), 200),
+        (EV_SYN, SYN_REPORT, 0),
+    ];
+    session.write_events(&frame).await;
+    assert_eq!(reader.next_frame().await, frame);
+}
+
+#[tokio::test]
+async fn mirror_can_inherit_the_source_ids() {
+    let manager = TestManager::with_config(ManagerConfig {
+        mirror_source_ids: true,
+        ..Default::default()
+    })
+    .await;
+    let client = manager.client().await;
+    let source = client
+        .create_device(ControllerTemplates::ps4())
+        .await
+        .unwrap();
+
+    let mut session = UinputSession::connect(&manager).await;
+    let mirror_id = session.create("Steam Virtual Gamepad", &[Button::A]).await;
+
+    // EVIOCGID answers from the handshake's config
+    let config = &source.config();
+    let mirror = manager.open(&event_node_name("", mirror_id)).await;
+    let mirrored = &mirror.handshake.config;
+    assert_eq!(mirrored.name, config.name);
+    assert_eq!(
+        (
+            mirrored.bustype,
+            mirrored.vendor_id,
+            mirrored.product_id,
+            mirrored.version
+        ),
+        (
+            config.bustype,
+            config.vendor_id,
+            config.product_id,
+            config.version
+        )
+    );
+}
@@ -1,7 +1,8 @@
 use clap::Parser;
 use std::path::PathBuf;
 use tracing_subscriber::EnvFilter;
-use vimputti::manager::Manager;
+use vimputti::manager::{EventLogConfig, Manager, ManagerConfig};
+use vimputti::quirks::QuirkTable;
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
@@ -9,9 +10,36 @@ struct Args {
     /// Socket path for the manager
     #[arg(short, long)]
     socket: Option<PathBuf>,
-    /// Instance number (used to generate socket path)
+    /// Instance number, for the socket path `/tmp/vimputti-<instance>` unless `--socket` is given
     #[arg(short, long, default_value = "0")]
     instance: u32,
+    /// Maximum number of devices that may exist at once
+    #[arg(long, default_value_t = ManagerConfig::default().max_devices)]
+    max_devices: usize,
+    /// Maximum number of events a client may send in one input command
+    #[arg(long, value_name = "EVENTS", default_value_t = ManagerConfig::default().max_batch_events)]
+    max_batch_events: usize,
+    /// Destroy devices with no connected readers and no input for this many seconds
+    #[arg(long, value_name = "SECS")]
+    idle_timeout: Option<u64>,
+    /// JSON file with compatibility quirks, overriding the built-in ones
+    #[arg(long)]
+    quirks: Option<PathBuf>,
+    /// Only log every Nth frame in the event trace log (`RUST_LOG=vimputti::events=trace`)
+    #[arg(long, value_name = "N", default_value = "1")]
+    log_sample: u32,
+    /// Log at most this many frames per second in the event trace log
+    #[arg(long, value_name = "FRAMES")]
+    log_max_rate: Option<u32>,
+    /// Log event counts every this many seconds instead of individual frames
+    #[arg(long, value_name = "SECS")]
+    log_summary: Option<u64>,
+    /// Prefix of the device node names, e.g. `b` for `eventb0`, for managers sharing a /dev/input
+    #[arg(long, value_name = "PREFIX", default_value = "")]
+    node_prefix: String,
+    /// Give uinput mirrors the name and ids of the device they mirror instead of the consumer's
+    #[arg(long)]
+    mirror_source_ids: bool,
 }
 
 #[tokio::main]
@@ -29,14 +57,34 @@ async fn main() -> anyhow::Result<()> {
     let socket_path = if let Some(path) = args.socket {
         path
     } else {
-        PathBuf::from("/tmp/vimputti-0")
+        vimputti::protocol::instance_socket_path(args.instance)
     };
 
     tracing::info!("Starting vimputti manager");
     tracing::info!("Socket path: {}", socket_path.display());
 
     // Create and run manager
-    let mut manager = Manager::new(&socket_path)?;
+    let mut quirks = QuirkTable::builtin();
+    if let Some(path) = &args.quirks {
+        quirks.extend(QuirkTable::load(path)?);
+        tracing::info!("Loaded quirks from {}", path.display());
+    }
+
+    let config = ManagerConfig {
+        max_devices: args.max_devices,
+        max_batch_events: args.max_batch_events,
+        idle_timeout: args.idle_timeout.map(std::time::Duration::from_secs),
+        quirks,
+        event_log: EventLogConfig {
+            sample_every: args.log_sample,
+            max_per_second: args.log_max_rate,
+            summary_interval: args.log_summary.map(std::time::Duration::from_secs),
+        },
+        node_prefix: args.node_prefix,
+        mirror_source_ids: args.mirror_source_ids,
+        ..ManagerConfig::default()
+    };
+    let mut manager = Manager::with_config(&socket_path, config)?;
     manager.run().await?;
 
     Ok(())
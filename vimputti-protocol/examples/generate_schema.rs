@@ -0,0 +1,24 @@
+//! Regenerate the JSON schemas in `schema/`
+//!
+//! Run with `cargo run -p vimputti-protocol --features schema --example generate_schema`.
+
+use std::path::Path;
+use vimputti_protocol::*;
+
+fn main() -> anyhow::Result<()> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("schema");
+    std::fs::create_dir_all(&dir)?;
+
+    let schemas = [
+        ("control_message", schemars::schema_for!(ControlMessage)),
+        ("control_response", schemars::schema_for!(ControlResponse)),
+        ("device_handshake", schemars::schema_for!(DeviceHandshake)),
+    ];
+    for (name, schema) in schemas {
+        let path = dir.join(format!("{}.schema.json", name));
+        std::fs::write(&path, serde_json::to_string_pretty(&schema)? + "\n")?;
+        println!("Wrote {}", path.display());
+    }
+
+    Ok(())
+}
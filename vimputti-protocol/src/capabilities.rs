@@ -0,0 +1,238 @@
+//! Evdev capability bitmaps of a device
+//!
+//! The single source for what a device advertises, used for the shim's EVIOCGBIT answers
+//! and the sysfs `capabilities/*` files, so both always agree. Tests can use it to assert
+//! capabilities without issuing ioctls, see `ensure_consistent`.
+
+use crate::*;
+
+/// Bitmap over the codes of one event type, in the layout of the kernel's `unsigned long` arrays
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Bitmap {
+    words: Vec<u64>,
+}
+impl Bitmap {
+    /// Empty bitmap for the codes `0..=max`
+    pub fn with_max(max: u16) -> Self {
+        Self {
+            words: vec![0; max as usize / 64 + 1],
+        }
+    }
+
+    /// Set the bit of a code, codes beyond the bitmap are ignored
+    pub fn set(&mut self, code: u16) {
+        if let Some(word) = self.words.get_mut(code as usize / 64) {
+            *word |= 1 << (code % 64);
+        }
+    }
+
+    /// Check if the bit of a code is set
+    pub fn contains(&self, code: u16) -> bool {
+        self.words
+            .get(code as usize / 64)
+            .is_some_and(|word| word & (1 << (code % 64)) != 0)
+    }
+
+    /// Codes with their bit set, in ascending order
+    pub fn codes(&self) -> impl Iterator<Item = u16> + '_ {
+        (0..self.words.len() * 64)
+            .map(|code| code as u16)
+            .filter(|&code| self.contains(code))
+    }
+
+    /// Bytes as returned by EVIOCGBIT
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.words.iter().flat_map(|w| w.to_le_bytes()).collect()
+    }
+
+    /// Copy as many bytes as fit into an EVIOCGBIT buffer, zeroing the rest
+    ///
+    /// Returns the number of bytes copied.
+    pub fn write_to(&self, buf: &mut [u8]) -> usize {
+        let bytes = self.to_bytes();
+        let len = bytes.len().min(buf.len());
+        buf[..len].copy_from_slice(&bytes[..len]);
+        buf[len..].fill(0);
+        len
+    }
+
+    /// Formatted like the sysfs `capabilities/*` files: hex words, most significant first
+    pub fn to_sysfs_string(&self) -> String {
+        let words: Vec<String> = self
+            .words
+            .iter()
+            .rev()
+            .skip_while(|&&w| w == 0)
+            .map(|w| format!("{:x}", w))
+            .collect();
+
+        if words.is_empty() {
+            "0".to_string()
+        } else {
+            words.join(" ")
+        }
+    }
+
+    /// Parse the contents of a sysfs `capabilities/*` file back into a bitmap for `0..=max`
+    ///
+    /// Returns `None` if a word isn't valid hex.
+    pub fn from_sysfs_string(s: &str, max: u16) -> Option<Self> {
+        let mut bitmap = Self::with_max(max);
+        for (i, word) in s.split_whitespace().rev().enumerate() {
+            let word = u64::from_str_radix(word, 16).ok()?;
+            if let Some(slot) = bitmap.words.get_mut(i) {
+                *slot = word;
+            }
+        }
+        Some(bitmap)
+    }
+}
+
+/// Capability bitmaps of a device, per event type
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EvBitmaps {
+    /// Supported event types
+    pub ev: Bitmap,
+    pub key: Bitmap,
+    pub rel: Bitmap,
+    pub abs: Bitmap,
+    pub msc: Bitmap,
+    pub ff: Bitmap,
+}
+impl EvBitmaps {
+    /// Bitmap for EVIOCGBIT(ev_type), type 0 being the event types themselves
+    pub fn get(&self, ev_type: u16) -> Option<&Bitmap> {
+        match ev_type {
+            0 => Some(&self.ev),
+            EV_KEY => Some(&self.key),
+            EV_REL => Some(&self.rel),
+            EV_ABS => Some(&self.abs),
+            EV_MSC => Some(&self.msc),
+            EV_FF => Some(&self.ff),
+            _ => None,
+        }
+    }
+}
+
+/// Compute the capability bitmaps a device advertises
+pub fn ev_bitmap(config: &DeviceConfig) -> EvBitmaps {
+    let mut ev = Bitmap::with_max(EV_MAX);
+    let mut key = Bitmap::with_max(KEY_MAX);
+    let mut rel = Bitmap::with_max(REL_MAX);
+    let mut abs = Bitmap::with_max(ABS_MAX);
+    let mut msc = Bitmap::with_max(MSC_MAX);
+    let mut ff = Bitmap::with_max(FF_MAX);
+
+    ev.set(EV_SYN);
+
+    if !config.buttons.is_empty() {
+        ev.set(EV_KEY);
+        for button in &config.buttons {
+            key.set(button.to_ev_code());
+        }
+    }
+
+    if !config.axes.is_empty() {
+        ev.set(EV_ABS);
+        for axis in &config.axes {
+            abs.set(axis.axis.to_ev_code());
+        }
+    }
+
+    if !config.rel_axes.is_empty() {
+        ev.set(EV_REL);
+        for axis in &config.rel_axes {
+            rel.set(axis.to_ev_code());
+        }
+    }
+
+    if config.emit_sequence {
+        ev.set(EV_MSC);
+        msc.set(MSC_RAW);
+    }
+
+    // Every device takes rumble through its feedback socket
+    ev.set(EV_FF);
+    ff.set(FF_RUMBLE);
+
+    EvBitmaps {
+        ev,
+        key,
+        rel,
+        abs,
+        msc,
+        ff,
+    }
+}
+
+/// Compute the device properties (`INPUT_PROP_*`) a device advertises, as for EVIOCGPROP
+pub fn prop_bitmap(config: &DeviceConfig) -> Bitmap {
+    let mut prop = Bitmap::with_max(INPUT_PROP_MAX);
+    if config.is_motion_sensor() {
+        prop.set(INPUT_PROP_ACCELEROMETER);
+    }
+    prop
+}
+
+/// Check that the bitmaps of a device cover its config and survive the forms they're served in
+///
+/// Each bitmap must read back the same from its sysfs string and EVIOCGBIT bytes, the event
+/// types must be set exactly for the types with codes, and every button and axis of the config
+/// must be set. Returns a description of the first mismatch.
+pub fn ensure_consistent(config: &DeviceConfig) -> Result<(), String> {
+    let bitmaps = ev_bitmap(config);
+    for (ev_type, max) in [
+        (0, EV_MAX),
+        (EV_KEY, KEY_MAX),
+        (EV_REL, REL_MAX),
+        (EV_ABS, ABS_MAX),
+        (EV_MSC, MSC_MAX),
+        (EV_FF, FF_MAX),
+    ] {
+        let bitmap = bitmaps.get(ev_type).expect("known event type");
+        if Bitmap::from_sysfs_string(&bitmap.to_sysfs_string(), max).as_ref() != Some(bitmap) {
+            return Err(format!("Type {} changes through its sysfs form", ev_type));
+        }
+
+        let mut bytes = vec![0xff; bitmap.to_bytes().len()];
+        bitmap.write_to(&mut bytes);
+        if bytes != bitmap.to_bytes() {
+            return Err(format!(
+                "Type {} changes through its EVIOCGBIT form",
+                ev_type
+            ));
+        }
+
+        if ev_type != 0 && bitmaps.ev.contains(ev_type) != bitmap.codes().next().is_some() {
+            return Err(format!(
+                "Type {} is advertised without matching codes",
+                ev_type
+            ));
+        }
+    }
+
+    let missing = config
+        .buttons
+        .iter()
+        .map(|button| (EV_KEY, button.to_ev_code()))
+        .chain(
+            config
+                .axes
+                .iter()
+                .map(|axis| (EV_ABS, axis.axis.to_ev_code())),
+        )
+        .chain(
+            config
+                .rel_axes
+                .iter()
+                .map(|axis| (EV_REL, axis.to_ev_code())),
+        )
+        .find(|&(ev_type, code)| !bitmaps.get(ev_type).is_some_and(|b| b.contains(code)));
+    match missing {
+        Some((ev_type, code)) => Err(format!(
+            "Code {} of type {} is not advertised",
+            code, ev_type
+        )),
+        None => Ok(()),
+    }
+}
@@ -0,0 +1,415 @@
+//! Framing of the length-prefixed messages on uinput and device sockets
+//!
+//! Every frame starts with a fixed `FRAME_HEADER_LEN` byte header: the `FRAME_MAGIC` byte,
+//! the `FRAME_VERSION` byte, a flags byte, a reserved byte, the 4-byte little-endian payload
+//! length and the 4-byte little-endian CRC32 of the payload (zero unless `FRAME_FLAG_CRC` is
+//! set). A reader that lost its place in the stream sees a bad magic, version or checksum
+//! and can drop the connection, instead of taking arbitrary bytes as a length and blocking
+//! on a read that never completes.
+//!
+//! Readers also accept the legacy framing from before the header, a bare 4-byte
+//! little-endian payload length, so requests from older shims still work. Such frames have
+//! no checksum and a desync in them goes undetected like before. Older peers can't read the
+//! current header, so answers go out in the `Framing` of the request they answer, and
+//! messages sent before the peer said anything, like the device handshake, in the legacy
+//! framing.
+//!
+//! `read_frame` reads a whole frame from a stream, with async variants behind the `tokio`
+//! feature. Messages are framed by their `to_bytes` and written whole.
+
+use std::fmt;
+use std::io::{self, Read};
+
+/// First byte of every frame
+pub const FRAME_MAGIC: u8 = 0x56;
+/// Version of the frame header, bumped on incompatible changes
+pub const FRAME_VERSION: u8 = 1;
+/// The header carries a CRC32 of the payload
+pub const FRAME_FLAG_CRC: u8 = 0x01;
+/// Size of the frame header in bytes
+pub const FRAME_HEADER_LEN: usize = 12;
+/// Size of the legacy header, just the payload length
+pub const LEGACY_HEADER_LEN: usize = 4;
+/// Largest payload accepted
+pub const MAX_FRAME_LEN: usize = 1_000_000;
+
+/// Reasons a frame is rejected
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FrameError {
+    /// The header doesn't start with `FRAME_MAGIC`, the stream is out of sync
+    BadMagic(u8),
+    /// The peer speaks a different frame version
+    UnsupportedVersion(u8),
+    /// The payload length is zero or above `MAX_FRAME_LEN`
+    BadLength(usize),
+    /// The payload doesn't match the checksum in the header
+    ChecksumMismatch { expected: u32, actual: u32 },
+}
+impl fmt::Display for FrameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::BadMagic(byte) => write!(f, "bad frame magic 0x{:02x}", byte),
+            Self::UnsupportedVersion(version) => {
+                write!(f, "unsupported frame version {}", version)
+            }
+            Self::BadLength(len) => write!(f, "invalid frame length {}", len),
+            Self::ChecksumMismatch { expected, actual } => write!(
+                f,
+                "frame checksum mismatch (expected {:08x}, got {:08x})",
+                expected, actual
+            ),
+        }
+    }
+}
+impl std::error::Error for FrameError {}
+impl From<FrameError> for io::Error {
+    fn from(error: FrameError) -> Self {
+        io::Error::new(io::ErrorKind::InvalidData, error)
+    }
+}
+
+/// Header format of a frame
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Framing {
+    /// The `FRAME_HEADER_LEN` byte header with magic, version and checksum
+    #[default]
+    Current,
+    /// The bare payload length of peers from before the header
+    Legacy,
+}
+impl Framing {
+    /// Prefix a payload with a header of this framing
+    pub fn encode(self, payload: &[u8]) -> Vec<u8> {
+        match self {
+            Self::Current => encode_frame(payload),
+            Self::Legacy => {
+                let mut bytes = Vec::with_capacity(LEGACY_HEADER_LEN + payload.len());
+                bytes.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+                bytes.extend_from_slice(payload);
+                bytes
+            }
+        }
+    }
+}
+
+/// Decoded frame header
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameHeader {
+    /// Length of the payload following the header
+    pub len: usize,
+    /// CRC32 of the payload, if the sender included one
+    pub crc: Option<u32>,
+    /// Header format the frame was sent in
+    pub framing: Framing,
+}
+impl FrameHeader {
+    /// Parse and validate a header
+    pub fn decode(bytes: &[u8; FRAME_HEADER_LEN]) -> Result<Self, FrameError> {
+        if bytes[0] != FRAME_MAGIC {
+            return Err(FrameError::BadMagic(bytes[0]));
+        }
+        if bytes[1] != FRAME_VERSION {
+            return Err(FrameError::UnsupportedVersion(bytes[1]));
+        }
+
+        let len = u32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]) as usize;
+        if len == 0 || len > MAX_FRAME_LEN {
+            return Err(FrameError::BadLength(len));
+        }
+
+        let crc = (bytes[2] & FRAME_FLAG_CRC != 0)
+            .then(|| u32::from_le_bytes([bytes[8], bytes[9], bytes[10], bytes[11]]));
+
+        Ok(Self {
+            len,
+            crc,
+            framing: Framing::Current,
+        })
+    }
+
+    /// Parse and validate a legacy header, the bare payload length
+    pub fn decode_legacy(bytes: &[u8; LEGACY_HEADER_LEN]) -> Result<Self, FrameError> {
+        let len = u32::from_le_bytes(*bytes) as usize;
+        if len == 0 || len > MAX_FRAME_LEN {
+            return Err(FrameError::BadLength(len));
+        }
+        Ok(Self {
+            len,
+            crc: None,
+            framing: Framing::Legacy,
+        })
+    }
+
+    /// Check a received payload against the header
+    pub fn verify(&self, payload: &[u8]) -> Result<(), FrameError> {
+        if let Some(expected) = self.crc {
+            let actual = crc32(payload);
+            if actual != expected {
+                return Err(FrameError::ChecksumMismatch { expected, actual });
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Prefix a payload with a frame header, including its checksum
+pub fn encode_frame(payload: &[u8]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(FRAME_HEADER_LEN + payload.len());
+    bytes.extend_from_slice(&[FRAME_MAGIC, FRAME_VERSION, FRAME_FLAG_CRC, 0]);
+    bytes.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(&crc32(payload).to_le_bytes());
+    bytes.extend_from_slice(payload);
+    bytes
+}
+
+/// Read the payload of the next frame
+///
+/// Invalid frames fail with `ErrorKind::InvalidData`, after which the stream is out of sync
+/// and should be dropped. The peer disconnecting is `ErrorKind::UnexpectedEof`.
+pub fn read_frame<R: Read>(reader: &mut R) -> io::Result<Vec<u8>> {
+    read_frame_limited(reader, MAX_FRAME_LEN)
+}
+
+/// Read the payload of the next frame, rejecting payloads above `max_len` before reading them
+pub fn read_frame_limited<R: Read>(reader: &mut R, max_len: usize) -> io::Result<Vec<u8>> {
+    let mut bytes = [0u8; FRAME_HEADER_LEN];
+    reader.read_exact(&mut bytes[..LEGACY_HEADER_LEN])?;
+    if bytes[0] == FRAME_MAGIC {
+        reader.read_exact(&mut bytes[LEGACY_HEADER_LEN..])?;
+    }
+    let (header, mut payload) = decode_start(&bytes, max_len)?;
+
+    let start = payload.len();
+    payload.resize(header.len, 0);
+    reader.read_exact(&mut payload[start..])?;
+    header.verify(&payload)?;
+    Ok(payload)
+}
+
+/// Read the payload of the next frame, see `read_frame`
+#[cfg(feature = "tokio")]
+pub async fn read_frame_async<R>(reader: &mut R) -> io::Result<Vec<u8>>
+where
+    R: tokio::io::AsyncRead + Unpin,
+{
+    Ok(read_frame_with_framing_async(reader).await?.0)
+}
+
+/// Read the payload of the next frame along with its framing, to answer it in kind
+#[cfg(feature = "tokio")]
+pub async fn read_frame_with_framing_async<R>(reader: &mut R) -> io::Result<(Vec<u8>, Framing)>
+where
+    R: tokio::io::AsyncRead + Unpin,
+{
+    use tokio::io::AsyncReadExt;
+
+    let mut bytes = [0u8; FRAME_HEADER_LEN];
+    reader.read_exact(&mut bytes[..LEGACY_HEADER_LEN]).await?;
+    if bytes[0] == FRAME_MAGIC {
+        reader.read_exact(&mut bytes[LEGACY_HEADER_LEN..]).await?;
+    }
+    let (header, mut payload) = decode_start(&bytes, MAX_FRAME_LEN)?;
+
+    let start = payload.len();
+    payload.resize(header.len, 0);
+    reader.read_exact(&mut payload[start..]).await?;
+    header.verify(&payload)?;
+    Ok((payload, header.framing))
+}
+
+/// Decode the header at the start of a frame
+///
+/// `bytes` holds the whole header if the frame starts with `FRAME_MAGIC`, otherwise only
+/// its first `LEGACY_HEADER_LEN` bytes are read and it's a legacy header. A legacy length
+/// can start with the magic byte too. Its frame then fails to decode as a current one, and
+/// is taken as legacy if the bytes past the length start a JSON payload. Returns the header
+/// and the bytes read past it, which belong to the payload.
+fn decode_start(
+    bytes: &[u8; FRAME_HEADER_LEN],
+    max_len: usize,
+) -> Result<(FrameHeader, Vec<u8>), FrameError> {
+    let legacy = bytes[..LEGACY_HEADER_LEN].try_into().unwrap();
+    let (header, payload) = if bytes[0] != FRAME_MAGIC {
+        (FrameHeader::decode_legacy(legacy)?, Vec::new())
+    } else {
+        match FrameHeader::decode(bytes) {
+            Ok(header) => (header, Vec::new()),
+            // Lengths starting with the magic byte are at least 0x56, so the legacy payload
+            // covers all of the bytes read past the legacy header
+            Err(error) => match FrameHeader::decode_legacy(legacy) {
+                Ok(header) if matches!(bytes[LEGACY_HEADER_LEN], b'{' | b'"') => {
+                    (header, bytes[LEGACY_HEADER_LEN..].to_vec())
+                }
+                _ => return Err(error),
+            },
+        }
+    };
+
+    if header.len > max_len {
+        return Err(FrameError::BadLength(header.len));
+    }
+    Ok((header, payload))
+}
+
+/// CRC-32 (IEEE 802.3) of some bytes
+pub fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Cursor, Write};
+    use std::os::unix::net::UnixStream;
+    use std::time::Duration;
+
+    fn legacy_frame(payload: &[u8]) -> Vec<u8> {
+        let mut bytes = (payload.len() as u32).to_le_bytes().to_vec();
+        bytes.extend_from_slice(payload);
+        bytes
+    }
+
+    fn frame_error(bytes: &[u8]) -> FrameError {
+        let error = read_frame(&mut Cursor::new(bytes)).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData, "{}", error);
+        *error
+            .into_inner()
+            .unwrap()
+            .downcast::<FrameError>()
+            .unwrap()
+    }
+
+    #[test]
+    fn frames_round_trip() {
+        let payload = br#"{"DevCreate":{}}"#;
+        let bytes = encode_frame(payload);
+        assert_eq!(bytes.len(), FRAME_HEADER_LEN + payload.len());
+        assert_eq!(read_frame(&mut Cursor::new(bytes)).unwrap(), payload);
+    }
+
+    /// A stream handing out one byte per read
+    struct Trickle(Cursor<Vec<u8>>);
+    impl Read for Trickle {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let len = buf.len().min(1);
+            self.0.read(&mut buf[..len])
+        }
+    }
+
+    #[test]
+    fn partial_reads_are_reassembled() {
+        let mut bytes = encode_frame(b"{\"Ping\":1}");
+        bytes.extend_from_slice(&legacy_frame(b"{}"));
+        bytes.extend_from_slice(&encode_frame(b"\"Sync\""));
+        let mut stream = Trickle(Cursor::new(bytes));
+        assert_eq!(read_frame(&mut stream).unwrap(), b"{\"Ping\":1}");
+        assert_eq!(read_frame(&mut stream).unwrap(), b"{}");
+        assert_eq!(read_frame(&mut stream).unwrap(), b"\"Sync\"");
+
+        // Ending in the middle of a frame
+        let bytes = encode_frame(b"{\"Ping\":1}");
+        for len in [2, FRAME_HEADER_LEN, bytes.len() - 1] {
+            let mut stream = Trickle(Cursor::new(bytes[..len].to_vec()));
+            let error = read_frame(&mut stream).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof, "{}", len);
+        }
+    }
+
+    #[test]
+    fn oversized_frames_are_rejected_before_their_payload() {
+        let mut stream = Cursor::new(encode_frame(&[b'x'; 16]));
+        let error = read_frame_limited(&mut stream, 8).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData, "{}", error);
+        assert_eq!(stream.position(), FRAME_HEADER_LEN as u64);
+
+        let mut header = encode_frame(b"{}");
+        header[4..8].copy_from_slice(&(MAX_FRAME_LEN as u32 + 1).to_le_bytes());
+        assert_eq!(
+            frame_error(&header[..FRAME_HEADER_LEN]),
+            FrameError::BadLength(MAX_FRAME_LEN + 1)
+        );
+        assert_eq!(
+            frame_error(&[0; LEGACY_HEADER_LEN]),
+            FrameError::BadLength(0)
+        );
+    }
+
+    #[test]
+    fn legacy_frames_are_accepted() {
+        let payload = br#"{"DevCreate":{}}"#;
+        let mut stream = Cursor::new(legacy_frame(payload));
+        assert_eq!(read_frame(&mut stream).unwrap(), payload);
+
+        // A length of 0x156 starts with the magic and current version
+        let payload = format!(r#"{{"DevSetup":{{"name":"{}"}}}}"#, "x".repeat(0x156 - 24));
+        assert_eq!(payload.len(), 0x156);
+        let mut stream = Cursor::new(legacy_frame(payload.as_bytes()));
+        assert_eq!(read_frame(&mut stream).unwrap(), payload.as_bytes());
+    }
+
+    #[test]
+    fn frames_are_encoded_in_either_framing() {
+        let payload = br#"{"success":true}"#;
+        assert_eq!(Framing::Current.encode(payload), encode_frame(payload));
+        assert_eq!(Framing::Legacy.encode(payload), legacy_frame(payload));
+
+        let legacy = legacy_frame(payload);
+        let header = FrameHeader::decode_legacy(legacy[..LEGACY_HEADER_LEN].try_into().unwrap());
+        assert_eq!(header.unwrap().framing, Framing::Legacy);
+        let current = encode_frame(payload);
+        let header = FrameHeader::decode(current[..FRAME_HEADER_LEN].try_into().unwrap());
+        assert_eq!(header.unwrap().framing, Framing::Current);
+    }
+
+    #[test]
+    fn corrupted_frames_are_rejected() {
+        let mut bytes = encode_frame(b"{}");
+        bytes[1] = FRAME_VERSION + 1;
+        bytes.extend_from_slice(&[0; 16]);
+        assert_eq!(
+            frame_error(&bytes),
+            FrameError::UnsupportedVersion(FRAME_VERSION + 1)
+        );
+
+        let mut bytes = encode_frame(b"{\"Ping\":1}");
+        *bytes.last_mut().unwrap() = b']';
+        assert!(matches!(
+            frame_error(&bytes),
+            FrameError::ChecksumMismatch { .. }
+        ));
+
+        // Text where a header belongs, as after losing the place in the stream
+        assert!(matches!(
+            frame_error(b"vents\":[{\"Button\":{}}]}"),
+            FrameError::BadLength(_)
+        ));
+        assert_eq!(
+            frame_error(&[FRAME_MAGIC, FRAME_VERSION, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]),
+            FrameError::BadLength(0)
+        );
+    }
+
+    #[test]
+    fn corrupted_frame_fails_without_waiting_for_its_length() {
+        // The writer stays open, a reader trusting the length would block
+        let (mut writer, mut reader) = UnixStream::pair().unwrap();
+        reader
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        writer.write_all(&[0xff; FRAME_HEADER_LEN]).unwrap();
+
+        let error = read_frame(&mut reader).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData, "{}", error);
+    }
+}
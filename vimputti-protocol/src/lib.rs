@@ -0,0 +1,2303 @@
+//! Wire protocol of the vimputti manager
+//!
+//! Control messages are newline-delimited JSON `ControlMessage`s answered by `ControlResponse`s.
+//! Device sockets send a JSON `DeviceHandshake` in a frame (see `framing`), followed by raw
+//! `LinuxInputEvent`s. With the `schema` feature the JSON types implement
+//! `schemars::JsonSchema`, the generated schemas are in `schema/`.
+
+pub mod capabilities;
+pub mod framing;
+pub mod linux;
+
+use serde::{Deserialize, Serialize};
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+
+// Linux input event type constants
+pub const EV_SYN: u16 = 0x00;
+pub const EV_KEY: u16 = 0x01;
+pub const EV_REL: u16 = 0x02;
+pub const EV_ABS: u16 = 0x03;
+pub const EV_MSC: u16 = 0x04;
+pub const EV_FF: u16 = 0x15;
+
+// Mouse buttons, BTN_LEFT up to BTN_TASK, below the joystick range
+pub const BTN_MOUSE: u16 = 0x110;
+
+pub const FF_RUMBLE: u16 = 0x50;
+
+// Codes of feedback sockets for effect uploads and erases, above FF_MAX so no kernel code clashes
+pub const FF_UPLOAD_MAGNITUDE: u16 = 0x80;
+pub const FF_UPLOAD: u16 = 0x81;
+pub const FF_ERASE: u16 = 0x82;
+
+pub const SYN_REPORT: u16 = 0;
+/// Events were lost, readers skip to the next SYN_REPORT and query the device state
+pub const SYN_DROPPED: u16 = 3;
+
+pub const MSC_RAW: u16 = 0x03;
+
+// First of the BTN_TRIGGER_HAPPY1..40 buttons
+pub const BTN_TRIGGER_HAPPY: u16 = 0x2c0;
+
+// Start of the legacy joystick buttons (BTN_TRIGGER..BTN_DEAD) and the gamepad buttons
+// (BTN_A/BTN_SOUTH..BTN_THUMBR)
+pub const BTN_JOYSTICK: u16 = 0x120;
+pub const BTN_GAMEPAD: u16 = 0x130;
+
+// Touch buttons accompanying the multitouch protocol, the BTN_TOOL_* ones by finger count
+pub const BTN_TOUCH: u16 = 0x14a;
+pub const BTN_TOOL_FINGER: u16 = 0x145;
+pub const BTN_TOOL_DOUBLETAP: u16 = 0x14d;
+pub const BTN_TOOL_TRIPLETAP: u16 = 0x14e;
+pub const BTN_TOOL_QUADTAP: u16 = 0x14f;
+pub const BTN_TOOL_QUINTTAP: u16 = 0x148;
+
+// Multitouch (type B) axes, ABS_MT_SLOT up to ABS_MT_TOOL_Y
+pub const ABS_MT_SLOT: u16 = 0x2f;
+pub const ABS_MT_POSITION_X: u16 = 0x35;
+pub const ABS_MT_POSITION_Y: u16 = 0x36;
+pub const ABS_MT_TRACKING_ID: u16 = 0x39;
+pub const ABS_MT_TOOL_Y: u16 = 0x3d;
+
+// Device property of motion sensors, reporting acceleration and angular velocity on their axes
+pub const INPUT_PROP_ACCELEROMETER: u16 = 0x06;
+pub const INPUT_PROP_MAX: u16 = 0x1f;
+
+// Highest valid code of each event type
+pub const EV_MAX: u16 = 0x1f;
+pub const KEY_MAX: u16 = 0x2ff;
+pub const REL_MAX: u16 = 0x0f;
+pub const ABS_MAX: u16 = 0x3f;
+pub const MSC_MAX: u16 = 0x07;
+pub const FF_MAX: u16 = 0x7f;
+
+/// Unique identifier for a virtual device
+pub type DeviceId = u64;
+
+/// Name of the abstract Unix socket for a socket path using the `@name` convention
+///
+/// Abstract sockets live outside the filesystem and disappear with the process that bound them.
+pub fn abstract_socket_name(socket_path: &Path) -> Option<&[u8]> {
+    socket_path.as_os_str().as_bytes().strip_prefix(b"@")
+}
+
+/// Control socket of manager instance `instance`, `/tmp/vimputti-{instance}`
+///
+/// Instance 0 is the default one, further instances run next to it.
+pub fn instance_socket_path(instance: u32) -> PathBuf {
+    PathBuf::from(format!("/tmp/vimputti-{}", instance))
+}
+
+/// Instance number of a control socket named like `instance_socket_path`'s, e.g. 1 for
+/// `/tmp/vimputti-1`, wherever it is
+pub fn socket_instance(socket_path: &Path) -> Option<u32> {
+    let name = socket_path.file_name()?.to_str()?;
+    let number = name.strip_prefix("vimputti-")?;
+    // Only the canonical spelling, so each instance has a single socket name
+    number
+        .parse()
+        .ok()
+        .filter(|n: &u32| n.to_string() == number)
+}
+
+/// Base directory for device sockets and sysfs of the manager at `socket_path`
+///
+/// This is `vimputti` next to a file socket, e.g. `/tmp/vimputti` for `/tmp/vimputti-0`,
+/// and `/tmp/vimputti` for abstract sockets. Instances other than 0 get their own, the socket
+/// path with `.d` appended (`/tmp/vimputti-1.d`), so they can run next to each other.
+pub fn base_path_for_socket(socket_path: &Path) -> PathBuf {
+    if abstract_socket_name(socket_path).is_none()
+        && socket_instance(socket_path).is_some_and(|instance| instance != 0)
+    {
+        return socket_path.with_extension("d");
+    }
+
+    let parent = match abstract_socket_name(socket_path) {
+        Some(_) => None,
+        None => socket_path.parent(),
+    };
+    parent.unwrap_or_else(|| Path::new("/tmp")).join("vimputti")
+}
+
+/// Check that a node prefix keeps node names unambiguous: ASCII letters, `-` and `_` only
+pub fn valid_node_prefix(prefix: &str) -> bool {
+    prefix
+        .bytes()
+        .all(|b| b.is_ascii_alphabetic() || b == b'-' || b == b'_')
+}
+
+/// Name of a device's evdev node, `event{prefix}{id}`
+///
+/// Managers sharing a `/dev/input`, e.g. when nested, use distinct prefixes so their nodes
+/// don't collide. The prefix is empty by default, giving the usual `event0`.
+pub fn event_node_name(prefix: &str, id: DeviceId) -> String {
+    format!("event{}{}", prefix, id)
+}
+
+/// Name of a device's joystick node, `js{prefix}{id}`
+pub fn joystick_node_name(prefix: &str, id: DeviceId) -> String {
+    format!("js{}{}", prefix, id)
+}
+
+/// Name of a device's input node in sysfs, `input{prefix}{id}`
+pub fn input_node_name(prefix: &str, id: DeviceId) -> String {
+    format!("input{}{}", prefix, id)
+}
+
+/// Device id of an event or joystick node name using `prefix`, e.g. 3 for `eventb3` with `b`
+pub fn node_device_id(prefix: &str, node: &str) -> Option<DeviceId> {
+    node.strip_prefix("event")
+        .or_else(|| node.strip_prefix("js"))?
+        .strip_prefix(prefix)?
+        .parse()
+        .ok()
+}
+
+/// Message sent from library client to manager
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct ControlMessage {
+    pub id: String, // ULID for request/response matching
+    pub command: ControlCommand,
+}
+
+/// Response sent from manager to library client
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct ControlResponse {
+    pub id: String, // Matches request ID
+    pub result: ControlResult,
+}
+
+/// Commands that can be sent to the manager
+///
+/// `ListDevices` is also accepted in the unit form `"ListDevices"` sent by clients from
+/// before filters.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(remote = "Self")]
+pub enum ControlCommand {
+    /// Create a new virtual device
+    CreateDevice { config: DeviceConfig },
+    /// Create a new virtual device with a specific ID, e.g. to get a known `eventN`
+    CreateDeviceWithId {
+        device_id: DeviceId,
+        config: DeviceConfig,
+    },
+    /// Return the device registered under `key`, creating it if there is none
+    ///
+    /// Makes test setup idempotent across reruns against a long-lived manager. A device that
+    /// exists under the key with a different config is handled according to `on_mismatch`.
+    EnsureDevice {
+        key: String,
+        config: DeviceConfig,
+        #[serde(default)]
+        on_mismatch: EnsureMismatch,
+    },
+    /// Destroy a virtual device (explicit, though drop also works)
+    DestroyDevice { device_id: DeviceId },
+    /// Send input events to a device
+    ///
+    /// At most `ManagerInfo::max_batch_events` events, larger batches are rejected whole.
+    SendInput {
+        device_id: DeviceId,
+        events: Vec<InputEvent>,
+    },
+    /// Send inputs by logical name, e.g. `{ "button": "A", "pressed": true }`
+    ///
+    /// A sync is appended after the inputs, which are limited like those of `SendInput`.
+    SendNamed {
+        device_id: DeviceId,
+        inputs: Vec<NamedInput>,
+    },
+    /// Stop or resume forwarding input of a device to its readers
+    SetDevicePaused {
+        device_id: DeviceId,
+        paused: bool,
+        /// What happens to input sent while paused, ignored when resuming
+        #[serde(default)]
+        mode: PauseMode,
+    },
+    /// Deliver feedback to a device's feedback readers as if a game produced it
+    ///
+    /// Meant for testing the feedback path without a game uploading real effects.
+    InjectFeedback {
+        device_id: DeviceId,
+        event: FeedbackEvent,
+    },
+    /// Delay frames of a device by `base_ms ± jitter_ms` before they reach readers
+    ///
+    /// Set both to 0 to remove the delay again.
+    SetLatency {
+        device_id: DeviceId,
+        base_ms: u32,
+        jitter_ms: u32,
+        /// Seed for reproducible jitter, random if not set
+        #[serde(default)]
+        seed: Option<u64>,
+    },
+    /// Change the ids a device presents, e.g. to find out if a game's detection depends on them
+    ///
+    /// Readers opening the device afterwards and sysfs see the new ids, and a udev `change`
+    /// event is sent. Already open fds keep the ids they were given, so some applications only
+    /// notice after reopening the device. `version` and `bustype` are kept when not given.
+    SetDeviceId {
+        device_id: DeviceId,
+        vendor_id: u16,
+        product_id: u16,
+        #[serde(default)]
+        version: Option<u16>,
+        #[serde(default)]
+        bustype: Option<BusType>,
+    },
+    /// Remove or bring back the joystick interface (`jsN`) of a device, keeping its evdev node
+    ///
+    /// Forces games down their evdev path, or back, without recreating the device. A udev
+    /// event for the joystick node is sent, and removing it ends the streams of its readers.
+    SetJoystickInterface { device_id: DeviceId, enabled: bool },
+    /// Update the battery of a device, which must have one (see `DeviceConfig::battery`)
+    ///
+    /// Its power supply in sysfs shows the new state and a udev `change` event carrying the
+    /// `POWER_SUPPLY_*` properties is sent.
+    SetBattery {
+        device_id: DeviceId,
+        capacity: u8,
+        status: BatteryStatus,
+    },
+    /// Wait until all input sent to a device so far has been written to its readers
+    Flush { device_id: DeviceId },
+    /// Query how many readers have a device open, e.g. to check a game opened it before
+    /// sending input
+    GetDeviceClients { device_id: DeviceId },
+    /// Replace the config of a device, keeping its id and nodes
+    ///
+    /// Quirks are applied and the config validated as for `CreateDevice`. Sysfs and readers
+    /// opening the device from now on see the new capabilities, and a udev `change` event is
+    /// sent. Readers that already have the device open keep the old capabilities, so they
+    /// have to open it again. Input sent afterwards is checked against the new layout. The ids
+    /// and battery the device presents are kept, see `SetDeviceId` and `SetBattery`.
+    UpdateDevice {
+        device_id: DeviceId,
+        config: DeviceConfig,
+    },
+    /// Query the full config of a device, including changes made since it was created
+    GetDeviceConfig { device_id: DeviceId },
+    /// Query active devices, all of them unless a filter is given
+    ListDevices {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        filter: Option<DeviceFilter>,
+    },
+    /// Send udev and netlink add events for all devices again
+    ///
+    /// Prompts monitors that connected after the devices were created, or tests their
+    /// handling of add events.
+    RebroadcastDevices,
+    /// Run a program whenever a device is added or removed, or stop running it with `None`
+    ///
+    /// `command` is the program followed by its arguments, run with the device in
+    /// `VIMPUTTI_DEVICE_ID`, `VIMPUTTI_EVENT_NODE` and `VIMPUTTI_DEVICE_NAME`. Only managers
+    /// built with the `hooks` feature run programs, others answer with an error.
+    SetHook {
+        event: HookEvent,
+        command: Option<Vec<String>>,
+    },
+    /// Query manager version, paths, limits and supported features
+    GetManagerInfo,
+    /// Ping to check if manager is alive
+    Ping,
+}
+impl Serialize for ControlCommand {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        Self::serialize(self, serializer)
+    }
+}
+impl<'de> Deserialize<'de> for ControlCommand {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = serde_json::Value::deserialize(deserializer)?;
+        // The unit form of clients from before filters
+        if value == "ListDevices" {
+            return Ok(Self::ListDevices { filter: None });
+        }
+        Self::deserialize(value).map_err(serde::de::Error::custom)
+    }
+}
+
+impl ControlCommand {
+    /// Feature a manager advertises in `ManagerInfo::features` when it handles this command
+    ///
+    /// None for commands every manager handles.
+    pub fn feature(&self) -> Option<&'static str> {
+        match self {
+            ControlCommand::CreateDevice { .. }
+            | ControlCommand::DestroyDevice { .. }
+            | ControlCommand::SendInput { .. }
+            | ControlCommand::ListDevices { filter: None }
+            | ControlCommand::GetManagerInfo
+            | ControlCommand::Ping => None,
+            ControlCommand::CreateDeviceWithId { .. } => Some("create_with_id"),
+            ControlCommand::EnsureDevice { .. } => Some("ensure"),
+            ControlCommand::SendNamed { .. } => Some("send_named"),
+            ControlCommand::SetDevicePaused { .. } => Some("pause"),
+            ControlCommand::InjectFeedback { .. } => Some("inject_feedback"),
+            ControlCommand::SetLatency { .. } => Some("latency"),
+            ControlCommand::SetDeviceId { .. } => Some("set_device_id"),
+            ControlCommand::SetJoystickInterface { .. } => Some("joystick_interface"),
+            ControlCommand::SetBattery { .. } => Some("battery"),
+            ControlCommand::Flush { .. } => Some("flush"),
+            ControlCommand::GetDeviceClients { .. } => Some("device_clients"),
+            ControlCommand::UpdateDevice { .. } => Some("update_device"),
+            ControlCommand::GetDeviceConfig { .. } => Some("device_config"),
+            ControlCommand::ListDevices { filter: Some(_) } => Some("list_filter"),
+            ControlCommand::RebroadcastDevices => Some("rebroadcast"),
+            ControlCommand::SetHook { .. } => Some("hooks"),
+        }
+    }
+}
+
+/// Results returned by the manager
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub enum ControlResult {
+    /// Device successfully created
+    DeviceCreated {
+        device_id: DeviceId,
+        event_node: String, // e.g., "event0"
+        /// Config the device was created with, after the manager's quirks
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        config: Option<Box<DeviceConfig>>,
+    },
+    /// Device registered under the key of an `EnsureDevice`
+    DeviceEnsured {
+        device_id: DeviceId,
+        event_node: String,
+        /// The device was created by this request rather than already existing
+        created: bool,
+        /// Config the device has, after the manager's quirks
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        config: Option<Box<DeviceConfig>>,
+    },
+    /// Device successfully destroyed
+    DeviceDestroyed,
+    /// Input events successfully sent
+    InputSent,
+    /// Device paused or resumed
+    DevicePaused { paused: bool },
+    /// Feedback delivered to the device's feedback readers
+    FeedbackInjected,
+    /// Device latency updated
+    LatencySet,
+    /// Device ids changed
+    DeviceIdSet,
+    /// Config of a device replaced
+    DeviceUpdated,
+    /// Joystick interface added or removed, with the joystick node it has now
+    JoystickInterfaceSet { joystick_node: Option<String> },
+    /// Battery of a device updated
+    BatterySet,
+    /// All previously sent input was written to the device's readers
+    Flushed,
+    /// Number of clients connected to each of a device's sockets
+    DeviceClients(ClientCounts),
+    /// Config of a device
+    DeviceConfig(Box<DeviceConfig>),
+    /// List of active devices
+    DeviceList(Vec<DeviceInfo>),
+    /// Add events sent for this many devices
+    DevicesRebroadcast { count: usize },
+    /// Hook program set or cleared
+    HookSet,
+    /// Manager details
+    ManagerInfo(ManagerInfo),
+    /// Pong response
+    Pong,
+    /// Error occurred
+    Error {
+        #[serde(default)]
+        code: ErrorCode,
+        message: String,
+    },
+}
+
+/// What a paused device does with input sent to it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub enum PauseMode {
+    /// Input is dropped, readers resume from the state before the pause
+    #[default]
+    Discard,
+    /// Input still updates the device state, readers catch up to it on resume
+    Track,
+}
+
+/// Device lifecycle event hooks run on, see `SetHook`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub enum HookEvent {
+    /// A device was created and announced
+    DeviceAdded,
+    /// A device was destroyed
+    DeviceRemoved,
+}
+
+/// What `EnsureDevice` does when the device under the key has a different config
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub enum EnsureMismatch {
+    /// Fail with `ErrorCode::AlreadyExists`, leaving the device as it is
+    #[default]
+    Error,
+    /// Destroy the device and create it again with the new config
+    Recreate,
+}
+
+/// Machine-readable category of a `ControlResult::Error`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub enum ErrorCode {
+    /// Unexpected failure inside the manager
+    #[default]
+    Internal,
+    /// The referenced device does not exist
+    NotFound,
+    /// A configured manager limit was reached
+    LimitExceeded,
+    /// The request was malformed or doesn't fit the device
+    InvalidInput,
+    /// The requested device ID is already in use, or an ensured device differs
+    AlreadyExists,
+}
+
+/// How a device presents its analog triggers
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub enum TriggerLayout {
+    /// Each trigger on its own axis, `ABS_Z` and `ABS_RZ`
+    ///
+    /// How the Linux drivers present Xbox (xpad) and PlayStation (hid-sony,
+    /// hid-playstation) controllers.
+    #[default]
+    Separate,
+    /// Both triggers on `ABS_Z`, the right trigger towards the maximum and the left towards
+    /// the minimum, resting in the middle
+    ///
+    /// How DirectInput presents Xbox 360 and One controllers, and what xboxdrv's
+    /// `--trigger-as-zaxis` emulates. Older SDL mappings expect it.
+    CombinedZ,
+}
+
+/// Battery of a wireless device, presented as a power supply in sysfs and udev
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct BatteryConfig {
+    /// Charge in percent, 0 to 100
+    pub capacity: u8,
+    pub status: BatteryStatus,
+}
+
+/// Charging state of a battery, as in the power supply's `status` file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub enum BatteryStatus {
+    #[default]
+    Unknown,
+    Charging,
+    Discharging,
+    NotCharging,
+    Full,
+}
+impl BatteryStatus {
+    /// Name the kernel uses for the status, e.g. `Not charging`
+    pub fn sysfs_name(self) -> &'static str {
+        match self {
+            BatteryStatus::Unknown => "Unknown",
+            BatteryStatus::Charging => "Charging",
+            BatteryStatus::Discharging => "Discharging",
+            BatteryStatus::NotCharging => "Not charging",
+            BatteryStatus::Full => "Full",
+        }
+    }
+}
+
+/// How a device reports its D-pad
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub enum DPadStyle {
+    /// `ABS_HAT0X`/`ABS_HAT0Y` axes (`Axis::DPadX`/`Axis::DPadY`) from -1 to 1
+    ///
+    /// How xpad (Xbox 360/One), hid-sony and hid-playstation (DualShock 4, DualSense),
+    /// hid-nintendo for the Switch Pro Controller and hid-generic for HID hat switches
+    /// report it.
+    #[default]
+    Hat,
+    /// `BTN_DPAD_*` buttons (`Button::DPadUp` etc.)
+    ///
+    /// How hid-nintendo reports the left Joy-Con, and xpad with `dpad_to_buttons` set.
+    Buttons,
+}
+
+/// Which `BTN_*` range a device's face and shoulder buttons are emitted in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub enum ButtonNamespace {
+    /// The gamepad range from `BTN_GAMEPAD` (`BTN_A`/`BTN_SOUTH`), see `Button::to_ev_code`
+    ///
+    /// What modern pad drivers (xpad, hid-playstation, hid-nintendo) use.
+    #[default]
+    Gamepad,
+    /// The legacy joystick range `BTN_TRIGGER`..`BTN_DEAD` from `BTN_JOYSTICK`
+    ///
+    /// The gamepad range buttons of the device are numbered in the order of `buttons`,
+    /// the first becoming `BTN_TRIGGER`, up to 16 of them. How hid-generic presents
+    /// flight sticks and other HID joysticks, some flight sims only recognize these.
+    Joystick,
+}
+
+/// Version of the serialized `DeviceConfig` format
+///
+/// Bump when a change would make older vimputti versions misread saved configs.
+pub const DEVICE_CONFIG_SCHEMA_VERSION: u32 = 1;
+
+fn default_schema_version() -> u32 {
+    // Configs saved before the field existed are in the first format
+    1
+}
+
+/// Configuration for creating a virtual device
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct DeviceConfig {
+    /// Format version this config was serialized with
+    #[serde(default = "default_schema_version")]
+    pub schema_version: u32,
+    pub name: String,
+    pub vendor_id: u16,
+    pub product_id: u16,
+    pub version: u16,
+    pub bustype: BusType,
+    pub buttons: Vec<Button>,
+    pub axes: Vec<AxisConfig>,
+    /// Relative axes, e.g. the motion and wheels of a mouse
+    #[serde(default)]
+    pub rel_axes: Vec<RelAxis>,
+    /// Evdev driver version reported by EVIOCGVERSION (defaults to `DEFAULT_EVDEV_VERSION`)
+    #[serde(default)]
+    pub evdev_version: Option<i32>,
+    /// Joystick driver version reported by JSIOCGVERSION (defaults to `DEFAULT_JS_VERSION`)
+    #[serde(default)]
+    pub js_version: Option<i32>,
+    /// Model name presented in udev properties such as `ID_MODEL_FROM_DATABASE`,
+    /// if it should differ from `name` (see `DeviceConfig::udev_model`)
+    #[serde(default)]
+    pub udev_model_name: Option<String>,
+    /// udev `ID_BUS` presented to applications (see `DeviceConfig::udev_bus_name`)
+    #[serde(default)]
+    pub udev_bus: Option<String>,
+    /// Apply the manager's compatibility quirks for this vendor/product (see `quirks`)
+    #[serde(default)]
+    pub apply_quirks: bool,
+    /// Buttons emitted as other buttons, as (from, to) pairs
+    #[serde(default)]
+    pub button_remap: Vec<(Button, Button)>,
+    /// Additional udev properties for the device, e.g. custom `ID_*` keys udev rules match on
+    ///
+    /// Included in udev and netlink events, the udev database and the shim's libudev answers.
+    /// Keys vimputti generates itself are left alone unless `override_udev_properties` is set.
+    #[serde(default)]
+    pub extra_udev_properties: Vec<(String, String)>,
+    /// Let `extra_udev_properties` replace generated properties of the same key
+    #[serde(default)]
+    pub override_udev_properties: bool,
+    /// Precede each SYN_REPORT with an increasing `EV_MSC`/`MSC_RAW` frame counter,
+    /// so readers can detect lost frames
+    #[serde(default)]
+    pub emit_sequence: bool,
+    /// Write each SYN_REPORT-terminated frame to evdev readers separately
+    ///
+    /// Readers blocked in `poll`/`epoll_wait` wake per frame instead of finding several frames
+    /// of a batch merged into one read. Costs a write per frame.
+    #[serde(default)]
+    pub per_frame_writes: bool,
+    /// Most frames per second written to readers, None to write input as it's sent
+    ///
+    /// Input is held back and written as one frame per `1 / max_event_hz` seconds, keeping
+    /// only the last value of each absolute axis and the sum of each relative one. Button
+    /// changes are never merged, a button changing again within a frame starts the next one.
+    #[serde(default)]
+    pub max_event_hz: Option<u32>,
+    /// Only present the device for enumeration and hotplug, input sent to it is rejected
+    ///
+    /// Its nodes still accept connections and handshake, so detection logic can be tested
+    /// without driving input.
+    #[serde(default)]
+    pub passive: bool,
+    /// How the triggers are presented, see `DeviceConfig::advertised`
+    #[serde(default)]
+    pub trigger_layout: TriggerLayout,
+    /// How the D-pad is reported, which `VirtualController::set_dpad` follows
+    ///
+    /// None to infer it from the buttons and axes, see `DeviceConfig::dpad_style`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dpad_style: Option<DPadStyle>,
+    /// Which `BTN_*` range buttons are emitted in, see `DeviceConfig::advertised`
+    #[serde(default)]
+    pub button_namespace: ButtonNamespace,
+    /// Input applied as the device is created, before any evdev reader is let in
+    ///
+    /// The first reader opening the device already sees this state, e.g. a button held since
+    /// the game started or a stick deflected. Only buttons and axes of the device may be used.
+    #[serde(default)]
+    pub initial_state: Vec<InputEvent>,
+    /// Battery of a wireless device, None for wired ones which present no power supply
+    #[serde(default)]
+    pub battery: Option<BatteryConfig>,
+}
+impl Default for DeviceConfig {
+    fn default() -> Self {
+        Self {
+            schema_version: DEVICE_CONFIG_SCHEMA_VERSION,
+            name: String::new(),
+            vendor_id: 0x0000,
+            product_id: 0x0000,
+            version: 0x0100,
+            bustype: BusType::default(),
+            buttons: Vec::new(),
+            axes: Vec::new(),
+            rel_axes: Vec::new(),
+            evdev_version: None,
+            js_version: None,
+            udev_model_name: None,
+            udev_bus: None,
+            apply_quirks: false,
+            button_remap: Vec::new(),
+            extra_udev_properties: Vec::new(),
+            override_udev_properties: false,
+            emit_sequence: false,
+            per_frame_writes: false,
+            max_event_hz: None,
+            passive: false,
+            trigger_layout: TriggerLayout::default(),
+            dpad_style: None,
+            button_namespace: ButtonNamespace::default(),
+            initial_state: Vec::new(),
+            battery: None,
+        }
+    }
+}
+impl DeviceConfig {
+    /// Get the configuration of an axis, if the device has it
+    pub fn axis(&self, axis: Axis) -> Option<&AxisConfig> {
+        self.axes.iter().find(|a| a.axis == axis)
+    }
+
+    /// Check if the device has a button
+    pub fn has_button(&self, button: Button) -> bool {
+        self.buttons.contains(&button)
+    }
+
+    /// How the D-pad is reported, the configured style or the one its buttons and axes use
+    ///
+    /// Without a configured style, a device with D-pad buttons and no hat axes reports the
+    /// D-pad as buttons, any other as a hat.
+    pub fn dpad_style(&self) -> DPadStyle {
+        self.dpad_style.unwrap_or_else(|| {
+            let hat = self
+                .axes
+                .iter()
+                .any(|a| matches!(a.axis, Axis::DPadX | Axis::DPadY));
+            let buttons = self.buttons.iter().any(|b| {
+                matches!(
+                    b,
+                    Button::DPadUp | Button::DPadDown | Button::DPadLeft | Button::DPadRight
+                )
+            });
+            if buttons && !hat {
+                DPadStyle::Buttons
+            } else {
+                DPadStyle::Hat
+            }
+        })
+    }
+
+    /// Check if the device is a motion sensor, i.e. has accelerometer or gyroscope axes
+    ///
+    /// Motion sensors are marked with `INPUT_PROP_ACCELEROMETER`, which tells readers such as
+    /// SDL to take the axes as motion of the controller with the same IDs.
+    pub fn is_motion_sensor(&self) -> bool {
+        self.axes.iter().any(|a| a.axis.is_motion())
+    }
+
+    /// Check if the device gets a joystick interface (`jsN`), which needs buttons or axes
+    ///
+    /// Keyboard keys and mouse buttons (below `BTN_JOYSTICK`) alone don't count, joydev
+    /// leaves keyboards and mice alone too. Neither do motion sensors, joydev skips devices
+    /// with `INPUT_PROP_ACCELEROMETER`.
+    pub fn has_joystick_interface(&self) -> bool {
+        if self.is_motion_sensor() {
+            return false;
+        }
+        !self.axes.is_empty()
+            || self
+                .buttons
+                .iter()
+                .any(|button| button.to_ev_code() >= BTN_JOYSTICK)
+    }
+
+    /// Button actually emitted for a button input, after `button_remap`
+    pub fn remapped_button(&self, button: Button) -> Button {
+        self.button_remap
+            .iter()
+            .find(|(from, _)| *from == button)
+            .map_or(button, |(_, to)| *to)
+    }
+
+    /// Button actually emitted for a button input, after `button_remap` and `button_namespace`
+    pub fn emitted_button(&self, button: Button) -> Button {
+        self.namespaced_button(self.remapped_button(button))
+    }
+
+    /// Events as emitted, with buttons after `button_remap` and `button_namespace`
+    pub fn emitted_events(&self, events: &[InputEvent]) -> Vec<InputEvent> {
+        events
+            .iter()
+            .map(|event| match *event {
+                InputEvent::Button { button, pressed } => InputEvent::Button {
+                    button: self.emitted_button(button),
+                    pressed,
+                },
+                ref other => other.clone(),
+            })
+            .collect()
+    }
+
+    fn namespaced_button(&self, button: Button) -> Button {
+        if self.button_namespace == ButtonNamespace::Gamepad {
+            return button;
+        }
+
+        // Legacy joystick buttons, numbered by position among the gamepad range buttons
+        self.buttons
+            .iter()
+            .filter(|b| (BTN_GAMEPAD..BTN_GAMEPAD + 16).contains(&b.to_ev_code()))
+            .position(|b| *b == button)
+            .filter(|&index| index < 16)
+            .map_or(button, |index| Button::Custom(BTN_JOYSTICK + index as u16))
+    }
+
+    /// Check if the device has an axis
+    pub fn has_axis(&self, axis: Axis) -> bool {
+        self.axis(axis).is_some()
+    }
+
+    /// Check if the device has a relative axis
+    pub fn has_rel_axis(&self, axis: RelAxis) -> bool {
+        self.rel_axes.contains(&axis)
+    }
+
+    /// Analog trigger axes of the device
+    pub fn trigger_axes(&self) -> impl Iterator<Item = &AxisConfig> {
+        self.axes
+            .iter()
+            .filter(|a| matches!(a.axis, Axis::LowerLeftTrigger | Axis::LowerRightTrigger))
+    }
+
+    /// Left and right trigger axes, if they are presented as a single axis
+    ///
+    /// None unless `trigger_layout` is `CombinedZ` and the device has both triggers.
+    pub fn combined_triggers(&self) -> Option<(&AxisConfig, &AxisConfig)> {
+        if self.trigger_layout != TriggerLayout::CombinedZ {
+            return None;
+        }
+        Some((
+            self.axis(Axis::LowerLeftTrigger)?,
+            self.axis(Axis::LowerRightTrigger)?,
+        ))
+    }
+
+    /// Value of the combined trigger axis for the given trigger values
+    ///
+    /// The offsets of the triggers from their minimums, right minus left.
+    pub fn combined_trigger_value(&self, left: i32, right: i32) -> i32 {
+        match self.combined_triggers() {
+            Some((l, r)) => (r.clamp(right) - r.min) - (l.clamp(left) - l.min),
+            None => right - left,
+        }
+    }
+
+    /// Check if the device takes multitouch input, i.e. has an `ABS_MT_TRACKING_ID` axis
+    pub fn is_multitouch(&self) -> bool {
+        self.axes
+            .iter()
+            .any(|a| a.axis.to_ev_code() == ABS_MT_TRACKING_ID)
+    }
+
+    /// Touch buttons the manager emits for a multitouch device, see `advertised`
+    ///
+    /// `BTN_TOUCH` and a `BTN_TOOL_*` per finger count, up to the number of slots.
+    pub fn touch_buttons(&self) -> Vec<Button> {
+        if !self.is_multitouch() {
+            return Vec::new();
+        }
+
+        let slots = self
+            .axis(Axis::Custom(ABS_MT_SLOT))
+            .map_or(1, |a| (a.max - a.min + 1).max(1) as usize);
+        std::iter::once(BTN_TOUCH)
+            .chain(
+                [
+                    BTN_TOOL_FINGER,
+                    BTN_TOOL_DOUBLETAP,
+                    BTN_TOOL_TRIPLETAP,
+                    BTN_TOOL_QUADTAP,
+                    BTN_TOOL_QUINTTAP,
+                ]
+                .into_iter()
+                .take(slots),
+            )
+            .map(Button::Custom)
+            .collect()
+    }
+
+    /// Config as presented to applications
+    ///
+    /// With combined triggers, the trigger axes are replaced by a single `ABS_Z` axis
+    /// (`Axis::LowerLeftTrigger`) spanning both, see `combined_trigger_value`. In the joystick
+    /// button namespace, buttons are replaced by their legacy joystick codes (see
+    /// `emitted_button`). Multitouch devices gain their `touch_buttons`, which libinput
+    /// requires.
+    pub fn advertised(&self) -> DeviceConfig {
+        let mut config = self.clone();
+        if self.button_namespace != ButtonNamespace::Gamepad {
+            config.buttons = self
+                .buttons
+                .iter()
+                .map(|&b| self.namespaced_button(b))
+                .collect();
+        }
+        if let Some((left, right)) = self.combined_triggers() {
+            let combined = AxisConfig {
+                axis: Axis::LowerLeftTrigger,
+                min: -(left.max - left.min),
+                max: right.max - right.min,
+                fuzz: left.fuzz.max(right.fuzz),
+                flat: left.flat.max(right.flat),
+                center: (left.center.is_some() || right.center.is_some())
+                    .then(|| self.combined_trigger_value(left.rest(), right.rest())),
+                smoothing: left.smoothing.or(right.smoothing),
+                resolution: left.resolution,
+            };
+            config.axes.retain(|a| a.axis != Axis::LowerRightTrigger);
+            for axis in config.axes.iter_mut() {
+                if axis.axis == Axis::LowerLeftTrigger {
+                    *axis = combined;
+                }
+            }
+        }
+        for button in self.touch_buttons() {
+            if !config.buttons.contains(&button) {
+                config.buttons.push(button);
+            }
+        }
+        config
+    }
+
+    /// Analog stick axes of the device
+    pub fn stick_axes(&self) -> impl Iterator<Item = &AxisConfig> {
+        self.axes.iter().filter(|a| {
+            matches!(
+                a.axis,
+                Axis::LeftStickX | Axis::LeftStickY | Axis::RightStickX | Axis::RightStickY
+            )
+        })
+    }
+
+    /// Add `extra_udev_properties` to generated udev properties
+    ///
+    /// Extra properties with a generated key replace it with `override_udev_properties`,
+    /// otherwise they are skipped.
+    pub fn merge_extra_udev_properties(&self, properties: &mut Vec<(String, String)>) {
+        for (key, value) in &self.extra_udev_properties {
+            match properties.iter_mut().find(|(k, _)| k == key) {
+                Some((_, existing)) if self.override_udev_properties => existing.clone_from(value),
+                Some(_) => {}
+                None => properties.push((key.clone(), value.clone())),
+            }
+        }
+    }
+
+    /// Model name presented through udev, `udev_model_name` or else the evdev `name`
+    pub fn udev_model(&self) -> &str {
+        self.udev_model_name.as_deref().unwrap_or(&self.name)
+    }
+
+    /// udev `ID_BUS` value presented for this device
+    ///
+    /// SDL and some games ignore devices on the `virtual` bus, so unless overridden
+    /// through `udev_bus`, `BusType::Virtual` devices present as `usb` in udev.
+    /// The real bustype is still reported through EVIOCGID and sysfs `id/bustype`.
+    pub fn udev_bus_name(&self) -> &str {
+        match (&self.udev_bus, self.bustype) {
+            (Some(bus), _) => bus,
+            (None, BusType::Virtual) => "usb",
+            (None, bustype) => bustype.udev_name(),
+        }
+    }
+
+    /// Save configs to a JSON file as a list, e.g. those of `VimputtiClient::export_devices`
+    pub fn to_json_file(path: impl AsRef<Path>, configs: &[DeviceConfig]) -> std::io::Result<()> {
+        let content = serde_json::to_string_pretty(configs)?;
+        std::fs::write(path, content + "\n")
+    }
+
+    /// Load configs saved with `to_json_file`
+    ///
+    /// Configs of older format versions load too, ones of newer versions are rejected by
+    /// `validate` when creating the devices.
+    pub fn from_json_file(path: impl AsRef<Path>) -> std::io::Result<Vec<DeviceConfig>> {
+        let content = std::fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&content)?)
+    }
+
+    /// Check that the config describes a device the kernel could create
+    ///
+    /// Rejects configs from a newer format version, and checks that custom button and axis
+    /// codes fall within the KEY_* and ABS_* ranges, a code from the wrong range would end up
+    /// advertised in the wrong capability bitmap.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.schema_version > DEVICE_CONFIG_SCHEMA_VERSION {
+            return Err(format!(
+                "Config format version {} is newer than the supported version {}",
+                self.schema_version, DEVICE_CONFIG_SCHEMA_VERSION
+            ));
+        }
+
+        for button in &self.buttons {
+            match *button {
+                Button::Custom(code) if Button::custom_key(code).is_none() => {
+                    return Err(format!(
+                        "Custom button code 0x{:x} is outside the KEY_*/BTN_* range (0x1..=0x{:x})",
+                        code, KEY_MAX
+                    ));
+                }
+                Button::TriggerHappy(n) if !(1..=40).contains(&n) => {
+                    return Err(format!(
+                        "TriggerHappy button {} is outside BTN_TRIGGER_HAPPY1..40",
+                        n
+                    ));
+                }
+                _ => {}
+            }
+        }
+
+        for axis in &self.axes {
+            if let Axis::Custom(code) = axis.axis
+                && Axis::custom_abs(code).is_none()
+            {
+                return Err(format!(
+                    "Custom axis code 0x{:x} is outside the ABS_* range (0x0..=0x{:x})",
+                    code, ABS_MAX
+                ));
+            }
+            if let Some(center) = axis.center
+                && axis.clamp(center) != center
+            {
+                return Err(format!(
+                    "Center {} of axis {:?} is outside its range {}..={}",
+                    center, axis.axis, axis.min, axis.max
+                ));
+            }
+            if let Some(alpha) = axis.smoothing
+                && !(0.0..1.0).contains(&alpha)
+            {
+                return Err(format!(
+                    "Smoothing {} of axis {:?} is outside 0..1",
+                    alpha, axis.axis
+                ));
+            }
+            if axis.axis.is_motion()
+                && let Some(other) = self.axes.iter().find(|other| {
+                    other.axis != axis.axis && other.axis.to_ev_code() == axis.axis.to_ev_code()
+                })
+            {
+                return Err(format!(
+                    "Motion axis {:?} shares its code with axis {:?}, \
+                     motion sensors need a device of their own",
+                    axis.axis, other.axis
+                ));
+            }
+        }
+
+        for axis in &self.rel_axes {
+            if let RelAxis::Custom(code) = *axis
+                && RelAxis::custom_rel(code).is_none()
+            {
+                return Err(format!(
+                    "Custom relative axis code 0x{:x} is outside the REL_* range (0x0..=0x{:x})",
+                    code, REL_MAX
+                ));
+            }
+        }
+
+        if let Some(style) = self.dpad_style {
+            let hat = [Axis::DPadX, Axis::DPadY];
+            let buttons = [
+                Button::DPadUp,
+                Button::DPadDown,
+                Button::DPadLeft,
+                Button::DPadRight,
+            ];
+            let any_dpad = hat.iter().any(|&axis| self.axis(axis).is_some())
+                || buttons.iter().any(|&button| self.has_button(button));
+            let complete = match style {
+                DPadStyle::Hat => hat.iter().all(|&axis| self.axis(axis).is_some()),
+                DPadStyle::Buttons => buttons.iter().all(|&button| self.has_button(button)),
+            };
+            if any_dpad && !complete {
+                return Err(format!(
+                    "D-pad style {:?} doesn't match the D-pad buttons and axes of the device",
+                    style
+                ));
+            }
+        }
+
+        if self.max_event_hz == Some(0) {
+            return Err("Maximum event rate of 0 Hz would never write input".to_string());
+        }
+
+        if let Some(battery) = self.battery
+            && battery.capacity > 100
+        {
+            return Err(format!(
+                "Battery capacity {} is over 100 percent",
+                battery.capacity
+            ));
+        }
+
+        for event in &self.initial_state {
+            match *event {
+                InputEvent::Button { button, .. } if !self.has_button(button) => {
+                    return Err(format!(
+                        "Initial state uses button {:?}, which the device doesn't have",
+                        button
+                    ));
+                }
+                InputEvent::Axis { axis, value } => match self.axis(axis) {
+                    None => {
+                        return Err(format!(
+                            "Initial state uses axis {:?}, which the device doesn't have",
+                            axis
+                        ));
+                    }
+                    Some(config) if config.clamp(value) != value => {
+                        return Err(format!(
+                            "Initial value {} of axis {:?} is outside its range {}..={}",
+                            value, axis, config.min, config.max
+                        ));
+                    }
+                    Some(_) => {}
+                },
+                InputEvent::Relative { .. } => {
+                    return Err("Initial state can't hold relative movement".to_string());
+                }
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Evdev driver version reported when a device doesn't override it (1.0.1)
+pub const DEFAULT_EVDEV_VERSION: i32 = 0x010001;
+/// Joystick driver version reported when a device doesn't override it (2.1.0)
+pub const DEFAULT_JS_VERSION: i32 = 0x020100;
+
+/// Bus a device is attached to
+///
+/// Serialized as the variant name, e.g. `"Usb"`, or `{"Other": 25}` for buses without a variant.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub enum BusType {
+    Usb,
+    Bluetooth,
+    #[default]
+    Virtual,
+    I2c,
+    Spi,
+    /// Any other Linux `BUS_*` number
+    Other(u16),
+}
+impl BusType {
+    /// Linux `BUS_*` number, as reported by EVIOCGID
+    pub fn to_u16(&self) -> u16 {
+        match self {
+            BusType::Usb => 0x03,
+            BusType::Bluetooth => 0x05,
+            BusType::Virtual => 0x06,
+            BusType::I2c => 0x18,
+            BusType::Spi => 0x1c,
+            BusType::Other(bus) => *bus,
+        }
+    }
+
+    /// Bus type for a Linux `BUS_*` number
+    pub fn from_u16(bus: u16) -> Self {
+        match bus {
+            0x03 => BusType::Usb,
+            0x05 => BusType::Bluetooth,
+            0x06 => BusType::Virtual,
+            0x18 => BusType::I2c,
+            0x1c => BusType::Spi,
+            other => BusType::Other(other),
+        }
+    }
+
+    /// udev name of the bus
+    pub fn udev_name(&self) -> &'static str {
+        match self {
+            BusType::Usb => "usb",
+            BusType::Bluetooth => "bluetooth",
+            BusType::Virtual => "virtual",
+            BusType::I2c => "i2c",
+            BusType::Spi => "spi",
+            BusType::Other(_) => "unknown",
+        }
+    }
+}
+
+/// Common controller buttons
+///
+/// Serialized as the variant name, e.g. `"A"`, or as `{"Custom": 317}` for variants carrying
+/// a number. Saved configs and recordings rely on this, so keep it stable, `tests/fixtures`
+/// pins it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub enum Button {
+    // Face buttons
+    A,
+    B,
+    X,
+    Y,
+
+    // Shoulder buttons
+    UpperLeftBumper,
+    UpperRightBumper,
+    LowerLeftTrigger,
+    LowerRightTrigger,
+
+    // Stick buttons
+    LeftStick,
+    RightStick,
+
+    // D-pad
+    DPadUp,
+    DPadDown,
+    DPadLeft,
+    DPadRight,
+
+    // Special buttons
+    Start,
+    Select,
+    Guide,
+
+    // Extra buttons of arcade encoders, HOTAS etc. (1..=40)
+    TriggerHappy(u8),
+
+    // Custom button with raw code
+    Custom(u16),
+}
+impl Button {
+    /// Convert button to Linux input event code
+    ///
+    /// Face buttons use the codes xpad and hid-playstation emit, which the kernel defines
+    /// under two names each: `BTN_A` is `BTN_SOUTH` (and `BTN_GAMEPAD`), `BTN_B` is
+    /// `BTN_EAST`, `BTN_X` is `BTN_NORTH` and `BTN_Y` is `BTN_WEST`. Whatever name a game
+    /// looks for, it sees the same code. Devices in `ButtonNamespace::Joystick` emit other
+    /// codes, see `DeviceConfig::emitted_button`.
+    pub fn to_ev_code(self) -> u16 {
+        match self {
+            Button::A => 0x130,                 // BTN_SOUTH
+            Button::B => 0x131,                 // BTN_EAST
+            Button::X => 0x133,                 // BTN_NORTH
+            Button::Y => 0x134,                 // BTN_WEST
+            Button::UpperLeftBumper => 0x136,   // BTN_TL
+            Button::UpperRightBumper => 0x137,  // BTN_TR
+            Button::LowerLeftTrigger => 0x138,  // BTN_TL2
+            Button::LowerRightTrigger => 0x139, // BTN_TR2
+            Button::LeftStick => 0x13d,         // BTN_THUMBL
+            Button::RightStick => 0x13e,        // BTN_THUMBR
+            Button::Start => 0x13b,             // BTN_START
+            Button::Select => 0x13a,            // BTN_SELECT
+            Button::Guide => 0x13c,             // BTN_MODE
+            Button::DPadUp => 0x220,            // BTN_DPAD_UP
+            Button::DPadDown => 0x221,          // BTN_DPAD_DOWN
+            Button::DPadLeft => 0x222,          // BTN_DPAD_LEFT
+            Button::DPadRight => 0x223,         // BTN_DPAD_RIGHT
+            Button::TriggerHappy(n) => BTN_TRIGGER_HAPPY + (n.max(1) - 1) as u16,
+            Button::Custom(code) => code,
+        }
+    }
+
+    /// Convert from Linux input event code to Button
+    ///
+    /// Codes without a variant, e.g. keyboard keys, become `Custom`, so converting back with
+    /// `to_ev_code` gives the same code. None for codes outside the KEY_*/BTN_* range.
+    pub fn from_ev_code(code: u16) -> Option<Self> {
+        match code {
+            0x130 => Some(Button::A),
+            0x131 => Some(Button::B),
+            0x133 => Some(Button::X),
+            0x134 => Some(Button::Y),
+            0x136 => Some(Button::UpperLeftBumper),
+            0x137 => Some(Button::UpperRightBumper),
+            0x138 => Some(Button::LowerLeftTrigger),
+            0x139 => Some(Button::LowerRightTrigger),
+            0x13d => Some(Button::LeftStick),
+            0x13e => Some(Button::RightStick),
+            0x13b => Some(Button::Start),
+            0x13a => Some(Button::Select),
+            0x13c => Some(Button::Guide),
+            0x220 => Some(Button::DPadUp),
+            0x221 => Some(Button::DPadDown),
+            0x222 => Some(Button::DPadLeft),
+            0x223 => Some(Button::DPadRight),
+            BTN_TRIGGER_HAPPY..=0x2e7 => {
+                Some(Button::TriggerHappy((code - BTN_TRIGGER_HAPPY + 1) as u8))
+            }
+            _ => Self::custom_key(code),
+        }
+    }
+
+    /// Custom button from a KEY_* or BTN_* code (`0x1..=KEY_MAX`)
+    ///
+    /// Returns None for codes outside that range.
+    pub fn custom_key(code: u16) -> Option<Self> {
+        (1..=KEY_MAX)
+            .contains(&code)
+            .then_some(Button::Custom(code))
+    }
+
+    /// Returns all standard button variants (without Custom)
+    pub fn all_standard() -> &'static [Button] {
+        &[
+            Button::A,
+            Button::B,
+            Button::X,
+            Button::Y,
+            Button::UpperLeftBumper,
+            Button::UpperRightBumper,
+            Button::LowerLeftTrigger,
+            Button::LowerRightTrigger,
+            Button::LeftStick,
+            Button::RightStick,
+            Button::DPadUp,
+            Button::DPadDown,
+            Button::DPadLeft,
+            Button::DPadRight,
+            Button::Start,
+            Button::Select,
+            Button::Guide,
+        ]
+    }
+
+    /// Returns all standard button event codes
+    pub fn all_standard_codes() -> &'static [u16] {
+        &[
+            0x130, 0x131, 0x133, 0x134, // A, B, X, Y
+            0x136, 0x137, // Upper bumpers
+            0x138, 0x139, // Lower triggers
+            0x13d, 0x13e, // Stick clicks
+            0x220, 0x221, 0x222, 0x223, // DPad
+            0x13b, 0x13a, 0x13c, // Start, Select, Guide
+        ]
+    }
+}
+
+/// Controller axis
+///
+/// Serialized like `Button`, e.g. `"LeftStickX"` or `{"Custom": 40}`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub enum Axis {
+    LeftStickX,
+    LeftStickY,
+    RightStickX,
+    RightStickY,
+    LowerLeftTrigger,
+    LowerRightTrigger,
+    DPadX,
+    DPadY,
+    // Simulation axes of flight sticks, pedals and wheels
+    Throttle,
+    Rudder,
+    Wheel,
+    Gas,
+    Brake,
+    /// Vendor specific extra axis (`ABS_MISC`), e.g. the pressure of a button
+    Misc,
+    // Motion sensor axes, acceleration on ABS_X..ABS_Z and angular velocity on ABS_RX..ABS_RZ.
+    // They share codes with the sticks, so like the kernel's motion sensor devices they go on a
+    // device of their own, next to the controller (see `DeviceConfig::is_motion_sensor`)
+    AccelX,
+    AccelY,
+    AccelZ,
+    GyroX,
+    GyroY,
+    GyroZ,
+    Custom(u16),
+}
+impl Axis {
+    /// Custom axis from an ABS_* code (`0x0..=ABS_MAX`)
+    ///
+    /// Returns None for codes outside that range.
+    pub fn custom_abs(code: u16) -> Option<Self> {
+        (code <= ABS_MAX).then_some(Axis::Custom(code))
+    }
+
+    /// Check if this is an accelerometer or gyroscope axis
+    pub fn is_motion(self) -> bool {
+        matches!(
+            self,
+            Axis::AccelX | Axis::AccelY | Axis::AccelZ | Axis::GyroX | Axis::GyroY | Axis::GyroZ
+        )
+    }
+
+    /// Check if this axis is pulled from rest towards one end, like an analog trigger or a
+    /// pedal, rather than moving both ways like a stick
+    pub fn is_trigger(self) -> bool {
+        matches!(
+            self,
+            Axis::LowerLeftTrigger | Axis::LowerRightTrigger | Axis::Gas | Axis::Brake
+        )
+    }
+
+    /// Check if this is a multitouch axis (`ABS_MT_*`), whose values are per slot
+    pub fn is_multitouch(self) -> bool {
+        (ABS_MT_SLOT..=ABS_MT_TOOL_Y).contains(&self.to_ev_code())
+    }
+
+    /// Convert axis to Linux input event code
+    pub fn to_ev_code(self) -> u16 {
+        match self {
+            Axis::LeftStickX => 0x00,        // ABS_X
+            Axis::LeftStickY => 0x01,        // ABS_Y
+            Axis::RightStickX => 0x03,       // ABS_RX
+            Axis::RightStickY => 0x04,       // ABS_RY
+            Axis::LowerLeftTrigger => 0x02,  // ABS_Z
+            Axis::LowerRightTrigger => 0x05, // ABS_RZ
+            Axis::DPadX => 0x10,             // ABS_HAT0X
+            Axis::DPadY => 0x11,             // ABS_HAT0Y
+            Axis::Throttle => 0x06,          // ABS_THROTTLE
+            Axis::Rudder => 0x07,            // ABS_RUDDER
+            Axis::Wheel => 0x08,             // ABS_WHEEL
+            Axis::Gas => 0x09,               // ABS_GAS
+            Axis::Brake => 0x0a,             // ABS_BRAKE
+            Axis::Misc => 0x28,              // ABS_MISC
+            Axis::AccelX => 0x00,            // ABS_X
+            Axis::AccelY => 0x01,            // ABS_Y
+            Axis::AccelZ => 0x02,            // ABS_Z
+            Axis::GyroX => 0x03,             // ABS_RX
+            Axis::GyroY => 0x04,             // ABS_RY
+            Axis::GyroZ => 0x05,             // ABS_RZ
+            Axis::Custom(code) => code,
+        }
+    }
+
+    /// Convert from Linux input event code to Axis
+    ///
+    /// Codes shared with the motion axes give the stick and trigger axes, see
+    /// `from_motion_ev_code` for motion sensor devices.
+    pub fn from_ev_code(code: u16) -> Option<Self> {
+        match code {
+            0x00 => Some(Axis::LeftStickX),
+            0x01 => Some(Axis::LeftStickY),
+            0x03 => Some(Axis::RightStickX),
+            0x04 => Some(Axis::RightStickY),
+            0x02 => Some(Axis::LowerLeftTrigger),
+            0x05 => Some(Axis::LowerRightTrigger),
+            0x10 => Some(Axis::DPadX),
+            0x11 => Some(Axis::DPadY),
+            0x06 => Some(Axis::Throttle),
+            0x07 => Some(Axis::Rudder),
+            0x08 => Some(Axis::Wheel),
+            0x09 => Some(Axis::Gas),
+            0x0a => Some(Axis::Brake),
+            0x28 => Some(Axis::Misc),
+            _ => None,
+        }
+    }
+
+    /// Convert from Linux input event code to Axis on a motion sensor device
+    ///
+    /// Codes other than the motion axes' convert like `from_ev_code`.
+    pub fn from_motion_ev_code(code: u16) -> Option<Self> {
+        match code {
+            0x00 => Some(Axis::AccelX),
+            0x01 => Some(Axis::AccelY),
+            0x02 => Some(Axis::AccelZ),
+            0x03 => Some(Axis::GyroX),
+            0x04 => Some(Axis::GyroY),
+            0x05 => Some(Axis::GyroZ),
+            _ => Self::from_ev_code(code),
+        }
+    }
+}
+
+/// Relative axis, reporting movement rather than a position
+///
+/// Serialized like `Axis`, e.g. `"X"` or `{"Custom": 11}`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub enum RelAxis {
+    X,
+    Y,
+    /// Vertical scroll wheel
+    WheelV,
+    /// Horizontal scroll wheel, or a tilting wheel
+    WheelH,
+    Custom(u16),
+}
+impl RelAxis {
+    /// Custom relative axis from a REL_* code (`0x0..=REL_MAX`)
+    ///
+    /// Returns None for codes outside that range.
+    pub fn custom_rel(code: u16) -> Option<Self> {
+        (code <= REL_MAX).then_some(RelAxis::Custom(code))
+    }
+
+    /// Convert relative axis to Linux input event code
+    pub fn to_ev_code(self) -> u16 {
+        match self {
+            RelAxis::X => 0x00,      // REL_X
+            RelAxis::Y => 0x01,      // REL_Y
+            RelAxis::WheelH => 0x06, // REL_HWHEEL
+            RelAxis::WheelV => 0x08, // REL_WHEEL
+            RelAxis::Custom(code) => code,
+        }
+    }
+
+    /// Convert from Linux input event code to RelAxis
+    ///
+    /// Codes without a variant become `Custom`, None for codes outside the REL_* range.
+    pub fn from_ev_code(code: u16) -> Option<Self> {
+        match code {
+            0x00 => Some(RelAxis::X),
+            0x01 => Some(RelAxis::Y),
+            0x06 => Some(RelAxis::WheelH),
+            0x08 => Some(RelAxis::WheelV),
+            _ => Self::custom_rel(code),
+        }
+    }
+}
+
+/// Configuration for an axis
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct AxisConfig {
+    pub axis: Axis,
+    pub min: i32,
+    pub max: i32,
+    pub fuzz: i32,
+    pub flat: i32,
+    /// Rest position, for axes that don't rest at 0 such as a throttle resting at full-back
+    ///
+    /// Must be within `min..=max`. See `AxisConfig::rest`.
+    #[serde(default)]
+    pub center: Option<i32>,
+    /// Exponential smoothing of the values sent, for jittery sources
+    ///
+    /// Each value reported is `alpha * previous + (1 - alpha) * sent`, with `alpha` in `0..1`.
+    /// Higher values smooth more but also lag more behind the input, and as the axis only
+    /// moves when sent, a source should keep sending it until it settles.
+    #[serde(default)]
+    pub smoothing: Option<f32>,
+    /// Resolution reported by EVIOCGABS, in units per mm (units per radian for rotation)
+    ///
+    /// 0 means unknown. Touchpads and tablets need it for libinput to scale motion. Motion
+    /// axes report units per g for acceleration and units per degree per second for the
+    /// gyroscope, which games need to turn the values into physical units.
+    #[serde(default)]
+    pub resolution: i32,
+}
+impl AxisConfig {
+    pub fn new(axis: Axis, min: i32, max: i32) -> Self {
+        Self {
+            axis,
+            min,
+            max,
+            fuzz: 0,
+            flat: 0,
+            center: None,
+            smoothing: None,
+            resolution: 0,
+        }
+    }
+
+    /// Set the rest position of the axis
+    pub fn with_center(mut self, center: i32) -> Self {
+        self.center = Some(center);
+        self
+    }
+
+    /// Smooth the values sent with factor `alpha` in `0..1`, see `AxisConfig::smoothing`
+    pub fn with_smoothing(mut self, alpha: f32) -> Self {
+        self.smoothing = Some(alpha);
+        self
+    }
+
+    /// Set the resolution in units per mm
+    pub fn with_resolution(mut self, units_per_mm: i32) -> Self {
+        self.resolution = units_per_mm;
+        self
+    }
+
+    /// Set the resolution from dots (units) per inch, rounded to units per mm
+    pub fn with_dpi(self, dpi: u32) -> Self {
+        self.with_resolution((dpi as f64 / 25.4).round() as i32)
+    }
+
+    /// Value of the axis at rest, `center` or else 0 clamped into the range
+    pub fn rest(&self) -> i32 {
+        self.center.unwrap_or_else(|| self.clamp(0))
+    }
+
+    /// Clamp a value into the axis range
+    pub fn clamp(&self, value: i32) -> i32 {
+        value.clamp(self.min.min(self.max), self.max.max(self.min))
+    }
+
+    /// Where normalized values are measured from, `center` or else the minimum of triggers and
+    /// the middle of the range of other axes
+    fn normalized_origin(&self) -> i32 {
+        self.center.unwrap_or(if self.axis.is_trigger() {
+            self.min
+        } else {
+            (self.min + self.max) / 2
+        })
+    }
+
+    /// Map a normalized value to the axis range
+    ///
+    /// Triggers and pedals (see `Axis::is_trigger`) map `0.0..=1.0` from min to max. Other
+    /// axes map `-1.0..=1.0` around their middle, or their `center` if set, -1.0 being the
+    /// minimum and 1.0 the maximum however far they are from the middle. Out of range values
+    /// are clamped, and values within `flat` of the origin, the deadzone, end up at it.
+    pub fn denormalize(&self, value: f32) -> i32 {
+        let origin = self.normalized_origin();
+        let value = if self.center.is_none() && self.axis.is_trigger() {
+            value.clamp(0.0, 1.0)
+        } else {
+            value.clamp(-1.0, 1.0)
+        };
+        let span = if value < 0.0 {
+            origin - self.min
+        } else {
+            self.max - origin
+        };
+
+        let offset = (value * span as f32).round() as i32;
+        if offset.abs() <= self.flat {
+            origin
+        } else {
+            origin + offset
+        }
+    }
+
+    /// Map a value of the axis range to a normalized one, the inverse of `denormalize`
+    ///
+    /// Values within `flat` of the origin, the deadzone, are 0.0.
+    pub fn normalize(&self, value: i32) -> f32 {
+        let origin = self.normalized_origin();
+        let offset = self.clamp(value) - origin;
+        let span = if offset < 0 {
+            origin - self.min
+        } else {
+            self.max - origin
+        };
+
+        if offset.abs() <= self.flat || span == 0 {
+            0.0
+        } else {
+            offset as f32 / span as f32
+        }
+    }
+}
+
+/// Input event to send to a device
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub enum InputEvent {
+    /// Button press/release
+    Button { button: Button, pressed: bool },
+    /// Axis movement
+    Axis { axis: Axis, value: i32 },
+    /// Relative movement, e.g. of a mouse
+    Relative { axis: RelAxis, value: i32 },
+    /// Raw Linux input event
+    Raw {
+        event_type: u16,
+        code: u16,
+        value: i32,
+    },
+    /// Synchronization event (automatically added if not present)
+    Sync,
+}
+/// Input referring to buttons and axes by their enum names, for raw JSON clients
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(untagged)]
+pub enum NamedInput {
+    /// e.g. `{ "button": "A", "pressed": true }`
+    Button { button: String, pressed: bool },
+    /// e.g. `{ "axis": "LeftStickX", "value": 12000 }`
+    Axis { axis: String, value: i32 },
+}
+impl NamedInput {
+    /// Resolve into an `InputEvent`, validating the name against the device config
+    pub fn resolve(&self, config: &DeviceConfig) -> Result<InputEvent, String> {
+        match self {
+            NamedInput::Button { button, pressed } => {
+                let parsed: Button =
+                    serde_json::from_value(serde_json::Value::String(button.clone()))
+                        .map_err(|_| format!("Unknown button name: {}", button))?;
+                if !config.has_button(parsed) {
+                    return Err(format!("Device has no button {}", button));
+                }
+                Ok(InputEvent::Button {
+                    button: parsed,
+                    pressed: *pressed,
+                })
+            }
+            NamedInput::Axis { axis, value } => {
+                let parsed: Axis = serde_json::from_value(serde_json::Value::String(axis.clone()))
+                    .map_err(|_| format!("Unknown axis name: {}", axis))?;
+                if !config.has_axis(parsed) {
+                    return Err(format!("Device has no axis {}", axis));
+                }
+                Ok(InputEvent::Axis {
+                    axis: parsed,
+                    value: *value,
+                })
+            }
+        }
+    }
+}
+
+impl InputEvent {
+    /// Convert to LinuxInputEvent
+    pub fn to_linux_input_event(&self) -> LinuxInputEvent {
+        match self {
+            InputEvent::Button { button, pressed } => {
+                LinuxInputEvent::new(EV_KEY, button.to_ev_code(), if *pressed { 1 } else { 0 })
+            }
+            InputEvent::Axis { axis, value } => {
+                LinuxInputEvent::new(EV_ABS, axis.to_ev_code(), *value)
+            }
+            InputEvent::Relative { axis, value } => {
+                LinuxInputEvent::new(EV_REL, axis.to_ev_code(), *value)
+            }
+            InputEvent::Raw {
+                event_type,
+                code,
+                value,
+            } => LinuxInputEvent::new(*event_type, *code, *value),
+            InputEvent::Sync => LinuxInputEvent::new(EV_SYN, SYN_REPORT, 0),
+        }
+    }
+    /// Convert from a LinuxInputEvent, e.g. one written to uinput
+    ///
+    /// The code is looked up by event type, as buttons and axes share code numbers: code
+    /// 0x02 is `Axis::LowerLeftTrigger` (`ABS_Z`) as `EV_ABS` but a keyboard key as `EV_KEY`,
+    /// where the trigger click is `Button::LowerLeftTrigger` (`BTN_TL2`, 0x138). Codes without
+    /// a variant become `Custom`, event types other than keys, absolute and relative axes `Raw`.
+    pub fn from_linux_input_event(event: &LinuxInputEvent) -> Self {
+        let (event_type, code, value) = (event.event_type, event.code, event.value);
+        match event_type {
+            EV_KEY => match Button::from_ev_code(code) {
+                Some(button) => InputEvent::Button {
+                    button,
+                    pressed: value != 0,
+                },
+                None => InputEvent::Raw {
+                    event_type,
+                    code,
+                    value,
+                },
+            },
+            EV_ABS => match Axis::from_ev_code(code).or_else(|| Axis::custom_abs(code)) {
+                Some(axis) => InputEvent::Axis { axis, value },
+                None => InputEvent::Raw {
+                    event_type,
+                    code,
+                    value,
+                },
+            },
+            EV_REL => match RelAxis::from_ev_code(code) {
+                Some(axis) => InputEvent::Relative { axis, value },
+                None => InputEvent::Raw {
+                    event_type,
+                    code,
+                    value,
+                },
+            },
+            EV_SYN if code == SYN_REPORT => InputEvent::Sync,
+            _ => InputEvent::Raw {
+                event_type,
+                code,
+                value,
+            },
+        }
+    }
+}
+
+/// Information about an active device
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct DeviceInfo {
+    pub device_id: DeviceId,
+    pub name: String,
+    pub event_node: String,
+    pub joystick_node: Option<String>,
+    pub vendor_id: u16,
+    pub product_id: u16,
+}
+
+/// Number of clients connected to each of a device's sockets
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct ClientCounts {
+    pub evdev: usize,
+    pub joystick: usize,
+    pub feedback: usize,
+}
+
+/// Criteria for listing only some devices
+///
+/// Unset fields match every device, a device has to match all set fields.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(default)]
+pub struct DeviceFilter {
+    /// Part of the device name, compared case-insensitively
+    pub name: Option<String>,
+    pub vendor_id: Option<u16>,
+    pub product_id: Option<u16>,
+    /// Whether the device has a joystick (`jsN`) node
+    pub has_joystick: Option<bool>,
+    /// A button the device must have
+    pub button: Option<Button>,
+    /// An axis the device must have
+    pub axis: Option<Axis>,
+}
+impl DeviceFilter {
+    /// Check if a device matches the filter
+    pub fn matches(&self, config: &DeviceConfig, has_joystick: bool) -> bool {
+        if let Some(name) = &self.name
+            && !config.name.to_lowercase().contains(&name.to_lowercase())
+        {
+            return false;
+        }
+
+        self.vendor_id.is_none_or(|id| id == config.vendor_id)
+            && self.product_id.is_none_or(|id| id == config.product_id)
+            && self.has_joystick.is_none_or(|js| js == has_joystick)
+            && self.button.is_none_or(|b| config.buttons.contains(&b))
+            && self
+                .axis
+                .is_none_or(|a| config.axes.iter().any(|c| c.axis == a))
+    }
+}
+
+/// Information about the running manager
+///
+/// Fields missing from an older or newer manager deserialize to their defaults.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(default)]
+pub struct ManagerInfo {
+    /// Crate version of the manager
+    pub version: String,
+    /// Base directory for device sockets and sysfs, e.g. `/tmp/vimputti`
+    ///
+    /// Canonical (symlinks resolved), shims should never redirect outside of it.
+    pub base_path: String,
+    /// Control socket the manager listens on
+    pub socket_path: String,
+    /// Number of currently active devices
+    pub device_count: usize,
+    /// Maximum number of devices that may exist at once
+    pub max_devices: usize,
+    /// Maximum number of events in one `SendInput` or `SendNamed`, 0 if not reported
+    #[serde(default)]
+    pub max_batch_events: usize,
+    /// Optional features this manager supports, e.g. `"send_named"`
+    pub features: Vec<String>,
+    /// Prefix of the device node names, see `event_node_name`
+    #[serde(default)]
+    pub node_prefix: String,
+}
+impl ManagerInfo {
+    /// Check if the manager advertises a feature
+    pub fn has_feature(&self, feature: &str) -> bool {
+        self.features.iter().any(|f| f == feature)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct DeviceHandshake {
+    pub device_id: DeviceId,
+    pub config: DeviceConfig,
+}
+impl DeviceHandshake {
+    /// Serialize to a frame (header + JSON, see `framing`)
+    ///
+    /// Sent before the reader says anything, so in the legacy framing readers of any version
+    /// understand.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, serde_json::Error> {
+        Ok(framing::Framing::Legacy.encode(&serde_json::to_vec(self)?))
+    }
+
+    /// Deserialize from the payload of a frame (without header)
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, serde_json::Error> {
+        serde_json::from_slice(bytes)
+    }
+}
+
+/// Linux input event structure (for sending to device sockets)
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[repr(C)]
+pub struct LinuxInputEvent {
+    pub time: TimeVal,
+    pub event_type: u16,
+    pub code: u16,
+    pub value: i32,
+}
+impl LinuxInputEvent {
+    pub fn new(event_type: u16, code: u16, value: i32) -> Self {
+        Self {
+            time: TimeVal::now(),
+            event_type,
+            code,
+            value,
+        }
+    }
+
+    pub fn to_bytes(&self) -> [u8; 24] {
+        unsafe { std::mem::transmute(*self) }
+    }
+}
+
+/// Linux ABS input event structure (for absolute axes)
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[repr(C)]
+pub struct LinuxAbsEvent {
+    pub value: i32,
+    pub minimum: i32,
+    pub maximum: i32,
+    pub fuzz: i32,
+    pub flat: i32,
+    pub resolution: i32,
+}
+
+/// Linux joystick input event structure (for joystick nodes)
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[repr(C, packed)]
+pub struct LinuxJsEvent {
+    pub time: u32,
+    pub value: i16,
+    pub type_: u8,
+    pub number: u8,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[repr(C)]
+pub struct TimeVal {
+    pub tv_sec: i64,
+    pub tv_usec: i64,
+}
+impl TimeVal {
+    pub fn now() -> Self {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap();
+        Self {
+            tv_sec: now.as_secs() as i64,
+            tv_usec: now.subsec_micros() as i64,
+        }
+    }
+}
+
+/* uinput structures */
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct DeviceSetup {
+    pub name: String,
+    pub vendor_id: u16,
+    pub product_id: u16,
+    pub version: u16,
+    pub bustype: u16,
+}
+
+/// Request on a uinput session
+///
+/// Every request is answered with a `UinputResponse`, except `WriteEvents`. A manager answers
+/// requests it doesn't know with an unsuccessful response, so requests added later have to be
+/// answered too for older managers to keep up.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub enum UinputRequest {
+    /// ioctl: UI_SET_EVBIT
+    SetEvBit { ev_type: u16 },
+    /// ioctl: UI_SET_KEYBIT
+    SetKeyBit { key_code: u16 },
+    /// ioctl: UI_SET_ABSBIT
+    SetAbsBit { abs_code: u16 },
+    /// ioctl: UI_SET_RELBIT
+    SetRelBit { rel_code: u16 },
+    /// ioctl: UI_ABS_SETUP
+    AbsSetup { code: u16, absinfo: LinuxAbsEvent },
+    /// ioctl: UI_DEV_SETUP
+    DevSetup { setup: DeviceSetup },
+    /// ioctl: UI_DEV_CREATE
+    DevCreate {},
+    /// ioctl: UI_DEV_DESTROY
+    DevDestroy {},
+    /// write() - send input events
+    WriteEvents { events: Vec<LinuxInputEvent> },
+}
+impl UinputRequest {
+    /// Serialize to a frame (header + JSON, see `framing`)
+    pub fn to_bytes(&self) -> Result<Vec<u8>, serde_json::Error> {
+        Ok(framing::encode_frame(&serde_json::to_vec(self)?))
+    }
+
+    /// Deserialize from the payload of a frame (without header)
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, serde_json::Error> {
+        serde_json::from_slice(bytes)
+    }
+
+    /// Name of the request in a payload, also for requests `from_bytes` doesn't know
+    ///
+    /// None if the payload isn't a request at all.
+    pub fn name_of(bytes: &[u8]) -> Option<String> {
+        match serde_json::from_slice(bytes).ok()? {
+            serde_json::Value::String(name) => Some(name),
+            serde_json::Value::Object(map) if map.len() == 1 => map.keys().next().cloned(),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct UinputResponse {
+    pub success: bool,
+    pub device_id: Option<DeviceId>,
+    pub error: Option<String>,
+}
+impl UinputResponse {
+    /// Serialize to a frame (header + JSON, see `framing`)
+    pub fn to_bytes(&self) -> Result<Vec<u8>, serde_json::Error> {
+        self.to_bytes_in(framing::Framing::Current)
+    }
+
+    /// Serialize to a frame in the framing of the request it answers
+    pub fn to_bytes_in(&self, framing: framing::Framing) -> Result<Vec<u8>, serde_json::Error> {
+        Ok(framing.encode(&serde_json::to_vec(self)?))
+    }
+
+    /// Deserialize from the payload of a frame (without header)
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, serde_json::Error> {
+        serde_json::from_slice(bytes)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub enum FeedbackEvent {
+    /// Simple rumble
+    Rumble {
+        strong_magnitude: u16, // 0-65535
+        weak_magnitude: u16,   // 0-65535
+        duration_ms: u16,      // Duration in milliseconds (0 = infinite/until stopped)
+    },
+    /// Stop rumble
+    RumbleStop,
+    /// Rumble effect uploaded with `EVIOCSFF`, played later by its ID
+    ///
+    /// Uploading to the ID of an existing effect replaces it.
+    RumbleUpload {
+        id: i16,
+        strong: u16,
+        weak: u16,
+        duration: u16, // Duration in milliseconds
+    },
+    /// Effect removed with `EVIOCRMFF`
+    RumbleErase { id: i16 },
+    /// Raw event
+    Raw { code: u16, value: i32 },
+}
+impl FeedbackEvent {
+    /// EV_FF events carrying this feedback on a device's feedback socket
+    ///
+    /// Rumble is sent as `FF_RUMBLE` with `strong << 16 | weak`, followed by
+    /// `FF_RUMBLE + 1` with the duration, the same way the shim forwards a game's effects.
+    /// Uploads are `FF_UPLOAD_MAGNITUDE` with the magnitudes the same way, followed by
+    /// `FF_UPLOAD` with `id << 16 | duration`, and erases are `FF_ERASE` with the ID.
+    pub fn to_linux_input_events(&self) -> Vec<LinuxInputEvent> {
+        match *self {
+            FeedbackEvent::Rumble {
+                strong_magnitude,
+                weak_magnitude,
+                duration_ms,
+            } => vec![
+                LinuxInputEvent::new(
+                    EV_FF,
+                    FF_RUMBLE,
+                    ((strong_magnitude as i32) << 16) | weak_magnitude as i32,
+                ),
+                LinuxInputEvent::new(EV_FF, FF_RUMBLE + 1, duration_ms as i32),
+            ],
+            FeedbackEvent::RumbleStop => vec![LinuxInputEvent::new(EV_FF, FF_RUMBLE, 0)],
+            FeedbackEvent::RumbleUpload {
+                id,
+                strong,
+                weak,
+                duration,
+            } => vec![
+                LinuxInputEvent::new(
+                    EV_FF,
+                    FF_UPLOAD_MAGNITUDE,
+                    ((strong as i32) << 16) | weak as i32,
+                ),
+                LinuxInputEvent::new(EV_FF, FF_UPLOAD, ((id as i32) << 16) | duration as i32),
+            ],
+            FeedbackEvent::RumbleErase { id } => {
+                vec![LinuxInputEvent::new(EV_FF, FF_ERASE, id as i32)]
+            }
+            FeedbackEvent::Raw { code, value } => vec![LinuxInputEvent::new(EV_FF, code, value)],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn with_button(button: Button) -> DeviceConfig {
+        DeviceConfig {
+            buttons: vec![button],
+            ..Default::default()
+        }
+    }
+
+    fn with_axis(axis: Axis) -> DeviceConfig {
+        DeviceConfig {
+            axes: vec![AxisConfig::new(axis, -100, 100)],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn custom_codes_within_range_are_valid() {
+        assert_eq!(with_button(Button::Custom(0x130)).validate(), Ok(()));
+        assert_eq!(with_button(Button::Custom(KEY_MAX)).validate(), Ok(()));
+        assert_eq!(with_axis(Axis::Custom(0)).validate(), Ok(()));
+        assert_eq!(with_axis(Axis::Custom(ABS_MAX)).validate(), Ok(()));
+    }
+
+    #[test]
+    fn custom_codes_out_of_range_are_rejected() {
+        let error = with_button(Button::Custom(0)).validate().unwrap_err();
+        assert!(error.contains("KEY_*/BTN_*"), "{}", error);
+        assert!(with_button(Button::Custom(KEY_MAX + 1)).validate().is_err());
+
+        let error = with_axis(Axis::Custom(ABS_MAX + 1)).validate().unwrap_err();
+        assert!(error.contains("ABS_*"), "{}", error);
+    }
+
+    #[test]
+    fn custom_constructors_check_the_range() {
+        assert_eq!(Button::custom_key(0x130), Some(Button::Custom(0x130)));
+        assert_eq!(Button::custom_key(0), None);
+        assert_eq!(Button::custom_key(KEY_MAX + 1), None);
+        assert_eq!(Axis::custom_abs(ABS_MAX), Some(Axis::Custom(ABS_MAX)));
+        assert_eq!(Axis::custom_abs(ABS_MAX + 1), None);
+    }
+
+    #[test]
+    fn motion_sensors_get_no_joystick_interface() {
+        assert!(with_axis(Axis::LeftStickX).has_joystick_interface());
+        assert!(!with_axis(Axis::GyroX).has_joystick_interface());
+
+        let mut config = with_axis(Axis::AccelX);
+        config.buttons.push(Button::A);
+        assert!(!config.has_joystick_interface());
+    }
+
+    #[test]
+    fn trigger_happy_buttons_round_trip() {
+        assert_eq!(Button::TriggerHappy(1).to_ev_code(), 0x2c0);
+        assert_eq!(Button::TriggerHappy(40).to_ev_code(), 0x2e7);
+        for n in 1..=40 {
+            let code = Button::TriggerHappy(n).to_ev_code();
+            assert_eq!(Button::from_ev_code(code), Some(Button::TriggerHappy(n)));
+        }
+        assert!(with_button(Button::TriggerHappy(40)).validate().is_ok());
+        assert!(with_button(Button::TriggerHappy(41)).validate().is_err());
+    }
+
+    #[test]
+    fn bus_types_round_trip() {
+        let buses = [
+            BusType::Usb,
+            BusType::Bluetooth,
+            BusType::Virtual,
+            BusType::I2c,
+            BusType::Spi,
+            BusType::Other(0x19),
+        ];
+        for bus in buses {
+            assert_eq!(BusType::from_u16(bus.to_u16()), bus);
+            let json = serde_json::to_string(&bus).unwrap();
+            assert_eq!(serde_json::from_str::<BusType>(&json).unwrap(), bus);
+        }
+        assert_eq!(BusType::I2c.to_u16(), 0x18);
+        for bus in 0..=u16::MAX {
+            assert_eq!(BusType::from_u16(bus).to_u16(), bus);
+        }
+    }
+
+    #[test]
+    fn normalized_mapping_follows_the_axis_kind() {
+        // An unsigned stick range is still centered, a signed trigger range still one-way
+        let stick = AxisConfig::new(Axis::LeftStickX, 0, 255);
+        assert_eq!(
+            [-1.0, 0.0, 1.0].map(|value| stick.denormalize(value)),
+            [0, 127, 255]
+        );
+        let trigger = AxisConfig::new(Axis::LowerLeftTrigger, -32768, 32767);
+        assert_eq!(
+            [-1.0, 0.0, 1.0].map(|value| trigger.denormalize(value)),
+            [-32768, -32768, 32767]
+        );
+
+        for axis in [stick, trigger] {
+            for value in [axis.min, axis.max] {
+                assert_eq!(axis.denormalize(axis.normalize(value)), value);
+            }
+        }
+    }
+
+    #[test]
+    fn normalized_values_in_the_deadzone_are_at_rest() {
+        let stick = AxisConfig {
+            flat: 1000,
+            ..AxisConfig::new(Axis::LeftStickX, -32768, 32767)
+        };
+        assert_eq!(stick.denormalize(0.02), 0);
+        assert_eq!(stick.denormalize(-0.5), -16384);
+        assert_eq!(stick.normalize(-900), 0.0);
+        assert_eq!(stick.normalize(32767), 1.0);
+
+        let trigger = AxisConfig {
+            flat: 10,
+            ..AxisConfig::new(Axis::LowerRightTrigger, 0, 255)
+        };
+        assert_eq!(trigger.denormalize(0.02), 0);
+        assert_eq!(trigger.normalize(8), 0.0);
+        assert_eq!(trigger.normalize(255), 1.0);
+    }
+
+    #[test]
+    fn dpad_style_follows_the_layout() {
+        let hat = DeviceConfig {
+            axes: vec![
+                AxisConfig::new(Axis::DPadX, -1, 1),
+                AxisConfig::new(Axis::DPadY, -1, 1),
+            ],
+            ..Default::default()
+        };
+        let buttons = DeviceConfig {
+            buttons: vec![
+                Button::DPadUp,
+                Button::DPadDown,
+                Button::DPadLeft,
+                Button::DPadRight,
+            ],
+            ..Default::default()
+        };
+        assert_eq!(hat.dpad_style(), DPadStyle::Hat);
+        assert_eq!(buttons.dpad_style(), DPadStyle::Buttons);
+        assert_eq!(DeviceConfig::default().dpad_style(), DPadStyle::Hat);
+
+        // A configured style must be the one the device has
+        for (config, style, valid) in [
+            (&hat, DPadStyle::Hat, true),
+            (&hat, DPadStyle::Buttons, false),
+            (&buttons, DPadStyle::Buttons, true),
+            (&buttons, DPadStyle::Hat, false),
+        ] {
+            let config = DeviceConfig {
+                dpad_style: Some(style),
+                ..config.clone()
+            };
+            assert_eq!(config.validate().is_ok(), valid, "{:?}", config);
+            assert_eq!(config.dpad_style(), style);
+        }
+        let no_dpad = DeviceConfig {
+            dpad_style: Some(DPadStyle::Buttons),
+            ..Default::default()
+        };
+        assert!(no_dpad.validate().is_ok());
+    }
+
+    #[test]
+    fn list_devices_accepts_the_unit_form() {
+        let command: ControlCommand = serde_json::from_str(r#""ListDevices""#).unwrap();
+        assert!(matches!(
+            command,
+            ControlCommand::ListDevices { filter: None }
+        ));
+
+        for command in [
+            ControlCommand::ListDevices { filter: None },
+            ControlCommand::ListDevices {
+                filter: Some(DeviceFilter {
+                    has_joystick: Some(true),
+                    ..Default::default()
+                }),
+            },
+            ControlCommand::Ping,
+        ] {
+            let json = serde_json::to_string(&command).unwrap();
+            let parsed: ControlCommand = serde_json::from_str(&json).unwrap();
+            assert_eq!(
+                format!("{:?}", parsed),
+                format!("{:?}", command),
+                "{}",
+                json
+            );
+        }
+
+        let error = serde_json::from_str::<ControlCommand>(r#""ListAll""#).unwrap_err();
+        assert!(error.to_string().contains("ListAll"), "{}", error);
+    }
+
+    #[test]
+    fn errors_without_a_code_are_internal() {
+        // As sent by managers from before error codes
+        let json = r#"{"Error":{"message":"Device 3 not found"}}"#;
+        match serde_json::from_str::<ControlResult>(json).unwrap() {
+            ControlResult::Error { code, message } => {
+                assert_eq!(code, ErrorCode::Internal);
+                assert_eq!(message, "Device 3 not found");
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+}
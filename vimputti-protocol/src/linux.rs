@@ -0,0 +1,229 @@
+//! Linux evdev, joystick and uinput ioctl requests
+//!
+//! Request numbers as the kernel headers define them (`linux/input.h`, `linux/joystick.h` and
+//! `linux/uinput.h`), built with the same `_IOC` encoding, for the shim answering them and the
+//! tools sending them. Requests taking a buffer length are functions of it, and their request
+//! numbers (`*_NR`) are given for matching them whatever the length.
+
+use crate::{ABS_MAX, KEY_MAX, LinuxAbsEvent};
+
+pub const IOC_NONE: u32 = 0;
+pub const IOC_WRITE: u32 = 1;
+pub const IOC_READ: u32 = 2;
+
+const IOC_NRBITS: u32 = 8;
+const IOC_TYPEBITS: u32 = 8;
+const IOC_SIZEBITS: u32 = 14;
+const IOC_NRSHIFT: u32 = 0;
+const IOC_TYPESHIFT: u32 = IOC_NRSHIFT + IOC_NRBITS;
+const IOC_SIZESHIFT: u32 = IOC_TYPESHIFT + IOC_TYPEBITS;
+const IOC_DIRSHIFT: u32 = IOC_SIZESHIFT + IOC_SIZEBITS;
+
+/// `_IOC(dir, type, nr, size)`
+pub const fn ioc(dir: u32, ty: u8, nr: u32, size: usize) -> u32 {
+    (dir << IOC_DIRSHIFT)
+        | ((size as u32) << IOC_SIZESHIFT)
+        | ((ty as u32) << IOC_TYPESHIFT)
+        | (nr << IOC_NRSHIFT)
+}
+
+/// `_IO(type, nr)`
+pub const fn io(ty: u8, nr: u32) -> u32 {
+    ioc(IOC_NONE, ty, nr, 0)
+}
+
+/// `_IOR(type, nr, size)`
+pub const fn ior(ty: u8, nr: u32, size: usize) -> u32 {
+    ioc(IOC_READ, ty, nr, size)
+}
+
+/// `_IOW(type, nr, size)`
+pub const fn iow(ty: u8, nr: u32, size: usize) -> u32 {
+    ioc(IOC_WRITE, ty, nr, size)
+}
+
+/// `_IOC_DIR(request)`
+pub const fn ioc_dir(request: u32) -> u32 {
+    request >> IOC_DIRSHIFT
+}
+
+/// `_IOC_TYPE(request)`
+pub const fn ioc_type(request: u32) -> u8 {
+    (request >> IOC_TYPESHIFT) as u8
+}
+
+/// `_IOC_NR(request)`
+pub const fn ioc_nr(request: u32) -> u32 {
+    (request >> IOC_NRSHIFT) & ((1 << IOC_NRBITS) - 1)
+}
+
+/// `_IOC_SIZE(request)`
+pub const fn ioc_size(request: u32) -> usize {
+    ((request >> IOC_SIZESHIFT) & ((1 << IOC_SIZEBITS) - 1)) as usize
+}
+
+/* evdev */
+
+pub const EVDEV_IOC_TYPE: u8 = b'E';
+
+pub const EVIOCGVERSION: u32 = ior(EVDEV_IOC_TYPE, 0x01, 4);
+pub const EVIOCGID: u32 = ior(EVDEV_IOC_TYPE, 0x02, 8);
+/// Size of `struct ff_effect` on 64-bit
+pub const EVIOCSFF: u32 = iow(EVDEV_IOC_TYPE, 0x80, 48);
+pub const EVIOCRMFF: u32 = iow(EVDEV_IOC_TYPE, 0x81, 4);
+pub const EVIOCGEFFECTS: u32 = ior(EVDEV_IOC_TYPE, 0x84, 4);
+pub const EVIOCGRAB: u32 = iow(EVDEV_IOC_TYPE, 0x90, 4);
+pub const EVIOCREVOKE: u32 = iow(EVDEV_IOC_TYPE, 0x91, 4);
+/// Clock of the event timestamps of the fd, e.g. `CLOCK_MONOTONIC`
+pub const EVIOCSCLOCKID: u32 = iow(EVDEV_IOC_TYPE, 0xa0, 4);
+
+pub const EVIOCGNAME_NR: u32 = 0x06;
+pub const EVIOCGPHYS_NR: u32 = 0x07;
+pub const EVIOCGUNIQ_NR: u32 = 0x08;
+pub const EVIOCGPROP_NR: u32 = 0x09;
+pub const EVIOCGKEY_NR: u32 = 0x18;
+pub const EVIOCGLED_NR: u32 = 0x19;
+pub const EVIOCGSND_NR: u32 = 0x1a;
+pub const EVIOCGSW_NR: u32 = 0x1b;
+/// `EVIOCGBIT` numbers are this plus the event type, up to `EVIOCGABS_NR`
+pub const EVIOCGBIT_NR: u32 = 0x20;
+/// `EVIOCGABS` numbers are this plus the axis code, up to `ABS_MAX`
+pub const EVIOCGABS_NR: u32 = 0x40;
+/// `EVIOCSABS` numbers are this plus the axis code, up to `ABS_MAX`
+pub const EVIOCSABS_NR: u32 = 0xc0;
+
+pub const fn eviocgname(len: usize) -> u32 {
+    ior(EVDEV_IOC_TYPE, EVIOCGNAME_NR, len)
+}
+
+pub const fn eviocgprop(len: usize) -> u32 {
+    ior(EVDEV_IOC_TYPE, EVIOCGPROP_NR, len)
+}
+
+pub const fn eviocgbit(ev_type: u16, len: usize) -> u32 {
+    ior(EVDEV_IOC_TYPE, EVIOCGBIT_NR + ev_type as u32, len)
+}
+
+pub const fn eviocgabs(code: u16) -> u32 {
+    ior(
+        EVDEV_IOC_TYPE,
+        EVIOCGABS_NR + code as u32,
+        size_of::<LinuxAbsEvent>(),
+    )
+}
+
+/// Check if an evdev request number is an `EVIOCGABS`
+pub const fn is_eviocgabs_nr(nr: u32) -> bool {
+    nr >= EVIOCGABS_NR && nr <= EVIOCGABS_NR + ABS_MAX as u32
+}
+
+/* joystick */
+
+pub const JS_IOC_TYPE: u8 = b'j';
+
+pub const JSIOCGVERSION: u32 = ior(JS_IOC_TYPE, 0x01, 4);
+pub const JSIOCGAXES: u32 = ior(JS_IOC_TYPE, 0x11, 1);
+pub const JSIOCGBUTTONS: u32 = ior(JS_IOC_TYPE, 0x12, 1);
+pub const JSIOCGNAME_NR: u32 = 0x13;
+pub const JSIOCGAXMAP_NR: u32 = 0x32;
+pub const JSIOCGBTNMAP_NR: u32 = 0x34;
+/// A byte for each of the `ABS_MAX + 1` axes
+pub const JSIOCGAXMAP: u32 = ior(JS_IOC_TYPE, JSIOCGAXMAP_NR, ABS_MAX as usize + 1);
+/// A `u16` for each key from `BTN_MISC` up, older kernels with a lower `KEY_MAX` ask for less
+pub const JSIOCGBTNMAP: u32 = ior(
+    JS_IOC_TYPE,
+    JSIOCGBTNMAP_NR,
+    (KEY_MAX - BTN_MISC + 1) as usize * 2,
+);
+
+pub const fn jsiocgname(len: usize) -> u32 {
+    ior(JS_IOC_TYPE, JSIOCGNAME_NR, len)
+}
+
+/// First key the joystick interface maps to buttons
+pub const BTN_MISC: u16 = 0x100;
+
+// Types of joystick events, `JS_EVENT_INIT` is or-ed in for the initial state
+pub const JS_EVENT_BUTTON: u8 = 0x01;
+pub const JS_EVENT_AXIS: u8 = 0x02;
+pub const JS_EVENT_INIT: u8 = 0x80;
+
+/* uinput */
+
+pub const UINPUT_IOC_TYPE: u8 = b'U';
+
+pub const UI_DEV_CREATE: u32 = io(UINPUT_IOC_TYPE, 1);
+pub const UI_DEV_DESTROY: u32 = io(UINPUT_IOC_TYPE, 2);
+/// Size of `struct uinput_setup`
+pub const UI_DEV_SETUP: u32 = iow(UINPUT_IOC_TYPE, 3, 92);
+/// Size of `struct uinput_abs_setup`
+pub const UI_ABS_SETUP: u32 = iow(UINPUT_IOC_TYPE, 4, 28);
+pub const UI_GET_SYSNAME_NR: u32 = 44;
+pub const UI_GET_VERSION: u32 = ior(UINPUT_IOC_TYPE, 45, 4);
+
+pub const UI_SET_EVBIT: u32 = iow(UINPUT_IOC_TYPE, 100, 4);
+pub const UI_SET_KEYBIT: u32 = iow(UINPUT_IOC_TYPE, 101, 4);
+pub const UI_SET_RELBIT: u32 = iow(UINPUT_IOC_TYPE, 102, 4);
+pub const UI_SET_ABSBIT: u32 = iow(UINPUT_IOC_TYPE, 103, 4);
+pub const UI_SET_MSCBIT: u32 = iow(UINPUT_IOC_TYPE, 104, 4);
+pub const UI_SET_LEDBIT: u32 = iow(UINPUT_IOC_TYPE, 105, 4);
+pub const UI_SET_SNDBIT: u32 = iow(UINPUT_IOC_TYPE, 106, 4);
+pub const UI_SET_FFBIT: u32 = iow(UINPUT_IOC_TYPE, 107, 4);
+/// Takes a `char *`, so its size differs between 32 and 64-bit processes
+pub const UI_SET_PHYS: u32 = iow(UINPUT_IOC_TYPE, 108, size_of::<usize>());
+pub const UI_SET_SWBIT: u32 = iow(UINPUT_IOC_TYPE, 109, 4);
+pub const UI_SET_PROPBIT: u32 = iow(UINPUT_IOC_TYPE, 110, 4);
+
+pub const fn ui_get_sysname(len: usize) -> u32 {
+    ior(UINPUT_IOC_TYPE, UI_GET_SYSNAME_NR, len)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn requests_match_the_kernel_headers() {
+        // As the headers give them on 64-bit
+        assert_eq!(EVIOCGVERSION, 0x80044501);
+        assert_eq!(EVIOCGID, 0x80084502);
+        assert_eq!(EVIOCSFF, 0x40304580);
+        assert_eq!(EVIOCRMFF, 0x40044581);
+        assert_eq!(EVIOCGRAB, 0x40044590);
+        assert_eq!(EVIOCREVOKE, 0x40044591);
+        assert_eq!(EVIOCSCLOCKID, 0x400445a0);
+        assert_eq!(eviocgname(256), 0x81004506);
+        assert_eq!(eviocgbit(0x03, 8), 0x80084523);
+        assert_eq!(eviocgabs(0x28), 0x80184568);
+        assert_eq!(JSIOCGVERSION, 0x80046a01);
+        assert_eq!(JSIOCGAXES, 0x80016a11);
+        assert_eq!(JSIOCGBUTTONS, 0x80016a12);
+        assert_eq!(JSIOCGAXMAP, 0x80406a32);
+        assert_eq!(JSIOCGBTNMAP, 0x84006a34);
+        assert_eq!(jsiocgname(128), 0x80806a13);
+        assert_eq!(UI_DEV_CREATE, 0x5501);
+        assert_eq!(UI_DEV_DESTROY, 0x5502);
+        assert_eq!(UI_DEV_SETUP, 0x405c5503);
+        assert_eq!(UI_ABS_SETUP, 0x401c5504);
+        assert_eq!(UI_SET_EVBIT, 0x40045564);
+        assert_eq!(UI_SET_KEYBIT, 0x40045565);
+        assert_eq!(UI_SET_PHYS, 0x4008556c);
+        assert_eq!(UI_SET_PROPBIT, 0x4004556e);
+        assert_eq!(UI_GET_VERSION, 0x8004552d);
+    }
+
+    #[test]
+    fn requests_decode_into_their_parts() {
+        let request = eviocgabs(0x28);
+        assert_eq!(ioc_dir(request), IOC_READ);
+        assert_eq!(ioc_type(request), EVDEV_IOC_TYPE);
+        assert_eq!(ioc_nr(request), EVIOCGABS_NR + 0x28);
+        assert_eq!(ioc_size(request), 24);
+        assert!(is_eviocgabs_nr(ioc_nr(request)));
+        assert!(!is_eviocgabs_nr(EVIOCSABS_NR));
+
+        assert_eq!(ioc_dir(UI_DEV_CREATE), IOC_NONE);
+        assert_eq!(ioc_nr(JSIOCGBTNMAP), JSIOCGBTNMAP_NR);
+        assert_eq!(ioc_size(ui_get_sysname(64)), 64);
+    }
+}
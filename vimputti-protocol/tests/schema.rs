@@ -0,0 +1,99 @@
+//! The checked-in schemas and the JSON other clients write against them
+//!
+//! Run with `cargo test -p vimputti-protocol --features schema`.
+
+#![cfg(feature = "schema")]
+
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use serde_json::{Value, json};
+use std::path::Path;
+use vimputti_protocol::*;
+
+fn assert_schema_current<T: schemars::JsonSchema>(name: &str) {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("schema")
+        .join(format!("{}.schema.json", name));
+    let checked_in: Value = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+    let generated = serde_json::to_value(schemars::schema_for!(T)).unwrap();
+    assert!(
+        checked_in == generated,
+        "{} is out of date, run the generate_schema example",
+        path.display()
+    );
+}
+
+/// Parse `example`, serialize it again and expect the same JSON back
+fn assert_round_trip<T: Serialize + DeserializeOwned>(example: Value) {
+    let parsed: T = serde_json::from_value(example.clone())
+        .unwrap_or_else(|e| panic!("{} doesn't parse: {}", example, e));
+    assert_eq!(serde_json::to_value(&parsed).unwrap(), example);
+}
+
+fn config() -> Value {
+    serde_json::to_value(DeviceConfig::default()).unwrap()
+}
+
+#[test]
+fn checked_in_schemas_match_the_types() {
+    assert_schema_current::<ControlMessage>("control_message");
+    assert_schema_current::<ControlResponse>("control_response");
+    assert_schema_current::<DeviceHandshake>("device_handshake");
+}
+
+#[test]
+fn command_examples_round_trip() {
+    let commands = [
+        json!({ "CreateDevice": { "config": config() } }),
+        json!({ "CreateDeviceWithId": { "device_id": 3, "config": config() } }),
+        json!({ "EnsureDevice": { "key": "pad", "config": config(), "on_mismatch": "Recreate" } }),
+        json!({ "DestroyDevice": { "device_id": 0 } }),
+        json!({ "SendInput": { "device_id": 0, "events": [
+            { "Button": { "button": "A", "pressed": true } },
+            { "Axis": { "axis": "LeftStickX", "value": -12000 } },
+            { "Raw": { "event_type": 4, "code": 3, "value": 1 } },
+            "Sync",
+        ] } }),
+        json!({ "SendNamed": { "device_id": 0, "inputs": [
+            { "button": "B", "pressed": false },
+            { "axis": "LeftStickX", "value": 12000 },
+        ] } }),
+        json!({ "SetDevicePaused": { "device_id": 0, "paused": true, "mode": "Track" } }),
+        json!({ "InjectFeedback": { "device_id": 0, "event": "RumbleStop" } }),
+        json!({ "SetLatency": { "device_id": 0, "base_ms": 10, "jitter_ms": 2, "seed": 7 } }),
+        json!({ "SetBattery": { "device_id": 0, "capacity": 80, "status": "Discharging" } }),
+        json!({ "Flush": { "device_id": 0 } }),
+        json!({ "ListDevices": { "filter": { "name": null, "vendor_id": 1118, "product_id": null,
+            "has_joystick": true, "button": null, "axis": null } } }),
+        json!({ "SetHook": { "event": "DeviceAdded", "command": ["true"] } }),
+        json!("RebroadcastDevices"),
+        json!("GetManagerInfo"),
+        json!("Ping"),
+    ];
+    for command in commands {
+        assert_round_trip::<ControlMessage>(json!({ "id": "01J", "command": command }));
+    }
+}
+
+#[test]
+fn result_examples_round_trip() {
+    let results = [
+        json!({ "DeviceCreated": { "device_id": 0, "event_node": "event0" } }),
+        json!({ "DeviceEnsured": { "device_id": 1, "event_node": "event1", "created": false } }),
+        json!({ "DeviceClients": { "evdev": 1, "joystick": 0, "feedback": 2 } }),
+        json!({ "JoystickInterfaceSet": { "joystick_node": "js0" } }),
+        json!({ "DeviceList": [{ "device_id": 0, "name": "Pad", "event_node": "event0",
+            "joystick_node": null, "vendor_id": 1118, "product_id": 654 }] }),
+        json!({ "Error": { "code": "NotFound", "message": "Device 9 not found" } }),
+        json!("InputSent"),
+        json!("Pong"),
+    ];
+    for result in results {
+        assert_round_trip::<ControlResponse>(json!({ "id": "01J", "result": result }));
+    }
+}
+
+#[test]
+fn handshake_example_round_trips() {
+    assert_round_trip::<DeviceHandshake>(json!({ "device_id": 0, "config": config() }));
+}
@@ -0,0 +1,216 @@
+//! Discovery of the manager's base path and node prefix
+//!
+//! The base path is asked from the manager at the control socket (`VIMPUTTI_SOCKET`, by default
+//! `/tmp/vimputti-0`, `@name` for abstract sockets) with `GetManagerInfo`, once, and cached for
+//! the life of the process. If the manager can't be reached, `VIMPUTTI_BASE_PATH` is used, and
+//! otherwise the base path the manager derives from the socket path (`/tmp/vimputti` by default).
+//! The node prefix comes along with it, falling back to `VIMPUTTI_NODE_PREFIX` or none.
+//!
+//! The query goes through raw syscalls, as the shim's own `socket()`, `connect()`, `read()` and
+//! `write()` would otherwise intercept it while the base path is still being resolved.
+
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+use std::time::Duration;
+use tracing::{debug, warn};
+use vimputti::protocol::{
+    ControlCommand, ControlMessage, ControlResponse, ControlResult, ManagerInfo,
+    abstract_socket_name, base_path_for_socket,
+};
+
+/// Control socket of the default manager instance
+const DEFAULT_SOCKET: &str = "/tmp/vimputti-0";
+/// How long the manager gets to answer
+const QUERY_TIMEOUT: Duration = Duration::from_millis(500);
+/// Largest manager answer read
+const MAX_RESPONSE_LEN: usize = 64 * 1024;
+
+lazy_static::lazy_static! {
+    static ref MANAGER: (String, String) = discover();
+}
+
+/// Base directory of the manager's device sockets and sysfs
+pub fn base_path() -> &'static str {
+    &MANAGER.0
+}
+
+/// Prefix of the manager's device node names, e.g. `b` for `eventb0`
+pub fn node_prefix() -> &'static str {
+    &MANAGER.1
+}
+
+fn discover() -> (String, String) {
+    let socket_path = std::env::var("VIMPUTTI_SOCKET").unwrap_or_else(|_| DEFAULT_SOCKET.into());
+
+    if let Some(info) = query_manager(Path::new(&socket_path)) {
+        debug!(
+            "Using base path {} and node prefix {:?} of manager at {}",
+            info.base_path, info.node_prefix, socket_path
+        );
+        return (info.base_path, info.node_prefix);
+    }
+
+    let node_prefix = std::env::var("VIMPUTTI_NODE_PREFIX").unwrap_or_default();
+    (fallback_base_path(&socket_path), node_prefix)
+}
+
+fn fallback_base_path(socket_path: &str) -> String {
+    if let Ok(base_path) = std::env::var("VIMPUTTI_BASE_PATH")
+        && !base_path.is_empty()
+    {
+        debug!(
+            "Manager at {} not reachable, using VIMPUTTI_BASE_PATH",
+            socket_path
+        );
+        return base_path;
+    }
+
+    let base_path = base_path_for_socket(Path::new(socket_path))
+        .to_string_lossy()
+        .to_string();
+    warn!(
+        "Manager at {} not reachable, assuming base path {}",
+        socket_path, base_path
+    );
+    base_path
+}
+
+/// Ask the manager for its base path and node prefix
+fn query_manager(socket_path: &Path) -> Option<ManagerInfo> {
+    let socket = RawSocket::connect(socket_path)?;
+
+    let message = ControlMessage {
+        id: "shim-base-path".to_string(),
+        command: ControlCommand::GetManagerInfo,
+    };
+    let mut request = serde_json::to_vec(&message).ok()?;
+    request.push(b'\n');
+    socket.write_all(&request)?;
+
+    let line = socket.read_line()?;
+    match serde_json::from_slice::<ControlResponse>(&line)
+        .ok()?
+        .result
+    {
+        ControlResult::ManagerInfo(info) if !info.base_path.is_empty() => Some(info),
+        other => {
+            debug!("Unexpected answer to GetManagerInfo: {:?}", other);
+            None
+        }
+    }
+}
+
+/// Unix stream socket used through raw syscalls, closed on drop
+struct RawSocket {
+    fd: i32,
+}
+impl RawSocket {
+    fn connect(socket_path: &Path) -> Option<Self> {
+        let mut addr: libc::sockaddr_un = unsafe { std::mem::zeroed() };
+        addr.sun_family = libc::AF_UNIX as libc::sa_family_t;
+
+        // Abstract names start with a NUL byte and aren't NUL terminated
+        let (name, offset) = match abstract_socket_name(socket_path) {
+            Some(name) => (name, 1),
+            None => (socket_path.as_os_str().as_bytes(), 0),
+        };
+        if offset + name.len() >= addr.sun_path.len() {
+            debug!("Socket path {} is too long", socket_path.display());
+            return None;
+        }
+        for (i, &byte) in name.iter().enumerate() {
+            addr.sun_path[offset + i] = byte as libc::c_char;
+        }
+        let addr_len = std::mem::size_of::<libc::sa_family_t>()
+            + offset
+            + name.len()
+            + if offset == 0 { 1 } else { 0 };
+
+        let fd = unsafe {
+            libc::syscall(
+                libc::SYS_socket,
+                libc::AF_UNIX,
+                libc::SOCK_STREAM | libc::SOCK_CLOEXEC,
+                0,
+            )
+        };
+        if fd < 0 {
+            return None;
+        }
+        let socket = Self { fd: fd as i32 };
+
+        let timeout = libc::timeval {
+            tv_sec: QUERY_TIMEOUT.as_secs() as libc::time_t,
+            tv_usec: QUERY_TIMEOUT.subsec_micros() as libc::suseconds_t,
+        };
+        for option in [libc::SO_RCVTIMEO, libc::SO_SNDTIMEO] {
+            unsafe {
+                libc::syscall(
+                    libc::SYS_setsockopt,
+                    socket.fd,
+                    libc::SOL_SOCKET,
+                    option,
+                    &timeout as *const libc::timeval,
+                    std::mem::size_of::<libc::timeval>(),
+                )
+            };
+        }
+
+        let result = unsafe {
+            libc::syscall(
+                libc::SYS_connect,
+                socket.fd,
+                &addr as *const libc::sockaddr_un,
+                addr_len,
+            )
+        };
+        if result < 0 {
+            debug!(
+                "Failed to connect to manager at {}: {}",
+                socket_path.display(),
+                std::io::Error::last_os_error()
+            );
+            return None;
+        }
+
+        Some(socket)
+    }
+
+    fn write_all(&self, mut bytes: &[u8]) -> Option<()> {
+        while !bytes.is_empty() {
+            let written =
+                unsafe { libc::syscall(libc::SYS_write, self.fd, bytes.as_ptr(), bytes.len()) };
+            if written <= 0 {
+                return None;
+            }
+            bytes = &bytes[written as usize..];
+        }
+        Some(())
+    }
+
+    /// Read up to the first newline, without it
+    fn read_line(&self) -> Option<Vec<u8>> {
+        let mut line = Vec::new();
+        let mut buf = [0u8; 4096];
+        loop {
+            let read =
+                unsafe { libc::syscall(libc::SYS_read, self.fd, buf.as_mut_ptr(), buf.len()) };
+            if read <= 0 {
+                return None;
+            }
+            line.extend_from_slice(&buf[..read as usize]);
+            if let Some(end) = line.iter().position(|&b| b == b'\n') {
+                line.truncate(end);
+                return Some(line);
+            }
+            if line.len() > MAX_RESPONSE_LEN {
+                return None;
+            }
+        }
+    }
+}
+impl Drop for RawSocket {
+    fn drop(&mut self) {
+        unsafe { libc::syscall(libc::SYS_close, self.fd) };
+    }
+}
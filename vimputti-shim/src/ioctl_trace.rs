@@ -0,0 +1,212 @@
+//! Readable trace of the ioctls answered by the shim
+//!
+//! Enabled with `VIMPUTTI_TRACE_IOCTL=1`, which logs each evdev, joystick and uinput ioctl
+//! under `TRACE_TARGET` with its symbolic name (e.g. `EVIOCGBIT(EV_KEY, 96)`), the result
+//! and the bytes written back to the caller. Otherwise nothing is decoded.
+
+use libc::{c_int, c_void};
+use tracing::trace;
+use vimputti::protocol::linux::*;
+
+/// Tracing target of the ioctl trace
+pub const TRACE_TARGET: &str = "vimputti::ioctl";
+
+/// Largest number of written bytes included in a trace line
+const MAX_TRACED_BYTES: usize = 256;
+
+lazy_static::lazy_static! {
+    static ref ENABLED: bool = std::env::var("VIMPUTTI_TRACE_IOCTL")
+        .is_ok_and(|v| !v.is_empty() && v != "0");
+}
+
+/// Check if ioctl tracing was asked for
+pub fn enabled() -> bool {
+    *ENABLED
+}
+
+/// Log an answered ioctl, along with what was written to its argument
+///
+/// # Safety
+/// For ioctls reading from the device, `arg` must be valid for the size encoded in `request`.
+pub unsafe fn trace_ioctl(fd: c_int, request: u32, arg: *mut c_void, result: c_int) {
+    let size = ioc_size(request);
+    let written = if ioc_dir(request) & IOC_READ != 0 && result >= 0 && !arg.is_null() && size > 0 {
+        let bytes = unsafe { std::slice::from_raw_parts(arg as *const u8, size) };
+        Some(hex(&bytes[..size.min(MAX_TRACED_BYTES)]))
+    } else {
+        None
+    };
+
+    match written {
+        Some(written) => trace!(
+            target: TRACE_TARGET,
+            "fd={} {} -> {} wrote [{}]",
+            fd,
+            ioctl_name(request),
+            result,
+            written
+        ),
+        None => trace!(
+            target: TRACE_TARGET,
+            "fd={} {} arg={:p} -> {}",
+            fd,
+            ioctl_name(request),
+            arg,
+            result
+        ),
+    }
+}
+
+/// Symbolic name of an evdev, joystick or uinput ioctl request
+///
+/// Requests that aren't known are shown with their decoded fields.
+pub fn ioctl_name(request: u32) -> String {
+    let nr = ioc_nr(request);
+    let size = ioc_size(request);
+
+    match ioc_type(request) {
+        EVDEV_IOC_TYPE => match nr {
+            0x01 => "EVIOCGVERSION".to_string(),
+            0x02 => "EVIOCGID".to_string(),
+            0x03 if ioc_dir(request) & IOC_READ != 0 => "EVIOCGREP".to_string(),
+            0x03 => "EVIOCSREP".to_string(),
+            0x04 if ioc_dir(request) & IOC_READ != 0 => "EVIOCGKEYCODE".to_string(),
+            0x04 => "EVIOCSKEYCODE".to_string(),
+            EVIOCGNAME_NR => format!("EVIOCGNAME({})", size),
+            EVIOCGPHYS_NR => format!("EVIOCGPHYS({})", size),
+            EVIOCGUNIQ_NR => format!("EVIOCGUNIQ({})", size),
+            EVIOCGPROP_NR => format!("EVIOCGPROP({})", size),
+            0x0a => format!("EVIOCGMTSLOTS({})", size),
+            EVIOCGKEY_NR => format!("EVIOCGKEY({})", size),
+            EVIOCGLED_NR => format!("EVIOCGLED({})", size),
+            EVIOCGSND_NR => format!("EVIOCGSND({})", size),
+            EVIOCGSW_NR => format!("EVIOCGSW({})", size),
+            EVIOCGBIT_NR..EVIOCGABS_NR => {
+                format!("EVIOCGBIT({}, {})", ev_type_name(nr - EVIOCGBIT_NR), size)
+            }
+            _ if is_eviocgabs_nr(nr) => format!("EVIOCGABS({})", abs_name(nr - EVIOCGABS_NR)),
+            0x80 => "EVIOCSFF".to_string(),
+            0x81 => "EVIOCRMFF".to_string(),
+            0x84 => "EVIOCGEFFECTS".to_string(),
+            0x90 => "EVIOCGRAB".to_string(),
+            0x91 => "EVIOCREVOKE".to_string(),
+            0x92 => "EVIOCGMASK".to_string(),
+            0x93 => "EVIOCSMASK".to_string(),
+            0xa0 => "EVIOCSCLOCKID".to_string(),
+            EVIOCSABS_NR..=0xff => format!("EVIOCSABS({})", abs_name(nr - EVIOCSABS_NR)),
+            _ => unknown(request),
+        },
+        JS_IOC_TYPE => match nr {
+            0x01 => "JSIOCGVERSION".to_string(),
+            0x11 => "JSIOCGAXES".to_string(),
+            0x12 => "JSIOCGBUTTONS".to_string(),
+            JSIOCGNAME_NR => format!("JSIOCGNAME({})", size),
+            0x21 => "JSIOCSCORR".to_string(),
+            0x22 => "JSIOCGCORR".to_string(),
+            0x31 => "JSIOCSAXMAP".to_string(),
+            JSIOCGAXMAP_NR => "JSIOCGAXMAP".to_string(),
+            0x33 => "JSIOCSBTNMAP".to_string(),
+            JSIOCGBTNMAP_NR => "JSIOCGBTNMAP".to_string(),
+            _ => unknown(request),
+        },
+        UINPUT_IOC_TYPE => match nr {
+            0x01 => "UI_DEV_CREATE".to_string(),
+            0x02 => "UI_DEV_DESTROY".to_string(),
+            0x03 => "UI_DEV_SETUP".to_string(),
+            0x04 => "UI_ABS_SETUP".to_string(),
+            UI_GET_SYSNAME_NR => format!("UI_GET_SYSNAME({})", size),
+            0x2d => "UI_GET_VERSION".to_string(),
+            0x64 => "UI_SET_EVBIT".to_string(),
+            0x65 => "UI_SET_KEYBIT".to_string(),
+            0x66 => "UI_SET_RELBIT".to_string(),
+            0x67 => "UI_SET_ABSBIT".to_string(),
+            0x68 => "UI_SET_MSCBIT".to_string(),
+            0x69 => "UI_SET_LEDBIT".to_string(),
+            0x6a => "UI_SET_SNDBIT".to_string(),
+            0x6b => "UI_SET_FFBIT".to_string(),
+            0x6c => "UI_SET_PHYS".to_string(),
+            0x6d => "UI_SET_SWBIT".to_string(),
+            0x6e => "UI_SET_PROPBIT".to_string(),
+            0xc8 => "UI_BEGIN_FF_UPLOAD".to_string(),
+            0xc9 => "UI_END_FF_UPLOAD".to_string(),
+            0xca => "UI_BEGIN_FF_ERASE".to_string(),
+            0xcb => "UI_END_FF_ERASE".to_string(),
+            _ => unknown(request),
+        },
+        _ => unknown(request),
+    }
+}
+
+fn unknown(request: u32) -> String {
+    format!(
+        "0x{:08x} (dir={}, type=0x{:02x}, nr=0x{:02x}, size={})",
+        request,
+        ioc_dir(request),
+        ioc_type(request),
+        ioc_nr(request),
+        ioc_size(request)
+    )
+}
+
+fn ev_type_name(ev_type: u32) -> String {
+    match ev_type {
+        0x00 => "0".to_string(),
+        0x01 => "EV_KEY".to_string(),
+        0x02 => "EV_REL".to_string(),
+        0x03 => "EV_ABS".to_string(),
+        0x04 => "EV_MSC".to_string(),
+        0x05 => "EV_SW".to_string(),
+        0x11 => "EV_LED".to_string(),
+        0x12 => "EV_SND".to_string(),
+        0x14 => "EV_REP".to_string(),
+        0x15 => "EV_FF".to_string(),
+        0x16 => "EV_PWR".to_string(),
+        0x17 => "EV_FF_STATUS".to_string(),
+        _ => format!("0x{:02x}", ev_type),
+    }
+}
+
+fn abs_name(code: u32) -> String {
+    let name = match code {
+        0x00 => "ABS_X",
+        0x01 => "ABS_Y",
+        0x02 => "ABS_Z",
+        0x03 => "ABS_RX",
+        0x04 => "ABS_RY",
+        0x05 => "ABS_RZ",
+        0x06 => "ABS_THROTTLE",
+        0x07 => "ABS_RUDDER",
+        0x08 => "ABS_WHEEL",
+        0x09 => "ABS_GAS",
+        0x0a => "ABS_BRAKE",
+        0x10 => "ABS_HAT0X",
+        0x11 => "ABS_HAT0Y",
+        0x12 => "ABS_HAT1X",
+        0x13 => "ABS_HAT1Y",
+        0x14 => "ABS_HAT2X",
+        0x15 => "ABS_HAT2Y",
+        0x16 => "ABS_HAT3X",
+        0x17 => "ABS_HAT3Y",
+        0x18 => "ABS_PRESSURE",
+        0x19 => "ABS_DISTANCE",
+        0x1a => "ABS_TILT_X",
+        0x1b => "ABS_TILT_Y",
+        0x1c => "ABS_TOOL_WIDTH",
+        0x20 => "ABS_VOLUME",
+        0x28 => "ABS_MISC",
+        0x2f => "ABS_MT_SLOT",
+        0x35 => "ABS_MT_POSITION_X",
+        0x36 => "ABS_MT_POSITION_Y",
+        0x39 => "ABS_MT_TRACKING_ID",
+        _ => return format!("ABS_0x{:02x}", code),
+    };
+    name.to_string()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
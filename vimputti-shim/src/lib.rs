@@ -6,7 +6,10 @@ use std::ffi::{CStr, CString};
 use std::os::raw::c_long;
 use std::path::PathBuf;
 use tracing::debug;
+use vimputti::protocol::node_device_id;
 
+mod base_path;
+mod ioctl_trace;
 mod libudev;
 mod path_redirect;
 mod syscalls;
@@ -58,6 +61,7 @@ struct OriginalFunctions {
     read: Option<unsafe extern "C" fn(c_int, *mut c_void, libc::size_t) -> libc::ssize_t>,
     write: Option<unsafe extern "C" fn(c_int, *const c_void, libc::size_t) -> libc::ssize_t>,
     poll: Option<unsafe extern "C" fn(*mut libc::pollfd, libc::nfds_t, c_int) -> c_int>,
+    epoll_ctl: Option<unsafe extern "C" fn(c_int, c_int, c_int, *mut libc::epoll_event) -> c_int>,
     epoll_wait: Option<unsafe extern "C" fn(c_int, *mut libc::epoll_event, c_int, c_int) -> c_int>,
     epoll_pwait: Option<
         unsafe extern "C" fn(
@@ -78,6 +82,14 @@ struct OriginalFunctions {
     fstat64: Option<unsafe extern "C" fn(c_int, *mut libc::stat64) -> c_int>,
     fxstat: Option<unsafe extern "C" fn(c_int, c_int, *mut libc::stat) -> c_int>,
     fxstat64: Option<unsafe extern "C" fn(c_int, c_int, *mut libc::stat64) -> c_int>,
+    fstatat: Option<unsafe extern "C" fn(c_int, *const c_char, *mut libc::stat, c_int) -> c_int>,
+    fstatat64:
+        Option<unsafe extern "C" fn(c_int, *const c_char, *mut libc::stat64, c_int) -> c_int>,
+    fxstatat:
+        Option<unsafe extern "C" fn(c_int, c_int, *const c_char, *mut libc::stat, c_int) -> c_int>,
+    fxstatat64: Option<
+        unsafe extern "C" fn(c_int, c_int, *const c_char, *mut libc::stat64, c_int) -> c_int,
+    >,
 }
 impl OriginalFunctions {
     fn new() -> Self {
@@ -110,6 +122,7 @@ impl OriginalFunctions {
                 read: Self::get_original("read"),
                 write: Self::get_original("write"),
                 poll: Self::get_original("poll"),
+                epoll_ctl: Self::get_original("epoll_ctl"),
                 epoll_wait: Self::get_original("epoll_wait"),
                 epoll_pwait: Self::get_original("epoll_pwait"),
                 inotify_init: Self::get_original("inotify_init"),
@@ -122,6 +135,10 @@ impl OriginalFunctions {
                 fstat64: Self::get_original("fstat64"),
                 fxstat: Self::get_original("__fxstat"),
                 fxstat64: Self::get_original("__fxstat64"),
+                fstatat: Self::get_original("fstatat"),
+                fstatat64: Self::get_original("fstatat64"),
+                fxstatat: Self::get_original("__fxstatat"),
+                fxstatat64: Self::get_original("__fxstatat64"),
             }
         }
     }
@@ -141,24 +158,39 @@ impl OriginalFunctions {
 #[ctor::ctor]
 fn init_shim() {
     // Initialize tracing
-    tracing_subscriber::fmt()
-        .with_env_filter(
-            tracing_subscriber::EnvFilter::try_from_default_env()
-                .unwrap_or_else(|_| tracing_subscriber::EnvFilter::new("warn")),
-        )
-        .init();
+    let mut filter = tracing_subscriber::EnvFilter::try_from_default_env()
+        .unwrap_or_else(|_| tracing_subscriber::EnvFilter::new("warn"));
+    if ioctl_trace::enabled()
+        && let Ok(directive) = format!("{}=trace", ioctl_trace::TRACE_TARGET).parse()
+    {
+        filter = filter.add_directive(directive);
+    }
+    tracing_subscriber::fmt().with_env_filter(filter).init();
 }
 
 // =============================================================================
 // Intercepted functions
 // =============================================================================
 
+/// Read the optional mode argument of the open family
+///
+/// The mode is only passed along with O_CREAT or O_TMPFILE, reading it otherwise
+/// would read past the actual arguments.
+unsafe fn open_mode(flags: c_int, args: &mut std::ffi::VaList) -> c_uint {
+    if flags & libc::O_CREAT != 0 || flags & libc::O_TMPFILE == libc::O_TMPFILE {
+        unsafe { args.next_arg() }
+    } else {
+        0
+    }
+}
+
 /// Intercept open() - redirect paths and handle device nodes
 #[unsafe(no_mangle)]
 pub unsafe extern "C" fn open(pathname: *const c_char, flags: c_int, mut args: ...) -> c_int {
     if pathname.is_null() {
         if let Some(orig_open) = ORIGINAL_FUNCTIONS.open {
-            return unsafe { orig_open(pathname, flags, args) };
+            let mode = unsafe { open_mode(flags, &mut args) };
+            return unsafe { orig_open(pathname, flags, mode) };
         }
         return -1;
     }
@@ -167,7 +199,7 @@ pub unsafe extern "C" fn open(pathname: *const c_char, flags: c_int, mut args: .
         Ok(s) => s,
         Err(_) => {
             // Invalid UTF-8, pass through
-            let mode: c_uint = unsafe { args.arg() };
+            let mode = unsafe { open_mode(flags, &mut args) };
             if let Some(orig_open) = ORIGINAL_FUNCTIONS.open {
                 return unsafe { orig_open(pathname, flags, mode) };
             }
@@ -189,7 +221,7 @@ pub unsafe extern "C" fn open(pathname: *const c_char, flags: c_int, mut args: .
 
         // Regular file redirection
         let new_path = CString::new(redirected).unwrap();
-        let mode: c_uint = unsafe { args.arg() };
+        let mode = unsafe { open_mode(flags, &mut args) };
         if let Some(orig_open) = ORIGINAL_FUNCTIONS.open {
             return unsafe { orig_open(new_path.as_ptr(), flags, mode) };
         }
@@ -197,7 +229,7 @@ pub unsafe extern "C" fn open(pathname: *const c_char, flags: c_int, mut args: .
     }
 
     // Pass through to original open
-    let mode: c_uint = unsafe { args.arg() };
+    let mode = unsafe { open_mode(flags, &mut args) };
     if let Some(orig_open) = ORIGINAL_FUNCTIONS.open {
         return unsafe { orig_open(pathname, flags, mode) };
     }
@@ -209,7 +241,8 @@ pub unsafe extern "C" fn open(pathname: *const c_char, flags: c_int, mut args: .
 pub unsafe extern "C" fn open64(pathname: *const c_char, flags: c_int, mut args: ...) -> c_int {
     if pathname.is_null() {
         if let Some(orig_open64) = ORIGINAL_FUNCTIONS.open64 {
-            return unsafe { orig_open64(pathname, flags, args) };
+            let mode = unsafe { open_mode(flags, &mut args) };
+            return unsafe { orig_open64(pathname, flags, mode) };
         }
         return -1;
     }
@@ -217,7 +250,7 @@ pub unsafe extern "C" fn open64(pathname: *const c_char, flags: c_int, mut args:
     let path_str = match unsafe { CStr::from_ptr(pathname).to_str() } {
         Ok(s) => s,
         Err(_) => {
-            let mode: c_uint = unsafe { args.arg() };
+            let mode = unsafe { open_mode(flags, &mut args) };
             if let Some(orig_open64) = ORIGINAL_FUNCTIONS.open64 {
                 return unsafe { orig_open64(pathname, flags, mode) };
             }
@@ -236,14 +269,14 @@ pub unsafe extern "C" fn open64(pathname: *const c_char, flags: c_int, mut args:
         }
 
         let new_path = CString::new(redirected).unwrap();
-        let mode: c_uint = unsafe { args.arg() };
+        let mode = unsafe { open_mode(flags, &mut args) };
         if let Some(orig_open64) = ORIGINAL_FUNCTIONS.open64 {
             return unsafe { orig_open64(new_path.as_ptr(), flags, mode) };
         }
         return -1;
     }
 
-    let mode: c_uint = unsafe { args.arg() };
+    let mode = unsafe { open_mode(flags, &mut args) };
     if let Some(orig_open64) = ORIGINAL_FUNCTIONS.open64 {
         return unsafe { orig_open64(pathname, flags, mode) };
     }
@@ -260,7 +293,8 @@ pub unsafe extern "C" fn openat(
 ) -> c_int {
     if pathname.is_null() {
         if let Some(orig_openat) = ORIGINAL_FUNCTIONS.openat {
-            return unsafe { orig_openat(dirfd, pathname, flags, args) };
+            let mode = unsafe { open_mode(flags, &mut args) };
+            return unsafe { orig_openat(dirfd, pathname, flags, mode) };
         }
         return -1;
     }
@@ -268,7 +302,7 @@ pub unsafe extern "C" fn openat(
     let path_str = match unsafe { CStr::from_ptr(pathname).to_str() } {
         Ok(s) => s,
         Err(_) => {
-            let mode: c_uint = unsafe { args.arg() };
+            let mode = unsafe { open_mode(flags, &mut args) };
             if let Some(orig_openat) = ORIGINAL_FUNCTIONS.openat {
                 return unsafe { orig_openat(dirfd, pathname, flags, mode) };
             }
@@ -289,7 +323,7 @@ pub unsafe extern "C" fn openat(
             }
 
             let new_path = CString::new(redirected).unwrap();
-            let mode: c_uint = unsafe { args.arg() };
+            let mode = unsafe { open_mode(flags, &mut args) };
             if let Some(orig_openat) = ORIGINAL_FUNCTIONS.openat {
                 return unsafe { orig_openat(dirfd, new_path.as_ptr(), flags, mode) };
             }
@@ -297,7 +331,7 @@ pub unsafe extern "C" fn openat(
         }
     }
 
-    let mode: c_uint = unsafe { args.arg() };
+    let mode = unsafe { open_mode(flags, &mut args) };
     if let Some(orig_openat) = ORIGINAL_FUNCTIONS.openat {
         return unsafe { orig_openat(dirfd, pathname, flags, mode) };
     }
@@ -314,7 +348,8 @@ pub unsafe extern "C" fn openat64(
 ) -> c_int {
     if pathname.is_null() {
         if let Some(orig_openat64) = ORIGINAL_FUNCTIONS.openat64 {
-            return unsafe { orig_openat64(dirfd, pathname, flags) };
+            let mode = unsafe { open_mode(flags, &mut args) };
+            return unsafe { orig_openat64(dirfd, pathname, flags, mode) };
         }
         return -1;
     }
@@ -322,7 +357,7 @@ pub unsafe extern "C" fn openat64(
     let path_str = match unsafe { CStr::from_ptr(pathname).to_str() } {
         Ok(s) => s,
         Err(_) => {
-            let mode: c_uint = unsafe { args.arg() };
+            let mode = unsafe { open_mode(flags, &mut args) };
             if let Some(orig_openat64) = ORIGINAL_FUNCTIONS.openat64 {
                 return unsafe { orig_openat64(dirfd, pathname, flags, mode) };
             }
@@ -342,7 +377,7 @@ pub unsafe extern "C" fn openat64(
             }
 
             let new_path = CString::new(redirected).unwrap();
-            let mode: c_uint = unsafe { args.arg() };
+            let mode = unsafe { open_mode(flags, &mut args) };
             if let Some(orig_openat64) = ORIGINAL_FUNCTIONS.openat64 {
                 return unsafe { orig_openat64(dirfd, new_path.as_ptr(), flags, mode) };
             }
@@ -350,7 +385,7 @@ pub unsafe extern "C" fn openat64(
         }
     }
 
-    let mode: c_uint = unsafe { args.arg() };
+    let mode = unsafe { open_mode(flags, &mut args) };
     if let Some(orig_openat64) = ORIGINAL_FUNCTIONS.openat64 {
         return unsafe { orig_openat64(dirfd, pathname, flags, mode) };
     }
@@ -417,36 +452,7 @@ pub unsafe extern "C" fn stat(pathname: *const c_char, statbuf: *mut libc::stat)
         let new_path = CString::new(redirected).unwrap();
         if let Some(orig_stat) = ORIGINAL_FUNCTIONS.stat {
             let result = unsafe { orig_stat(new_path.as_ptr(), statbuf) };
-
-            // Fake the DEVICE NUMBER for input devices - SDL you sonovabitch
-            if path_str.starts_with("/dev/input/event") {
-                if result == 0 && !statbuf.is_null() {
-                    let event_num: u64 = path_str
-                        .trim_start_matches("/dev/input/event")
-                        .parse()
-                        .unwrap_or(0);
-
-                    tracing::warn!("STAT WITH event_num: {}", event_num);
-                    unsafe {
-                        (*statbuf).st_rdev = libc::makedev(13, 64 + event_num as u32);
-                        (*statbuf).st_mode = ((*statbuf).st_mode & !libc::S_IFMT) | libc::S_IFCHR;
-                    }
-                }
-            } else if path_str.starts_with("/dev/input/js") {
-                if result == 0 && !statbuf.is_null() {
-                    let js_num: u64 = path_str
-                        .trim_start_matches("/dev/input/js")
-                        .parse()
-                        .unwrap_or(0);
-
-                    unsafe {
-                        (*statbuf).st_rdev = libc::makedev(81, js_num as u32);
-                        (*statbuf).st_mode = ((*statbuf).st_mode & !libc::S_IFMT) | libc::S_IFCHR;
-                    }
-                }
-            }
-
-            return result;
+            return unsafe { fake_node_stat("stat", result, path_str, statbuf) };
         }
         return -1;
     }
@@ -482,35 +488,7 @@ pub unsafe extern "C" fn lstat(pathname: *const c_char, statbuf: *mut libc::stat
         let new_path = CString::new(redirected).unwrap();
         if let Some(orig_lstat) = ORIGINAL_FUNCTIONS.lstat {
             let result = unsafe { orig_lstat(new_path.as_ptr(), statbuf) };
-
-            // Fake the DEVICE NUMBER for input devices - SDL you sonovabitch
-            if path_str.starts_with("/dev/input/event") {
-                if result == 0 && !statbuf.is_null() {
-                    let event_num: u64 = path_str
-                        .trim_start_matches("/dev/input/event")
-                        .parse()
-                        .unwrap_or(0);
-
-                    unsafe {
-                        (*statbuf).st_rdev = libc::makedev(13, 64 + event_num as u32);
-                        (*statbuf).st_mode = ((*statbuf).st_mode & !libc::S_IFMT) | libc::S_IFCHR;
-                    }
-                }
-            } else if path_str.starts_with("/dev/input/js") {
-                if result == 0 && !statbuf.is_null() {
-                    let js_num: u64 = path_str
-                        .trim_start_matches("/dev/input/js")
-                        .parse()
-                        .unwrap_or(0);
-
-                    unsafe {
-                        (*statbuf).st_rdev = libc::makedev(81, js_num as u32);
-                        (*statbuf).st_mode = ((*statbuf).st_mode & !libc::S_IFMT) | libc::S_IFCHR;
-                    }
-                }
-            }
-
-            return result;
+            return unsafe { fake_node_stat("lstat", result, path_str, statbuf) };
         }
         return -1;
     }
@@ -563,6 +541,11 @@ pub unsafe extern "C" fn readlink(
 /// Intercept read() - handle device reads
 #[unsafe(no_mangle)]
 pub unsafe extern "C" fn read(fd: c_int, buf: *mut c_void, count: libc::size_t) -> libc::ssize_t {
+    if syscalls::is_revoked_fd(fd) {
+        unsafe { *libc::__errno_location() = libc::ENODEV };
+        return -1;
+    }
+
     // Check if this is a uinput emulator FD
     if syscalls::is_uinput_fd(fd) {
         // Return EAGAIN (would block)
@@ -574,7 +557,22 @@ pub unsafe extern "C" fn read(fd: c_int, buf: *mut c_void, count: libc::size_t)
     }
 
     if let Some(orig_read) = ORIGINAL_FUNCTIONS.read {
-        return unsafe { orig_read(fd, buf, count) };
+        let result = unsafe { orig_read(fd, buf, count) };
+
+        // A virtual device reading EOF or a reset means the manager went away
+        if syscalls::is_virtual_device_fd(fd)
+            && count > 0
+            && (result == 0
+                || (result < 0
+                    && matches!(
+                        unsafe { *libc::__errno_location() },
+                        libc::ECONNRESET | libc::ENOTCONN
+                    )))
+            && syscalls::reconnect_virtual_device(fd)
+        {
+            return unsafe { orig_read(fd, buf, count) };
+        }
+        return result;
     }
     -1
 }
@@ -586,6 +584,11 @@ pub unsafe extern "C" fn write(
     buf: *const c_void,
     count: libc::size_t,
 ) -> libc::ssize_t {
+    if syscalls::is_revoked_fd(fd) {
+        unsafe { *libc::__errno_location() = libc::ENODEV };
+        return -1;
+    }
+
     // Check if this is a uinput emulator FD
     if syscalls::is_uinput_fd(fd) {
         return unsafe { syscalls::handle_uinput_write(fd, buf, count) };
@@ -605,19 +608,32 @@ pub unsafe extern "C" fn write(
 /// Intercept ioctl() - handle device capability queries
 #[unsafe(no_mangle)]
 pub unsafe extern "C" fn ioctl(fd: c_int, request: c_long, mut args: ...) -> c_int {
-    // Check if this is a uinput emulator FD
-    if syscalls::is_uinput_fd(fd) {
-        return unsafe { syscalls::handle_uinput_ioctl(fd, request as u32, &mut args) };
+    if syscalls::is_revoked_fd(fd) {
+        unsafe { *libc::__errno_location() = libc::ENODEV };
+        return -1;
     }
 
-    // Check if this is one of our virtual device FDs
-    if syscalls::is_virtual_device_fd(fd) {
-        return unsafe { syscalls::handle_ioctl(fd, request as u32, &mut args) };
+    let is_uinput = syscalls::is_uinput_fd(fd);
+    if is_uinput || syscalls::lookup_virtual_device_fd(fd) {
+        // Peek at the argument on a copy, the handlers consume the original
+        let traced_arg: Option<*mut c_void> =
+            ioctl_trace::enabled().then(|| unsafe { args.clone().next_arg() });
+
+        let result = if is_uinput {
+            unsafe { syscalls::handle_uinput_ioctl(fd, request as u32, &mut args) }
+        } else {
+            unsafe { syscalls::handle_ioctl(fd, request as u32, &mut args) }
+        };
+
+        if let Some(arg) = traced_arg {
+            unsafe { ioctl_trace::trace_ioctl(fd, request as u32, arg, result) };
+        }
+        return result;
     }
 
     // Pass through to original ioctl
     if let Some(orig_ioctl) = ORIGINAL_FUNCTIONS.ioctl {
-        let arg: *mut c_void = unsafe { args.arg() };
+        let arg: *mut c_void = unsafe { args.next_arg() };
         return unsafe { orig_ioctl(fd, request, arg) };
     }
     -1
@@ -657,6 +673,7 @@ pub unsafe extern "C" fn close(fd: c_int) -> c_int {
     if syscalls::is_virtual_device_fd(fd) {
         syscalls::close_virtual_device(fd);
     }
+    syscalls::forget_epoll_fd(fd);
 
     // Call the real close
     if let Some(orig_close) = ORIGINAL_FUNCTIONS.close {
@@ -929,35 +946,7 @@ pub unsafe extern "C" fn stat64(pathname: *const c_char, statbuf: *mut libc::sta
         let new_path = CString::new(redirected).unwrap();
         if let Some(orig_stat64) = ORIGINAL_FUNCTIONS.stat64 {
             let result = unsafe { orig_stat64(new_path.as_ptr(), statbuf) };
-
-            // Fake the DEVICE NUMBER for input devices - SDL you sonovabitch
-            if path_str.starts_with("/dev/input/event") {
-                if result == 0 && !statbuf.is_null() {
-                    let event_num: u64 = path_str
-                        .trim_start_matches("/dev/input/event")
-                        .parse()
-                        .unwrap_or(0);
-
-                    unsafe {
-                        (*statbuf).st_rdev = libc::makedev(13, 64 + event_num as u32);
-                        (*statbuf).st_mode = ((*statbuf).st_mode & !libc::S_IFMT) | libc::S_IFCHR;
-                    }
-                }
-            } else if path_str.starts_with("/dev/input/js") {
-                if result == 0 && !statbuf.is_null() {
-                    let js_num: u64 = path_str
-                        .trim_start_matches("/dev/input/js")
-                        .parse()
-                        .unwrap_or(0);
-
-                    unsafe {
-                        (*statbuf).st_rdev = libc::makedev(81, js_num as u32);
-                        (*statbuf).st_mode = ((*statbuf).st_mode & !libc::S_IFMT) | libc::S_IFCHR;
-                    }
-                }
-            }
-
-            return result;
+            return unsafe { fake_node_stat("stat64", result, path_str, statbuf) };
         }
         return -1;
     }
@@ -993,35 +982,7 @@ pub unsafe extern "C" fn lstat64(pathname: *const c_char, statbuf: *mut libc::st
         let new_path = CString::new(redirected).unwrap();
         if let Some(orig_lstat64) = ORIGINAL_FUNCTIONS.lstat64 {
             let result = unsafe { orig_lstat64(new_path.as_ptr(), statbuf) };
-
-            // Fake the DEVICE NUMBER for input devices - SDL you sonovabitch
-            if path_str.starts_with("/dev/input/event") {
-                if result == 0 && !statbuf.is_null() {
-                    let event_num: u64 = path_str
-                        .trim_start_matches("/dev/input/event")
-                        .parse()
-                        .unwrap_or(0);
-
-                    unsafe {
-                        (*statbuf).st_rdev = libc::makedev(13, 64 + event_num as u32);
-                        (*statbuf).st_mode = ((*statbuf).st_mode & !libc::S_IFMT) | libc::S_IFCHR;
-                    }
-                }
-            } else if path_str.starts_with("/dev/input/js") {
-                if result == 0 && !statbuf.is_null() {
-                    let js_num: u64 = path_str
-                        .trim_start_matches("/dev/input/js")
-                        .parse()
-                        .unwrap_or(0);
-
-                    unsafe {
-                        (*statbuf).st_rdev = libc::makedev(81, js_num as u32);
-                        (*statbuf).st_mode = ((*statbuf).st_mode & !libc::S_IFMT) | libc::S_IFCHR;
-                    }
-                }
-            }
-
-            return result;
+            return unsafe { fake_node_stat("lstat64", result, path_str, statbuf) };
         }
         return -1;
     }
@@ -1061,35 +1022,7 @@ pub unsafe extern "C" fn __xstat(
         let new_path = CString::new(redirected).unwrap();
         if let Some(orig_xstat) = ORIGINAL_FUNCTIONS.xstat {
             let result = unsafe { orig_xstat(ver, new_path.as_ptr(), statbuf) };
-
-            // Fake the DEVICE NUMBER for input devices - SDL you sonovabitch
-            if path_str.starts_with("/dev/input/event") {
-                if result == 0 && !statbuf.is_null() {
-                    let event_num: u64 = path_str
-                        .trim_start_matches("/dev/input/event")
-                        .parse()
-                        .unwrap_or(0);
-
-                    unsafe {
-                        (*statbuf).st_rdev = libc::makedev(13, 64 + event_num as u32);
-                        (*statbuf).st_mode = ((*statbuf).st_mode & !libc::S_IFMT) | libc::S_IFCHR;
-                    }
-                }
-            } else if path_str.starts_with("/dev/input/js") {
-                if result == 0 && !statbuf.is_null() {
-                    let js_num: u64 = path_str
-                        .trim_start_matches("/dev/input/js")
-                        .parse()
-                        .unwrap_or(0);
-
-                    unsafe {
-                        (*statbuf).st_rdev = libc::makedev(81, js_num as u32);
-                        (*statbuf).st_mode = ((*statbuf).st_mode & !libc::S_IFMT) | libc::S_IFCHR;
-                    }
-                }
-            }
-
-            return result;
+            return unsafe { fake_node_stat("__xstat", result, path_str, statbuf) };
         }
         return -1;
     }
@@ -1129,35 +1062,7 @@ pub unsafe extern "C" fn __xstat64(
         let new_path = CString::new(redirected).unwrap();
         if let Some(orig_xstat64) = ORIGINAL_FUNCTIONS.xstat64 {
             let result = unsafe { orig_xstat64(ver, new_path.as_ptr(), statbuf) };
-
-            // Fake the DEVICE NUMBER for input devices - SDL you sonovabitch
-            if path_str.starts_with("/dev/input/event") {
-                if result == 0 && !statbuf.is_null() {
-                    let event_num: u64 = path_str
-                        .trim_start_matches("/dev/input/event")
-                        .parse()
-                        .unwrap_or(0);
-
-                    unsafe {
-                        (*statbuf).st_rdev = libc::makedev(13, 64 + event_num as u32);
-                        (*statbuf).st_mode = ((*statbuf).st_mode & !libc::S_IFMT) | libc::S_IFCHR;
-                    }
-                }
-            } else if path_str.starts_with("/dev/input/js") {
-                if result == 0 && !statbuf.is_null() {
-                    let js_num: u64 = path_str
-                        .trim_start_matches("/dev/input/js")
-                        .parse()
-                        .unwrap_or(0);
-
-                    unsafe {
-                        (*statbuf).st_rdev = libc::makedev(81, js_num as u32);
-                        (*statbuf).st_mode = ((*statbuf).st_mode & !libc::S_IFMT) | libc::S_IFCHR;
-                    }
-                }
-            }
-
-            return result;
+            return unsafe { fake_node_stat("__xstat64", result, path_str, statbuf) };
         }
         return -1;
     }
@@ -1197,35 +1102,7 @@ pub unsafe extern "C" fn __lxstat(
         let new_path = CString::new(redirected).unwrap();
         if let Some(orig_lxstat) = ORIGINAL_FUNCTIONS.lxstat {
             let result = unsafe { orig_lxstat(ver, new_path.as_ptr(), statbuf) };
-
-            // Fake the DEVICE NUMBER for input devices - SDL you sonovabitch
-            if path_str.starts_with("/dev/input/event") {
-                if result == 0 && !statbuf.is_null() {
-                    let event_num: u64 = path_str
-                        .trim_start_matches("/dev/input/event")
-                        .parse()
-                        .unwrap_or(0);
-
-                    unsafe {
-                        (*statbuf).st_rdev = libc::makedev(13, 64 + event_num as u32);
-                        (*statbuf).st_mode = ((*statbuf).st_mode & !libc::S_IFMT) | libc::S_IFCHR;
-                    }
-                }
-            } else if path_str.starts_with("/dev/input/js") {
-                if result == 0 && !statbuf.is_null() {
-                    let js_num: u64 = path_str
-                        .trim_start_matches("/dev/input/js")
-                        .parse()
-                        .unwrap_or(0);
-
-                    unsafe {
-                        (*statbuf).st_rdev = libc::makedev(81, js_num as u32);
-                        (*statbuf).st_mode = ((*statbuf).st_mode & !libc::S_IFMT) | libc::S_IFCHR;
-                    }
-                }
-            }
-
-            return result;
+            return unsafe { fake_node_stat("__lxstat", result, path_str, statbuf) };
         }
         return -1;
     }
@@ -1265,35 +1142,7 @@ pub unsafe extern "C" fn __lxstat64(
         let new_path = CString::new(redirected).unwrap();
         if let Some(orig_lxstat64) = ORIGINAL_FUNCTIONS.lxstat64 {
             let result = unsafe { orig_lxstat64(ver, new_path.as_ptr(), statbuf) };
-
-            // Fake the DEVICE NUMBER for input devices - SDL you sonovabitch
-            if path_str.starts_with("/dev/input/event") {
-                if result == 0 && !statbuf.is_null() {
-                    let event_num: u64 = path_str
-                        .trim_start_matches("/dev/input/event")
-                        .parse()
-                        .unwrap_or(0);
-
-                    unsafe {
-                        (*statbuf).st_rdev = libc::makedev(13, 64 + event_num as u32);
-                        (*statbuf).st_mode = ((*statbuf).st_mode & !libc::S_IFMT) | libc::S_IFCHR;
-                    }
-                }
-            } else if path_str.starts_with("/dev/input/js") {
-                if result == 0 && !statbuf.is_null() {
-                    let js_num: u64 = path_str
-                        .trim_start_matches("/dev/input/js")
-                        .parse()
-                        .unwrap_or(0);
-
-                    unsafe {
-                        (*statbuf).st_rdev = libc::makedev(81, js_num as u32);
-                        (*statbuf).st_mode = ((*statbuf).st_mode & !libc::S_IFMT) | libc::S_IFCHR;
-                    }
-                }
-            }
-
-            return result;
+            return unsafe { fake_node_stat("__lxstat64", result, path_str, statbuf) };
         }
         return -1;
     }
@@ -1304,13 +1153,14 @@ pub unsafe extern "C" fn __lxstat64(
     -1
 }
 
-/// Intercept poll() to monitor udev fds
+/// Intercept poll() to monitor udev fds and reconnect virtual device fds
 #[unsafe(no_mangle)]
 pub unsafe extern "C" fn poll(fds: *mut libc::pollfd, nfds: libc::nfds_t, timeout: c_int) -> c_int {
-    // Check if any udev fds are being polled
+    // Check if any udev fds are being polled, and if device fds lost their manager
     if !fds.is_null() && nfds > 0 {
         let fds_slice = unsafe { std::slice::from_raw_parts(fds, nfds as usize) };
         for pfd in fds_slice {
+            syscalls::lookup_virtual_device_fd(pfd.fd);
             if syscalls::is_uinput_fd(pfd.fd) {
                 tracing::trace!("poll: uinput fd {} being polled", pfd.fd);
             }
@@ -1326,7 +1176,53 @@ pub unsafe extern "C" fn poll(fds: *mut libc::pollfd, nfds: libc::nfds_t, timeou
     -1
 }
 
-// Intercept epoll_wait
+/// Intercept epoll_ctl() to track which FDs are registered with which user data
+///
+/// # Safety
+/// Same contract as libc's `epoll_ctl()`: `event` must be null or point to a valid epoll
+/// event.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn epoll_ctl(
+    epfd: c_int,
+    op: c_int,
+    fd: c_int,
+    event: *mut libc::epoll_event,
+) -> c_int {
+    if let Some(orig_epoll_ctl) = ORIGINAL_FUNCTIONS.epoll_ctl {
+        let result = unsafe { orig_epoll_ctl(epfd, op, fd, event) };
+        if result == 0 {
+            let registered = if event.is_null() {
+                None
+            } else {
+                Some(unsafe { ((*event).events, (*event).u64) })
+            };
+            syscalls::track_epoll_ctl(epfd, op, fd, registered);
+        }
+        return result;
+    }
+    -1
+}
+
+/// Remaining timeout for a retried epoll wait, None once the deadline has passed
+fn remaining_epoll_timeout(timeout: c_int, deadline: Option<std::time::Instant>) -> Option<c_int> {
+    match deadline {
+        None => Some(timeout),
+        Some(deadline) => {
+            let remaining = deadline.saturating_duration_since(std::time::Instant::now());
+            if remaining.is_zero() {
+                None
+            } else {
+                Some(remaining.as_millis().max(1) as c_int)
+            }
+        }
+    }
+}
+
+/// Intercept epoll_wait() to drop spurious readiness of uinput FDs
+///
+/// # Safety
+/// Same contract as libc's `epoll_wait()`: `events` must point to at least `maxevents`
+/// writable epoll events.
 #[unsafe(no_mangle)]
 pub unsafe extern "C" fn epoll_wait(
     epfd: c_int,
@@ -1335,32 +1231,38 @@ pub unsafe extern "C" fn epoll_wait(
     timeout: c_int,
 ) -> c_int {
     if let Some(orig_epoll_wait) = ORIGINAL_FUNCTIONS.epoll_wait {
-        let result = unsafe { orig_epoll_wait(epfd, events, maxevents, timeout) };
-
-        // Log which fds got events
-        if result > 0 && !events.is_null() {
-            let events_slice = unsafe { std::slice::from_raw_parts(events, result as usize) };
-            for event in events_slice {
-                let fd = event.u64 as c_int; // Typically fd stored in u64
-                if syscalls::is_uinput_fd(fd) {
-                    tracing::trace!("epoll_wait: uinput fd {} ready (events={:x})", fd, {
-                        event.events
-                    });
-                }
-                if syscalls::is_udev_monitor_fd(fd) {
-                    tracing::trace!("epoll_wait: UDEV MONITOR fd {} ready (events={:x})", fd, {
-                        event.events
-                    });
-                }
+        syscalls::reconnect_epoll_members(epfd);
+        let deadline = (timeout > 0)
+            .then(|| std::time::Instant::now() + std::time::Duration::from_millis(timeout as u64));
+        let mut wait_timeout = timeout;
+
+        loop {
+            let result = unsafe { orig_epoll_wait(epfd, events, maxevents, wait_timeout) };
+            if result <= 0 {
+                return result;
             }
-        }
 
-        return result;
+            log_epoll_events("epoll_wait", events, result);
+
+            // Drop spurious uinput readiness, and keep waiting if that was all we got
+            let filtered = unsafe { syscalls::filter_uinput_epoll_events(epfd, events, result) };
+            if filtered > 0 || timeout == 0 {
+                return filtered;
+            }
+            match remaining_epoll_timeout(timeout, deadline) {
+                Some(remaining) => wait_timeout = remaining,
+                None => return 0,
+            }
+        }
     }
     -1
 }
 
-// Intercept epoll_pwait
+/// Intercept epoll_pwait() to drop spurious readiness of uinput FDs
+///
+/// # Safety
+/// Same contract as libc's `epoll_pwait()`: `events` must point to at least `maxevents`
+/// writable epoll events, and `sigmask` must be null or point to a valid signal set.
 #[unsafe(no_mangle)]
 pub unsafe extern "C" fn epoll_pwait(
     epfd: c_int,
@@ -1370,31 +1272,55 @@ pub unsafe extern "C" fn epoll_pwait(
     sigmask: *const libc::sigset_t,
 ) -> c_int {
     if let Some(orig_epoll_pwait) = ORIGINAL_FUNCTIONS.epoll_pwait {
-        let result = unsafe { orig_epoll_pwait(epfd, events, maxevents, timeout, sigmask) };
-
-        // Log which fds got events
-        if result > 0 && !events.is_null() {
-            let events_slice = unsafe { std::slice::from_raw_parts(events, result as usize) };
-            for event in events_slice {
-                let fd = event.u64 as c_int; // Typically fd stored in u64
-                if syscalls::is_uinput_fd(fd) {
-                    tracing::trace!("epoll_pwait: uinput fd {} ready (events={:x})", fd, {
-                        event.events
-                    });
-                }
-                if syscalls::is_udev_monitor_fd(fd) {
-                    tracing::trace!("epoll_pwait: UDEV MONITOR fd {} ready (events={:x})", fd, {
-                        event.events
-                    });
-                }
+        syscalls::reconnect_epoll_members(epfd);
+        let deadline = (timeout > 0)
+            .then(|| std::time::Instant::now() + std::time::Duration::from_millis(timeout as u64));
+        let mut wait_timeout = timeout;
+
+        loop {
+            let result =
+                unsafe { orig_epoll_pwait(epfd, events, maxevents, wait_timeout, sigmask) };
+            if result <= 0 {
+                return result;
             }
-        }
 
-        return result;
+            log_epoll_events("epoll_pwait", events, result);
+
+            // Drop spurious uinput readiness, and keep waiting if that was all we got
+            let filtered = unsafe { syscalls::filter_uinput_epoll_events(epfd, events, result) };
+            if filtered > 0 || timeout == 0 {
+                return filtered;
+            }
+            match remaining_epoll_timeout(timeout, deadline) {
+                Some(remaining) => wait_timeout = remaining,
+                None => return 0,
+            }
+        }
     }
     -1
 }
 
+/// Log which tracked fds got epoll events
+fn log_epoll_events(func: &str, events: *const libc::epoll_event, count: c_int) {
+    if events.is_null() || count <= 0 {
+        return;
+    }
+    let events_slice = unsafe { std::slice::from_raw_parts(events, count as usize) };
+    for event in events_slice {
+        let fd = event.u64 as c_int; // Typically fd stored in u64
+        if syscalls::is_uinput_fd(fd) {
+            tracing::trace!("{}: uinput fd {} ready (events={:x})", func, fd, {
+                event.events
+            });
+        }
+        if syscalls::is_udev_monitor_fd(fd) {
+            tracing::trace!("{}: UDEV MONITOR fd {} ready (events={:x})", func, fd, {
+                event.events
+            });
+        }
+    }
+}
+
 // Intercept inotify_init
 #[unsafe(no_mangle)]
 pub unsafe extern "C" fn inotify_init() -> c_int {
@@ -1493,6 +1419,14 @@ pub unsafe extern "C" fn connect(
             let unix_addr = addr as *const libc::sockaddr_un;
             let path_bytes = unsafe { &(*unix_addr).sun_path };
 
+            // Abstract sockets (leading NUL) have no path to redirect, pass them through as-is
+            if path_bytes[0] == 0 {
+                if let Some(orig_connect) = ORIGINAL_FUNCTIONS.connect {
+                    return unsafe { orig_connect(sockfd, addr, addrlen) };
+                }
+                return -1;
+            }
+
             // Find null terminator
             let path_len = path_bytes.iter().position(|&b| b == 0).unwrap_or(108);
             let path_slice = &path_bytes[..path_len];
@@ -1566,39 +1500,12 @@ pub unsafe extern "C" fn fstat(fd: c_int, statbuf: *mut libc::stat) -> c_int {
     };
 
     // If this is a virtual device fd, fake its device number
-    if result == 0 && !statbuf.is_null() && syscalls::is_virtual_device_fd(fd) {
-        if let Some(device_info) = syscalls::get_virtual_device_info(fd) {
-            tracing::debug!(
-                "fstat: faking device number for fd={}, node={}",
-                fd,
-                device_info.event_node
-            );
-
-            // Parse event/js number from node name
-            if device_info.event_node.starts_with("event") {
-                if let Ok(event_num) = device_info
-                    .event_node
-                    .trim_start_matches("event")
-                    .parse::<u32>()
-                {
-                    unsafe {
-                        (*statbuf).st_rdev = libc::makedev(13, 64 + event_num);
-                        (*statbuf).st_mode = ((*statbuf).st_mode & !libc::S_IFMT) | libc::S_IFCHR;
-                    }
-                }
-            } else if device_info.event_node.starts_with("js") {
-                if let Ok(js_num) = device_info
-                    .event_node
-                    .trim_start_matches("js")
-                    .parse::<u32>()
-                {
-                    unsafe {
-                        (*statbuf).st_rdev = libc::makedev(81, js_num);
-                        (*statbuf).st_mode = ((*statbuf).st_mode & !libc::S_IFMT) | libc::S_IFCHR;
-                    }
-                }
-            }
-        }
+    if result == 0
+        && !statbuf.is_null()
+        && let Some(rdev) = virtual_fd_rdev(fd)
+    {
+        tracing::debug!("fstat: faking device number for fd={}", fd);
+        unsafe { (*statbuf).fake_char_device(rdev) };
     }
 
     result
@@ -1613,38 +1520,12 @@ pub unsafe extern "C" fn fstat64(fd: c_int, statbuf: *mut libc::stat64) -> c_int
         return -1;
     };
 
-    if result == 0 && !statbuf.is_null() && syscalls::is_virtual_device_fd(fd) {
-        if let Some(device_info) = syscalls::get_virtual_device_info(fd) {
-            tracing::debug!(
-                "fstat64: faking device number for fd={}, node={}",
-                fd,
-                device_info.event_node
-            );
-
-            if device_info.event_node.starts_with("event") {
-                if let Ok(event_num) = device_info
-                    .event_node
-                    .trim_start_matches("event")
-                    .parse::<u32>()
-                {
-                    unsafe {
-                        (*statbuf).st_rdev = libc::makedev(13, 64 + event_num);
-                        (*statbuf).st_mode = ((*statbuf).st_mode & !libc::S_IFMT) | libc::S_IFCHR;
-                    }
-                }
-            } else if device_info.event_node.starts_with("js") {
-                if let Ok(js_num) = device_info
-                    .event_node
-                    .trim_start_matches("js")
-                    .parse::<u32>()
-                {
-                    unsafe {
-                        (*statbuf).st_rdev = libc::makedev(81, js_num);
-                        (*statbuf).st_mode = ((*statbuf).st_mode & !libc::S_IFMT) | libc::S_IFCHR;
-                    }
-                }
-            }
-        }
+    if result == 0
+        && !statbuf.is_null()
+        && let Some(rdev) = virtual_fd_rdev(fd)
+    {
+        tracing::debug!("fstat64: faking device number for fd={}", fd);
+        unsafe { (*statbuf).fake_char_device(rdev) };
     }
 
     result
@@ -1667,32 +1548,12 @@ pub unsafe extern "C" fn __fxstat(ver: c_int, fd: c_int, statbuf: *mut libc::sta
     };
 
     // Same device number faking logic
-    if result == 0 && !statbuf.is_null() && syscalls::is_virtual_device_fd(fd) {
-        if let Some(device_info) = syscalls::get_virtual_device_info(fd) {
-            if device_info.event_node.starts_with("event") {
-                if let Ok(event_num) = device_info
-                    .event_node
-                    .trim_start_matches("event")
-                    .parse::<u32>()
-                {
-                    unsafe {
-                        (*statbuf).st_rdev = libc::makedev(13, 64 + event_num);
-                        (*statbuf).st_mode = ((*statbuf).st_mode & !libc::S_IFMT) | libc::S_IFCHR;
-                    }
-                }
-            } else if device_info.event_node.starts_with("js") {
-                if let Ok(js_num) = device_info
-                    .event_node
-                    .trim_start_matches("js")
-                    .parse::<u32>()
-                {
-                    unsafe {
-                        (*statbuf).st_rdev = libc::makedev(81, js_num);
-                        (*statbuf).st_mode = ((*statbuf).st_mode & !libc::S_IFMT) | libc::S_IFCHR;
-                    }
-                }
-            }
-        }
+    if result == 0
+        && !statbuf.is_null()
+        && let Some(rdev) = virtual_fd_rdev(fd)
+    {
+        tracing::debug!("__fxstat: faking device number for fd={}", fd);
+        unsafe { (*statbuf).fake_char_device(rdev) };
     }
 
     result
@@ -1712,33 +1573,197 @@ pub unsafe extern "C" fn __fxstat64(ver: c_int, fd: c_int, statbuf: *mut libc::s
         return -1;
     };
 
-    if result == 0 && !statbuf.is_null() && syscalls::is_virtual_device_fd(fd) {
-        if let Some(device_info) = syscalls::get_virtual_device_info(fd) {
-            if device_info.event_node.starts_with("event") {
-                if let Ok(event_num) = device_info
-                    .event_node
-                    .trim_start_matches("event")
-                    .parse::<u32>()
-                {
-                    unsafe {
-                        (*statbuf).st_rdev = libc::makedev(13, 64 + event_num);
-                        (*statbuf).st_mode = ((*statbuf).st_mode & !libc::S_IFMT) | libc::S_IFCHR;
-                    }
-                }
-            } else if device_info.event_node.starts_with("js") {
-                if let Ok(js_num) = device_info
-                    .event_node
-                    .trim_start_matches("js")
-                    .parse::<u32>()
-                {
-                    unsafe {
-                        (*statbuf).st_rdev = libc::makedev(81, js_num);
-                        (*statbuf).st_mode = ((*statbuf).st_mode & !libc::S_IFMT) | libc::S_IFCHR;
-                    }
-                }
-            }
-        }
+    if result == 0
+        && !statbuf.is_null()
+        && let Some(rdev) = virtual_fd_rdev(fd)
+    {
+        tracing::debug!("__fxstat64: faking device number for fd={}", fd);
+        unsafe { (*statbuf).fake_char_device(rdev) };
     }
 
     result
 }
+
+/// Device number of one of this instance's event or joystick nodes, by name or path, e.g.
+/// `event3` or `/dev/input/js3`
+fn node_rdev(node: &str) -> Option<libc::dev_t> {
+    let node = node.strip_prefix("/dev/input/").unwrap_or(node);
+    let id = u32::try_from(node_device_id(crate::base_path::node_prefix(), node)?).ok()?;
+    Some(match node.starts_with("js") {
+        true => libc::makedev(81, id),
+        false => libc::makedev(13, 64 + id),
+    })
+}
+
+/// Device number to report for a virtual device fd, if it is one
+fn virtual_fd_rdev(fd: c_int) -> Option<libc::dev_t> {
+    if !syscalls::is_virtual_device_fd(fd) {
+        return None;
+    }
+    node_rdev(&syscalls::get_virtual_device_info(fd)?.event_node)
+}
+
+/// Check for the fstatat(fd, "", AT_EMPTY_PATH) form of fstat()
+fn is_empty_path_stat(pathname: *const c_char, flags: c_int) -> bool {
+    flags & libc::AT_EMPTY_PATH != 0 && (pathname.is_null() || unsafe { *pathname } == 0)
+}
+
+/// `stat` and `stat64`, for faking character devices in either
+trait StatBuf {
+    fn fake_char_device(&mut self, rdev: libc::dev_t);
+}
+impl StatBuf for libc::stat {
+    fn fake_char_device(&mut self, rdev: libc::dev_t) {
+        self.st_rdev = rdev;
+        self.st_mode = (self.st_mode & !libc::S_IFMT) | libc::S_IFCHR;
+    }
+}
+impl StatBuf for libc::stat64 {
+    fn fake_char_device(&mut self, rdev: libc::dev_t) {
+        self.st_rdev = rdev;
+        self.st_mode = (self.st_mode & !libc::S_IFMT) | libc::S_IFCHR;
+    }
+}
+
+/// Fake a character device for the fstat() form of a successful fstatat() on a virtual
+/// device fd, shared by the fstatat family
+///
+/// # Safety
+/// `statbuf` must be null or point to a valid stat buffer.
+unsafe fn fake_empty_path_stat<S: StatBuf>(
+    func: &str,
+    result: c_int,
+    dirfd: c_int,
+    pathname: *const c_char,
+    statbuf: *mut S,
+    flags: c_int,
+) -> c_int {
+    if result == 0
+        && !statbuf.is_null()
+        && is_empty_path_stat(pathname, flags)
+        && let Some(rdev) = virtual_fd_rdev(dirfd)
+    {
+        tracing::debug!("{}: faking device number for fd={}", func, dirfd);
+        unsafe { (*statbuf).fake_char_device(rdev) };
+    }
+
+    result
+}
+
+/// Fake a character device for a successful stat of a redirected event or joystick node,
+/// shared by the stat family
+///
+/// Names that aren't nodes of this instance, e.g. those of another instance with its own
+/// node prefix in a merged `/dev/input`, fail with ENOENT rather than pass for its devices.
+///
+/// # Safety
+/// `statbuf` must be null or point to a valid stat buffer.
+unsafe fn fake_node_stat<S: StatBuf>(
+    func: &str,
+    result: c_int,
+    path: &str,
+    statbuf: *mut S,
+) -> c_int {
+    let is_node = path.starts_with("/dev/input/event") || path.starts_with("/dev/input/js");
+    if result != 0 || statbuf.is_null() || !is_node {
+        return result;
+    }
+
+    match node_rdev(path) {
+        Some(rdev) => {
+            tracing::debug!("{}: faking device number for {}", func, path);
+            unsafe { (*statbuf).fake_char_device(rdev) };
+            0
+        }
+        None => {
+            unsafe { *libc::__errno_location() = libc::ENOENT };
+            -1
+        }
+    }
+}
+
+/// Intercept fstatat() - libc may implement fstat(fd) as fstatat(fd, "", AT_EMPTY_PATH)
+///
+/// # Safety
+/// Same contract as libc's `fstatat()`: `pathname` must be a valid C string and `statbuf` must
+/// be null or point to a writable stat buffer.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn fstatat(
+    dirfd: c_int,
+    pathname: *const c_char,
+    statbuf: *mut libc::stat,
+    flags: c_int,
+) -> c_int {
+    let result = if let Some(orig_fstatat) = ORIGINAL_FUNCTIONS.fstatat {
+        unsafe { orig_fstatat(dirfd, pathname, statbuf, flags) }
+    } else {
+        return -1;
+    };
+    unsafe { fake_empty_path_stat("fstatat", result, dirfd, pathname, statbuf, flags) }
+}
+
+/// Intercept fstatat64()
+///
+/// # Safety
+/// Same contract as libc's `fstatat64()`: `pathname` must be a valid C string and `statbuf` must
+/// be null or point to a writable stat buffer.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn fstatat64(
+    dirfd: c_int,
+    pathname: *const c_char,
+    statbuf: *mut libc::stat64,
+    flags: c_int,
+) -> c_int {
+    let result = if let Some(orig_fstatat64) = ORIGINAL_FUNCTIONS.fstatat64 {
+        unsafe { orig_fstatat64(dirfd, pathname, statbuf, flags) }
+    } else {
+        return -1;
+    };
+    unsafe { fake_empty_path_stat("fstatat64", result, dirfd, pathname, statbuf, flags) }
+}
+
+/// Intercept __fxstatat (older glibc wrapper for fstatat)
+///
+/// # Safety
+/// Same contract as libc's `fstatat()`: `pathname` must be a valid C string and `statbuf` must
+/// be null or point to a writable stat buffer.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn __fxstatat(
+    ver: c_int,
+    dirfd: c_int,
+    pathname: *const c_char,
+    statbuf: *mut libc::stat,
+    flags: c_int,
+) -> c_int {
+    let result = if let Some(orig_fxstatat) = ORIGINAL_FUNCTIONS.fxstatat {
+        unsafe { orig_fxstatat(ver, dirfd, pathname, statbuf, flags) }
+    } else if let Some(orig_fstatat) = ORIGINAL_FUNCTIONS.fstatat {
+        unsafe { orig_fstatat(dirfd, pathname, statbuf, flags) }
+    } else {
+        return -1;
+    };
+    unsafe { fake_empty_path_stat("__fxstatat", result, dirfd, pathname, statbuf, flags) }
+}
+
+/// Intercept __fxstatat64 (older glibc wrapper for fstatat64)
+///
+/// # Safety
+/// Same contract as libc's `fstatat64()`: `pathname` must be a valid C string and `statbuf` must
+/// be null or point to a writable stat buffer.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn __fxstatat64(
+    ver: c_int,
+    dirfd: c_int,
+    pathname: *const c_char,
+    statbuf: *mut libc::stat64,
+    flags: c_int,
+) -> c_int {
+    let result = if let Some(orig_fxstatat64) = ORIGINAL_FUNCTIONS.fxstatat64 {
+        unsafe { orig_fxstatat64(ver, dirfd, pathname, statbuf, flags) }
+    } else if let Some(orig_fstatat64) = ORIGINAL_FUNCTIONS.fstatat64 {
+        unsafe { orig_fstatat64(dirfd, pathname, statbuf, flags) }
+    } else {
+        return -1;
+    };
+    unsafe { fake_empty_path_stat("__fxstatat64", result, dirfd, pathname, statbuf, flags) }
+}
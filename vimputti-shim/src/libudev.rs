@@ -7,6 +7,7 @@ use std::os::unix::net::UnixStream;
 use std::ptr;
 use std::sync::Mutex;
 use tracing::{debug, trace};
+use vimputti::manager::udev::wire;
 
 lazy_static::lazy_static! {
     static ref FAKE_UDEV_CONTEXTS: Mutex<HashMap<usize, FakeUdevContext>> = Mutex::new(HashMap::new());
@@ -28,6 +29,7 @@ struct FakeUdevDevice {
     syspath: String,
     devnode: String,
     subsystem: String,
+    devtype: Option<String>,
     properties: HashMap<String, String>,
 }
 
@@ -43,6 +45,45 @@ struct FakeUdevContext {
 struct FakeUdevMonitor {
     socket: Option<UnixStream>,
     fd: RawFd,
+    /// Subsystem and optional devtype matches, events matching none are skipped
+    filters: Vec<(String, Option<String>)>,
+    /// Received bytes not yet returned as a device, messages arrive back to back
+    buffer: Vec<u8>,
+}
+impl FakeUdevMonitor {
+    /// Check if an event passes the filters, like libudev no filters pass everything
+    ///
+    /// The header hashes are compared first, as libudev's socket filter does, then the
+    /// decoded values to rule out hash collisions.
+    fn accepts(&self, message: &[u8], device: &FakeUdevDevice) -> bool {
+        if self.filters.is_empty() {
+            return true;
+        }
+
+        let hashes = wire::filter_hashes(message);
+        self.filters.iter().any(|(subsystem, devtype)| {
+            let hash_matches = hashes.is_none_or(|(subsystem_hash, devtype_hash)| {
+                subsystem_hash == wire::filter_hash(subsystem)
+                    && devtype
+                        .as_ref()
+                        .is_none_or(|d| devtype_hash == wire::filter_hash(d))
+            });
+            hash_matches
+                && device.subsystem == *subsystem
+                && devtype
+                    .as_ref()
+                    .is_none_or(|d| device.devtype.as_deref() == Some(d.as_str()))
+        })
+    }
+
+    /// Next complete message in the buffer, removed from it
+    fn next_message(&mut self) -> Option<Vec<u8>> {
+        let len = wire::message_len(&self.buffer)?;
+        if self.buffer.len() < len {
+            return None;
+        }
+        Some(self.buffer.drain(..len).collect())
+    }
 }
 
 /// Helper to create a cached CString pointer
@@ -102,18 +143,19 @@ fn create_fake_device_from_config(
     );
     properties.insert(
         "ID_MODEL_ENC".to_string(),
-        config.name.replace(' ', "\\x20"),
+        config.udev_model().replace(' ', "\\x20"),
     );
-    properties.insert("ID_MODEL_FROM_DATABASE".to_string(), config.name.clone());
-    properties.insert("ID_PRODUCT_FROM_DATABASE".to_string(), config.name.clone());
-
-    // Bus type from config
-    let bus_name = match config.bustype {
-        vimputti::BusType::Usb => "usb",
-        vimputti::BusType::Bluetooth => "bluetooth",
-        vimputti::BusType::Virtual => "virtual",
-    };
-    properties.insert("ID_BUS".to_string(), bus_name.to_string());
+    properties.insert(
+        "ID_MODEL_FROM_DATABASE".to_string(),
+        config.udev_model().to_string(),
+    );
+    properties.insert(
+        "ID_PRODUCT_FROM_DATABASE".to_string(),
+        config.udev_model().to_string(),
+    );
+
+    // Bus type from config (Virtual presents as usb, see DeviceConfig::udev_bus_name)
+    properties.insert("ID_BUS".to_string(), config.udev_bus_name().to_string());
 
     if matches!(config.bustype, vimputti::BusType::Usb) {
         properties.insert("ID_USB_INTERFACES".to_string(), ":030000:".to_string());
@@ -147,6 +189,12 @@ fn create_fake_device_from_config(
     );
     properties.insert("TAGS".to_string(), ":uaccess:".to_string());
 
+    for (key, value) in &config.extra_udev_properties {
+        if config.override_udev_properties || !properties.contains_key(key) {
+            properties.insert(key.clone(), value.clone());
+        }
+    }
+
     debug!(
         "Created fake device from config: {} (vendor={:04x} product={:04x})",
         config.name, config.vendor_id, config.product_id
@@ -156,37 +204,129 @@ fn create_fake_device_from_config(
         syspath,
         devnode,
         subsystem: "input".to_string(),
+        devtype: None,
         properties,
     }
 }
 
 /// Get list of virtual device paths WITH their configs
+///
+/// Devices opened by this process come with the config from their handshake, the others
+/// present in the devices directory are read back from their generated sysfs files.
 fn get_virtual_devices_with_configs() -> Vec<(String, vimputti::DeviceConfig)> {
     let base_path = crate::syscalls::get_base_path();
     let devices_dir = std::path::Path::new(&base_path).join("devices");
 
-    // Get configs from currently open devices
-    let device_configs = crate::syscalls::get_all_device_configs();
-
-    // Also scan directory for any unopened devices
-    let mut devices = Vec::new();
-
-    // First, add opened devices (we have full config for these)
-    for (event_node, config) in device_configs {
-        let devnode = devices_dir.join(&event_node).to_string_lossy().to_string();
-        devices.push((devnode, config));
+    // Opened devices first, their config is the full one
+    let mut configs: HashMap<String, vimputti::DeviceConfig> =
+        crate::syscalls::get_all_device_configs()
+            .into_iter()
+            .collect();
+
+    // Then any device nobody opened yet, e.g. when enumerating before opening (SDL does)
+    if let Ok(entries) = std::fs::read_dir(&devices_dir) {
+        for entry in entries.flatten() {
+            let Some(node) = entry.file_name().to_str().map(str::to_string) else {
+                continue;
+            };
+            if !(node.starts_with("event") || node.starts_with("js")) || configs.contains_key(&node)
+            {
+                continue;
+            }
+            match read_sysfs_device_config(&base_path, &node) {
+                Some(config) => {
+                    configs.insert(node, config);
+                }
+                None => debug!("No sysfs entry for unopened device {}, skipping", node),
+            }
+        }
     }
 
-    // TODO: For unopened devices, we could read from sysfs as fallback,
-    // but Steam will typically open devices before enumerating, so this is rare
+    let mut devices: Vec<(String, vimputti::DeviceConfig)> = configs
+        .into_iter()
+        .map(|(node, config)| (devices_dir.join(node).to_string_lossy().to_string(), config))
+        .collect();
+    devices.sort_by(|a, b| a.0.cmp(&b.0));
 
     debug!("Found {} virtual devices with configs", devices.len());
     devices
 }
 
+/// Synthesize the config of a device from its sysfs files, for devices not opened yet
+///
+/// Covers name, ids, buttons and axes, relative ones too. Axis ranges aren't in sysfs and
+/// are left at 0, udev properties don't depend on them.
+///
+/// The udev properties the manager recorded in the udev database, extra ones included,
+/// replace generated ones.
+fn read_sysfs_device_config(base_path: &str, node: &str) -> Option<vimputti::DeviceConfig> {
+    use vimputti::protocol::{
+        ABS_MAX, KEY_MAX, REL_MAX, RelAxis, capabilities::Bitmap, event_node_name, input_node_name,
+        node_device_id,
+    };
+    use vimputti::{Axis, AxisConfig, BusType, Button};
+
+    let node_prefix = crate::base_path::node_prefix();
+    let id = node_device_id(node_prefix, node)?;
+    let input_dir = std::path::Path::new(base_path)
+        .join("sysfs/devices/virtual/input")
+        .join(input_node_name(node_prefix, id));
+    let read = |name: &str| {
+        std::fs::read_to_string(input_dir.join(name))
+            .ok()
+            .map(|s| s.trim_end_matches('\n').to_string())
+    };
+    let read_hex = |name: &str| u16::from_str_radix(&read(name)?, 16).ok();
+
+    // The manager makes names unique with the event node, e.g. "Pad (event0)"
+    let name = read("name")?;
+    let name = name
+        .strip_suffix(&format!(" ({})", event_node_name(node_prefix, id)))
+        .unwrap_or(&name)
+        .to_string();
+
+    let buttons = Bitmap::from_sysfs_string(&read("capabilities/key")?, KEY_MAX)?
+        .codes()
+        .filter_map(Button::from_ev_code)
+        .collect();
+    let axes = Bitmap::from_sysfs_string(&read("capabilities/abs")?, ABS_MAX)?
+        .codes()
+        .map(|code| AxisConfig::new(Axis::from_ev_code(code).unwrap_or(Axis::Custom(code)), 0, 0))
+        .collect();
+    let rel_axes = Bitmap::from_sysfs_string(&read("capabilities/rel")?, REL_MAX)?
+        .codes()
+        .filter_map(RelAxis::from_ev_code)
+        .collect();
+
+    // `E:KEY=VALUE` lines of the event node's entry, c13:<minor>
+    let udev_data = std::path::Path::new(base_path)
+        .join("udev_data")
+        .join(format!("c13:{}", 64 + id));
+    let extra_udev_properties = std::fs::read_to_string(udev_data)
+        .unwrap_or_default()
+        .lines()
+        .filter_map(|line| line.strip_prefix("E:")?.split_once('='))
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect();
+
+    Some(vimputti::DeviceConfig {
+        name,
+        bustype: BusType::from_u16(read_hex("id/bustype")?),
+        vendor_id: read_hex("id/vendor")?,
+        product_id: read_hex("id/product")?,
+        version: read_hex("id/version")?,
+        buttons,
+        axes,
+        rel_axes,
+        extra_udev_properties,
+        override_udev_properties: true,
+        ..Default::default()
+    })
+}
+
 /// Get the path to our fake udev socket
 fn get_udev_socket_path() -> String {
-    "/tmp/vimputti/udev".to_string()
+    format!("{}/udev", crate::syscalls::get_base_path())
 }
 
 /// Get next fake pointer
@@ -264,7 +404,12 @@ pub unsafe extern "C" fn udev_monitor_new_from_netlink(
 
     let fd = socket.as_ref().map(|s| s.as_raw_fd()).unwrap_or(-1);
 
-    let monitor = FakeUdevMonitor { socket, fd };
+    let monitor = FakeUdevMonitor {
+        socket,
+        fd,
+        filters: Vec::new(),
+        buffer: Vec::new(),
+    };
     FAKE_UDEV_MONITORS
         .lock()
         .unwrap()
@@ -288,17 +433,40 @@ pub unsafe extern "C" fn udev_monitor_filter_add_match_subsystem_devtype(
     devtype: *const c_char,
 ) -> c_int {
     let monitor_ptr = udev_monitor as usize;
-    let subsystem_str = if subsystem.is_null() {
-        "none"
-    } else {
-        unsafe { CStr::from_ptr(subsystem).to_str().unwrap_or("unknown") }
-    };
+    if subsystem.is_null() {
+        return -libc::EINVAL;
+    }
+    let subsystem_str = unsafe { CStr::from_ptr(subsystem).to_string_lossy().into_owned() };
+    let devtype_str = (!devtype.is_null())
+        .then(|| unsafe { CStr::from_ptr(devtype).to_string_lossy().into_owned() });
 
     trace!(
-        "[UDEV] filter_add_match for monitor {:x}: subsystem={}",
-        monitor_ptr, subsystem_str
+        "[UDEV] filter_add_match for monitor {:x}: subsystem={} devtype={:?}",
+        monitor_ptr, subsystem_str, devtype_str
     );
-    0
+
+    match FAKE_UDEV_MONITORS.lock().unwrap().get_mut(&monitor_ptr) {
+        Some(monitor) => {
+            monitor.filters.push((subsystem_str, devtype_str));
+            0
+        }
+        None => -libc::EINVAL,
+    }
+}
+
+/// Intercept udev_monitor_filter_remove() - drop all filters of a monitor
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn udev_monitor_filter_remove(udev_monitor: *mut c_void) -> c_int {
+    let monitor_ptr = udev_monitor as usize;
+    trace!("[UDEV] filter_remove for monitor {:x}", monitor_ptr);
+
+    match FAKE_UDEV_MONITORS.lock().unwrap().get_mut(&monitor_ptr) {
+        Some(monitor) => {
+            monitor.filters.clear();
+            0
+        }
+        None => -libc::EINVAL,
+    }
 }
 
 /// Intercept udev_monitor_filter_update()
@@ -348,6 +516,9 @@ pub unsafe extern "C" fn udev_monitor_get_fd(udev_monitor: *mut c_void) -> c_int
 }
 
 /// Intercept udev_monitor_receive_device() - read device event from our socket
+///
+/// Returns the next event passing the monitor's filters, skipping the others, or null once
+/// no more are available without blocking.
 #[unsafe(no_mangle)]
 pub unsafe extern "C" fn udev_monitor_receive_device(udev_monitor: *mut c_void) -> *mut c_void {
     use std::io::Read;
@@ -360,46 +531,64 @@ pub unsafe extern "C" fn udev_monitor_receive_device(udev_monitor: *mut c_void)
     );
 
     let mut monitors = FAKE_UDEV_MONITORS.lock().unwrap();
-    if let Some(monitor) = monitors.get_mut(&monitor_ptr) {
-        if let Some(socket) = &mut monitor.socket {
-            // Read message from socket
-            let mut buffer = vec![0u8; 4096];
-
-            match socket.read(&mut buffer) {
-                Ok(0) => {
-                    debug!("[UDEV] Socket closed");
-                    return ptr::null_mut();
-                }
-                Ok(n) => {
-                    let message = String::from_utf8_lossy(&buffer[..n]);
-                    debug!(
-                        "[UDEV] Received {} bytes: {}",
-                        n,
-                        message.lines().next().unwrap_or("")
-                    );
+    let Some(monitor) = monitors.get_mut(&monitor_ptr) else {
+        return ptr::null_mut();
+    };
 
-                    // Parse the message
-                    let device = parse_udev_message(&message);
+    loop {
+        // Return buffered events before reading more
+        while let Some(message) = monitor.next_message() {
+            let Some(device) = parse_udev_message(&message) else {
+                continue;
+            };
+            if !monitor.accepts(&message, &device) {
+                trace!(
+                    "[UDEV] Skipping {} event, filtered by monitor {:x}",
+                    device.subsystem, monitor_ptr
+                );
+                continue;
+            }
 
-                    if let Some(device) = device {
-                        let device_ptr = next_ptr();
-                        FAKE_UDEV_DEVICES.lock().unwrap().insert(device_ptr, device);
-                        debug!("[UDEV] Created device from monitor event: {:x}", device_ptr);
-                        return device_ptr as *mut c_void;
-                    }
-                }
-                Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
-                    // No data available right now
-                    return ptr::null_mut();
-                }
-                Err(e) => {
-                    debug!("[UDEV] Socket read error: {}", e);
-                    return ptr::null_mut();
+            let device_ptr = next_ptr();
+            FAKE_UDEV_DEVICES.lock().unwrap().insert(device_ptr, device);
+            debug!("[UDEV] Created device from monitor event: {:x}", device_ptr);
+            return device_ptr as *mut c_void;
+        }
+
+        let Some(socket) = &mut monitor.socket else {
+            return ptr::null_mut();
+        };
+
+        // Read message from socket
+        let mut buffer = vec![0u8; 4096];
+        match socket.read(&mut buffer) {
+            Ok(0) => {
+                debug!("[UDEV] Socket closed");
+                return ptr::null_mut();
+            }
+            Ok(n) => {
+                debug!("[UDEV] Received {} bytes", n);
+                monitor.buffer.extend_from_slice(&buffer[..n]);
+
+                // Drop what can't be a monitor message, so the stream doesn't stall on it
+                if monitor.buffer.len() >= 8 && !monitor.buffer.starts_with(b"libudev\0") {
+                    debug!(
+                        "[UDEV] Discarding {} unexpected bytes",
+                        monitor.buffer.len()
+                    );
+                    monitor.buffer.clear();
                 }
             }
+            Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                // No data available right now
+                return ptr::null_mut();
+            }
+            Err(e) => {
+                debug!("[UDEV] Socket read error: {}", e);
+                return ptr::null_mut();
+            }
         }
     }
-    ptr::null_mut()
 }
 
 /// Intercept udev_monitor_unref()
@@ -693,43 +882,26 @@ pub unsafe extern "C" fn udev_device_get_property_value(
 }
 
 /// Parse a udev netlink-style message into a FakeUdevDevice
-fn parse_udev_message(message: &str) -> Option<FakeUdevDevice> {
-    let mut properties = HashMap::new();
-    let mut devname = String::new();
-    let mut devpath = String::new();
-    let mut subsystem = String::new();
-    let mut syspath = String::new();
-
-    for line in message.lines() {
-        if line.is_empty() {
-            break; // Empty line terminates message
-        }
+fn parse_udev_message(message: &[u8]) -> Option<FakeUdevDevice> {
+    let Some(event) = wire::decode_event(message) else {
+        debug!("[UDEV] Failed to decode monitor message");
+        return None;
+    };
+    debug!("[UDEV] Device action: {}", event.action.as_str());
 
-        if let Some((key, value)) = line.split_once('=') {
-            match key {
-                "DEVNAME" => devname = value.to_string(),
-                "DEVPATH" => devpath = value.to_string(),
-                "SUBSYSTEM" => subsystem = value.to_string(),
-                "ACTION" => {
-                    debug!("[UDEV] Device action: {}", value);
-                }
-                _ => {
-                    properties.insert(key.to_string(), value.to_string());
-                }
-            }
-        }
-    }
+    let info = event.device_info;
+    let devname = info.devname;
+    let devpath = info.devpath;
+    let subsystem = info.subsystem;
+    let properties: HashMap<String, String> = info.properties.into_iter().collect();
 
     if devname.is_empty() {
         debug!("[UDEV] No DEVNAME in message");
         return None;
     }
 
-    // Construct syspath if not provided
-    if syspath.is_empty() && !devpath.is_empty() {
-        let base_path = crate::syscalls::get_base_path();
-        syspath = format!("{}/sysfs{}", base_path, devpath);
-    }
+    // Point syspath at our sysfs tree
+    let syspath = format!("{}/sysfs{}", crate::syscalls::get_base_path(), devpath);
 
     debug!(
         "[UDEV] Parsed device: devname={}, subsystem={}",
@@ -740,6 +912,7 @@ fn parse_udev_message(message: &str) -> Option<FakeUdevDevice> {
         syspath,
         devnode: devname,
         subsystem,
+        devtype: (!info.devtype.is_empty()).then_some(info.devtype),
         properties,
     })
 }
@@ -1,3 +1,6 @@
+use std::path::{Component, Path, PathBuf};
+use tracing::warn;
+
 pub struct PathRedirector {
     base_path: String,
 }
@@ -5,12 +8,44 @@ pub struct PathRedirector {
 impl PathRedirector {
     pub fn new() -> Self {
         Self {
-            base_path: "/tmp/vimputti".to_string(),
+            base_path: crate::syscalls::get_base_path(),
         }
     }
 
     /// Check if a path should be redirected, and return the new path
+    ///
+    /// Redirects that would escape the base path (e.g. `/dev/input/event0/../../..`)
+    /// are refused, the original path is used for them instead.
     pub fn redirect(&self, path: &str) -> Option<String> {
+        let redirected = self.redirect_unchecked(path)?;
+        if !self.is_within_base(&redirected) {
+            warn!(
+                "Refusing redirect of {} outside of {}",
+                path, self.base_path
+            );
+            return None;
+        }
+        Some(redirected)
+    }
+
+    /// Check that a path stays within the base path, resolving `..` lexically
+    fn is_within_base(&self, path: &str) -> bool {
+        let mut normalized = PathBuf::new();
+        for component in Path::new(path).components() {
+            match component {
+                Component::ParentDir => {
+                    if !normalized.pop() {
+                        return false;
+                    }
+                }
+                Component::CurDir => {}
+                other => normalized.push(other),
+            }
+        }
+        normalized.starts_with(&self.base_path)
+    }
+
+    fn redirect_unchecked(&self, path: &str) -> Option<String> {
         // Redirect /dev/uinput to our fake uinput
         // We use a special marker so open() knows to return a fake FD
         if path == "/dev/uinput" {
@@ -50,6 +85,17 @@ impl PathRedirector {
             ));
         }
 
+        // Redirect the batteries of devices in /sys/class/power_supply, the host's power
+        // supplies next to them stay as they are
+        if let Some(suffix) = path.strip_prefix("/sys/class/power_supply/")
+            && suffix.starts_with("vimputti-battery-")
+        {
+            return Some(format!(
+                "{}/sysfs/class/power_supply/{}",
+                self.base_path, suffix
+            ));
+        }
+
         // Redirect /sys/class/input itself (for directory listing)
         if path == "/sys/class/input" {
             return Some(format!("{}/sysfs/class/input", self.base_path));
@@ -6,7 +6,8 @@ use std::os::unix::io::RawFd;
 use std::os::unix::net::UnixStream;
 use std::sync::Arc;
 use tracing::{debug, trace};
-use vimputti::protocol::DeviceHandshake;
+use vimputti::protocol::linux::*;
+use vimputti::protocol::{DeviceHandshake, framing};
 use vimputti::*;
 
 lazy_static::lazy_static! {
@@ -18,10 +19,31 @@ lazy_static::lazy_static! {
     static ref UDEV_MONITOR_FDS: Mutex<HashSet<RawFd>> = Mutex::new(HashSet::new());
     // Track Unix domain sockets (to intercept connect() calls for netlink)
     static ref UNIX_SOCKET_FDS: Mutex<HashSet<RawFd>> = Mutex::new(HashSet::new());
+    // Track virtual device FDs revoked with EVIOCREVOKE
+    static ref REVOKED_FDS: Mutex<HashSet<RawFd>> = Mutex::new(HashSet::new());
     // Track uploaded force feedback effects per device FD
     static ref FF_EFFECTS: Mutex<HashMap<RawFd, HashMap<i16, FfEffectInfo>>> = Mutex::new(HashMap::new());
+    // Track FDs registered in epoll sets
+    static ref EPOLL_REGISTRATIONS: Mutex<EpollRegistrations> = Mutex::new(EpollRegistrations::default());
+    // How many times to try reconnecting a dead device socket (VIMPUTTI_RECONNECT, 0 = never)
+    static ref RECONNECT_ATTEMPTS: u32 = std::env::var("VIMPUTTI_RECONNECT")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(0);
 }
 
+/// Delay between device socket reconnect attempts
+const RECONNECT_DELAY: std::time::Duration = std::time::Duration::from_millis(100);
+
+/// How long `open()` waits for the manager's device handshake
+const HANDSHAKE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(2);
+
+/// Largest device handshake accepted, far above any real device config
+const MAX_HANDSHAKE_LEN: usize = 64 * 1024;
+
+/// Effects a device FD can have uploaded at once, as reported by `EVIOCGEFFECTS`
+const FF_MAX_EFFECTS: i16 = 16;
+
 #[derive(Clone, Debug)]
 struct FfEffectInfo {
     effect_type: u16,
@@ -30,6 +52,63 @@ struct FfEffectInfo {
     duration_ms: u16,
 }
 
+/// Events and user data an FD was registered with in an epoll set
+#[derive(Clone, Copy, Debug)]
+struct EpollRegistration {
+    events: u32,
+    data: u64,
+}
+
+/// Registrations of FDs in epoll sets, indexed both ways so closing an FD is cheap
+#[derive(Default)]
+struct EpollRegistrations {
+    /// epfd -> (fd -> registration)
+    sets: HashMap<RawFd, HashMap<RawFd, EpollRegistration>>,
+    /// fd -> epfds it's registered in
+    registered_in: HashMap<RawFd, HashSet<RawFd>>,
+}
+impl EpollRegistrations {
+    fn insert(&mut self, epfd: RawFd, fd: RawFd, registration: EpollRegistration) {
+        self.sets.entry(epfd).or_default().insert(fd, registration);
+        self.registered_in.entry(fd).or_default().insert(epfd);
+    }
+
+    fn remove(&mut self, epfd: RawFd, fd: RawFd) {
+        if let Some(set) = self.sets.get_mut(&epfd) {
+            set.remove(&fd);
+            if set.is_empty() {
+                self.sets.remove(&epfd);
+            }
+        }
+        if let Some(epfds) = self.registered_in.get_mut(&fd) {
+            epfds.remove(&epfd);
+            if epfds.is_empty() {
+                self.registered_in.remove(&fd);
+            }
+        }
+    }
+
+    /// Forget an FD being closed, both as an epoll set and as an FD registered in sets
+    fn forget(&mut self, fd: RawFd) {
+        for epfd in self.registered_in.remove(&fd).unwrap_or_default() {
+            if let Some(set) = self.sets.get_mut(&epfd) {
+                set.remove(&fd);
+                if set.is_empty() {
+                    self.sets.remove(&epfd);
+                }
+            }
+        }
+        for member in self.sets.remove(&fd).unwrap_or_default().into_keys() {
+            if let Some(epfds) = self.registered_in.get_mut(&member) {
+                epfds.remove(&fd);
+                if epfds.is_empty() {
+                    self.registered_in.remove(&member);
+                }
+            }
+        }
+    }
+}
+
 #[repr(C, packed)]
 struct InputEvent32 {
     tv_sec: i32,
@@ -47,6 +126,7 @@ struct UinputConnection {
 pub(crate) struct DeviceInfo {
     pub(crate) device_id: DeviceId,
     pub(crate) event_node: String,
+    pub(crate) socket_path: String,
     pub(crate) is_joystick: bool,
     pub(crate) config: DeviceConfig,
 }
@@ -76,13 +156,13 @@ pub fn get_virtual_device_info(fd: RawFd) -> Option<DeviceInfo> {
     VIRTUAL_DEVICE_FDS.lock().get(&fd).cloned()
 }
 
+/// Base path of the manager, see `base_path`
 pub(crate) fn get_base_path() -> String {
-    "/tmp/vimputti".to_string()
+    crate::base_path::base_path().to_string()
 }
 
 /// Open a device node (actually connect to Unix socket)
 pub fn open_device_node(socket_path: &str, _flags: c_int) -> c_int {
-    use std::io::Read;
     use std::os::unix::io::IntoRawFd;
     use std::os::unix::net::UnixStream;
 
@@ -93,7 +173,6 @@ pub fn open_device_node(socket_path: &str, _flags: c_int) -> c_int {
             // Check if this is the uinput socket
             if socket_path.ends_with("/uinput") {
                 let fd = stream.as_raw_fd();
-
                 let connection = UinputConnection { stream };
 
                 UINPUT_FDS
@@ -114,40 +193,7 @@ pub fn open_device_node(socket_path: &str, _flags: c_int) -> c_int {
             // Check if this is a joystick device
             let is_joystick = event_node.starts_with("js");
 
-            // Receive device handshake from daemon
-            // Format: 4-byte length prefix + JSON handshake
-            let mut len_buf = [0u8; 4];
-            let handshake = match stream.read_exact(&mut len_buf) {
-                Ok(_) => {
-                    let handshake_len = u32::from_le_bytes(len_buf) as usize;
-                    debug!("Receiving device handshake ({} bytes)", handshake_len);
-
-                    let mut handshake_buf = vec![0u8; handshake_len];
-                    match stream.read_exact(&mut handshake_buf) {
-                        Ok(_) => match serde_json::from_slice::<DeviceHandshake>(&handshake_buf) {
-                            Ok(handshake) => {
-                                debug!(
-                                    "Successfully received device handshake: {}",
-                                    handshake.config.name
-                                );
-                                Some(handshake)
-                            }
-                            Err(e) => {
-                                debug!("Failed to deserialize device handshake: {}", e);
-                                None
-                            }
-                        },
-                        Err(e) => {
-                            debug!("Failed to read device handshake data: {}", e);
-                            None
-                        }
-                    }
-                }
-                Err(e) => {
-                    debug!("Failed to read handshake length: {}", e);
-                    None
-                }
-            };
+            let handshake = read_device_handshake(&mut stream);
 
             let fd = stream.into_raw_fd();
 
@@ -158,6 +204,7 @@ pub fn open_device_node(socket_path: &str, _flags: c_int) -> c_int {
                     DeviceInfo {
                         device_id: handshake.device_id,
                         event_node: event_node.clone(),
+                        socket_path: socket_path.to_string(),
                         is_joystick,
                         config: handshake.config.clone(),
                     },
@@ -181,11 +228,213 @@ pub fn open_device_node(socket_path: &str, _flags: c_int) -> c_int {
     }
 }
 
+/// Receive the device handshake the manager sends on connect
+///
+/// Format: frame header + JSON handshake (see `protocol::framing`)
+///
+/// Gives up after `HANDSHAKE_TIMEOUT` so a degraded manager can't block the application's
+/// `open()`. The stream is left blocking without a timeout, as the application expects.
+fn read_device_handshake(stream: &mut UnixStream) -> Option<DeviceHandshake> {
+    if let Err(e) = stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT)) {
+        debug!("Failed to set handshake timeout: {}", e);
+    }
+    let handshake = receive_device_handshake(stream);
+    if let Err(e) = stream.set_read_timeout(None) {
+        debug!("Failed to clear handshake timeout: {}", e);
+    }
+    handshake
+}
+
+fn receive_device_handshake(stream: &mut UnixStream) -> Option<DeviceHandshake> {
+    let handshake_buf = match framing::read_frame_limited(stream, MAX_HANDSHAKE_LEN) {
+        Ok(payload) => payload,
+        Err(e) => {
+            debug!("Failed to read device handshake: {}", e);
+            return None;
+        }
+    };
+    debug!("Received device handshake ({} bytes)", handshake_buf.len());
+
+    match DeviceHandshake::from_bytes(&handshake_buf) {
+        Ok(handshake) => {
+            debug!(
+                "Successfully received device handshake: {}",
+                handshake.config.name
+            );
+            Some(handshake)
+        }
+        Err(e) => {
+            debug!("Failed to deserialize device handshake: {}", e);
+            None
+        }
+    }
+}
+
+/// Try to reconnect a virtual device FD whose manager connection was lost
+///
+/// Only done if enabled through `VIMPUTTI_RECONNECT=<attempts>`. The new connection takes over
+/// the same FD number, file status flags and epoll registrations. Gives up if the device at
+/// the socket path is no longer the same controller.
+pub fn reconnect_virtual_device(fd: RawFd) -> bool {
+    use std::os::unix::io::IntoRawFd;
+
+    let attempts = *RECONNECT_ATTEMPTS;
+    if attempts == 0 {
+        return false;
+    }
+    let Some(info) = get_virtual_device_info(fd) else {
+        return false;
+    };
+
+    for attempt in 1..=attempts {
+        debug!(
+            "Reconnecting {} (attempt {}/{})",
+            info.socket_path, attempt, attempts
+        );
+
+        let Ok(mut stream) = UnixStream::connect(&info.socket_path) else {
+            std::thread::sleep(RECONNECT_DELAY);
+            continue;
+        };
+        let Some(handshake) = read_device_handshake(&mut stream) else {
+            std::thread::sleep(RECONNECT_DELAY);
+            continue;
+        };
+
+        if handshake.config.vendor_id != info.config.vendor_id
+            || handshake.config.product_id != info.config.product_id
+            || handshake.config.name != info.config.name
+        {
+            debug!(
+                "Device at {} is now {}, not reconnecting",
+                info.socket_path, handshake.config.name
+            );
+            return false;
+        }
+
+        let new_fd = stream.into_raw_fd();
+        let reconnected = unsafe {
+            let flags = libc::fcntl(fd, libc::F_GETFL);
+            let ok = libc::dup2(new_fd, fd) == fd;
+            if ok && flags >= 0 {
+                libc::fcntl(fd, libc::F_SETFL, flags);
+            }
+            libc::close(new_fd);
+            ok
+        };
+        if !reconnected {
+            return false;
+        }
+
+        if let Some(info) = VIRTUAL_DEVICE_FDS.lock().get_mut(&fd) {
+            info.device_id = handshake.device_id;
+            info.config = handshake.config;
+        }
+        restore_epoll_registrations(fd);
+        debug!("Reconnected fd {} to {}", fd, info.socket_path);
+        return true;
+    }
+
+    debug!("Giving up reconnecting {}", info.socket_path);
+    false
+}
+
+/// Register an FD whose connection was replaced in the epoll sets it was in again
+///
+/// epoll keeps registrations per open file, so the closed connection's were dropped with it.
+fn restore_epoll_registrations(fd: RawFd) {
+    let Some(orig_epoll_ctl) = crate::ORIGINAL_FUNCTIONS.epoll_ctl else {
+        return;
+    };
+    let restored: Vec<(RawFd, EpollRegistration)> = {
+        let registrations = EPOLL_REGISTRATIONS.lock();
+        registrations
+            .registered_in
+            .get(&fd)
+            .into_iter()
+            .flatten()
+            .filter_map(|&epfd| Some((epfd, *registrations.sets.get(&epfd)?.get(&fd)?)))
+            .collect()
+    };
+
+    for (epfd, registration) in restored {
+        let mut event = libc::epoll_event {
+            events: registration.events,
+            u64: registration.data,
+        };
+        if unsafe { orig_epoll_ctl(epfd, libc::EPOLL_CTL_ADD, fd, &mut event) } != 0 {
+            debug!(
+                "Failed to register reconnected fd {} in epoll {}: {}",
+                fd,
+                epfd,
+                std::io::Error::last_os_error()
+            );
+        }
+    }
+}
+
+/// Check if an FD is one of our virtual devices, first reconnecting it if the manager end hung
+/// up, see `reconnect_virtual_device`
+///
+/// Used where the application is about to use the FD, so ioctl(), poll() and epoll waits recover
+/// from a manager restart as read() does.
+pub fn lookup_virtual_device_fd(fd: RawFd) -> bool {
+    if !is_virtual_device_fd(fd) {
+        return false;
+    }
+    if *RECONNECT_ATTEMPTS > 0 && connection_lost(fd) {
+        reconnect_virtual_device(fd);
+    }
+    true
+}
+
+/// Reconnect the virtual device FDs registered in an epoll set whose connection was lost
+pub fn reconnect_epoll_members(epfd: RawFd) {
+    if *RECONNECT_ATTEMPTS == 0 {
+        return;
+    }
+    let members: Vec<RawFd> = EPOLL_REGISTRATIONS
+        .lock()
+        .sets
+        .get(&epfd)
+        .map(|set| set.keys().copied().collect())
+        .unwrap_or_default();
+    for fd in members {
+        lookup_virtual_device_fd(fd);
+    }
+}
+
+/// Check if the manager closed its end of a device connection, with nothing left to read
+fn connection_lost(fd: RawFd) -> bool {
+    let mut byte = 0u8;
+    let peeked = unsafe {
+        libc::recv(
+            fd,
+            (&raw mut byte).cast(),
+            1,
+            libc::MSG_PEEK | libc::MSG_DONTWAIT,
+        )
+    };
+    peeked == 0
+        || (peeked < 0
+            && matches!(
+                std::io::Error::last_os_error().raw_os_error(),
+                Some(libc::ECONNRESET | libc::ENOTCONN)
+            ))
+}
+
 /// Check if an FD is one of our virtual devices
 pub fn is_virtual_device_fd(fd: RawFd) -> bool {
     VIRTUAL_DEVICE_FDS.lock().contains_key(&fd)
 }
 
+/// Check if an FD is a virtual device revoked with EVIOCREVOKE
+///
+/// Everything but close() fails with ENODEV on those, like on a revoked evdev node.
+pub fn is_revoked_fd(fd: RawFd) -> bool {
+    REVOKED_FDS.lock().contains(&fd)
+}
+
 /// Check if an FD is a uinput emulator FD
 pub fn is_uinput_fd(fd: RawFd) -> bool {
     UINPUT_FDS.lock().contains_key(&fd)
@@ -200,6 +449,115 @@ pub fn is_udev_monitor_fd(fd: RawFd) -> bool {
     UDEV_MONITOR_FDS.lock().contains(&fd)
 }
 
+/// Record epoll_ctl() changes
+///
+/// All registrations are kept, not just those of uinput FDs: epoll only reports the user
+/// data, and other FDs in the same set may have been registered with the same data.
+pub fn track_epoll_ctl(epfd: RawFd, op: c_int, fd: RawFd, event: Option<(u32, u64)>) {
+    let mut registrations = EPOLL_REGISTRATIONS.lock();
+    match (op, event) {
+        (libc::EPOLL_CTL_ADD | libc::EPOLL_CTL_MOD, Some((events, data))) => {
+            if is_uinput_fd(fd) {
+                debug!(
+                    "epoll {}: tracking uinput fd {} (data={:#x})",
+                    epfd, fd, data
+                );
+            }
+            registrations.insert(epfd, fd, EpollRegistration { events, data });
+        }
+        (libc::EPOLL_CTL_DEL, _) => registrations.remove(epfd, fd),
+        _ => {}
+    }
+}
+
+/// Forget an FD that is being closed, both as an epoll set and as a registered FD
+pub fn forget_epoll_fd(fd: RawFd) {
+    EPOLL_REGISTRATIONS.lock().forget(fd);
+}
+
+/// Strip readable readiness from uinput FDs in epoll_wait() results
+///
+/// Uinput FDs never carry data to read, so any EPOLLIN reported for them is spurious.
+/// An event is only taken to be a uinput FD's if every FD registered in the set with its
+/// user data is a uinput FD. Events left with no readiness bits are removed and the
+/// remainder compacted. Returns the new event count.
+///
+/// The uinput FDs the readiness came from are re-registered without readable events, so a
+/// level-triggered set doesn't report the same readiness again on the next wait.
+///
+/// # Safety
+/// `events` must point to at least `count` valid epoll events.
+pub unsafe fn filter_uinput_epoll_events(
+    epfd: RawFd,
+    events: *mut libc::epoll_event,
+    count: c_int,
+) -> c_int {
+    if count <= 0 || events.is_null() {
+        return count;
+    }
+
+    const READABLE: u32 = (libc::EPOLLIN | libc::EPOLLRDNORM | libc::EPOLLPRI) as u32;
+
+    let mut registrations = EPOLL_REGISTRATIONS.lock();
+    let Some(set) = registrations.sets.get_mut(&epfd) else {
+        return count;
+    };
+    let uinput_fds = UINPUT_FDS.lock();
+    if !set.keys().any(|fd| uinput_fds.contains_key(fd)) {
+        return count;
+    }
+
+    let events_slice = unsafe { std::slice::from_raw_parts_mut(events, count as usize) };
+    let mut muted = Vec::new();
+    let mut kept = 0;
+    for idx in 0..events_slice.len() {
+        let mut event = events_slice[idx];
+        let data = event.u64;
+        let owners: Vec<_> = set
+            .iter_mut()
+            .filter(|(_, registration)| registration.data == data)
+            .collect();
+        if !owners.is_empty() && owners.iter().all(|(fd, _)| uinput_fds.contains_key(*fd)) {
+            if event.events & READABLE != 0 {
+                for (&fd, registration) in owners {
+                    if registration.events & READABLE != 0 {
+                        registration.events &= !READABLE;
+                        muted.push((fd, *registration));
+                    }
+                }
+            }
+            event.events &= !READABLE;
+            if event.events == 0 {
+                trace!(
+                    "epoll {}: suppressed readiness for uinput data {:#x}",
+                    epfd, data
+                );
+                continue;
+            }
+        }
+        events_slice[kept] = event;
+        kept += 1;
+    }
+    drop(uinput_fds);
+    drop(registrations);
+
+    if let Some(orig_epoll_ctl) = crate::ORIGINAL_FUNCTIONS.epoll_ctl {
+        for (fd, registration) in muted {
+            debug!(
+                "epoll {}: no longer waiting for uinput fd {} to be readable",
+                epfd, fd
+            );
+            let mut event = libc::epoll_event {
+                events: registration.events,
+                u64: registration.data,
+            };
+            unsafe { orig_epoll_ctl(epfd, libc::EPOLL_CTL_MOD, fd, &mut event) };
+        }
+    }
+
+    kept as c_int
+}
+
 /// Handle ioctl() calls on virtual device FDs
 pub unsafe fn handle_ioctl(fd: RawFd, request: c_uint, args: &mut std::ffi::VaList) -> c_int {
     // Get device info
@@ -223,30 +581,25 @@ unsafe fn handle_joystick_ioctl(
     args: &mut std::ffi::VaList,
     device_info: &DeviceInfo,
 ) -> c_int {
-    // Joystick interface ioctl constants
-    const JSIOCGVERSION: u32 = 0x80046a01;
-    const JSIOCGAXES: u32 = 0x80016a11;
-    const JSIOCGBUTTONS: u32 = 0x80016a12;
-    const JSIOCGNAME_BASE: u32 = 0x80006a13;
-    const JSIOCGBTNMAP: u32 = 0x80406a34;
-    const JSIOCGAXMAP: u32 = 0x80406a32;
-
-    let request_type = (request >> 8) & 0xFF;
-    let request_nr = request & 0xFF;
+    let request_type = ioc_type(request);
+    let request_nr = ioc_nr(request);
 
     match request {
         JSIOCGVERSION => {
-            let ptr: *mut c_int = unsafe { args.arg() };
+            let ptr: *mut c_int = unsafe { args.next_arg() };
             if !ptr.is_null() {
                 unsafe {
-                    *ptr = 0x020100; // Version 2.1.0
+                    *ptr = device_info
+                        .config
+                        .js_version
+                        .unwrap_or(protocol::DEFAULT_JS_VERSION);
                 }
             }
             0
         }
 
         JSIOCGAXES => {
-            let ptr: *mut u8 = unsafe { args.arg() };
+            let ptr: *mut u8 = unsafe { args.next_arg() };
             if !ptr.is_null() {
                 unsafe {
                     *ptr = device_info.num_axes();
@@ -256,7 +609,7 @@ unsafe fn handle_joystick_ioctl(
         }
 
         JSIOCGBUTTONS => {
-            let ptr: *mut u8 = unsafe { args.arg() };
+            let ptr: *mut u8 = unsafe { args.next_arg() };
             if !ptr.is_null() {
                 unsafe {
                     *ptr = device_info.num_buttons();
@@ -265,9 +618,10 @@ unsafe fn handle_joystick_ioctl(
             0
         }
 
-        JSIOCGAXMAP => {
-            let ptr: *mut u8 = unsafe { args.arg() };
-            let len = ((request >> 16) & 0x3FFF) as usize;
+        // Maps are matched by number, their size depends on the kernel headers of the caller
+        _ if request_type == JS_IOC_TYPE && request_nr == JSIOCGAXMAP_NR => {
+            let ptr: *mut u8 = unsafe { args.next_arg() };
+            let len = ioc_size(request);
 
             if !ptr.is_null() && len > 0 {
                 // Build axis map from device config
@@ -277,17 +631,19 @@ unsafe fn handle_joystick_ioctl(
                     axis_map.push(axis_config.axis.to_ev_code() as u8);
                 }
 
+                // Like joydev, entries past the device's axes are zero rather than left as is
                 let copy_len = std::cmp::min(axis_map.len(), len);
                 unsafe {
                     std::ptr::copy_nonoverlapping(axis_map.as_ptr(), ptr, copy_len);
+                    std::ptr::write_bytes(ptr.add(copy_len), 0, len - copy_len);
                 }
             }
             0
         }
 
-        JSIOCGBTNMAP => {
-            let ptr: *mut u16 = unsafe { args.arg() };
-            let len = ((request >> 16) & 0x3FFF) as usize / 2;
+        _ if request_type == JS_IOC_TYPE && request_nr == JSIOCGBTNMAP_NR => {
+            let ptr: *mut u16 = unsafe { args.next_arg() };
+            let len = ioc_size(request) / 2;
 
             if !ptr.is_null() && len > 0 {
                 // Build button map from device config
@@ -300,14 +656,15 @@ unsafe fn handle_joystick_ioctl(
                 let copy_len = std::cmp::min(button_map.len(), len);
                 unsafe {
                     std::ptr::copy_nonoverlapping(button_map.as_ptr(), ptr, copy_len);
+                    std::ptr::write_bytes(ptr.add(copy_len), 0, len - copy_len);
                 }
             }
             0
         }
 
-        _ if request_type == 0x6a && request_nr == 0x13 => {
-            let ptr: *mut u8 = unsafe { args.arg() };
-            let len = ((request >> 16) & 0xFF) as usize;
+        _ if request_type == JS_IOC_TYPE && request_nr == JSIOCGNAME_NR => {
+            let ptr: *mut u8 = unsafe { args.next_arg() };
+            let len = ioc_size(request);
 
             if !ptr.is_null() && len > 0 {
                 let name_bytes = device_info.device_name().as_bytes();
@@ -326,11 +683,56 @@ unsafe fn handle_joystick_ioctl(
 
         _ => {
             debug!("ioctl: unknown joystick request 0x{:08x}", request);
-            0
+            unsafe { handle_unknown_ioctl(request, args) }
         }
     }
 }
 
+/// Answer an ioctl the shim doesn't implement, by its direction
+///
+/// Behaves like a driver accepting the request without acting on it: requests without data
+/// (`_IOC_NONE`) and requests passing data in (`_IOC_WRITE`) succeed and are ignored, requests
+/// reading data out (`_IOC_READ`, also with `_IOC_WRITE`) get their buffer zeroed.
+/// Applies the same to evdev, joystick and uinput fds.
+unsafe fn handle_unknown_ioctl(request: c_uint, args: &mut std::ffi::VaList) -> c_int {
+    let size = ioc_size(request);
+
+    if ioc_dir(request) & IOC_READ != 0 && size > 0 {
+        let ptr: *mut u8 = unsafe { args.next_arg() };
+        if !ptr.is_null() {
+            unsafe {
+                std::ptr::write_bytes(ptr, 0, size);
+            }
+        }
+    }
+
+    0
+}
+
+/// Fuzz and flat EVIOCGABS reports for an axis
+///
+/// Those configured are reported as they are. Wide stick and trigger axes without any keep
+/// the noise filtering of real gamepad drivers, other axes such as `ABS_MISC` report none.
+fn reported_noise(axis: &AxisConfig) -> (i32, i32) {
+    let gamepad_axis = matches!(
+        axis.axis,
+        Axis::LeftStickX
+            | Axis::LeftStickY
+            | Axis::RightStickX
+            | Axis::RightStickY
+            | Axis::LowerLeftTrigger
+            | Axis::LowerRightTrigger
+    );
+
+    if axis.fuzz != 0 || axis.flat != 0 {
+        (axis.fuzz, axis.flat)
+    } else if gamepad_axis && axis.max > 1000 {
+        (16, 128)
+    } else {
+        (0, 0)
+    }
+}
+
 /// Handle evdev interface ioctl calls
 unsafe fn handle_evdev_ioctl(
     fd: RawFd,
@@ -338,41 +740,8 @@ unsafe fn handle_evdev_ioctl(
     args: &mut std::ffi::VaList,
     device_info: &DeviceInfo,
 ) -> c_int {
-    const EVIOCGVERSION: c_uint = 0x80044501;
-    const EVIOCGID: c_uint = 0x80084502;
-    // for uploading force feedback effect
-    const EVIOCSFF: c_uint = 0x40304580;
-
-    // evdev ioctl request number ranges
-    const EVIOCG_TYPE_MASK: u32 = 0xFF;
-    const EVIOCG_NR_MASK: u32 = 0xFF;
-    const EVIOCG_SIZE_SHIFT: u32 = 16;
-    const EVIOCG_SIZE_MASK: u32 = 0x3FFF;
-
-    // Request type for evdev ioctls
-    const EVDEV_IOC_TYPE: u32 = b'E' as u32;
-
-    // evdev ioctl number ranges
-    const EVIOCGBIT_NR_BASE: u32 = 0x20;
-    const EVIOCGBIT_NR_END: u32 = 0x40;
-    const EVIOCGABS_NR_BASE: u32 = 0x40;
-    const EVIOCGABS_NR_END: u32 = 0x80;
-
-    // Helper to extract ioctl components
-    fn extract_request_type(request: u32) -> u32 {
-        (request >> 8) & EVIOCG_TYPE_MASK
-    }
-
-    fn extract_request_nr(request: u32) -> u32 {
-        request & EVIOCG_NR_MASK
-    }
-
-    fn extract_request_size(request: u32) -> usize {
-        ((request >> EVIOCG_SIZE_SHIFT) & EVIOCG_SIZE_MASK) as usize
-    }
-
-    let request_nr = extract_request_nr(request);
-    let request_type = extract_request_type(request);
+    let request_nr = ioc_nr(request);
+    let request_type = ioc_type(request);
 
     debug!(
         "[evdev] ioctl called: fd={}, full=0x{:08x}, type=0x{:02x}, nr=0x{:02x}, node={}",
@@ -381,11 +750,15 @@ unsafe fn handle_evdev_ioctl(
 
     match request {
         EVIOCGVERSION => {
-            let ptr: *mut c_int = unsafe { args.arg() };
+            let ptr: *mut c_int = unsafe { args.next_arg() };
             if !ptr.is_null() {
-                debug!("[evdev] EVIOCGVERSION return: 0x010001");
+                let version = device_info
+                    .config
+                    .evdev_version
+                    .unwrap_or(protocol::DEFAULT_EVDEV_VERSION);
+                debug!("[evdev] EVIOCGVERSION return: {:#08x}", version);
                 unsafe {
-                    *ptr = 0x010001;
+                    *ptr = version;
                 }
             }
             0
@@ -399,7 +772,7 @@ unsafe fn handle_evdev_ioctl(
                 version: u16,
             }
 
-            let ptr: *mut InputId = unsafe { args.arg() };
+            let ptr: *mut InputId = unsafe { args.next_arg() };
             if !ptr.is_null() {
                 debug!(
                     "[evdev] EVIOCGID return: bustype={:?}, vendor_id={}, product_id={}, version={}",
@@ -410,7 +783,7 @@ unsafe fn handle_evdev_ioctl(
                 );
                 unsafe {
                     *ptr = InputId {
-                        bustype: device_info.config.bustype as u16,
+                        bustype: device_info.config.bustype.to_u16(),
                         vendor: device_info.config.vendor_id,
                         product: device_info.config.product_id,
                         version: device_info.config.version,
@@ -419,6 +792,20 @@ unsafe fn handle_evdev_ioctl(
             }
             0
         }
+        EVIOCREVOKE => {
+            // The kernel only accepts a NULL argument
+            let arg: *mut libc::c_void = unsafe { args.next_arg() };
+            if !arg.is_null() {
+                unsafe { *libc::__errno_location() = libc::EINVAL };
+                return -1;
+            }
+
+            debug!("[evdev] EVIOCREVOKE: revoking {}", device_info.event_node);
+            REVOKED_FDS.lock().insert(fd);
+            // Tear down the connection, pending and future events are gone with it
+            unsafe { libc::shutdown(fd, libc::SHUT_RDWR) };
+            0
+        }
         EVIOCSFF => {
             #[repr(C)]
             struct FfEffect {
@@ -446,7 +833,8 @@ unsafe fn handle_evdev_ioctl(
             #[derive(Copy, Clone)]
             union FfEffectUnion {
                 rumble: FfRumbleEffect,
-                _padding: [u8; 44],
+                // The largest member, periodic effects, holds a pointer
+                _padding: [u64; 4],
             }
 
             #[repr(C)]
@@ -455,57 +843,104 @@ unsafe fn handle_evdev_ioctl(
                 strong_magnitude: u16,
                 weak_magnitude: u16,
             }
+            const _: () = assert!(size_of::<FfEffect>() == ioc_size(EVIOCSFF));
 
-            let ptr: *mut FfEffect = unsafe { args.arg() };
-            if !ptr.is_null() {
-                let effect = unsafe { &mut *ptr };
-                debug!(
-                    "EVIOCSFF: Uploading FF effect type=0x{:x}, id={}, duration={}ms",
-                    effect.type_, effect.id, effect.replay.length
-                );
+            let ptr: *mut FfEffect = unsafe { args.next_arg() };
+            if ptr.is_null() {
+                unsafe { *libc::__errno_location() = libc::EFAULT };
+                return -1;
+            }
 
-                // Assign an effect ID if it's -1 (new effect)
-                let effect_id = if effect.id == -1 {
-                    // Simple: use a counter or just use 0 for single effect
-                    effect.id = 0;
-                    0
-                } else {
-                    effect.id
-                };
+            let effect = unsafe { &mut *ptr };
+            debug!(
+                "EVIOCSFF: Uploading FF effect type=0x{:x}, id={}, duration={}ms",
+                effect.type_, effect.id, effect.replay.length
+            );
 
-                // Store effect info
-                if effect.type_ == protocol::FF_RUMBLE {
-                    let rumble = unsafe { effect.u.rumble };
-                    let effect_info = FfEffectInfo {
-                        effect_type: effect.type_,
-                        strong_magnitude: rumble.strong_magnitude,
-                        weak_magnitude: rumble.weak_magnitude,
-                        duration_ms: effect.replay.length,
-                    };
+            // Only rumble is advertised, the kernel refuses other effects the same way
+            if effect.type_ != protocol::FF_RUMBLE {
+                unsafe { *libc::__errno_location() = libc::EINVAL };
+                return -1;
+            }
+
+            let mut ff_effects = FF_EFFECTS.lock();
+            let effects = ff_effects.entry(fd).or_default();
 
-                    FF_EFFECTS
-                        .lock()
-                        .entry(fd)
-                        .or_insert_with(HashMap::new)
-                        .insert(effect_id, effect_info.clone());
-
-                    debug!(
-                        "Stored rumble effect {}: strong={}, weak={}, duration={}ms",
-                        effect_id,
-                        rumble.strong_magnitude,
-                        rumble.weak_magnitude,
-                        effect.replay.length
-                    );
+            // New effects get the lowest free ID, others must replace an uploaded one
+            let effect_id = if effect.id == -1 {
+                match (0..FF_MAX_EFFECTS).find(|id| !effects.contains_key(id)) {
+                    Some(id) => id,
+                    None => {
+                        unsafe { *libc::__errno_location() = libc::ENOSPC };
+                        return -1;
+                    }
                 }
+            } else if effects.contains_key(&effect.id) {
+                effect.id
+            } else {
+                unsafe { *libc::__errno_location() = libc::EINVAL };
+                return -1;
+            };
+            effect.id = effect_id;
+
+            let rumble = unsafe { effect.u.rumble };
+            let effect_info = FfEffectInfo {
+                effect_type: effect.type_,
+                strong_magnitude: rumble.strong_magnitude,
+                weak_magnitude: rumble.weak_magnitude,
+                duration_ms: effect.replay.length,
+            };
+            effects.insert(effect_id, effect_info);
+            drop(ff_effects);
 
-                return 0;
+            debug!(
+                "Stored rumble effect {}: strong={}, weak={}, duration={}ms",
+                effect_id, rumble.strong_magnitude, rumble.weak_magnitude, effect.replay.length
+            );
+            write_feedback(
+                fd,
+                &FeedbackEvent::RumbleUpload {
+                    id: effect_id,
+                    strong: rumble.strong_magnitude,
+                    weak: rumble.weak_magnitude,
+                    duration: effect.replay.length,
+                },
+            );
+            0
+        }
+        EVIOCRMFF => {
+            // The effect ID is passed by value
+            let effect_id: c_int = unsafe { args.next_arg() };
+            debug!("EVIOCRMFF: Erasing FF effect {}", effect_id);
+
+            let removed = FF_EFFECTS
+                .lock()
+                .get_mut(&fd)
+                .and_then(|effects| effects.remove(&(effect_id as i16)));
+            if removed.is_none() {
+                unsafe { *libc::__errno_location() = libc::EINVAL };
+                return -1;
             }
-            -1
+
+            write_feedback(
+                fd,
+                &FeedbackEvent::RumbleErase {
+                    id: effect_id as i16,
+                },
+            );
+            0
+        }
+        EVIOCGEFFECTS => {
+            let ptr: *mut c_int = unsafe { args.next_arg() };
+            if !ptr.is_null() {
+                unsafe { *ptr = FF_MAX_EFFECTS as c_int };
+            }
+            0
         }
         // EVIOCGNAME - get device name
-        _ if extract_request_type(request) == EVDEV_IOC_TYPE && request_nr == 0x06 => {
-            let ptr: *mut u8 = unsafe { args.arg() };
-            let len = extract_request_size(request);
+        _ if request_type == EVDEV_IOC_TYPE && request_nr == EVIOCGNAME_NR => {
+            let ptr: *mut u8 = unsafe { args.next_arg() };
+            let len = ioc_size(request);
 
             if !ptr.is_null() && len > 0 {
                 let name_str = device_info.device_name();
@@ -521,9 +956,9 @@ unsafe fn handle_evdev_ioctl(
             }
         }
         // EVIOCGPHYS - get physical location
-        _ if extract_request_type(request) == EVDEV_IOC_TYPE && request_nr == 0x07 => {
-            let ptr: *mut u8 = unsafe { args.arg() };
-            let len = extract_request_size(request);
+        _ if request_type == EVDEV_IOC_TYPE && request_nr == EVIOCGPHYS_NR => {
+            let ptr: *mut u8 = unsafe { args.next_arg() };
+            let len = ioc_size(request);
 
             if !ptr.is_null() && len > 0 {
                 let phys_str = format!("usb-vimputti.0/input{}\0", device_info.device_id);
@@ -539,9 +974,9 @@ unsafe fn handle_evdev_ioctl(
             }
         }
         // EVIOCGUNIQ - get unique identifier
-        _ if extract_request_type(request) == EVDEV_IOC_TYPE && request_nr == 0x08 => {
-            let ptr: *mut u8 = unsafe { args.arg() };
-            let len = extract_request_size(request);
+        _ if request_type == EVDEV_IOC_TYPE && request_nr == EVIOCGUNIQ_NR => {
+            let ptr: *mut u8 = unsafe { args.next_arg() };
+            let len = ioc_size(request);
 
             if !ptr.is_null() && len > 0 {
                 // Use connection_id to make each device unique
@@ -558,93 +993,49 @@ unsafe fn handle_evdev_ioctl(
             }
         }
         // EVIOCGPROP - get device properties
-        _ if extract_request_type(request) == EVDEV_IOC_TYPE && request_nr == 0x09 => {
-            let ptr: *mut u8 = unsafe { args.arg() };
-            let len = extract_request_size(request);
+        _ if request_type == EVDEV_IOC_TYPE && request_nr == EVIOCGPROP_NR => {
+            let ptr: *mut u8 = unsafe { args.next_arg() };
+            let len = ioc_size(request);
 
             if !ptr.is_null() && len > 0 {
-                debug!("[evdev] EVIOCGPROP return: 0",);
-                unsafe {
-                    std::ptr::write_bytes(ptr, 0, len);
-                }
-                0
+                let props = protocol::capabilities::prop_bitmap(&device_info.config);
+                debug!("[evdev] EVIOCGPROP return: {}", props.to_sysfs_string());
+                let buf = unsafe { std::slice::from_raw_parts_mut(ptr, len) };
+                props.write_to(buf) as c_int
             } else {
                 -1
             }
         }
         // EVIOCGBIT(ev, len) - get event bits for specific event type
-        _ if extract_request_type(request) == EVDEV_IOC_TYPE
-            && request_nr >= EVIOCGBIT_NR_BASE
-            && request_nr < EVIOCGBIT_NR_END =>
+        _ if request_type == EVDEV_IOC_TYPE
+            && (EVIOCGBIT_NR..EVIOCGABS_NR).contains(&request_nr) =>
         {
-            let ev_type = request_nr - EVIOCGBIT_NR_BASE;
-            let ptr: *mut u8 = unsafe { args.arg() };
-            let len = extract_request_size(request);
+            let ev_type = request_nr - EVIOCGBIT_NR;
+            let ptr: *mut u8 = unsafe { args.next_arg() };
+            let len = ioc_size(request);
 
             if !ptr.is_null() && len > 0 {
-                // Clear buffer
-                unsafe {
-                    std::ptr::write_bytes(ptr, 0, len);
-                }
-
                 debug!("[evdev] EVIOCGBIT return event bits: type={}", ev_type);
 
-                // Set bits based on device config
-                match ev_type as u16 {
-                    0 => {
-                        if len > 0 {
-                            unsafe {
-                                *ptr = 0b00001011;
-                            }
-                        }
-                    }
-                    EV_KEY => {
-                        for button in &device_info.config.buttons {
-                            let code = button.to_ev_code() as usize;
-                            unsafe {
-                                *ptr.add(code / 8) |= 1 << (code % 8);
-                            }
-                        }
-                    }
-                    EV_REL => {
-                        // No relative axes in our virtual devices..
-                    }
-                    EV_ABS => {
-                        for axis in &device_info.config.axes {
-                            let code = axis.axis.to_ev_code() as usize;
-                            unsafe {
-                                *ptr.add(code / 8) |= 1 << (code % 8);
-                            }
-                        }
-                    }
-                    EV_FF => {
-                        // Advertise force feedback capabilities
-                        let ff_rumble_code = protocol::FF_RUMBLE as usize;
-                        let byte_index = ff_rumble_code / 8;
-                        let bit_index = ff_rumble_code % 8;
-
-                        if len > byte_index {
-                            unsafe {
-                                *ptr.add(byte_index) |= 1 << bit_index;
-                            }
-                        }
-                    }
-                    _ => {
+                // Fill from the same bitmaps sysfs shows, zeroing what doesn't apply. Like the
+                // kernel, returns the bytes copied, at most the whole bitmap (96 for EV_KEY)
+                let buf = unsafe { std::slice::from_raw_parts_mut(ptr, len) };
+                match protocol::capabilities::ev_bitmap(&device_info.config).get(ev_type as u16) {
+                    Some(bitmap) => bitmap.write_to(buf) as c_int,
+                    None => {
                         debug!("ioctl EVIOCGBIT({}): unknown type", ev_type);
+                        buf.fill(0);
+                        0
                     }
                 }
-                0
             } else {
                 -1
             }
         }
         // EVIOCGABS(abs) - get abs axis info
-        _ if extract_request_type(request) == EVDEV_IOC_TYPE
-            && request_nr >= EVIOCGABS_NR_BASE
-            && request_nr < EVIOCGABS_NR_END =>
-        {
-            let axis_code = request_nr - EVIOCGABS_NR_BASE;
-            let ptr: *mut LinuxAbsEvent = unsafe { args.arg() };
+        _ if request_type == EVDEV_IOC_TYPE && is_eviocgabs_nr(request_nr) => {
+            let axis_code = request_nr - EVIOCGABS_NR;
+            let ptr: *mut LinuxAbsEvent = unsafe { args.next_arg() };
 
             if !ptr.is_null() {
                 // Try to find the axis in the device config
@@ -654,24 +1045,27 @@ unsafe fn handle_evdev_ioctl(
                         .axes
                         .iter()
                         .find(|a| a.axis.to_ev_code() as u32 == axis_code)
-                        .map(|a| LinuxAbsEvent {
-                            value: 0,
-                            minimum: a.min,
-                            maximum: a.max,
-                            fuzz: if a.max > 1000 { 16 } else { 0 },
-                            flat: if a.max > 1000 { 128 } else { 0 },
-                            resolution: 0,
+                        .map(|a| {
+                            let (fuzz, flat) = reported_noise(a);
+                            LinuxAbsEvent {
+                                value: a.rest(),
+                                minimum: a.min,
+                                maximum: a.max,
+                                fuzz,
+                                flat,
+                                resolution: a.resolution,
+                            }
                         })
                 };
 
-                // Fallback to defaults if not found
+                // Like the kernel, axes that aren't advertised read as all zero
                 unsafe {
                     *ptr = axis_info.unwrap_or(LinuxAbsEvent {
                         value: 0,
-                        minimum: -32768,
-                        maximum: 32767,
-                        fuzz: 16,
-                        flat: 128,
+                        minimum: 0,
+                        maximum: 0,
+                        fuzz: 0,
+                        flat: 0,
                         resolution: 0,
                     });
                 }
@@ -681,9 +1075,9 @@ unsafe fn handle_evdev_ioctl(
             }
         }
         // EVIOCGKEY - get current key state (bitmap of pressed keys)
-        _ if extract_request_type(request) == EVDEV_IOC_TYPE && request_nr == 0x18 => {
-            let ptr: *mut u8 = unsafe { args.arg() };
-            let len = extract_request_size(request);
+        _ if request_type == EVDEV_IOC_TYPE && request_nr == EVIOCGKEY_NR => {
+            let ptr: *mut u8 = unsafe { args.next_arg() };
+            let len = ioc_size(request);
 
             if !ptr.is_null() && len > 0 {
                 // All keys are released (zeros)
@@ -700,27 +1094,15 @@ unsafe fn handle_evdev_ioctl(
             }
         }
         _ => {
-            let req_type = extract_request_type(request);
-            let req_nr = extract_request_nr(request);
-            let req_size = extract_request_size(request);
-
             debug!(
                 "ioctl: unknown evdev request type=0x{:02x} nr=0x{:02x} size={} full=0x{:08x}",
-                req_type, req_nr, req_size, request
+                request_type,
+                request_nr,
+                ioc_size(request),
+                request
             );
 
-            // For read ioctls, zero out the buffer
-            if (request & 0xC0000000) == 0x80000000 {
-                let ptr: *mut u8 = unsafe { args.arg() };
-                let size = extract_request_size(request);
-                if !ptr.is_null() && size > 0 {
-                    unsafe {
-                        std::ptr::write_bytes(ptr, 0, size);
-                    }
-                }
-            }
-
-            0
+            unsafe { handle_unknown_ioctl(request, args) }
         }
     }
 }
@@ -858,18 +1240,31 @@ pub unsafe fn handle_virtual_device_write(
     count as libc::ssize_t
 }
 
+/// Forward feedback to the manager on a virtual device FD, for its feedback readers
+fn write_feedback(fd: RawFd, event: &FeedbackEvent) {
+    let bytes: Vec<u8> = event
+        .to_linux_input_events()
+        .iter()
+        .flat_map(|e| e.to_bytes())
+        .collect();
+    if let Some(orig_write) = crate::ORIGINAL_FUNCTIONS.write {
+        unsafe { orig_write(fd, bytes.as_ptr() as *const _, bytes.len()) };
+    }
+}
+
 /// Clean up when a virtual device FD is closed
 pub fn close_virtual_device(fd: RawFd) {
     //VIRTUAL_DEVICE_FDS.lock().remove(&fd);
     UINPUT_FDS.lock().remove(&fd);
     UDEV_MONITOR_FDS.lock().remove(&fd);
     UNIX_SOCKET_FDS.lock().remove(&fd);
+    REVOKED_FDS.lock().remove(&fd);
     FF_EFFECTS.lock().remove(&fd);
 }
 
 // Helper to send uinput request and get response
 fn send_uinput_request(fd: RawFd, request: vimputti::protocol::UinputRequest) -> c_int {
-    use std::io::{Read, Write};
+    use std::io::Write;
 
     let connection_arc = {
         let uinput_fds = UINPUT_FDS.lock();
@@ -884,7 +1279,7 @@ fn send_uinput_request(fd: RawFd, request: vimputti::protocol::UinputRequest) ->
 
     let mut connection = connection_arc.lock();
 
-    // Serialize request with length prefix
+    // Serialize request into a frame
     let request_bytes = match request.to_bytes() {
         Ok(b) => b,
         Err(e) => {
@@ -895,7 +1290,7 @@ fn send_uinput_request(fd: RawFd, request: vimputti::protocol::UinputRequest) ->
 
     trace!("Sending {} bytes to uinput fd={}", request_bytes.len(), fd);
 
-    // Send request (4-byte length prefix + JSON)
+    // Send request and read the response (frame header + JSON)
     if let Err(e) = connection.stream.write_all(&request_bytes) {
         debug!("Failed to write request to fd={}: {}", fd, e);
         return -1;
@@ -904,34 +1299,14 @@ fn send_uinput_request(fd: RawFd, request: vimputti::protocol::UinputRequest) ->
         debug!("Failed to flush fd={}: {}", fd, e);
         return -1;
     }
-
-    // Read response - 4-byte length prefix first
-    let mut len_buf = [0u8; 4];
-    match connection.stream.read_exact(&mut len_buf) {
-        Ok(_) => {}
+    let response_buf = match framing::read_frame(&mut connection.stream) {
+        Ok(payload) => payload,
         Err(e) => {
-            debug!("Failed to read response length from fd={}: {}", fd, e);
+            debug!("Failed to read response from fd={}: {}", fd, e);
             return -1;
         }
-    }
-
-    let response_len = u32::from_le_bytes(len_buf) as usize;
-    if response_len == 0 || response_len > 1_000_000 {
-        debug!("Invalid response length: {} from fd={}", response_len, fd);
-        return -1;
-    }
-
-    trace!("Reading {} byte response from fd={}", response_len, fd);
-
-    // Read response body
-    let mut response_buf = vec![0u8; response_len];
-    match connection.stream.read_exact(&mut response_buf) {
-        Ok(_) => {}
-        Err(e) => {
-            debug!("Failed to read response body from fd={}: {}", fd, e);
-            return -1;
-        }
-    }
+    };
+    trace!("Read {} byte response from fd={}", response_buf.len(), fd);
 
     let response: vimputti::protocol::UinputResponse =
         match vimputti::protocol::UinputResponse::from_bytes(&response_buf) {
@@ -953,30 +1328,13 @@ pub unsafe fn handle_uinput_ioctl(
     request: c_uint,
     args: &mut std::ffi::VaList,
 ) -> c_int {
-    const UI_SET_EVBIT: c_uint = 0x40045564;
-    const UI_SET_KEYBIT: c_uint = 0x40045565;
-    const UI_SET_RELBIT: c_uint = 0x40045566;
-    const UI_SET_ABSBIT: c_uint = 0x40045567;
-    const UI_SET_MSCBIT: c_uint = 0x40045568;
-    const UI_SET_LEDBIT: c_uint = 0x40045569;
-    const UI_SET_SNDBIT: c_uint = 0x4004556a;
-    const UI_SET_FFBIT: c_uint = 0x4004556b;
-    const UI_SET_PHYS: c_uint = 0x4004556c;
-    const UI_SET_SWBIT: c_uint = 0x4004556d;
-    const UI_SET_PROPBIT: c_uint = 0x4004556e;
-
-    const UI_DEV_SETUP: c_uint = 0x405c5503;
-    const UI_DEV_CREATE: c_uint = 0x5501;
-    const UI_DEV_DESTROY: c_uint = 0x5502;
-    const UI_ABS_SETUP: c_uint = 0x401c5504;
-
     const FIONREAD: c_uint = 0x5421;
 
     debug!("uinput ioctl: fd={}, request=0x{:x}", fd, request);
 
     match request {
         UI_SET_EVBIT => {
-            let ev_type: c_uint = unsafe { args.arg() };
+            let ev_type: c_uint = unsafe { args.next_arg() };
             send_uinput_request(
                 fd,
                 vimputti::protocol::UinputRequest::SetEvBit {
@@ -986,7 +1344,7 @@ pub unsafe fn handle_uinput_ioctl(
         }
 
         UI_SET_KEYBIT => {
-            let key_code: c_uint = unsafe { args.arg() };
+            let key_code: c_uint = unsafe { args.next_arg() };
             send_uinput_request(
                 fd,
                 vimputti::protocol::UinputRequest::SetKeyBit {
@@ -996,7 +1354,7 @@ pub unsafe fn handle_uinput_ioctl(
         }
 
         UI_SET_ABSBIT => {
-            let abs_code: c_uint = unsafe { args.arg() };
+            let abs_code: c_uint = unsafe { args.next_arg() };
             send_uinput_request(
                 fd,
                 vimputti::protocol::UinputRequest::SetAbsBit {
@@ -1006,7 +1364,7 @@ pub unsafe fn handle_uinput_ioctl(
         }
 
         UI_SET_RELBIT => {
-            let rel_code: c_uint = unsafe { args.arg() };
+            let rel_code: c_uint = unsafe { args.next_arg() };
             send_uinput_request(
                 fd,
                 vimputti::protocol::UinputRequest::SetRelBit {
@@ -1021,7 +1379,7 @@ pub unsafe fn handle_uinput_ioctl(
                 code: u16,
                 absinfo: vimputti::LinuxAbsEvent,
             }
-            let ptr: *const UiAbsSetup = unsafe { args.arg() };
+            let ptr: *const UiAbsSetup = unsafe { args.next_arg() };
             if !ptr.is_null() {
                 let setup = unsafe { &*ptr };
                 send_uinput_request(
@@ -1043,7 +1401,7 @@ pub unsafe fn handle_uinput_ioctl(
                 name: [u8; 80],
                 ff_effects_max: u32,
             }
-            let ptr: *const UiSetup = unsafe { args.arg() };
+            let ptr: *const UiSetup = unsafe { args.next_arg() };
             if !ptr.is_null() {
                 let setup = unsafe { &*ptr };
                 let name = std::ffi::CStr::from_bytes_until_nul(&setup.name)
@@ -1081,7 +1439,7 @@ pub unsafe fn handle_uinput_ioctl(
 
         FIONREAD => {
             // Return 0 bytes available (no data to read from uinput)
-            let ptr: *mut c_int = unsafe { args.arg() };
+            let ptr: *mut c_int = unsafe { args.next_arg() };
             if !ptr.is_null() {
                 unsafe {
                     *ptr = 0;
@@ -1092,7 +1450,7 @@ pub unsafe fn handle_uinput_ioctl(
 
         _ => {
             debug!("[UINPUT] Unknown ioctl request 0x{:x}", request);
-            0
+            unsafe { handle_unknown_ioctl(request, args) }
         }
     }
 }
@@ -0,0 +1,896 @@
+//! Evdev ioctls answered by the shim
+//!
+//! Each test sets up a device, then runs its `preloaded_` counterpart with the shim
+//! preloaded, see `preload`.
+
+#[path = "../../tests/common/mod.rs"]
+mod common;
+mod preload;
+
+use common::TestManager;
+use preload::{open, preloaded, run_preloaded, run_preloaded_with};
+use vimputti::protocol::linux::*;
+use vimputti::protocol::*;
+use vimputti::{ControllerBuilder, ControllerTemplates};
+
+#[tokio::test(flavor = "multi_thread")]
+async fn evdev_version_is_configurable() {
+    let manager = TestManager::start().await;
+    let client = manager.client().await;
+    let _device = client
+        .create_device(
+            ControllerBuilder::new("Versioned")
+                .button(Button::A)
+                .evdev_version(0x010002)
+                .js_version(0x020200)
+                .build(),
+        )
+        .await
+        .unwrap();
+
+    run_preloaded(&manager, "preloaded_evdev_version_is_configurable").await;
+}
+
+#[test]
+#[ignore = "run with the shim preloaded"]
+fn preloaded_evdev_version_is_configurable() {
+    if !preloaded() {
+        return;
+    }
+
+    let fd = open("/dev/input/event0", libc::O_RDONLY | libc::O_NONBLOCK);
+    let mut version: libc::c_int = 0;
+    assert_eq!(
+        unsafe { libc::ioctl(fd, EVIOCGVERSION as _, &mut version) },
+        0
+    );
+    assert_eq!(version, 0x010002);
+
+    let fd = open("/dev/input/js0", libc::O_RDONLY | libc::O_NONBLOCK);
+    assert_eq!(
+        unsafe { libc::ioctl(fd, JSIOCGVERSION as _, &mut version) },
+        0
+    );
+    assert_eq!(version, 0x020200);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn empty_path_fstatat_reports_char_device() {
+    let manager = TestManager::start().await;
+    let client = manager.client().await;
+    let _device = client
+        .create_device(ControllerBuilder::new("Stat").button(Button::A).build())
+        .await
+        .unwrap();
+
+    run_preloaded(&manager, "preloaded_empty_path_fstatat_reports_char_device").await;
+}
+
+#[test]
+#[ignore = "run with the shim preloaded"]
+fn preloaded_empty_path_fstatat_reports_char_device() {
+    if !preloaded() {
+        return;
+    }
+
+    for (path, rdev) in [
+        ("/dev/input/event0", libc::makedev(13, 64)),
+        ("/dev/input/js0", libc::makedev(81, 0)),
+    ] {
+        let fd = open(path, libc::O_RDONLY | libc::O_NONBLOCK);
+
+        let mut st: libc::stat = unsafe { std::mem::zeroed() };
+        assert_eq!(
+            unsafe { libc::fstatat(fd, c"".as_ptr(), &mut st, libc::AT_EMPTY_PATH) },
+            0
+        );
+        assert_eq!(st.st_mode & libc::S_IFMT, libc::S_IFCHR, "{}", path);
+        assert_eq!(st.st_rdev, rdev, "{}", path);
+
+        let mut st: libc::stat64 = unsafe { std::mem::zeroed() };
+        assert_eq!(
+            unsafe { libc::fstatat64(fd, c"".as_ptr(), &mut st, libc::AT_EMPTY_PATH) },
+            0
+        );
+        assert_eq!(st.st_mode & libc::S_IFMT, libc::S_IFCHR, "{}", path);
+        assert_eq!(st.st_rdev, rdev, "{}", path);
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn trigger_happy_buttons_are_all_reported() {
+    let manager = TestManager::start().await;
+    let client = manager.client().await;
+    let _device = client
+        .create_device(
+            ControllerBuilder::new("HOTAS")
+                .buttons((1..=40).map(Button::TriggerHappy))
+                .build(),
+        )
+        .await
+        .unwrap();
+
+    run_preloaded(&manager, "preloaded_trigger_happy_buttons_are_all_reported").await;
+}
+
+#[test]
+#[ignore = "run with the shim preloaded"]
+fn preloaded_trigger_happy_buttons_are_all_reported() {
+    if !preloaded() {
+        return;
+    }
+
+    let fd = open("/dev/input/event0", libc::O_RDONLY | libc::O_NONBLOCK);
+    let mut bits = [0u8; KEY_MAX as usize / 8 + 1];
+    let copied = unsafe { libc::ioctl(fd, eviocgbit(EV_KEY, bits.len()) as _, bits.as_mut_ptr()) };
+    assert_eq!(copied, bits.len() as i32);
+
+    let set: Vec<u16> = (0..=KEY_MAX)
+        .filter(|&code| bits[code as usize / 8] & (1 << (code % 8)) != 0)
+        .collect();
+    let expected: Vec<u16> = (1..=40)
+        .map(|n| Button::TriggerHappy(n).to_ev_code())
+        .collect();
+    assert_eq!(set, expected);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn undersized_eviocgbit_buffer_is_not_overrun() {
+    let manager = TestManager::start().await;
+    let client = manager.client().await;
+    let _device = client
+        .create_device(
+            ControllerBuilder::new("High Codes")
+                .buttons([Button::A, Button::TriggerHappy(40)])
+                .axis(Axis::LeftStickX, -32768, 32767)
+                .axis(Axis::DPadY, -1, 1)
+                .build(),
+        )
+        .await
+        .unwrap();
+
+    run_preloaded(
+        &manager,
+        "preloaded_undersized_eviocgbit_buffer_is_not_overrun",
+    )
+    .await;
+}
+
+#[test]
+#[ignore = "run with the shim preloaded"]
+fn preloaded_undersized_eviocgbit_buffer_is_not_overrun() {
+    if !preloaded() {
+        return;
+    }
+
+    const CANARY: u8 = 0xa5;
+    let fd = open("/dev/input/event0", libc::O_RDONLY | libc::O_NONBLOCK);
+
+    // BTN_SOUTH is in byte 38, BTN_TRIGGER_HAPPY40 in byte 92 and ABS_HAT0Y in byte 2, past
+    // the lengths the ioctls are told about
+    for (ev_type, len) in [(EV_KEY, 8), (EV_KEY, 40), (EV_ABS, 1)] {
+        let mut buf = [CANARY; 128];
+        let copied = unsafe { libc::ioctl(fd, eviocgbit(ev_type, len) as _, buf.as_mut_ptr()) };
+        assert_eq!(copied, len as i32, "type {} len {}", ev_type, len);
+        assert!(
+            buf[len..].iter().all(|&b| b == CANARY),
+            "type {} len {} wrote past the buffer",
+            ev_type,
+            len
+        );
+    }
+
+    // What fits is still filled in
+    let mut buf = [CANARY; 128];
+    unsafe { libc::ioctl(fd, eviocgbit(EV_KEY, 40) as _, buf.as_mut_ptr()) };
+    let south = Button::A.to_ev_code() as usize;
+    assert_ne!(buf[south / 8] & (1 << (south % 8)), 0);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn revoked_fd_fails_with_enodev() {
+    let manager = TestManager::start().await;
+    let client = manager.client().await;
+    let _device = client.create_device(common::gamepad()).await.unwrap();
+
+    run_preloaded(&manager, "preloaded_revoked_fd_fails_with_enodev").await;
+}
+
+#[test]
+#[ignore = "run with the shim preloaded"]
+fn preloaded_revoked_fd_fails_with_enodev() {
+    if !preloaded() {
+        return;
+    }
+    let last_error = || std::io::Error::last_os_error().raw_os_error();
+
+    let fd = open("/dev/input/event0", libc::O_RDWR | libc::O_NONBLOCK);
+    // Like the kernel, only a NULL argument is accepted
+    assert_eq!(unsafe { libc::ioctl(fd, EVIOCREVOKE as _, 1usize) }, -1);
+    assert_eq!(last_error(), Some(libc::EINVAL));
+    assert_eq!(unsafe { libc::ioctl(fd, EVIOCREVOKE as _, 0usize) }, 0);
+
+    let mut version: libc::c_int = 0;
+    assert_eq!(
+        unsafe { libc::ioctl(fd, EVIOCGVERSION as _, &mut version) },
+        -1
+    );
+    assert_eq!(last_error(), Some(libc::ENODEV));
+
+    let mut event = [0u8; 24];
+    assert_eq!(
+        unsafe { libc::read(fd, event.as_mut_ptr().cast(), event.len()) },
+        -1
+    );
+    assert_eq!(last_error(), Some(libc::ENODEV));
+    assert_eq!(
+        unsafe { libc::write(fd, event.as_ptr().cast(), event.len()) },
+        -1
+    );
+    assert_eq!(last_error(), Some(libc::ENODEV));
+
+    assert_eq!(unsafe { libc::close(fd) }, 0);
+
+    // A new open of the node works again
+    let fd = open("/dev/input/event0", libc::O_RDONLY | libc::O_NONBLOCK);
+    assert_eq!(
+        unsafe { libc::ioctl(fd, EVIOCGVERSION as _, &mut version) },
+        0
+    );
+}
+
+/// A device using every event type the capability helper knows about
+fn capabilities_config() -> DeviceConfig {
+    ControllerBuilder::new("Everything")
+        .buttons([Button::A, Button::B, Button::TriggerHappy(3)])
+        .axis(Axis::LeftStickX, -32768, 32767)
+        .axis(Axis::RightStickY, -32768, 32767)
+        .rel_axis(RelAxis::WheelV)
+        .emit_sequence(true)
+        .build()
+}
+
+const EV_TYPES: [u16; 6] = [0, EV_KEY, EV_REL, EV_ABS, EV_MSC, EV_FF];
+
+#[tokio::test(flavor = "multi_thread")]
+async fn capability_helper_matches_sysfs_and_eviocgbit() {
+    let manager = TestManager::start().await;
+    let client = manager.client().await;
+    let _device = client.create_device(capabilities_config()).await.unwrap();
+
+    capabilities::ensure_consistent(&capabilities_config()).unwrap();
+    let bitmaps = capabilities::ev_bitmap(&capabilities_config());
+    let caps = manager
+        .base_path()
+        .join("sysfs/class/input/input0/capabilities");
+    for (name, max, bitmap) in [
+        ("ev", EV_MAX, &bitmaps.ev),
+        ("key", KEY_MAX, &bitmaps.key),
+        ("rel", REL_MAX, &bitmaps.rel),
+        ("abs", ABS_MAX, &bitmaps.abs),
+        ("msc", MSC_MAX, &bitmaps.msc),
+        ("ff", FF_MAX, &bitmaps.ff),
+    ] {
+        let sysfs = std::fs::read_to_string(caps.join(name)).unwrap();
+        assert_eq!(
+            capabilities::Bitmap::from_sysfs_string(&sysfs, max).as_ref(),
+            Some(bitmap),
+            "{}",
+            name
+        );
+    }
+
+    run_preloaded(
+        &manager,
+        "preloaded_capability_helper_matches_sysfs_and_eviocgbit",
+    )
+    .await;
+}
+
+#[test]
+#[ignore = "run with the shim preloaded"]
+fn preloaded_capability_helper_matches_sysfs_and_eviocgbit() {
+    if !preloaded() {
+        return;
+    }
+
+    let bitmaps = capabilities::ev_bitmap(&capabilities_config());
+    let fd = open("/dev/input/event0", libc::O_RDONLY | libc::O_NONBLOCK);
+    for ev_type in EV_TYPES {
+        let expected = bitmaps.get(ev_type).unwrap().to_bytes();
+        let mut bits = vec![0u8; expected.len()];
+        let copied =
+            unsafe { libc::ioctl(fd, eviocgbit(ev_type, bits.len()) as _, bits.as_mut_ptr()) };
+        assert_eq!(copied, bits.len() as i32, "type {}", ev_type);
+        assert_eq!(bits, expected, "type {}", ev_type);
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn truncated_handshake_doesnt_block_open() {
+    use std::io::Write;
+
+    let manager = TestManager::start().await;
+
+    // A degraded manager on event0: a handshake cut short, then one claiming to be huge
+    let frame = DeviceHandshake {
+        device_id: 0,
+        config: common::gamepad(),
+    }
+    .to_bytes()
+    .unwrap();
+    let mut oversized = frame.clone();
+    oversized[4..8].copy_from_slice(&(framing::MAX_FRAME_LEN as u32).to_le_bytes());
+
+    let listener = std::os::unix::net::UnixListener::bind(manager.node_path("event0")).unwrap();
+    let server = std::thread::spawn(move || {
+        let mut streams = Vec::new();
+        for bytes in [&frame[..frame.len() / 2], &oversized[..]] {
+            let (mut stream, _) = listener.accept().unwrap();
+            stream.write_all(bytes).unwrap();
+            // Kept open, so the shim only stops waiting on its own
+            streams.push(stream);
+        }
+        streams
+    });
+
+    run_preloaded(&manager, "preloaded_truncated_handshake_doesnt_block_open").await;
+    drop(server.join().unwrap());
+}
+
+#[test]
+#[ignore = "run with the shim preloaded"]
+fn preloaded_truncated_handshake_doesnt_block_open() {
+    if !preloaded() {
+        return;
+    }
+
+    // Given up on after the shim's two second timeout
+    let start = std::time::Instant::now();
+    let fd = open("/dev/input/event0", libc::O_RDONLY);
+    let elapsed = start.elapsed();
+    assert!(elapsed < std::time::Duration::from_secs(4), "{:?}", elapsed);
+    unsafe { libc::close(fd) };
+
+    // Rejected by its length right away
+    let start = std::time::Instant::now();
+    let fd = open("/dev/input/event0", libc::O_RDONLY);
+    let elapsed = start.elapsed();
+    assert!(elapsed < std::time::Duration::from_secs(1), "{:?}", elapsed);
+    unsafe { libc::close(fd) };
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn ioctl_trace_shows_decoded_requests() {
+    let manager = TestManager::start().await;
+    let client = manager.client().await;
+    let _device = client.create_device(common::gamepad()).await.unwrap();
+
+    let output = run_preloaded_with(
+        &manager,
+        "preloaded_ioctl_trace_shows_decoded_requests",
+        &[("VIMPUTTI_TRACE_IOCTL", "1")],
+    )
+    .await;
+    let traced: Vec<&str> = output
+        .lines()
+        .filter(|line| line.contains("vimputti::ioctl"))
+        .collect();
+    let expected = [
+        "EVIOCGVERSION -> 0 wrote [01 00 01 00]",
+        "EVIOCGBIT(EV_KEY, 96) -> 96 wrote [",
+        "EVIOCGABS(ABS_X) -> 0 wrote [",
+        "JSIOCGAXES -> 0 wrote [",
+    ];
+    assert_eq!(traced.len(), expected.len(), "{:#?}", traced);
+    for (line, expected) in traced.iter().zip(expected) {
+        assert!(
+            line.contains(expected),
+            "{} doesn't show {}",
+            line,
+            expected
+        );
+    }
+
+    // Nothing is traced unless asked for
+    let output = run_preloaded(&manager, "preloaded_ioctl_trace_shows_decoded_requests").await;
+    assert!(!output.contains("vimputti::ioctl"), "{}", output);
+}
+
+#[test]
+#[ignore = "run with the shim preloaded"]
+fn preloaded_ioctl_trace_shows_decoded_requests() {
+    if !preloaded() {
+        return;
+    }
+
+    let fd = open("/dev/input/event0", libc::O_RDONLY | libc::O_NONBLOCK);
+    let mut version: libc::c_int = 0;
+    unsafe { libc::ioctl(fd, EVIOCGVERSION as _, &mut version) };
+    let mut bits = [0u8; 96];
+    unsafe { libc::ioctl(fd, eviocgbit(EV_KEY, bits.len()) as _, bits.as_mut_ptr()) };
+    let mut abs = [0i32; 6];
+    unsafe {
+        libc::ioctl(
+            fd,
+            eviocgabs(Axis::LeftStickX.to_ev_code()) as _,
+            abs.as_mut_ptr(),
+        )
+    };
+
+    let fd = open("/dev/input/js0", libc::O_RDONLY | libc::O_NONBLOCK);
+    let mut axes = 0u8;
+    unsafe { libc::ioctl(fd, JSIOCGAXES as _, &mut axes) };
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn udev_model_name_differs_from_evdev_name() {
+    let manager = TestManager::start().await;
+    let client = manager.client().await;
+    let _device = client
+        .create_device(
+            ControllerBuilder::new("Microsoft X-Box 360 pad")
+                .button(Button::A)
+                .udev_model_name("Xbox 360 Controller")
+                .build(),
+        )
+        .await
+        .unwrap();
+
+    let properties = std::fs::read_to_string(manager.base_path().join("udev_data/c13:64")).unwrap();
+    assert!(
+        properties.contains("E:ID_MODEL=Xbox_360_Controller\n"),
+        "{}",
+        properties
+    );
+    assert!(
+        properties.contains("E:ID_MODEL_ENC=Xbox\\x20360\\x20Controller\n"),
+        "{}",
+        properties
+    );
+
+    run_preloaded(
+        &manager,
+        "preloaded_udev_model_name_differs_from_evdev_name",
+    )
+    .await;
+}
+
+#[test]
+#[ignore = "run with the shim preloaded"]
+fn preloaded_udev_model_name_differs_from_evdev_name() {
+    if !preloaded() {
+        return;
+    }
+
+    let fd = open("/dev/input/event0", libc::O_RDONLY | libc::O_NONBLOCK);
+    let mut name = [0u8; 256];
+    let len = unsafe { libc::ioctl(fd, eviocgname(name.len()) as _, name.as_mut_ptr()) };
+    assert!(len > 0);
+    let name = std::ffi::CStr::from_bytes_until_nul(&name).unwrap();
+    assert!(
+        name.to_str()
+            .unwrap()
+            .starts_with("Microsoft X-Box 360 pad"),
+        "{:?}",
+        name
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn unknown_ioctls_are_answered_by_direction() {
+    let manager = TestManager::start().await;
+    let client = manager.client().await;
+    let _device = client.create_device(common::gamepad()).await.unwrap();
+
+    run_preloaded(
+        &manager,
+        "preloaded_unknown_ioctls_are_answered_by_direction",
+    )
+    .await;
+}
+
+#[test]
+#[ignore = "run with the shim preloaded"]
+fn preloaded_unknown_ioctls_are_answered_by_direction() {
+    if !preloaded() {
+        return;
+    }
+
+    for (path, ty, nr) in [
+        ("/dev/input/event0", EVDEV_IOC_TYPE, 0x8a),
+        ("/dev/input/js0", JS_IOC_TYPE, 0x40),
+        ("/dev/uinput", UINPUT_IOC_TYPE, 0x50),
+    ] {
+        let fd = open(path, libc::O_RDWR | libc::O_NONBLOCK);
+
+        // Without data, and with data passed in, the request is accepted and ignored
+        assert_eq!(unsafe { libc::ioctl(fd, io(ty, nr) as _) }, 0, "{}", path);
+        let mut data = [0xa5u8; 16];
+        assert_eq!(
+            unsafe { libc::ioctl(fd, iow(ty, nr, data.len()) as _, data.as_mut_ptr()) },
+            0,
+            "{}",
+            path
+        );
+        assert_eq!(data, [0xa5; 16], "{}", path);
+
+        // Data read out is zeroed, as far as the request's size goes
+        let mut data = [0xa5u8; 16];
+        assert_eq!(
+            unsafe { libc::ioctl(fd, ior(ty, nr, 12) as _, data.as_mut_ptr()) },
+            0,
+            "{}",
+            path
+        );
+        assert_eq!(data[..12], [0; 12], "{}", path);
+        assert_eq!(data[12..], [0xa5; 4], "{}", path);
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn dpad_style_decides_eviocgbit() {
+    let manager = TestManager::start().await;
+    let client = manager.client().await;
+    let _hat = client
+        .create_device(ControllerBuilder::new("Hat").dpad_axes().build())
+        .await
+        .unwrap();
+    let _buttons = client
+        .create_device(ControllerBuilder::new("Buttons").dpad_buttons().build())
+        .await
+        .unwrap();
+
+    run_preloaded(&manager, "preloaded_dpad_style_decides_eviocgbit").await;
+}
+
+#[test]
+#[ignore = "run with the shim preloaded"]
+fn preloaded_dpad_style_decides_eviocgbit() {
+    if !preloaded() {
+        return;
+    }
+
+    let is_set = |bits: &[u8], code: u16| bits[code as usize / 8] & (1 << (code % 8)) != 0;
+    let bits_of = |fd: i32, ev_type: u16| {
+        let mut bits = [0u8; KEY_MAX as usize / 8 + 1];
+        unsafe { libc::ioctl(fd, eviocgbit(ev_type, bits.len()) as _, bits.as_mut_ptr()) };
+        bits
+    };
+    let hats = [Axis::DPadX, Axis::DPadY].map(Axis::to_ev_code);
+    let buttons = [
+        Button::DPadUp,
+        Button::DPadDown,
+        Button::DPadLeft,
+        Button::DPadRight,
+    ]
+    .map(Button::to_ev_code);
+
+    let hat = open("/dev/input/event0", libc::O_RDONLY | libc::O_NONBLOCK);
+    let (abs, key) = (bits_of(hat, EV_ABS), bits_of(hat, EV_KEY));
+    assert!(hats.iter().all(|&code| is_set(&abs, code)));
+    assert!(!buttons.iter().any(|&code| is_set(&key, code)));
+
+    let button = open("/dev/input/event1", libc::O_RDONLY | libc::O_NONBLOCK);
+    let (abs, key) = (bits_of(button, EV_ABS), bits_of(button, EV_KEY));
+    assert!(!hats.iter().any(|&code| is_set(&abs, code)));
+    assert!(buttons.iter().all(|&code| is_set(&key, code)));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn changed_ids_are_read_by_eviocgid() {
+    let manager = TestManager::start().await;
+    let client = manager.client().await;
+    let device = client.create_device(common::gamepad()).await.unwrap();
+    device
+        .set_device_ids(0x054c, 0x09cc, Some(0x8111), Some(BusType::Bluetooth))
+        .await
+        .unwrap();
+
+    let id = manager
+        .base_path()
+        .join("sysfs/devices/virtual/input/input0/id");
+    for (file, value) in [("vendor", "054c"), ("product", "09cc"), ("version", "8111")] {
+        let read = std::fs::read_to_string(id.join(file)).unwrap();
+        assert_eq!(read.trim_end(), value, "{}", file);
+    }
+
+    run_preloaded(&manager, "preloaded_changed_ids_are_read_by_eviocgid").await;
+}
+
+#[test]
+#[ignore = "run with the shim preloaded"]
+fn preloaded_changed_ids_are_read_by_eviocgid() {
+    if !preloaded() {
+        return;
+    }
+
+    let fd = open("/dev/input/event0", libc::O_RDONLY | libc::O_NONBLOCK);
+    let mut id = [0u16; 4];
+    assert_eq!(
+        unsafe { libc::ioctl(fd, EVIOCGID as _, id.as_mut_ptr()) },
+        0
+    );
+    // bustype, vendor, product, version
+    assert_eq!(id, [0x05, 0x054c, 0x09cc, 0x8111]);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn flight_stick_axes_have_their_abs_codes() {
+    let manager = TestManager::start().await;
+    let client = manager.client().await;
+    let _device = client
+        .create_device(ControllerTemplates::flight_stick())
+        .await
+        .unwrap();
+
+    // The simulation axes round-trip through their codes
+    for (axis, code) in [
+        (Axis::Throttle, 0x06),
+        (Axis::Rudder, 0x07),
+        (Axis::Wheel, 0x08),
+        (Axis::Gas, 0x09),
+        (Axis::Brake, 0x0a),
+    ] {
+        assert_eq!(axis.to_ev_code(), code, "{:?}", axis);
+        assert_eq!(Axis::from_ev_code(code), Some(axis));
+    }
+
+    run_preloaded(&manager, "preloaded_flight_stick_axes_have_their_abs_codes").await;
+}
+
+#[test]
+#[ignore = "run with the shim preloaded"]
+fn preloaded_flight_stick_axes_have_their_abs_codes() {
+    if !preloaded() {
+        return;
+    }
+
+    let fd = open("/dev/input/event0", libc::O_RDONLY | libc::O_NONBLOCK);
+    let mut bits = [0u8; ABS_MAX as usize / 8 + 1];
+    let copied = unsafe { libc::ioctl(fd, eviocgbit(EV_ABS, bits.len()) as _, bits.as_mut_ptr()) };
+    assert_eq!(copied, bits.len() as i32);
+
+    let set: Vec<u16> = (0..=ABS_MAX)
+        .filter(|&code| bits[code as usize / 8] & (1 << (code % 8)) != 0)
+        .collect();
+    // ABS_X, ABS_Y, ABS_THROTTLE, ABS_RUDDER, ABS_HAT0X, ABS_HAT0Y
+    assert_eq!(set, [0x00, 0x01, 0x06, 0x07, 0x10, 0x11]);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn touchpad_resolution_is_read_by_eviocgabs() {
+    let manager = TestManager::start().await;
+    let client = manager.client().await;
+    let dpi = AxisConfig::new(Axis::Custom(ABS_MT_POSITION_Y), 0, 1000).with_dpi(1016);
+    assert_eq!(dpi.resolution, 40);
+    let _device = client
+        .create_device(
+            ControllerBuilder::new("Touchpad")
+                .multitouch(2, 1000, 1000)
+                .axis_resolution(Axis::Custom(ABS_MT_POSITION_X), 12)
+                .axis_resolution(Axis::Custom(ABS_MT_POSITION_Y), dpi.resolution)
+                .build(),
+        )
+        .await
+        .unwrap();
+
+    run_preloaded(
+        &manager,
+        "preloaded_touchpad_resolution_is_read_by_eviocgabs",
+    )
+    .await;
+}
+
+#[test]
+#[ignore = "run with the shim preloaded"]
+fn preloaded_touchpad_resolution_is_read_by_eviocgabs() {
+    if !preloaded() {
+        return;
+    }
+
+    let fd = open("/dev/input/event0", libc::O_RDONLY | libc::O_NONBLOCK);
+    let resolution = |code: u16| {
+        // value, minimum, maximum, fuzz, flat, resolution
+        let mut abs = [0i32; 6];
+        assert_eq!(
+            unsafe { libc::ioctl(fd, eviocgabs(code) as _, abs.as_mut_ptr()) },
+            0
+        );
+        abs[5]
+    };
+    assert_eq!(resolution(ABS_MT_POSITION_X), 12);
+    assert_eq!(resolution(ABS_MT_POSITION_Y), 40);
+    assert_eq!(resolution(ABS_MT_SLOT), 0);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn abs_misc_is_read_back_with_its_range() {
+    let manager = TestManager::start().await;
+    let client = manager.client().await;
+    let _device = client
+        .create_device(
+            ControllerBuilder::new("Pressure Pad")
+                .button(Button::A)
+                .axis(Axis::Misc, 0, 4095)
+                .axis(Axis::Custom(0x3e), -100, 100)
+                .build(),
+        )
+        .await
+        .unwrap();
+
+    run_preloaded(&manager, "preloaded_abs_misc_is_read_back_with_its_range").await;
+}
+
+#[test]
+#[ignore = "run with the shim preloaded"]
+fn preloaded_abs_misc_is_read_back_with_its_range() {
+    if !preloaded() {
+        return;
+    }
+
+    let fd = open("/dev/input/event0", libc::O_RDONLY | libc::O_NONBLOCK);
+    let mut bits = [0u8; ABS_MAX as usize / 8 + 1];
+    unsafe { libc::ioctl(fd, eviocgbit(EV_ABS, bits.len()) as _, bits.as_mut_ptr()) };
+    let set: Vec<u16> = (0..=ABS_MAX)
+        .filter(|&code| bits[code as usize / 8] & (1 << (code % 8)) != 0)
+        .collect();
+    assert_eq!(set, [0x28, 0x3e]);
+
+    // value, minimum, maximum, fuzz, flat, resolution, without the default stick fuzz
+    let abs = |code: u16| {
+        let mut abs = [0i32; 6];
+        assert_eq!(
+            unsafe { libc::ioctl(fd, eviocgabs(code) as _, abs.as_mut_ptr()) },
+            0
+        );
+        abs
+    };
+    assert_eq!(abs(0x28), [0, 0, 4095, 0, 0, 0]);
+    assert_eq!(abs(0x3e), [0, -100, 100, 0, 0, 0]);
+    // Not advertised
+    assert_eq!(abs(0x29), [0; 6]);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn gyro_samples_come_with_their_resolution() {
+    let manager = TestManager::start().await;
+    let client = manager.client().await;
+    let device = client
+        .create_device(ControllerTemplates::ps5_motion())
+        .await
+        .unwrap();
+    let mut reader = manager.open(device.event_node()).await;
+
+    // One degree per second around X
+    device.set_axis(Axis::GyroX, 1024).await.unwrap();
+    assert_eq!(
+        reader.next_frame().await,
+        [
+            (EV_ABS, Axis::GyroX.to_ev_code(), 1024),
+            (EV_SYN, SYN_REPORT, 0)
+        ]
+    );
+
+    run_preloaded(
+        &manager,
+        "preloaded_gyro_samples_come_with_their_resolution",
+    )
+    .await;
+}
+
+#[test]
+#[ignore = "run with the shim preloaded"]
+fn preloaded_gyro_samples_come_with_their_resolution() {
+    if !preloaded() {
+        return;
+    }
+
+    let fd = open("/dev/input/event0", libc::O_RDONLY | libc::O_NONBLOCK);
+    let abs = |code: u16| {
+        // value, minimum, maximum, fuzz, flat, resolution
+        let mut abs = [0i32; 6];
+        assert_eq!(
+            unsafe { libc::ioctl(fd, eviocgabs(code) as _, abs.as_mut_ptr()) },
+            0
+        );
+        abs
+    };
+    // Units per degree per second, and per g
+    assert_eq!(abs(Axis::GyroX.to_ev_code())[5], 1024);
+    assert_eq!(abs(Axis::AccelX.to_ev_code())[5], 8192);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn added_axis_is_read_by_eviocgabs_after_an_update() {
+    let manager = TestManager::start().await;
+    let client = manager.client().await;
+    let mut config = ControllerBuilder::new("Growing Pad")
+        .button(Button::A)
+        .axis(Axis::LeftStickX, -32768, 32767)
+        .build();
+    let mut device = client.create_device(config.clone()).await.unwrap();
+
+    config
+        .axes
+        .push(AxisConfig::new(Axis::LeftStickY, -1000, 1000));
+    device.update_config(config).await.unwrap();
+
+    run_preloaded(
+        &manager,
+        "preloaded_added_axis_is_read_by_eviocgabs_after_an_update",
+    )
+    .await;
+}
+
+#[test]
+#[ignore = "run with the shim preloaded"]
+fn preloaded_added_axis_is_read_by_eviocgabs_after_an_update() {
+    if !preloaded() {
+        return;
+    }
+
+    let fd = open("/dev/input/event0", libc::O_RDONLY | libc::O_NONBLOCK);
+    // value, minimum, maximum, fuzz, flat, resolution
+    let mut abs = [0i32; 6];
+    let y = Axis::LeftStickY.to_ev_code();
+    assert_eq!(
+        unsafe { libc::ioctl(fd, eviocgabs(y) as _, abs.as_mut_ptr()) },
+        0
+    );
+    assert_eq!(abs[1..3], [-1000, 1000]);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn joycon_joystick_maps_follow_their_layouts() {
+    let manager = TestManager::start().await;
+    let client = manager.client().await;
+    let _left = client
+        .create_device(ControllerTemplates::joycon_left())
+        .await
+        .unwrap();
+    let _right = client
+        .create_device(ControllerTemplates::joycon_right())
+        .await
+        .unwrap();
+
+    run_preloaded(
+        &manager,
+        "preloaded_joycon_joystick_maps_follow_their_layouts",
+    )
+    .await;
+}
+
+#[test]
+#[ignore = "run with the shim preloaded"]
+fn preloaded_joycon_joystick_maps_follow_their_layouts() {
+    if !preloaded() {
+        return;
+    }
+
+    for (node, config) in [
+        ("js0", ControllerTemplates::joycon_left()),
+        ("js1", ControllerTemplates::joycon_right()),
+    ] {
+        let fd = open(
+            &format!("/dev/input/{}", node),
+            libc::O_RDONLY | libc::O_NONBLOCK,
+        );
+        let config = config.advertised();
+
+        let mut axes = [0u8; ABS_MAX as usize + 1];
+        unsafe { libc::ioctl(fd, JSIOCGAXMAP as _, axes.as_mut_ptr()) };
+        let codes: Vec<u8> = config
+            .axes
+            .iter()
+            .map(|a| a.axis.to_ev_code() as u8)
+            .collect();
+        assert_eq!(axes[..codes.len()], codes, "{}", node);
+        assert_eq!(axes[codes.len()], 0, "{}", node);
+
+        let mut buttons = [0u16; ioc_size(JSIOCGBTNMAP) / 2];
+        unsafe { libc::ioctl(fd, JSIOCGBTNMAP as _, buttons.as_mut_ptr()) };
+        let codes: Vec<u16> = config.buttons.iter().map(|b| b.to_ev_code()).collect();
+        assert_eq!(buttons[..codes.len()], codes, "{}", node);
+    }
+}
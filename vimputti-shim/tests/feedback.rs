@@ -0,0 +1,116 @@
+//! Force feedback a game uploads and plays through the shim
+//!
+//! Each test sets up a device, then runs its `preloaded_` counterpart with the shim
+//! preloaded, see `preload`.
+
+#[path = "../../tests/common/mod.rs"]
+mod common;
+mod preload;
+
+use common::{TIMEOUT, TestManager};
+use preload::{open, preloaded, run_preloaded};
+use std::time::Duration;
+use vimputti::protocol::linux::*;
+use vimputti::protocol::*;
+
+/// `struct ff_effect` with a rumble effect, as the kernel headers lay it out on 64-bit
+#[repr(C)]
+struct FfRumble {
+    effect_type: u16,
+    id: i16,
+    direction: u16,
+    trigger: [u16; 2],
+    /// Length and delay in ms
+    replay: [u16; 2],
+    effect: EffectUnion,
+}
+
+/// The union of effects, 8 byte aligned for the pointer of periodic effects
+#[repr(C, align(8))]
+struct EffectUnion {
+    /// Strong and weak magnitudes of rumble
+    magnitudes: [u16; 2],
+    _rest: [u8; 28],
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn uploaded_rumble_reaches_feedback_readers() {
+    let manager = TestManager::start().await;
+    let client = manager.client().await;
+    let mut device = client.create_device(common::gamepad()).await.unwrap();
+
+    // Feedback is only sent to connected clients
+    let _rumble = device.on_rumble(|_, _, _| {}).await.unwrap();
+    tokio::time::timeout(TIMEOUT, async {
+        while device.client_counts().await.unwrap().feedback == 0 {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("feedback client didn't connect");
+
+    run_preloaded(
+        &manager,
+        "preloaded_uploaded_rumble_reaches_feedback_readers",
+    )
+    .await;
+
+    let mut feedback = Vec::new();
+    for _ in 0..3 {
+        let event = tokio::time::timeout(TIMEOUT, device.poll_feedback())
+            .await
+            .expect("no feedback")
+            .unwrap();
+        feedback.push(format!("{:?}", event));
+    }
+    let expected = [
+        FeedbackEvent::RumbleUpload {
+            id: 0,
+            strong: 0x4000,
+            weak: 0x2000,
+            duration: 300,
+        },
+        FeedbackEvent::Rumble {
+            strong_magnitude: 0x4000,
+            weak_magnitude: 0x2000,
+            duration_ms: 300,
+        },
+        FeedbackEvent::RumbleErase { id: 0 },
+    ]
+    .map(|event| format!("{:?}", event));
+    assert_eq!(feedback, expected);
+}
+
+#[test]
+#[ignore = "run with the shim preloaded"]
+fn preloaded_uploaded_rumble_reaches_feedback_readers() {
+    if !preloaded() {
+        return;
+    }
+
+    let fd = open("/dev/input/event0", libc::O_RDWR | libc::O_NONBLOCK);
+    assert_eq!(size_of::<FfRumble>(), ioc_size(EVIOCSFF));
+    let mut effect = FfRumble {
+        effect_type: FF_RUMBLE,
+        id: -1,
+        direction: 0,
+        trigger: [0; 2],
+        replay: [300, 0],
+        effect: EffectUnion {
+            magnitudes: [0x4000, 0x2000],
+            _rest: [0; 28],
+        },
+    };
+    assert_eq!(unsafe { libc::ioctl(fd, EVIOCSFF as _, &mut effect) }, 0);
+    assert_eq!(effect.id, 0);
+
+    // Played by its ID
+    let play = LinuxInputEvent::new(EV_FF, effect.id as u16, 1).to_bytes();
+    let written = unsafe { libc::write(fd, play.as_ptr().cast(), play.len()) };
+    assert_eq!(written, play.len() as isize);
+
+    assert_eq!(
+        unsafe { libc::ioctl(fd, EVIOCRMFF as _, effect.id as libc::c_int) },
+        0
+    );
+}
@@ -0,0 +1,283 @@
+//! Paths the shim redirects into the manager's base directory
+//!
+//! Each test sets up a device, then runs its `preloaded_` counterpart with the shim
+//! preloaded, see `preload`.
+
+#[path = "../../tests/common/mod.rs"]
+mod common;
+mod preload;
+
+use common::TestManager;
+use preload::{open, preloaded, run_preloaded, run_preloaded_with};
+use std::ffi::CString;
+use std::os::unix::fs::{PermissionsExt, symlink};
+use vimputti::manager::ManagerConfig;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn open_mode_is_only_used_with_o_creat() {
+    let manager = TestManager::start().await;
+    let client = manager.client().await;
+    let _device = client.create_device(common::gamepad()).await.unwrap();
+
+    run_preloaded(&manager, "preloaded_open_mode_is_only_used_with_o_creat").await;
+
+    for (file, mode) in [("created-open", 0o640), ("created-openat", 0o604)] {
+        let path = manager.base_path().join("udev_data").join(file);
+        let metadata = std::fs::metadata(&path).unwrap();
+        assert_eq!(metadata.permissions().mode() & 0o777, mode, "{}", file);
+    }
+}
+
+#[test]
+#[ignore = "run with the shim preloaded"]
+fn preloaded_open_mode_is_only_used_with_o_creat() {
+    if !preloaded() {
+        return;
+    }
+    unsafe { libc::umask(0) };
+
+    // Without O_CREAT no mode is passed, the redirected file opens as is
+    let fd = open("/run/udev/data/c13:64", libc::O_RDONLY);
+    let mut data = [0u8; 4096];
+    let read = unsafe { libc::read(fd, data.as_mut_ptr().cast(), data.len()) };
+    assert!(read > 0);
+    assert!(String::from_utf8_lossy(&data[..read as usize]).contains("E:ID_INPUT=1"));
+    unsafe { libc::close(fd) };
+
+    // With O_CREAT the mode is used
+    let path = CString::new("/run/udev/data/created-open").unwrap();
+    let fd = unsafe { libc::open(path.as_ptr(), libc::O_CREAT | libc::O_WRONLY, 0o640) };
+    assert!(fd >= 0, "{}", std::io::Error::last_os_error());
+    unsafe { libc::close(fd) };
+
+    let path = CString::new("/run/udev/data/created-openat").unwrap();
+    let fd = unsafe {
+        libc::openat(
+            libc::AT_FDCWD,
+            path.as_ptr(),
+            libc::O_CREAT | libc::O_WRONLY,
+            0o604,
+        )
+    };
+    assert!(fd >= 0, "{}", std::io::Error::last_os_error());
+    unsafe { libc::close(fd) };
+
+    // Reopening without O_CREAT leaves the mode alone
+    let fd = open("/run/udev/data/created-open", libc::O_RDONLY);
+    let mut st: libc::stat = unsafe { std::mem::zeroed() };
+    assert_eq!(unsafe { libc::fstat(fd, &mut st) }, 0);
+    assert_eq!(st.st_mode & 0o777, 0o640);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn redirect_escaping_the_base_path_is_refused() {
+    let manager = TestManager::start().await;
+
+    // Next to the base path, where the redirect of the path below would lead
+    let base = manager.base_path();
+    let name = format!("{}-escaped", base.file_name().unwrap().to_str().unwrap());
+    let escaped = base.parent().unwrap().join(&name);
+    std::fs::write(&escaped, "escaped").unwrap();
+
+    run_preloaded_with(
+        &manager,
+        "preloaded_redirect_escaping_the_base_path_is_refused",
+        &[("VIMPUTTI_TEST_ESCAPED", &name)],
+    )
+    .await;
+    std::fs::remove_file(&escaped).unwrap();
+}
+
+#[test]
+#[ignore = "run with the shim preloaded"]
+fn preloaded_redirect_escaping_the_base_path_is_refused() {
+    if !preloaded() {
+        return;
+    }
+    let name = std::env::var("VIMPUTTI_TEST_ESCAPED").unwrap();
+
+    // Redirected this is `<base>/sysfs/class/input/../../../../<name>`, outside of the base
+    // path. Unredirected it's `/<name>`, which doesn't exist.
+    let path = CString::new(format!("/sys/class/input/../../../../{}", name)).unwrap();
+    let fd = unsafe { libc::open(path.as_ptr(), libc::O_RDONLY) };
+    assert_eq!(fd, -1, "opened the file outside of the base path");
+    assert_eq!(
+        std::io::Error::last_os_error().raw_os_error(),
+        Some(libc::ENOENT)
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn base_path_is_asked_from_the_manager() {
+    let manager = TestManager::start().await;
+    let client = manager.client().await;
+    let _device = client.create_device(common::gamepad()).await.unwrap();
+
+    // Only a fallback, the manager's answer wins
+    let decoy = std::env::temp_dir().join(format!("vimputti-decoy-{}", std::process::id()));
+    std::fs::create_dir_all(decoy.join("devices")).unwrap();
+    assert!(!manager.base_path().starts_with("/tmp/vimputti/"));
+
+    run_preloaded_with(
+        &manager,
+        "preloaded_base_path_is_asked_from_the_manager",
+        &[("VIMPUTTI_BASE_PATH", decoy.to_str().unwrap())],
+    )
+    .await;
+    std::fs::remove_dir_all(&decoy).unwrap();
+}
+
+#[test]
+#[ignore = "run with the shim preloaded"]
+fn preloaded_base_path_is_asked_from_the_manager() {
+    if !preloaded() {
+        return;
+    }
+
+    let fd = open("/dev/input/event0", libc::O_RDONLY | libc::O_NONBLOCK);
+    let mut name = [0u8; 256];
+    let len = unsafe {
+        libc::ioctl(
+            fd,
+            vimputti::protocol::linux::eviocgname(name.len()) as _,
+            name.as_mut_ptr(),
+        )
+    };
+    assert!(len > 0);
+    let name = std::ffi::CStr::from_bytes_until_nul(&name).unwrap();
+    assert!(
+        name.to_str().unwrap().starts_with(&common::gamepad().name),
+        "{:?}",
+        name
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn instances_with_other_node_prefixes_dont_collide() {
+    let plain = TestManager::start().await;
+    let prefixed = TestManager::with_config(ManagerConfig {
+        node_prefix: "b".to_string(),
+        ..ManagerConfig::default()
+    })
+    .await;
+    // Both are device 0 of their manager
+    let _plain_device = plain
+        .client()
+        .await
+        .create_device(common::gamepad())
+        .await
+        .unwrap();
+    let _prefixed_device = prefixed
+        .client()
+        .await
+        .create_device(common::gamepad())
+        .await
+        .unwrap();
+
+    // Each tree holds the other's node too, as when they are merged
+    symlink(prefixed.node_path("eventb0"), plain.node_path("eventb0")).unwrap();
+    symlink(plain.node_path("event0"), prefixed.node_path("event0")).unwrap();
+
+    for (manager, own, other) in [
+        (&plain, "event0", "eventb0"),
+        (&prefixed, "eventb0", "event0"),
+    ] {
+        run_preloaded_with(
+            manager,
+            "preloaded_instances_with_other_node_prefixes_dont_collide",
+            &[("VIMPUTTI_TEST_OWN", own), ("VIMPUTTI_TEST_OTHER", other)],
+        )
+        .await;
+    }
+}
+
+#[test]
+#[ignore = "run with the shim preloaded"]
+fn preloaded_instances_with_other_node_prefixes_dont_collide() {
+    if !preloaded() {
+        return;
+    }
+    let own = format!("/dev/input/{}", std::env::var("VIMPUTTI_TEST_OWN").unwrap());
+    let other = format!(
+        "/dev/input/{}",
+        std::env::var("VIMPUTTI_TEST_OTHER").unwrap()
+    );
+
+    let mut st: libc::stat = unsafe { std::mem::zeroed() };
+    let path = CString::new(own.as_str()).unwrap();
+    assert_eq!(unsafe { libc::stat(path.as_ptr(), &mut st) }, 0, "{}", own);
+    assert_eq!(st.st_rdev, libc::makedev(13, 64), "{}", own);
+
+    let fd = open(&own, libc::O_RDONLY | libc::O_NONBLOCK);
+    let mut st: libc::stat = unsafe { std::mem::zeroed() };
+    assert_eq!(unsafe { libc::fstat(fd, &mut st) }, 0);
+    assert_eq!(st.st_rdev, libc::makedev(13, 64), "{}", own);
+
+    // The other instance's node doesn't pass for device 0 of this one
+    let path = CString::new(other.as_str()).unwrap();
+    assert_eq!(
+        unsafe { libc::stat(path.as_ptr(), &mut st) },
+        -1,
+        "{}",
+        other
+    );
+    assert_eq!(
+        std::io::Error::last_os_error().raw_os_error(),
+        Some(libc::ENOENT)
+    );
+}
+
+/// Names in a directory, sorted and comma separated, or `missing`
+fn listing(path: &str) -> String {
+    match std::fs::read_dir(path) {
+        Ok(entries) => {
+            let mut names: Vec<String> = entries
+                .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+                .collect();
+            names.sort();
+            names.join(",")
+        }
+        Err(_) => "missing".to_string(),
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn batteries_are_redirected_next_to_the_hosts_power_supplies() {
+    let manager = TestManager::start().await;
+    let client = manager.client().await;
+    let mut config = common::gamepad();
+    config.battery = Some(vimputti::protocol::BatteryConfig {
+        capacity: 55,
+        status: vimputti::protocol::BatteryStatus::Discharging,
+    });
+    let _device = client.create_device(config).await.unwrap();
+
+    run_preloaded_with(
+        &manager,
+        "preloaded_batteries_are_redirected_next_to_the_hosts_power_supplies",
+        &[(
+            "VIMPUTTI_TEST_POWER_SUPPLIES",
+            &listing("/sys/class/power_supply"),
+        )],
+    )
+    .await;
+}
+
+#[test]
+#[ignore = "run with the shim preloaded"]
+fn preloaded_batteries_are_redirected_next_to_the_hosts_power_supplies() {
+    if !preloaded() {
+        return;
+    }
+
+    let battery = "/sys/class/power_supply/vimputti-battery-event0";
+    let read = |file: &str| std::fs::read_to_string(format!("{}/{}", battery, file)).unwrap();
+    assert_eq!(read("capacity"), "55\n");
+    assert_eq!(read("status"), "Discharging\n");
+
+    // The class directory itself is still the host's
+    assert_eq!(
+        listing("/sys/class/power_supply"),
+        std::env::var("VIMPUTTI_TEST_POWER_SUPPLIES").unwrap()
+    );
+}
@@ -0,0 +1,121 @@
+//! Running tests in a child process with the shim preloaded
+//!
+//! A test sets up the manager side, then calls `run_preloaded` with the name of an ignored
+//! test in the same binary. That test runs again in a child process with the shim in
+//! `LD_PRELOAD`, talking to the manager, and checks `preloaded()` first so it does nothing
+//! when run directly with `--ignored`.
+
+#![allow(dead_code)]
+
+use crate::common::TestManager;
+use std::ffi::CString;
+use std::path::PathBuf;
+use std::process::{Child, Stdio};
+use std::sync::OnceLock;
+
+/// Path of the shim library, built on first use so it's never stale
+fn shim_path() -> &'static PathBuf {
+    static SHIM: OnceLock<PathBuf> = OnceLock::new();
+    SHIM.get_or_init(|| {
+        let mut command = std::process::Command::new(env!("CARGO"));
+        command.args(["build", "-p", "vimputti-shim"]);
+        if !cfg!(debug_assertions) {
+            command.arg("--release");
+        }
+        assert!(
+            command.status().unwrap().success(),
+            "building the shim failed"
+        );
+
+        // The test binary is in target/<profile>/deps
+        let exe = std::env::current_exe().unwrap();
+        exe.parent()
+            .and_then(|deps| deps.parent())
+            .unwrap()
+            .join("libvimputti_shim.so")
+    })
+}
+
+/// Run the ignored test `test` of this binary with the shim preloaded, against `manager`
+///
+/// Returns what the test wrote to stdout, which includes the shim's log.
+pub async fn run_preloaded(manager: &TestManager, test: &str) -> String {
+    run_preloaded_with(manager, test, &[]).await
+}
+
+/// `run_preloaded` with extra environment variables
+pub async fn run_preloaded_with(manager: &TestManager, test: &str, env: &[(&str, &str)]) -> String {
+    spawn_preloaded(manager, test, env).await.wait().await
+}
+
+/// Start `run_preloaded_with` without waiting for the test, for tests that act on the manager
+/// while the child runs
+pub async fn spawn_preloaded(
+    manager: &TestManager,
+    test: &str,
+    env: &[(&str, &str)],
+) -> PreloadedTest {
+    let mut command = std::process::Command::new(std::env::current_exe().unwrap());
+    command
+        .args(["--exact", test, "--ignored", "--nocapture"])
+        .env("VIMPUTTI_SOCKET", manager.socket_path())
+        .env("VIMPUTTI_SHIM_TEST", "1")
+        .envs(env.iter().copied())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    let child = tokio::task::spawn_blocking(move || {
+        command.env("LD_PRELOAD", shim_path());
+        command.spawn()
+    })
+    .await
+    .unwrap()
+    .unwrap();
+
+    PreloadedTest {
+        test: test.to_string(),
+        child,
+    }
+}
+
+/// A test started by `spawn_preloaded`
+pub struct PreloadedTest {
+    test: String,
+    child: Child,
+}
+impl PreloadedTest {
+    /// Wait for the test to finish, failing if it did, and return its stdout
+    pub async fn wait(self) -> String {
+        let output = tokio::task::spawn_blocking(move || self.child.wait_with_output())
+            .await
+            .unwrap()
+            .unwrap();
+
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(
+            output.status.success() && stdout.contains("1 passed"),
+            "{} failed:\n{}\n{}",
+            self.test,
+            stdout,
+            String::from_utf8_lossy(&output.stderr)
+        );
+        stdout.into_owned()
+    }
+}
+
+/// Whether this is the child process of `run_preloaded`
+pub fn preloaded() -> bool {
+    std::env::var_os("VIMPUTTI_SHIM_TEST").is_some()
+}
+
+/// Open a path through the shim
+pub fn open(path: &str, flags: i32) -> i32 {
+    let path = CString::new(path).unwrap();
+    let fd = unsafe { libc::open(path.as_ptr(), flags) };
+    assert!(
+        fd >= 0,
+        "open {:?} failed: {}",
+        path,
+        std::io::Error::last_os_error()
+    );
+    fd
+}
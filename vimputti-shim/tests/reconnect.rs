@@ -0,0 +1,157 @@
+//! Device fds surviving a manager restart with `VIMPUTTI_RECONNECT`
+
+#[path = "../../tests/common/mod.rs"]
+mod common;
+mod preload;
+
+use common::{TIMEOUT, TestManager};
+use preload::{open, preloaded, spawn_preloaded};
+use std::time::{Duration, Instant};
+use vimputti::protocol::*;
+use vimputti::{ControllerBuilder, VirtualController};
+
+fn controller() -> DeviceConfig {
+    ControllerBuilder::new("Reconnecting")
+        .buttons([Button::A, Button::B])
+        .build()
+}
+
+/// Wait for the shim to have the device open
+async fn wait_for_reader(device: &VirtualController) {
+    tokio::time::timeout(TIMEOUT, async {
+        while device.client_count().await.unwrap() == 0 {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("shim didn't connect");
+}
+
+/// Restart the manager under the preloaded `test`, which has the device open, and press A
+/// once the shim reconnected
+async fn restart_under(test: &str) {
+    let mut manager = TestManager::start().await;
+    let device = manager
+        .client()
+        .await
+        .create_device(controller())
+        .await
+        .unwrap();
+
+    let test = spawn_preloaded(&manager, test, &[("VIMPUTTI_RECONNECT", "50")]).await;
+    wait_for_reader(&device).await;
+    // Dropping it would destroy the device of the same ID on the new manager
+    device.detach();
+
+    // The same device comes back at the same node after the restart
+    manager.restart().await;
+    let client = manager.client().await;
+    let device = client.create_device(controller()).await.unwrap();
+    assert_eq!(device.event_node(), "event0");
+
+    wait_for_reader(&device).await;
+    device.press_button(Button::A).await.unwrap();
+    device.flush().await.unwrap();
+
+    test.wait().await;
+}
+
+/// Read an event from `fd` and check it's the press of A
+fn assert_reads_press(fd: libc::c_int) {
+    let mut event = [0u8; 24];
+    let read = unsafe { libc::read(fd, event.as_mut_ptr().cast(), event.len()) };
+    assert_eq!(read, 24, "{}", std::io::Error::last_os_error());
+
+    let event_type = u16::from_ne_bytes([event[16], event[17]]);
+    let code = u16::from_ne_bytes([event[18], event[19]]);
+    let value = i32::from_ne_bytes([event[20], event[21], event[22], event[23]]);
+    assert_eq!(
+        (event_type, code, value),
+        (EV_KEY, Button::A.to_ev_code(), 1)
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn open_fd_survives_manager_restart() {
+    restart_under("preloaded_open_fd_survives_manager_restart").await;
+}
+
+#[test]
+#[ignore = "run with the shim preloaded"]
+fn preloaded_open_fd_survives_manager_restart() {
+    if !preloaded() {
+        return;
+    }
+
+    // Blocks across the restart, the read hitting EOF is what reconnects
+    let fd = open("/dev/input/event0", libc::O_RDONLY);
+    assert_reads_press(fd);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn epoll_registration_survives_manager_restart() {
+    restart_under("preloaded_epoll_registration_survives_manager_restart").await;
+}
+
+#[test]
+#[ignore = "run with the shim preloaded"]
+fn preloaded_epoll_registration_survives_manager_restart() {
+    if !preloaded() {
+        return;
+    }
+
+    let fd = open("/dev/input/event0", libc::O_RDONLY | libc::O_NONBLOCK);
+    let epfd = unsafe { libc::epoll_create1(0) };
+    let mut registration = libc::epoll_event {
+        events: libc::EPOLLIN as u32,
+        u64: 7,
+    };
+    assert_eq!(
+        unsafe { libc::epoll_ctl(epfd, libc::EPOLL_CTL_ADD, fd, &mut registration) },
+        0
+    );
+
+    // Nothing is read, the wait after the hang-up is what reconnects
+    let deadline = Instant::now() + Duration::from_secs(10);
+    loop {
+        assert!(Instant::now() < deadline, "no input after the restart");
+        let mut ready = libc::epoll_event { events: 0, u64: 0 };
+        let count = unsafe { libc::epoll_wait(epfd, &mut ready, 1, 100) };
+        if count == 1 && ready.events & libc::EPOLLHUP as u32 == 0 {
+            assert_eq!({ ready.u64 }, 7);
+            break;
+        }
+    }
+    assert_reads_press(fd);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn polled_fd_survives_manager_restart() {
+    restart_under("preloaded_polled_fd_survives_manager_restart").await;
+}
+
+#[test]
+#[ignore = "run with the shim preloaded"]
+fn preloaded_polled_fd_survives_manager_restart() {
+    if !preloaded() {
+        return;
+    }
+
+    let fd = open("/dev/input/event0", libc::O_RDONLY | libc::O_NONBLOCK);
+
+    // Nothing is read, the poll after the hang-up is what reconnects
+    let deadline = Instant::now() + Duration::from_secs(10);
+    loop {
+        assert!(Instant::now() < deadline, "no input after the restart");
+        let mut pollfd = libc::pollfd {
+            fd,
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        let count = unsafe { libc::poll(&mut pollfd, 1, 100) };
+        if count == 1 && pollfd.revents & libc::POLLHUP == 0 {
+            break;
+        }
+    }
+    assert_reads_press(fd);
+}
@@ -0,0 +1,228 @@
+//! The libudev functions the shim exports
+//!
+//! Each test sets up a device, then runs its `preloaded_` counterpart with the shim
+//! preloaded, see `preload`. The functions are looked up at runtime, the tests don't link
+//! libudev.
+
+#[path = "../../tests/common/mod.rs"]
+mod common;
+mod preload;
+
+use common::TestManager;
+use preload::{open, preloaded, run_preloaded};
+use std::ffi::{CStr, CString, c_char, c_int, c_void};
+use vimputti::ControllerBuilder;
+use vimputti::protocol::*;
+
+/// Look up a function the preloaded shim exports
+unsafe fn udev_fn<T: Copy>(name: &str) -> T {
+    let name = CString::new(name).unwrap();
+    let symbol = unsafe { libc::dlsym(libc::RTLD_DEFAULT, name.as_ptr()) };
+    assert!(!symbol.is_null(), "{:?} isn't exported", name);
+    unsafe { std::mem::transmute_copy(&symbol) }
+}
+
+/// Enumerate devices through the shim, with the values of `keys` for each device
+fn enumerate(keys: &[&str]) -> Vec<Vec<Option<String>>> {
+    type New = unsafe extern "C" fn() -> *mut c_void;
+    type WithUdev = unsafe extern "C" fn(*mut c_void) -> *mut c_void;
+    type Scan = unsafe extern "C" fn(*mut c_void) -> c_int;
+    type Name = unsafe extern "C" fn(*mut c_void) -> *const c_char;
+    type FromSyspath = unsafe extern "C" fn(*mut c_void, *const c_char) -> *mut c_void;
+    type Property = unsafe extern "C" fn(*mut c_void, *const c_char) -> *const c_char;
+
+    let string = |s: *const c_char| {
+        (!s.is_null()).then(|| unsafe { CStr::from_ptr(s) }.to_str().unwrap().to_string())
+    };
+    let mut devices = Vec::new();
+    unsafe {
+        let udev = udev_fn::<New>("udev_new")();
+        let enumerate = udev_fn::<WithUdev>("udev_enumerate_new")(udev);
+        assert_eq!(udev_fn::<Scan>("udev_enumerate_scan_devices")(enumerate), 0);
+
+        let mut entry = udev_fn::<WithUdev>("udev_enumerate_get_list_entry")(enumerate);
+        while !entry.is_null() {
+            let syspath = udev_fn::<Name>("udev_list_entry_get_name")(entry);
+            let device = udev_fn::<FromSyspath>("udev_device_new_from_syspath")(udev, syspath);
+            assert!(!device.is_null());
+            let property = |key: &&str| {
+                let key = CString::new(*key).unwrap();
+                string(udev_fn::<Property>("udev_device_get_property_value")(
+                    device,
+                    key.as_ptr(),
+                ))
+            };
+            devices.push(keys.iter().map(property).collect());
+            entry = udev_fn::<WithUdev>("udev_list_entry_get_next")(entry);
+        }
+    }
+    devices
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn enumeration_sees_devices_before_any_open() {
+    let manager = TestManager::start().await;
+    let client = manager.client().await;
+    let _device = client.create_device(common::gamepad()).await.unwrap();
+
+    run_preloaded(
+        &manager,
+        "preloaded_enumeration_sees_devices_before_any_open",
+    )
+    .await;
+}
+
+#[test]
+#[ignore = "run with the shim preloaded"]
+fn preloaded_enumeration_sees_devices_before_any_open() {
+    if !preloaded() {
+        return;
+    }
+
+    let devices = enumerate(&["DEVNAME", "ID_MODEL_ID"]);
+    let model = format!("{:04x}", common::gamepad().product_id);
+    assert_eq!(devices.len(), 2, "{:?}", devices);
+    for (device, node) in devices.iter().zip(["event0", "js0"]) {
+        assert!(device[0].as_ref().unwrap().ends_with(node), "{:?}", devices);
+        assert_eq!(device[1].as_ref(), Some(&model), "{:?}", devices);
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn extra_udev_properties_are_queryable() {
+    let manager = TestManager::start().await;
+    let client = manager.client().await;
+    let _kept = client
+        .create_device(
+            ControllerBuilder::new("Tracker")
+                .button(Button::A)
+                .udev_property("ID_VIMPUTTI_TEST", "tracker")
+                .udev_property("ID_BUS", "bluetooth")
+                .build(),
+        )
+        .await
+        .unwrap();
+    let _overridden = client
+        .create_device(
+            ControllerBuilder::new("Tracker")
+                .button(Button::A)
+                .udev_property("ID_BUS", "bluetooth")
+                .override_udev_properties(true)
+                .build(),
+        )
+        .await
+        .unwrap();
+
+    run_preloaded(&manager, "preloaded_extra_udev_properties_are_queryable").await;
+}
+
+#[test]
+#[ignore = "run with the shim preloaded"]
+fn preloaded_extra_udev_properties_are_queryable() {
+    if !preloaded() {
+        return;
+    }
+
+    // Read back from the udev database before the nodes are opened, from the config after
+    for opened in [false, true] {
+        if opened {
+            for node in ["event0", "event1"] {
+                open(
+                    &format!("/dev/input/{}", node),
+                    libc::O_RDONLY | libc::O_NONBLOCK,
+                );
+            }
+        }
+        let devices = enumerate(&["DEVNAME", "ID_VIMPUTTI_TEST", "ID_BUS"]);
+        let property = |node: &str, index: usize| {
+            let device = devices
+                .iter()
+                .find(|device| device[0].as_ref().unwrap().ends_with(node))
+                .unwrap_or_else(|| panic!("no {} in {:?}", node, devices));
+            device[index].clone()
+        };
+
+        // Generated properties are kept unless overriding them is allowed
+        assert_eq!(
+            property("event0", 1).as_deref(),
+            Some("tracker"),
+            "{}",
+            opened
+        );
+        assert_eq!(property("event0", 2).as_deref(), Some("usb"), "{}", opened);
+        assert_eq!(property("event1", 1), None, "{}", opened);
+        assert_eq!(
+            property("event1", 2).as_deref(),
+            Some("bluetooth"),
+            "{}",
+            opened
+        );
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn monitor_filters_skip_other_subsystems() {
+    let manager = TestManager::start().await;
+    let client = manager.client().await;
+    let _device = client.create_device(common::gamepad()).await.unwrap();
+
+    run_preloaded(&manager, "preloaded_monitor_filters_skip_other_subsystems").await;
+}
+
+#[test]
+#[ignore = "run with the shim preloaded"]
+fn preloaded_monitor_filters_skip_other_subsystems() {
+    if !preloaded() {
+        return;
+    }
+
+    type New = unsafe extern "C" fn(*mut c_void, *const c_char) -> *mut c_void;
+    type AddMatch = unsafe extern "C" fn(*mut c_void, *const c_char, *const c_char) -> c_int;
+    type Fd = unsafe extern "C" fn(*mut c_void) -> c_int;
+    type Receive = unsafe extern "C" fn(*mut c_void) -> *mut c_void;
+    type Devnode = unsafe extern "C" fn(*mut c_void) -> *const c_char;
+
+    // Both get the add events of the existing devices when connecting
+    let monitor = |subsystem: &str| unsafe {
+        let name = CString::new("udev").unwrap();
+        let subsystem = CString::new(subsystem).unwrap();
+        let monitor =
+            udev_fn::<New>("udev_monitor_new_from_netlink")(std::ptr::null_mut(), name.as_ptr());
+        let added = udev_fn::<AddMatch>("udev_monitor_filter_add_match_subsystem_devtype")(
+            monitor,
+            subsystem.as_ptr(),
+            std::ptr::null(),
+        );
+        assert_eq!(added, 0);
+        monitor
+    };
+    let receive_all = |monitor: *mut c_void| unsafe {
+        let mut pollfd = libc::pollfd {
+            fd: udev_fn::<Fd>("udev_monitor_get_fd")(monitor),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        assert_eq!(libc::poll(&mut pollfd, 1, 5000), 1);
+        std::thread::sleep(std::time::Duration::from_millis(100));
+
+        let mut devnodes = Vec::new();
+        loop {
+            let device = udev_fn::<Receive>("udev_monitor_receive_device")(monitor);
+            if device.is_null() {
+                return devnodes;
+            }
+            let devnode = udev_fn::<Devnode>("udev_device_get_devnode")(device);
+            devnodes.push(CStr::from_ptr(devnode).to_str().unwrap().to_string());
+        }
+    };
+    let hidraw = monitor("hidraw");
+    let input = monitor("input");
+
+    let devnodes = receive_all(input);
+    assert!(
+        devnodes.iter().any(|devnode| devnode.ends_with("event0")),
+        "{:?}",
+        devnodes
+    );
+    assert_eq!(receive_all(hidraw), Vec::<String>::new());
+}
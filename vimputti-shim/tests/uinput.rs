@@ -0,0 +1,83 @@
+//! The shim's uinput emulation
+//!
+//! Each test sets up the manager, then runs its `preloaded_` counterpart with the shim
+//! preloaded, see `preload`.
+
+#[path = "../../tests/common/mod.rs"]
+mod common;
+mod preload;
+
+use common::TestManager;
+use preload::{open, preloaded, run_preloaded};
+use vimputti::protocol::*;
+
+/// Wait on an epoll set, returning the events
+fn epoll_wait(epfd: i32, timeout: i32) -> Vec<libc::epoll_event> {
+    let mut events = [libc::epoll_event { events: 0, u64: 0 }; 8];
+    let count = unsafe { libc::epoll_wait(epfd, events.as_mut_ptr(), 8, timeout) };
+    assert!(count >= 0);
+    events[..count as usize].to_vec()
+}
+
+fn epoll_add(epfd: i32, fd: i32, data: u64) {
+    let mut event = libc::epoll_event {
+        events: libc::EPOLLIN as u32,
+        u64: data,
+    };
+    assert_eq!(
+        unsafe { libc::epoll_ctl(epfd, libc::EPOLL_CTL_ADD, fd, &mut event) },
+        0
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn uinput_fd_is_never_epoll_readable() {
+    let manager = TestManager::start().await;
+    run_preloaded(&manager, "preloaded_uinput_fd_is_never_epoll_readable").await;
+}
+
+#[test]
+#[ignore = "run with the shim preloaded"]
+fn preloaded_uinput_fd_is_never_epoll_readable() {
+    if !preloaded() {
+        return;
+    }
+
+    let uinput = open("/dev/uinput", libc::O_RDWR | libc::O_NONBLOCK);
+
+    // Make the connection readable behind the shim's back, with a request whose answer
+    // isn't read
+    let request = UinputRequest::SetEvBit { ev_type: EV_KEY }
+        .to_bytes()
+        .unwrap();
+    let written =
+        unsafe { libc::syscall(libc::SYS_write, uinput, request.as_ptr(), request.len()) };
+    assert_eq!(written, request.len() as i64);
+
+    let epfd = unsafe { libc::epoll_create1(0) };
+    epoll_add(epfd, uinput, 1);
+    // Waited out without spinning on the readiness that keeps being there
+    let cpu_time = || {
+        let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+        assert_eq!(
+            unsafe { libc::getrusage(libc::RUSAGE_THREAD, &mut usage) },
+            0
+        );
+        let time = |t: libc::timeval| t.tv_sec * 1_000_000 + t.tv_usec;
+        time(usage.ru_utime) + time(usage.ru_stime)
+    };
+    let before = cpu_time();
+    assert!(epoll_wait(epfd, 200).is_empty());
+    let spent_us = cpu_time() - before;
+    assert!(spent_us < 50_000, "spent {}us waiting", spent_us);
+
+    // Other FDs are still reported when registered with the same data, as epoll
+    // doesn't tell which of the two became ready
+    let mut pipe = [0; 2];
+    assert_eq!(unsafe { libc::pipe(pipe.as_mut_ptr()) }, 0);
+    epoll_add(epfd, pipe[0], 1);
+    assert_eq!(unsafe { libc::write(pipe[1], b"x".as_ptr().cast(), 1) }, 1);
+    let events = epoll_wait(epfd, 200);
+    assert!(!events.is_empty());
+    assert!(events.iter().all(|event| { event.u64 } == 1));
+}